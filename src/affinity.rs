@@ -0,0 +1,79 @@
+use crate::value::Value;
+
+/// A column's type affinity, the storage class SQLite prefers for its
+/// values. Besides deciding how values are stored, it decides how a
+/// comparison converts its operands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Affinity {
+    Text,
+    Numeric,
+    Integer,
+    Real,
+    /// No preference: what a column without a declared type has.
+    Blob,
+}
+
+impl Affinity {
+    /// The affinity of a column declared with type `declared`, by SQLite's
+    /// rules, the first that matches: INT gives INTEGER; CHAR, CLOB or TEXT
+    /// gives TEXT; BLOB or no type gives BLOB; REAL, FLOA or DOUB gives REAL;
+    /// anything else NUMERIC.
+    pub fn of_type(declared: &str) -> Affinity {
+        let t = declared.to_ascii_uppercase();
+        let has = |words: &[&str]| words.iter().any(|w| t.contains(w));
+        if has(&["INT"]) {
+            Affinity::Integer
+        } else if has(&["CHAR", "CLOB", "TEXT"]) {
+            Affinity::Text
+        } else if t.is_empty() || has(&["BLOB"]) {
+            Affinity::Blob
+        } else if has(&["REAL", "FLOA", "DOUB"]) {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    /// The letter SQLite's bytecode names it by, as listed in a
+    /// comparison's p5.
+    pub fn code(self) -> i64 {
+        match self {
+            Affinity::Blob => 0x41,
+            Affinity::Text => 0x42,
+            Affinity::Numeric => 0x43,
+            Affinity::Integer => 0x44,
+            Affinity::Real => 0x45,
+        }
+    }
+
+    pub fn is_numeric(self) -> bool {
+        matches!(self, Affinity::Numeric | Affinity::Integer | Affinity::Real)
+    }
+
+    /// The affinity a comparison applies to both operands, given each
+    /// operand's own: `None` for an expression that is not a column, which
+    /// has none. Two columns compare numerically if either is numeric, and
+    /// as they are otherwise; a column and anything else compare under the
+    /// column's affinity; two other expressions compare as they are.
+    pub fn for_comparison(left: Option<Affinity>, right: Option<Affinity>) -> Affinity {
+        match (left, right) {
+            (Some(l), Some(r)) if l.is_numeric() || r.is_numeric() => Affinity::Numeric,
+            (Some(_), Some(_)) | (None, None) => Affinity::Blob,
+            (Some(a), None) | (None, Some(a)) => a,
+        }
+    }
+
+    /// `v` as a comparison under this affinity sees it: a numeric affinity
+    /// turns text that looks like a number into that number, TEXT turns a
+    /// number into its text, and BLOB leaves everything as it is.
+    pub fn apply(self, v: &Value) -> Option<Value> {
+        match (self, v) {
+            (Affinity::Text, Value::Integer(_) | Value::Real(_)) => {
+                Some(Value::Text(v.to_string()))
+            }
+            (Affinity::Text | Affinity::Blob, _) => None,
+            (_, Value::Text(_)) => v.as_numeric_text(),
+            _ => None,
+        }
+    }
+}
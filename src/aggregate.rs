@@ -0,0 +1,215 @@
+use anyhow::{bail, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::f64::consts::PI;
+use std::hash::{Hash, Hasher};
+
+use crate::functions::check_arity;
+use crate::value::Value;
+
+/// The running state of one aggregate call over the rows of a query.
+pub trait Accumulator {
+    fn step(&mut self, args: &[Value]) -> Result<()>;
+    fn finish(&mut self) -> Result<Value>;
+}
+
+/// A fresh accumulator for a call to the aggregate `name` with `arg_count`
+/// arguments.
+pub fn accumulator(name: &str, arg_count: usize) -> Result<Box<dyn Accumulator>> {
+    check_arity(name, &vec![Value::Null; arg_count])?;
+    match name {
+        "approx_count_distinct" => Ok(Box::new(HyperLogLog::new())),
+        "approx_percentile" => Ok(Box::new(ApproxPercentile::new())),
+        _ => bail!("no such function: {}", name),
+    }
+}
+
+/// Registers are addressed by the top `HLL_BITS` bits of each hash: 16384
+/// one-byte registers, for a standard error of about 0.8%.
+const HLL_BITS: u32 = 14;
+
+/// Counts distinct non-NULL values in fixed memory (Flajolet et al.'s
+/// HyperLogLog). Values are distinct as SQLite's DISTINCT sees them: `1` and
+/// `1.0` are the same value, `1` and `'1'` are not.
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> HyperLogLog {
+        HyperLogLog {
+            registers: vec![0; 1 << HLL_BITS],
+        }
+    }
+}
+
+impl Accumulator for HyperLogLog {
+    fn step(&mut self, args: &[Value]) -> Result<()> {
+        let mut hasher = DefaultHasher::new();
+        match &args[0] {
+            Value::Null => return Ok(()),
+            Value::Integer(i) => (0u8, *i).hash(&mut hasher),
+            Value::Real(r) if r.fract() == 0.0 && r.abs() < 9.2e18 => {
+                (0u8, *r as i64).hash(&mut hasher)
+            }
+            Value::Real(r) => (1u8, r.to_bits()).hash(&mut hasher),
+            Value::Text(s) => (2u8, s.as_bytes()).hash(&mut hasher),
+            Value::Blob(b) => (3u8, b.as_slice()).hash(&mut hasher),
+        }
+        let hash = hasher.finish();
+        let register = (hash >> (64 - HLL_BITS)) as usize;
+        // The rank of the first 1 bit in the remaining bits; a marker bit
+        // caps it when they are all zero.
+        let rank = ((hash << HLL_BITS) | (1 << (HLL_BITS - 1))).leading_zeros() as u8 + 1;
+        self.registers[register] = self.registers[register].max(rank);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<Value> {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let mut estimate = alpha * m * m / sum;
+        // Small cardinalities leave registers empty, and linear counting of
+        // those is the better estimate there.
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            estimate = m * (m / zeros as f64).ln();
+        }
+        Ok(Value::Integer(estimate.round() as i64))
+    }
+}
+
+/// Centroids a digest keeps, roughly; more is more accurate and slower.
+const COMPRESSION: f64 = 100.0;
+/// Values buffered before they are merged into the centroids.
+const BUFFER: usize = 2000;
+
+/// The `p`-th fraction (0 to 1) of the non-NULL numeric values, from a
+/// merging t-digest (Dunning and Ertl). Memory stays bounded by the
+/// compression however many rows there are; error is smallest near the
+/// extremes, where centroids are kept small. Up to `BUFFER` values nothing is
+/// merged, so the result is exact and interpolates between neighbours as
+/// SQLite's `percentile()` does.
+struct ApproxPercentile {
+    p: Option<f64>,
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl ApproxPercentile {
+    fn new() -> ApproxPercentile {
+        ApproxPercentile {
+            p: None,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Merges the buffer into the centroids. Neighbouring centroids combine
+    /// while the result spans at most one unit of the scale function
+    /// `k(q) = δ/2π · asin(2q - 1)`, which keeps them small near q = 0 and 1.
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut items = std::mem::take(&mut self.centroids);
+        items.extend(self.buffer.drain(..).map(|v| (v, 1.0)));
+        items.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let total: f64 = items.iter().map(|c| c.1).sum();
+        let k = |q: f64| COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).asin();
+        let q_of = |k: f64| ((2.0 * PI * k / COMPRESSION).sin() + 1.0) / 2.0;
+
+        let mut merged = Vec::new();
+        let mut current = items[0];
+        let mut before = 0.0;
+        let mut limit = q_of(k(0.0) + 1.0);
+        for &item in &items[1..] {
+            if (before + current.1 + item.1) / total <= limit {
+                let weight = current.1 + item.1;
+                current.0 += (item.0 - current.0) * item.1 / weight;
+                current.1 = weight;
+            } else {
+                before += current.1;
+                merged.push(current);
+                limit = q_of(k(before / total) + 1.0);
+                current = item;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+}
+
+impl Accumulator for ApproxPercentile {
+    fn step(&mut self, args: &[Value]) -> Result<()> {
+        let p = match &args[1] {
+            Value::Integer(i) => *i as f64,
+            Value::Real(r) => *r,
+            _ => bail!("approx_percentile: the fraction must be a number from 0 to 1"),
+        };
+        if !(0.0..=1.0).contains(&p) {
+            bail!("approx_percentile: the fraction must be a number from 0 to 1");
+        }
+        if self.p.is_some_and(|q| q != p) {
+            bail!("approx_percentile: the fraction must be the same for all rows");
+        }
+        self.p = Some(p);
+        let value = match &args[0] {
+            Value::Null => return Ok(()),
+            Value::Integer(i) => *i as f64,
+            Value::Real(r) => *r,
+            _ => bail!("approx_percentile: value is not numeric"),
+        };
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(value);
+        if self.buffer.len() >= BUFFER {
+            self.compress();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<Value> {
+        if self.centroids.is_empty() {
+            self.buffer.sort_by(f64::total_cmp);
+            self.centroids = self.buffer.drain(..).map(|v| (v, 1.0)).collect();
+        } else {
+            self.compress();
+        }
+        let Some(p) = self.p.filter(|_| !self.centroids.is_empty()) else {
+            return Ok(Value::Null);
+        };
+        // Positions count values from 0; a centroid of weight w starting at
+        // position s is centred on s + (w - 1) / 2.
+        let total: f64 = self.centroids.iter().map(|c| c.1).sum();
+        let target = p * (total - 1.0);
+        let mut start = 0.0;
+        let mut previous: Option<(f64, f64)> = None;
+        for &(mean, weight) in &self.centroids {
+            let centre = start + (weight - 1.0) / 2.0;
+            if target <= centre {
+                return Ok(Value::Real(
+                    match previous {
+                        None => {
+                            self.min + (mean - self.min) * target / centre.max(f64::MIN_POSITIVE)
+                        }
+                        Some((prev_centre, prev_mean)) => {
+                            let t = (target - prev_centre) / (centre - prev_centre);
+                            prev_mean + (mean - prev_mean) * t
+                        }
+                    }
+                    .clamp(self.min, self.max),
+                ));
+            }
+            previous = Some((centre, mean));
+            start += weight;
+        }
+        let (last_centre, last_mean) = previous.unwrap_or((0.0, self.max));
+        let t = (target - last_centre) / (total - 1.0 - last_centre).max(f64::MIN_POSITIVE);
+        Ok(Value::Real(last_mean + (self.max - last_mean) * t))
+    }
+}
@@ -0,0 +1,589 @@
+use anyhow::Result;
+use std::fmt::Write;
+
+use crate::error::corrupt;
+use crate::header::DbHeader;
+use crate::page::{PageBytes, MAX_DEPTH};
+use crate::pager::Pager;
+use crate::read_schema_rows;
+use crate::record::local_payload;
+use crate::settings::Settings;
+
+/// Space used by one b-tree: a row of sqlite3_analyzer's `space_used` table.
+#[derive(Default)]
+struct SpaceUsed {
+    name: String,
+    tblname: String,
+    is_index: bool,
+    is_without_rowid: bool,
+    nentry: u64,
+    leaf_entries: u64,
+    depth: u64,
+    payload: u64,
+    ovfl_payload: u64,
+    ovfl_cnt: u64,
+    mx_payload: u64,
+    int_pages: u64,
+    leaf_pages: u64,
+    ovfl_pages: u64,
+    int_unused: u64,
+    leaf_unused: u64,
+    ovfl_unused: u64,
+    gap_cnt: u64,
+}
+
+impl SpaceUsed {
+    fn pages(&self) -> u64 {
+        self.int_pages + self.leaf_pages + self.ovfl_pages
+    }
+}
+
+/// Produces sqlite3_analyzer's disk-space report for a database: the same
+/// sections, figures and trailing `space_used` SQL, minus the prose
+/// definitions of each figure.
+pub fn analyze_space(path: &str, settings: &Settings) -> Result<String> {
+    let mut pager = Pager::open(path, settings)?;
+    let header = pager.header()?;
+    let schema = read_schema_rows(&mut pager)?;
+
+    let mut btrees = vec![measure(
+        &mut pager,
+        &header,
+        "sqlite_schema",
+        "sqlite_schema",
+        1,
+    )?];
+    for row in schema.iter().filter(|r| r.rootpage > 0) {
+        btrees.push(measure(
+            &mut pager,
+            &header,
+            &row.name,
+            &row.tbl_name,
+            row.rootpage,
+        )?);
+    }
+
+    let page_size = header.page_size as u64;
+    let file_pgcnt = pager.page_count(&header)?;
+    let file_bytes = file_pgcnt * page_size;
+    let av_pgcnt = header.ptrmap_pages(file_pgcnt);
+    let inuse_pgcnt: u64 = btrees.iter().map(|b| b.pages()).sum();
+    let free_pgcnt = file_pgcnt.saturating_sub(inuse_pgcnt + av_pgcnt);
+    let free_pgcnt2 = header.freelist_pages as u64;
+    let ntable = schema.iter().filter(|r| r.kind == "table").count() + 1;
+    let nindex = schema.iter().filter(|r| r.kind == "index").count();
+    let nautoindex = schema
+        .iter()
+        .filter(|r| r.kind == "index" && r.name.starts_with("sqlite_autoindex"))
+        .count();
+    let user_payload: u64 = btrees
+        .iter()
+        .filter(|b| !b.is_index && b.name != "sqlite_schema")
+        .map(|b| b.payload)
+        .sum();
+    let file_pages = file_pgcnt as f64;
+
+    let mut out = String::new();
+    writeln!(out, "/** Disk-Space Utilization Report For {}", path)?;
+    writeln!(out)?;
+    statline(&mut out, "Page size in bytes", page_size, "")?;
+    statline(
+        &mut out,
+        "Pages in the whole file (measured)",
+        file_pgcnt,
+        "",
+    )?;
+    statline(
+        &mut out,
+        "Pages in the whole file (calculated)",
+        inuse_pgcnt + free_pgcnt2 + av_pgcnt,
+        "",
+    )?;
+    let pct = |n: u64| percent(n as f64, file_pages);
+    statline(
+        &mut out,
+        "Pages that store data",
+        inuse_pgcnt,
+        &pct(inuse_pgcnt),
+    )?;
+    statline(
+        &mut out,
+        "Pages on the freelist (per header)",
+        free_pgcnt2,
+        &pct(free_pgcnt2),
+    )?;
+    statline(
+        &mut out,
+        "Pages on the freelist (calculated)",
+        free_pgcnt,
+        &pct(free_pgcnt),
+    )?;
+    statline(
+        &mut out,
+        "Pages of auto-vacuum overhead",
+        av_pgcnt,
+        &pct(av_pgcnt),
+    )?;
+    statline(&mut out, "Number of tables in the database", ntable, "")?;
+    statline(&mut out, "Number of indices", nindex, "")?;
+    statline(
+        &mut out,
+        "Number of defined indices",
+        nindex - nautoindex,
+        "",
+    )?;
+    statline(&mut out, "Number of implied indices", nautoindex, "")?;
+    statline(&mut out, "Size of the file in bytes", file_bytes, "")?;
+    statline(
+        &mut out,
+        "Bytes of user payload stored",
+        user_payload,
+        &percent(user_payload as f64, file_bytes as f64),
+    )?;
+
+    titleline(&mut out, "Page counts for all tables with their indices")?;
+    let mut by_table: Vec<(&str, u64)> = Vec::new();
+    for b in &btrees {
+        match by_table.iter_mut().find(|(t, _)| *t == b.tblname) {
+            Some((_, pages)) => *pages += b.pages(),
+            None => by_table.push((&b.tblname, b.pages())),
+        }
+    }
+    by_table.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (name, pages) in &by_table {
+        statline(&mut out, &name.to_uppercase(), pages, &pct(*pages))?;
+    }
+
+    titleline(
+        &mut out,
+        "Page counts for all tables and indices separately",
+    )?;
+    let mut separately: Vec<&SpaceUsed> = btrees.iter().collect();
+    separately.sort_by(|a, b| b.pages().cmp(&a.pages()).then(a.name.cmp(&b.name)));
+    for b in separately {
+        statline(&mut out, &b.name.to_uppercase(), b.pages(), &pct(b.pages()))?;
+    }
+
+    let report = Report {
+        page_size,
+        file_pgcnt,
+    };
+    if nindex > 0 {
+        titleline(&mut out, "All tables and indices")?;
+        report.subreport(&mut out, btrees.iter().collect(), false)?;
+    }
+    titleline(&mut out, "All tables")?;
+    report.subreport(
+        &mut out,
+        btrees.iter().filter(|b| !b.is_index).collect(),
+        false,
+    )?;
+    if nindex > 0 {
+        titleline(&mut out, "All indices")?;
+        report.subreport(
+            &mut out,
+            btrees.iter().filter(|b| b.is_index).collect(),
+            false,
+        )?;
+    }
+
+    let mut tables: Vec<&str> = btrees.iter().map(|b| b.tblname.as_str()).collect();
+    tables.sort();
+    tables.dedup();
+    for table in tables {
+        let upper = table.to_uppercase();
+        let mut indices: Vec<&SpaceUsed> = btrees
+            .iter()
+            .filter(|b| b.tblname == table && b.is_index)
+            .collect();
+        indices.sort_by(|a, b| a.name.cmp(&b.name));
+        if indices.is_empty() {
+            titleline(&mut out, &format!("Table {}", upper))?;
+            report.subreport(
+                &mut out,
+                btrees.iter().filter(|b| b.tblname == table).collect(),
+                true,
+            )?;
+            continue;
+        }
+        titleline(&mut out, &format!("Table {} and all its indices", upper))?;
+        report.subreport(
+            &mut out,
+            btrees.iter().filter(|b| b.tblname == table).collect(),
+            false,
+        )?;
+        titleline(&mut out, &format!("Table {} w/o any indices", upper))?;
+        report.subreport(
+            &mut out,
+            btrees.iter().filter(|b| b.name == table).collect(),
+            true,
+        )?;
+        if indices.len() > 1 {
+            titleline(&mut out, &format!("Indices of table {}", upper))?;
+            report.subreport(&mut out, indices.clone(), false)?;
+        }
+        for index in indices {
+            let title = format!("Index {} of table {}", index.name.to_uppercase(), upper);
+            titleline(&mut out, &title)?;
+            report.subreport(&mut out, vec![index], true)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "{}", "*".repeat(79))?;
+    out.push_str(SPACE_USED_PREAMBLE);
+    for b in &btrees {
+        writeln!(
+            out,
+            "INSERT INTO space_used VALUES({},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{});",
+            sql_quote(&b.name),
+            sql_quote(&b.tblname),
+            b.is_index as u8,
+            b.is_without_rowid as u8,
+            b.nentry,
+            b.leaf_entries,
+            b.depth,
+            b.payload,
+            b.ovfl_payload,
+            b.ovfl_cnt,
+            b.mx_payload,
+            b.int_pages,
+            b.leaf_pages,
+            b.ovfl_pages,
+            b.int_unused,
+            b.leaf_unused,
+            b.ovfl_unused,
+            b.gap_cnt,
+            b.pages() * page_size,
+        )?;
+    }
+    writeln!(out, "COMMIT;")?;
+    Ok(out)
+}
+
+const SPACE_USED_PREAMBLE: &str = "\
+The entire text of this report can be sourced into any SQL database
+engine for further analysis.  All of the text above is an SQL comment.
+The data used to generate this report follows:
+*/
+BEGIN;
+CREATE TABLE space_used(
+   name clob,        -- Name of a table or index in the database file
+   tblname clob,     -- Name of associated table
+   is_index boolean, -- TRUE if it is an index, false for a table
+   is_without_rowid boolean, -- TRUE if WITHOUT ROWID table  
+   nentry int,       -- Number of entries in the BTree
+   leaf_entries int, -- Number of leaf entries
+   depth int,        -- Depth of the b-tree
+   payload int,      -- Total amount of data stored in this table or index
+   ovfl_payload int, -- Total amount of data stored on overflow pages
+   ovfl_cnt int,     -- Number of entries that use overflow
+   mx_payload int,   -- Maximum payload size
+   int_pages int,    -- Number of interior pages used
+   leaf_pages int,   -- Number of leaf pages used
+   ovfl_pages int,   -- Number of overflow pages used
+   int_unused int,   -- Number of unused bytes on interior pages
+   leaf_unused int,  -- Number of unused bytes on primary pages
+   ovfl_unused int,  -- Number of unused bytes on overflow pages
+   gap_cnt int,      -- Number of gaps in the page layout
+   compressed_size int  -- Total bytes stored on disk
+);
+";
+
+struct Report {
+    page_size: u64,
+    file_pgcnt: u64,
+}
+
+impl Report {
+    /// One block of statistics summed over `btrees`.
+    fn subreport(&self, out: &mut String, btrees: Vec<&SpaceUsed>, show_frag: bool) -> Result<()> {
+        let sum = |f: fn(&SpaceUsed) -> u64| -> u64 { btrees.iter().map(|b| f(b)).sum() };
+        let nentry = sum(|b| {
+            if b.is_index || b.is_without_rowid {
+                b.nentry
+            } else {
+                b.leaf_entries
+            }
+        });
+        let payload = sum(|b| b.payload);
+        let ovfl_cnt = sum(|b| b.ovfl_cnt);
+        let mx_payload = btrees.iter().map(|b| b.mx_payload).max().unwrap_or(0);
+        let int_pages = sum(|b| b.int_pages);
+        let leaf_pages = sum(|b| b.leaf_pages);
+        let ovfl_pages = sum(|b| b.ovfl_pages);
+        let int_unused = sum(|b| b.int_unused);
+        let leaf_unused = sum(|b| b.leaf_unused);
+        let ovfl_unused = sum(|b| b.ovfl_unused);
+        let gap_cnt = sum(|b| b.gap_cnt);
+
+        let total_pages = int_pages + leaf_pages + ovfl_pages;
+        let storage = total_pages * self.page_size;
+        let total_unused = int_unused + leaf_unused + ovfl_unused;
+        let total_meta = (storage + 4 * ovfl_pages) - payload - total_unused - 4 * ovfl_cnt;
+
+        let total_pct = percent(total_pages as f64, self.file_pgcnt as f64);
+        statline(out, "Percentage of total database", total_pct, "")?;
+        statline(out, "Number of entries", nentry, "")?;
+        statline(out, "Bytes of storage consumed", storage, "")?;
+        let of_storage = |n: u64| percent(n as f64, storage as f64);
+        statline(out, "Bytes of payload", payload, &of_storage(payload))?;
+        statline(
+            out,
+            "Bytes of metadata",
+            total_meta,
+            &of_storage(total_meta),
+        )?;
+        if btrees.len() == 1 {
+            statline(out, "B-tree depth", btrees[0].depth, "")?;
+        }
+        statline(
+            out,
+            "Average payload per entry",
+            divide(payload, nentry),
+            "",
+        )?;
+        let avg_unused = divide(total_unused, nentry);
+        statline(out, "Average unused bytes per entry", avg_unused, "")?;
+        statline(
+            out,
+            "Average metadata per entry",
+            divide(total_meta, nentry),
+            "",
+        )?;
+        let mut tables: Vec<&str> = btrees
+            .iter()
+            .filter(|b| !b.is_index)
+            .map(|b| b.tblname.as_str())
+            .collect();
+        tables.sort();
+        tables.dedup();
+        // Integer division, as sqlite3_analyzer's SQL does it.
+        let fanout = (leaf_pages + int_pages - tables.len() as u64).checked_div(int_pages);
+        if let Some(fanout) = fanout {
+            statline(out, "Average fanout", format!("{:.2}", fanout as f64), "")?;
+        }
+        if show_frag && total_pages > 1 {
+            let fragmentation = percent(gap_cnt as f64, (total_pages - 1) as f64);
+            statline(out, "Non-sequential pages", gap_cnt, &fragmentation)?;
+        }
+        statline(out, "Maximum payload per entry", mx_payload, "")?;
+        let ovfl_pct = percent(ovfl_cnt as f64, nentry as f64);
+        statline(out, "Entries that use overflow", ovfl_cnt, &ovfl_pct)?;
+        if int_pages > 0 {
+            statline(out, "Index pages used", int_pages, "")?;
+        }
+        statline(out, "Primary pages used", leaf_pages, "")?;
+        statline(out, "Overflow pages used", ovfl_pages, "")?;
+        statline(out, "Total pages used", total_pages, "")?;
+        let of_pages = |n: u64, pages: u64| percent(n as f64, (pages * self.page_size) as f64);
+        if int_unused > 0 {
+            let pct = of_pages(int_unused, int_pages);
+            statline(out, "Unused bytes on index pages", int_unused, &pct)?;
+        }
+        let pct = of_pages(leaf_unused, leaf_pages);
+        statline(out, "Unused bytes on primary pages", leaf_unused, &pct)?;
+        let pct = of_pages(ovfl_unused, ovfl_pages);
+        statline(out, "Unused bytes on overflow pages", ovfl_unused, &pct)?;
+        let pct = of_storage(total_unused);
+        statline(out, "Unused bytes on all pages", total_unused, &pct)?;
+        Ok(())
+    }
+}
+
+fn measure(
+    pager: &mut Pager,
+    header: &DbHeader,
+    name: &str,
+    tblname: &str,
+    root: u32,
+) -> Result<SpaceUsed> {
+    let mut btree = SpaceUsed {
+        name: name.to_string(),
+        tblname: tblname.to_string(),
+        is_index: name != tblname,
+        ..Default::default()
+    };
+    let mut visited = Vec::new();
+    walk_btree(pager, header, root, 1, &mut btree, &mut visited)?;
+
+    // A gap is a leaf page that doesn't directly follow the page before it
+    // in file order.
+    visited.sort();
+    for pair in visited.windows(2) {
+        let ((prev, _), (page_no, is_leaf)) = (pair[0], pair[1]);
+        if is_leaf && page_no != prev + 1 {
+            btree.gap_cnt += 1;
+        }
+    }
+    Ok(btree)
+}
+
+/// Adds a page and everything below it to `btree`, recording each page
+/// number (and whether it is a leaf) in `visited`.
+fn walk_btree(
+    pager: &mut Pager,
+    header: &DbHeader,
+    page_no: u32,
+    depth: u64,
+    btree: &mut SpaceUsed,
+    visited: &mut Vec<(u32, bool)>,
+) -> Result<()> {
+    let page = pager.read_page(page_no, header)?;
+    let page = PageBytes::new(page_no, &page[..header.usable_size()]);
+    if depth as usize > MAX_DEPTH {
+        return Err(page.corrupt("b-tree is deeper than any SQLite writes"));
+    }
+    let hdr = page.header_offset();
+    let page_type = page.page_type()?;
+    let is_leaf = match page_type {
+        0x0A | 0x0D => true,
+        0x02 | 0x05 => false,
+        t => return Err(page.corrupt(format!("page type {:#04x} in a b-tree", t))),
+    };
+    if depth == 1 {
+        btree.is_without_rowid = !btree.is_index && matches!(page_type, 0x02 | 0x0A);
+    }
+
+    let cell_count = page.cell_count()?;
+    let cell_ptrs = hdr + if is_leaf { 8 } else { 12 };
+    let content_start = match page.u16(hdr + 5)? {
+        0 => 65536,
+        n => n as usize,
+    };
+    if content_start > page.bytes.len() {
+        return Err(page.corrupt("cell content area starts past the page"));
+    }
+    let mut unused =
+        content_start.saturating_sub(cell_ptrs + 2 * cell_count) + page.u8(hdr + 7)? as usize;
+    let mut freeblock = page.u16(hdr + 1)? as usize;
+    while freeblock != 0 {
+        let next = page.u16(freeblock)? as usize;
+        if next != 0 && next <= freeblock {
+            return Err(page.corrupt("freeblock list is out of order"));
+        }
+        let size = page.u16(freeblock + 2)? as usize;
+        page.slice(freeblock, size)?;
+        unused += size;
+        freeblock = next;
+    }
+
+    visited.push((page_no, is_leaf));
+    btree.depth = btree.depth.max(depth);
+    btree.nentry += cell_count as u64;
+    if is_leaf {
+        btree.leaf_pages += 1;
+        btree.leaf_unused += unused as u64;
+        btree.leaf_entries += cell_count as u64;
+    } else {
+        btree.int_pages += 1;
+        btree.int_unused += unused as u64;
+    }
+
+    let usable = header.usable_size();
+    let mut children = Vec::new();
+    for i in 0..cell_count {
+        let mut offset = page.cell_offset(i)?;
+        if !is_leaf {
+            children.push(page.u32(offset)?);
+            offset += 4;
+        }
+        if page_type == 0x05 {
+            continue;
+        }
+        let (payload, n) = page.varint(offset)?;
+        offset += n;
+        if page_type == 0x0D {
+            offset += page.varint(offset)?.1;
+        }
+        btree.payload += payload;
+        btree.mx_payload = btree.mx_payload.max(payload);
+        let local = local_payload(usable, page_type, payload);
+        page.slice(offset, local as usize)?;
+        if payload > local {
+            let first = page.u32(offset + local as usize)?;
+            walk_overflow(pager, header, first, payload - local, btree, visited)?;
+        }
+    }
+    if !is_leaf {
+        children.push(page.u32(hdr + 8)?);
+    }
+    for child in children {
+        walk_btree(pager, header, child, depth + 1, btree, visited)?;
+    }
+    Ok(())
+}
+
+fn walk_overflow(
+    pager: &mut Pager,
+    header: &DbHeader,
+    first: u32,
+    overflow: u64,
+    btree: &mut SpaceUsed,
+    visited: &mut Vec<(u32, bool)>,
+) -> Result<()> {
+    let per_page = (header.usable_size() - 4) as u64;
+    let pages = overflow.div_ceil(per_page);
+    btree.ovfl_cnt += 1;
+    btree.ovfl_payload += overflow;
+    btree.ovfl_pages += pages;
+    btree.ovfl_unused += pages * per_page - overflow;
+
+    let mut page_no = first;
+    for _ in 0..pages {
+        if page_no == 0 {
+            return Err(corrupt(0, "overflow chain ends early"));
+        }
+        visited.push((page_no, false));
+        let page = pager.read_page(page_no, header)?;
+        page_no = PageBytes::new(page_no, &page).u32(0)?;
+    }
+    Ok(())
+}
+
+fn titleline(out: &mut String, title: &str) -> Result<()> {
+    let stars = "*".repeat(79usize.saturating_sub(title.chars().count() + 5));
+    writeln!(out)?;
+    writeln!(out, "*** {} {}", title, stars)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// `TITLE....... VALUE      EXTRA`, in sqlite3_analyzer's columns.
+fn statline(out: &mut String, title: &str, value: impl ToString, extra: &str) -> Result<()> {
+    let dots = ".".repeat(50usize.saturating_sub(title.chars().count()));
+    let value = value.to_string();
+    if extra.is_empty() {
+        writeln!(out, "{}{} {:<10}", title, dots, value)?;
+    } else {
+        writeln!(out, "{}{} {:<10} {}", title, dots, value, extra)?;
+    }
+    Ok(())
+}
+
+/// A percentage with sqlite3_analyzer's precision rules: more digits near
+/// 0% and 100%.
+fn percent(num: f64, denom: f64) -> String {
+    if denom == 0.0 {
+        return String::new();
+    }
+    let v = num * 100.0 / denom;
+    if v == 100.0 || v < 0.001 || (v > 1.0 && v < 99.0) {
+        format!("{:5.1}% ", v)
+    } else if !(0.1..=99.9).contains(&v) {
+        format!("{:7.3}% ", v)
+    } else {
+        format!("{:6.2}% ", v)
+    }
+}
+
+fn divide(num: u64, denom: u64) -> String {
+    if denom == 0 {
+        return "0.0".to_string();
+    }
+    format!("{:.2}", num as f64 / denom as f64)
+}
+
+fn sql_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
@@ -0,0 +1,56 @@
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+
+use crate::value::Value;
+
+/// The built-in collating sequences, which decide how two TEXT values
+/// compare. Values of other storage classes compare the same under each.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Collation {
+    /// Bytewise, as `memcmp` orders them.
+    #[default]
+    Binary,
+    /// Like BINARY, with ASCII letters folded to lower case. Other letters
+    /// keep their case, as in SQLite.
+    NoCase,
+    /// Like BINARY, ignoring trailing spaces.
+    Rtrim,
+}
+
+impl Collation {
+    pub fn parse(name: &str) -> Result<Collation> {
+        match name.to_ascii_lowercase().as_str() {
+            "binary" => Ok(Collation::Binary),
+            "nocase" => Ok(Collation::NoCase),
+            "rtrim" => Ok(Collation::Rtrim),
+            _ => bail!("no such collation sequence: {}", name),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Collation::Binary => "BINARY",
+            Collation::NoCase => "NOCASE",
+            Collation::Rtrim => "RTRIM",
+        }
+    }
+
+    pub fn compare_text(self, a: &str, b: &str) -> Ordering {
+        match self {
+            Collation::Binary => a.as_bytes().cmp(b.as_bytes()),
+            Collation::NoCase => a
+                .bytes()
+                .map(|c| c.to_ascii_lowercase())
+                .cmp(b.bytes().map(|c| c.to_ascii_lowercase())),
+            Collation::Rtrim => a.trim_end_matches(' ').cmp(b.trim_end_matches(' ')),
+        }
+    }
+
+    /// `Value::compare`, with text compared under this collation.
+    pub fn compare(self, a: &Value, b: &Value) -> Ordering {
+        match (a, b) {
+            (Value::Text(a), Value::Text(b)) => self.compare_text(a, b),
+            _ => a.compare(b),
+        }
+    }
+}
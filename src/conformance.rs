@@ -0,0 +1,129 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::Database;
+use crate::format_row;
+use crate::settings::Settings;
+
+/// Runs every `NAME.sql` case in `dir` and compares its output with
+/// `NAME.expected`. The query runs against `NAME.db`, or against the file
+/// named by a leading `-- db: FILE` comment (relative to `dir`). Expected
+/// output uses the CLI's format: one `|`-separated line per row, or a single
+/// `Error: ...` line. Cases that share a database share its handle, and so
+/// its cached schema and statements.
+pub fn run(dir: &str) -> Result<()> {
+    let dir = Path::new(dir);
+    let mut cases: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "sql"))
+        .collect();
+    cases.sort();
+    if cases.is_empty() {
+        bail!("no .sql cases in {}", dir.display());
+    }
+
+    let mut databases = HashMap::new();
+    let mut failed = 0;
+    for case in &cases {
+        let name = case.file_stem().unwrap_or_default().to_string_lossy();
+        match run_case(dir, case, &mut databases) {
+            Ok(None) => println!("PASS {}", name),
+            Ok(Some(diff)) => {
+                failed += 1;
+                println!("FAIL {}", name);
+                for line in diff {
+                    println!("    {}", line);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}: {}", name, e);
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", cases.len() - failed, failed);
+    if failed > 0 {
+        bail!("{} of {} conformance cases failed", failed, cases.len());
+    }
+    Ok(())
+}
+
+/// Returns `None` when the case passes, or a diff of expected vs actual.
+fn run_case(
+    dir: &Path,
+    case: &Path,
+    databases: &mut HashMap<PathBuf, Database>,
+) -> Result<Option<Vec<String>>> {
+    let sql = fs::read_to_string(case)?;
+    let expected_path = case.with_extension("expected");
+    let expected = match fs::read_to_string(&expected_path) {
+        Ok(text) => text,
+        Err(_) => bail!("missing {}", expected_path.display()),
+    };
+
+    let db = match sql
+        .lines()
+        .next()
+        .and_then(|l| l.trim().strip_prefix("-- db:"))
+    {
+        Some(file) => dir.join(file.trim()),
+        None => case.with_extension("db"),
+    };
+    let database = databases
+        .entry(db.clone())
+        .or_insert_with(|| Database::open(&db.to_string_lossy(), &Settings::default()));
+    let actual = match database.query(&sql) {
+        Ok(result) => result.rows.iter().map(|r| format_row(r)).collect(),
+        Err(e) => vec![format!("Error: {}", e)],
+    };
+
+    let expected: Vec<&str> = expected.lines().collect();
+    if expected == actual {
+        return Ok(None);
+    }
+    Ok(Some(diff_lines(&expected, &actual)))
+}
+
+/// Line diff via longest common subsequence: `-` lines are expected but
+/// missing, `+` lines are unexpected.
+fn diff_lines(expected: &[&str], actual: &[String]) -> Vec<String> {
+    let (n, m) = (expected.len(), actual.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push(format!("+ {}", actual[j]));
+            j += 1;
+        } else {
+            out.push(format!("- {}", expected[i]));
+            i += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    /// The cases in `tests/conformance`, with output captured from sqlite3.
+    #[test]
+    fn corpus() {
+        super::run(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/conformance")).unwrap();
+    }
+}
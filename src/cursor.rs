@@ -0,0 +1,97 @@
+use anyhow::Result;
+
+use crate::header::DbHeader;
+use crate::page::{Page, PageBytes, PageSource, MAX_DEPTH};
+use crate::record::{extract_row_from_table_cell, ColumnSource};
+use crate::settings::Settings;
+use crate::value::Value;
+
+/// Walks a table b-tree in rowid order, yielding one row at a time. Only the
+/// pages on the path from the root to the current leaf are held, so a scan
+/// needs the same memory however large the table is. Pages come from a
+/// `PageSource` rather than the file directly.
+pub struct TableCursor<'a, S: PageSource + ?Sized> {
+    pager: &'a mut S,
+    header: DbHeader,
+    sources: Vec<ColumnSource>,
+    settings: &'a Settings,
+    /// Each page on the path, with the index of the next cell to visit. On
+    /// an interior page, index `cell_count` stands for the right child.
+    path: Vec<(u32, Page, usize)>,
+}
+
+impl<'a, S: PageSource + ?Sized> TableCursor<'a, S> {
+    pub fn new(
+        pager: &'a mut S,
+        root: u32,
+        header: &DbHeader,
+        sources: &[ColumnSource],
+        settings: &'a Settings,
+    ) -> Result<TableCursor<'a, S>> {
+        let page = pager.read_page(root, header)?;
+        Ok(TableCursor {
+            pager,
+            header: header.clone(),
+            sources: sources.to_vec(),
+            settings,
+            path: vec![(root, page, 0)],
+        })
+    }
+
+    /// The page source the cursor reads, for other reads while it is open.
+    pub fn pager(&mut self) -> &mut S {
+        self.pager
+    }
+
+    fn step(&mut self) -> Result<Option<Vec<Value>>> {
+        loop {
+            let depth = self.path.len();
+            let Some((page_no, page, next)) = self.path.last_mut() else {
+                return Ok(None);
+            };
+            let page = PageBytes::new(*page_no, &page[..self.header.usable_size()]);
+            let page_type = page.page_type()?;
+            let cell_count = page.cell_count()?;
+
+            if page_type == 0x0D && *next < cell_count {
+                let cell_offset = page.cell_offset(*next)?;
+                *next += 1;
+                let row = extract_row_from_table_cell(
+                    page,
+                    cell_offset,
+                    &self.sources,
+                    self.header.text_encoding,
+                    self.settings,
+                )?;
+                return Ok(Some(row));
+            } else if page_type == 0x05 && *next <= cell_count {
+                let child = page.child(*next)?;
+                *next += 1;
+                if depth > MAX_DEPTH {
+                    return Err(page.corrupt("b-tree is deeper than any SQLite writes"));
+                }
+                let child_page = self.pager.read_page(child, &self.header)?;
+                self.path.push((child, child_page, 0));
+            } else if page_type != 0x0D && page_type != 0x05 {
+                return Err(page.corrupt(format!("page type {:#04x} in a table b-tree", page_type)));
+            } else {
+                self.path.pop();
+            }
+        }
+    }
+}
+
+impl<S: PageSource + ?Sized> Iterator for TableCursor<'_, S> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Result<Vec<Value>>> {
+        match self.step() {
+            Ok(row) => row.map(Ok),
+            Err(e) => {
+                // A corrupt page ends the scan rather than repeating the error.
+                self.path.clear();
+                Some(Err(e))
+            }
+        }
+    }
+}
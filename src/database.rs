@@ -0,0 +1,94 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::pager::{Pager, Version};
+use crate::settings::Settings;
+use crate::statement::Compiled;
+use crate::{prepare, QueryResult, SchemaRow};
+
+/// A database opened for any number of queries. Each query reads a snapshot
+/// of its own, as a separate run of the CLI would, so nothing is locked
+/// between queries; but the parsed schema and compiled statements are kept
+/// from one to the next. They are dropped whenever the database's `Version`
+/// changes, so another process changing the schema or the data is seen by
+/// the next query.
+pub struct Database {
+    path: String,
+    settings: Settings,
+    /// The version the cached schema and statements were read from.
+    version: Option<Version>,
+    schema: Option<Vec<SchemaRow>>,
+    statements: HashMap<String, Compiled>,
+}
+
+impl Database {
+    pub fn open(path: &str, settings: &Settings) -> Database {
+        Database {
+            path: path.to_string(),
+            settings: settings.clone(),
+            version: None,
+            schema: None,
+            statements: HashMap::new(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+    /// Sets how long each later query retries while a writer holds the
+    /// database locked, as `--busy-timeout` does for the first.
+    pub fn busy_timeout(&mut self, timeout: Duration) {
+        self.settings.busy_timeout = timeout;
+    }
+
+    /// Opens a snapshot for the next query, primed with the schema kept from
+    /// earlier ones if the database hasn't changed since.
+    pub fn snapshot(&mut self) -> Result<Pager> {
+        let mut pager = Pager::open(&self.path, &self.settings)?;
+        let version = pager.version()?;
+        if self.version != Some(version) {
+            self.schema = None;
+            self.statements.clear();
+            self.version = Some(version);
+        }
+        if let Some(schema) = &self.schema {
+            pager.cache_schema(schema.clone());
+        }
+        Ok(pager)
+    }
+
+    /// Keeps the schema a snapshot read, for the queries after it.
+    pub fn remember(&mut self, pager: &Pager) {
+        if self.schema.is_none() {
+            self.schema = pager.schema().map(<[SchemaRow]>::to_vec);
+        }
+    }
+
+    pub fn query(&mut self, sql: &str) -> Result<QueryResult> {
+        let mut pager = self.snapshot()?;
+        let compiled = match self.statements.get(sql) {
+            Some(compiled) => compiled.clone(),
+            None => {
+                let compiled = prepare(sql, None, None)?.compile(&mut pager, &self.settings)?;
+                self.statements.insert(sql.to_string(), compiled.clone());
+                compiled
+            }
+        };
+        self.remember(&pager);
+        let rows = compiled.run(&mut pager, &self.settings)?;
+        Ok(QueryResult {
+            columns: rows.columns().to_vec(),
+            rows: rows.collect::<Result<_>>()?,
+        })
+    }
+}
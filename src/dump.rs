@@ -0,0 +1,145 @@
+use anyhow::Result;
+use std::io::Write;
+
+use crate::functions::like_match;
+use crate::pager::Pager;
+use crate::record::ColumnSource;
+use crate::redact::{Mask, Redactions};
+use crate::settings::Settings;
+use crate::sql::{is_keyword, parse_create_table, ColumnConstraint};
+use crate::support::{check_definition, check_table};
+use crate::value::Value;
+use crate::{read_schema_rows, scan_table_btree_all_columns, SchemaRow};
+
+/// Writes the database as SQL text that recreates it, in the layout of
+/// sqlite3's `.dump`: tables and their rows, then indexes, views and
+/// triggers, all in one transaction. Given LIKE `patterns`, only the
+/// objects whose name or table matches one are written, so a table comes
+/// with its indexes and triggers.
+///
+/// Everything is read through one pager, so the dump is a single snapshot:
+/// the SHARED lock is held from the first page to the last, and in WAL mode
+/// the set of committed frames is fixed when the pager opens. Rows of related
+/// tables therefore agree with each other even while a writer is active.
+pub fn dump(
+    path: &str,
+    settings: &Settings,
+    patterns: &[String],
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut pager = Pager::open(path, settings)?;
+    let header = pager.header()?;
+    let mut rows = read_schema_rows(&mut pager)?;
+    if !patterns.is_empty() {
+        rows.retain(|r| {
+            patterns
+                .iter()
+                .any(|p| like_match(p, &r.name) || like_match(p, &r.tbl_name))
+        });
+    }
+
+    writeln!(out, "PRAGMA foreign_keys=OFF;")?;
+    writeln!(out, "BEGIN TRANSACTION;")?;
+    for row in rows.iter().filter(|r| r.kind == "table") {
+        if row.name == "sqlite_sequence" {
+            writeln!(out, "DELETE FROM sqlite_sequence;")?;
+        } else if row.name.starts_with("sqlite_") {
+            continue;
+        } else {
+            writeln!(out, "{};", row.sql)?;
+        }
+        let (columns, sources, masks) = table_columns(row, &settings.redactions)?;
+        let values =
+            scan_table_btree_all_columns(&mut pager, row.rootpage, &header, &sources, settings)?;
+        for mut values in values {
+            Redactions::apply(&masks, &mut values);
+            let literals: Vec<String> = values.iter().map(sql_literal).collect();
+            writeln!(
+                out,
+                "INSERT INTO {}{} VALUES({});",
+                quote_name(&row.name),
+                columns,
+                literals.join(",")
+            )?;
+        }
+    }
+    // Indexes come after the rows, as in sqlite3, so that loading the dump
+    // doesn't update them row by row. Automatic indexes have no SQL.
+    for row in &rows {
+        if row.kind != "table" && !row.sql.is_empty() && !row.name.starts_with("sqlite_") {
+            writeln!(out, "{};", row.sql)?;
+        }
+    }
+    writeln!(out, "COMMIT;")?;
+    Ok(())
+}
+
+/// The stored columns of a table, as the column list of its INSERT
+/// statements (empty unless generated columns must be skipped), the
+/// sources of their values and their redaction masks. An INTEGER PRIMARY KEY
+/// is read from the rowid.
+pub fn table_columns(
+    row: &SchemaRow,
+    redactions: &Redactions,
+) -> Result<(String, Vec<ColumnSource>, Vec<Option<Mask>>)> {
+    check_table(&row.kind, &row.name, &row.sql)?;
+    let table = parse_create_table(&row.sql)?;
+    check_definition(&table)?;
+
+    let mut names = Vec::new();
+    let mut sources = Vec::new();
+    let mut masks = Vec::new();
+    let mut skipped = false;
+    for (i, column) in table.columns.iter().enumerate() {
+        let generated = column
+            .constraints
+            .iter()
+            .any(|c| matches!(c, ColumnConstraint::Generated { .. }));
+        if generated {
+            skipped = true;
+            continue;
+        }
+        names.push(quote_name(&column.name));
+        masks.push(redactions.mask(&table.name, &column.name));
+        sources.push(ColumnSource::of(&table, i));
+    }
+    let columns = if skipped {
+        format!("({})", names.join(","))
+    } else {
+        String::new()
+    };
+    Ok((columns, sources, masks))
+}
+
+/// A name as it must be written in SQL: bare if it is a plain identifier
+/// and not a keyword, double-quoted otherwise.
+pub fn quote_name(name: &str) -> String {
+    let plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !is_keyword(name);
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+/// A value as a SQL literal that reads back as the same value and type.
+pub fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(i) => i.to_string(),
+        // Exponent form keeps integral reals from reading back as integers;
+        // SQLite has no literal for infinity, but 1e999 overflows to it.
+        Value::Real(r) if r.is_infinite() => if *r > 0.0 { "1e999" } else { "-1e999" }.to_string(),
+        Value::Real(r) => format!("{:e}", r),
+        Value::Text(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Blob(b) => {
+            let hex: String = b.iter().map(|byte| format!("{:02X}", byte)).collect();
+            format!("X'{}'", hex)
+        }
+    }
+}
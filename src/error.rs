@@ -0,0 +1,82 @@
+use thiserror::Error;
+
+/// Failures a caller may want to tell apart from the rest, carried inside
+/// `anyhow::Error` and recovered with `downcast_ref`. Anything else fails
+/// with a plain message.
+#[derive(Debug, Error)]
+pub enum RqliteError {
+    /// The file is too short for a database header, or lacks its magic.
+    #[error("file is not a SQLite database")]
+    NotADatabase,
+    #[error("no such table: {name}")]
+    TableNotFound { name: String },
+    #[error("no such column: {name}")]
+    ColumnNotFound { name: String, table: String },
+    /// SQL that doesn't parse. `position` is the char offset in `sql`
+    /// where the problem starts, its length if the SQL ends too soon.
+    #[error("{detail}")]
+    SyntaxError {
+        position: usize,
+        detail: String,
+        sql: String,
+    },
+    /// The file contradicts its own format: an offset outside its page, a
+    /// record that runs past its cell, a page number past the end of the
+    /// file. `page` is the page at fault, or 0 when there is none.
+    #[error("corrupt database: {}", corrupt_detail(*.page, .detail))]
+    Corrupt { page: u32, detail: String },
+    /// Another connection held a lock the reader needs for longer than
+    /// `--busy-timeout` allows.
+    #[error("database is locked")]
+    Busy,
+    /// SQL or a database feature the reader can't handle: `what` names it.
+    #[error("unsupported: {what}")]
+    Unsupported { what: String },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl RqliteError {
+    /// The CLI's exit status for this failure: SQLite's primary result
+    /// code for it.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            RqliteError::NotADatabase => 26,
+            RqliteError::Busy => 5,
+            RqliteError::Corrupt { .. } => 11,
+            RqliteError::Io(_) => 10,
+            _ => 1,
+        }
+    }
+}
+
+fn corrupt_detail(page: u32, detail: &str) -> String {
+    match page {
+        0 => detail.to_string(),
+        page => format!("page {}: {}", page, detail),
+    }
+}
+
+/// A `RqliteError::Corrupt` for `page`, ready to return.
+pub fn corrupt(page: u32, detail: impl Into<String>) -> anyhow::Error {
+    RqliteError::Corrupt {
+        page,
+        detail: detail.into(),
+    }
+    .into()
+}
+
+/// A `RqliteError::SyntaxError` at `position` in `sql`, ready to return.
+pub fn syntax_error(sql: &str, position: usize, detail: impl Into<String>) -> anyhow::Error {
+    RqliteError::SyntaxError {
+        position,
+        detail: detail.into(),
+        sql: sql.to_string(),
+    }
+    .into()
+}
+
+/// A `RqliteError::Unsupported` naming `what`, ready to return.
+pub fn unsupported(what: impl Into<String>) -> anyhow::Error {
+    RqliteError::Unsupported { what: what.into() }.into()
+}
@@ -0,0 +1,183 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+
+use crate::affinity::Affinity;
+use crate::collation::Collation;
+use crate::functions::{glob_match, like_match, regexp_match};
+use crate::sql::{BinaryOp, Expr, UnaryOp};
+use crate::value::Value;
+
+pub fn is_true(v: &Value) -> bool {
+    match v {
+        Value::Null => false,
+        Value::Integer(i) => *i != 0,
+        Value::Real(r) => *r != 0.0,
+        other => other.to_f64() != 0.0,
+    }
+}
+
+pub fn unary(op: UnaryOp, v: Value) -> Value {
+    match (op, v) {
+        (_, Value::Null) => Value::Null,
+        (UnaryOp::Plus, v) => v,
+        (UnaryOp::Neg, Value::Integer(i)) => match i.checked_neg() {
+            Some(n) => Value::Integer(n),
+            None => Value::Real(-(i as f64)),
+        },
+        (UnaryOp::Neg, v) => negate_numeric(&v),
+        (UnaryOp::Not, v) => Value::Integer(!is_true(&v) as i64),
+    }
+}
+
+fn negate_numeric(v: &Value) -> Value {
+    match to_numeric(v) {
+        Value::Integer(i) => Value::Integer(-i),
+        Value::Real(r) => Value::Real(-r),
+        other => other,
+    }
+}
+
+fn to_numeric(v: &Value) -> Value {
+    match v {
+        Value::Integer(_) | Value::Real(_) | Value::Null => v.clone(),
+        other => {
+            let r = other.to_f64();
+            let i = other.to_i64();
+            if (i as f64) == r {
+                Value::Integer(i)
+            } else {
+                Value::Real(r)
+            }
+        }
+    }
+}
+
+/// The collation a comparison of `left` with `right` uses, by SQLite's
+/// rules: a COLLATE on an operand, the left one first; otherwise the
+/// collation of an operand that is a column, the left one first; otherwise
+/// BINARY.
+pub fn comparison_collation(
+    left: &Expr,
+    right: &Expr,
+    column: &dyn Fn(&str) -> Collation,
+) -> Collation {
+    let explicit = |e: &Expr| match e {
+        Expr::Collate { collation, .. } => Some(*collation),
+        _ => None,
+    };
+    let declared = |e: &Expr| match e {
+        Expr::Column(name) | Expr::DoubleQuoted(name) => Some(column(name)),
+        _ => None,
+    };
+    explicit(left)
+        .or_else(|| explicit(right))
+        .or_else(|| declared(left))
+        .or_else(|| declared(right))
+        .unwrap_or_default()
+}
+
+/// The affinity a comparison of `left` with `right` applies, by SQLite's
+/// rules (see `Affinity::for_comparison`). A column has its declared
+/// affinity, through any COLLATE on it; other expressions have none.
+pub fn comparison_affinity(
+    left: &Expr,
+    right: &Expr,
+    column: &dyn Fn(&str) -> Affinity,
+) -> Affinity {
+    fn of(e: &Expr, column: &dyn Fn(&str) -> Affinity) -> Option<Affinity> {
+        match e {
+            Expr::Column(name) | Expr::DoubleQuoted(name) => Some(column(name)),
+            Expr::Collate { expr, .. } => of(expr, column),
+            _ => None,
+        }
+    }
+    Affinity::for_comparison(of(left, column), of(right, column))
+}
+
+/// Compares two values after applying `affinity` to each, so that a text
+/// operand compares as a number only against a numeric column, and text
+/// under `collation`.
+pub fn compare_values(l: &Value, r: &Value, affinity: Affinity, collation: Collation) -> Ordering {
+    let (l2, r2) = (affinity.apply(l), affinity.apply(r));
+    collation.compare(l2.as_ref().unwrap_or(l), r2.as_ref().unwrap_or(r))
+}
+
+pub fn binary(
+    op: BinaryOp,
+    l: &Value,
+    r: &Value,
+    affinity: Affinity,
+    collation: Collation,
+) -> Result<Value> {
+    if l.is_null() || r.is_null() {
+        return Ok(Value::Null);
+    }
+    let cmp = || compare_values(l, r, affinity, collation);
+    let result = match op {
+        BinaryOp::Eq => Value::Integer((cmp() == Ordering::Equal) as i64),
+        BinaryOp::NotEq => Value::Integer((cmp() != Ordering::Equal) as i64),
+        BinaryOp::Lt => Value::Integer((cmp() == Ordering::Less) as i64),
+        BinaryOp::LtEq => Value::Integer((cmp() != Ordering::Greater) as i64),
+        BinaryOp::Gt => Value::Integer((cmp() == Ordering::Greater) as i64),
+        BinaryOp::GtEq => Value::Integer((cmp() != Ordering::Less) as i64),
+        BinaryOp::Concat => Value::Text(format!("{}{}", l, r)),
+        BinaryOp::Like => Value::Integer(like_match(&r.to_string(), &l.to_string()) as i64),
+        BinaryOp::Glob => Value::Integer(glob_match(&r.to_string(), &l.to_string()) as i64),
+        BinaryOp::Regexp => Value::Integer(regexp_match(&r.to_string(), &l.to_string())? as i64),
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+            arithmetic(op, &to_numeric(l), &to_numeric(r))
+        }
+        BinaryOp::And | BinaryOp::Or => unreachable!("logical operators are short-circuited"),
+    };
+    Ok(result)
+}
+
+fn arithmetic(op: BinaryOp, l: &Value, r: &Value) -> Value {
+    if let (Value::Integer(a), Value::Integer(b)) = (l, r) {
+        let (a, b) = (*a, *b);
+        let checked = match op {
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Sub => a.checked_sub(b),
+            BinaryOp::Mul => a.checked_mul(b),
+            BinaryOp::Div => {
+                if b == 0 {
+                    return Value::Null;
+                }
+                a.checked_div(b)
+            }
+            BinaryOp::Rem => {
+                if b == 0 {
+                    return Value::Null;
+                }
+                Some(a.checked_rem(b).unwrap_or(0))
+            }
+            _ => None,
+        };
+        if let Some(v) = checked {
+            return Value::Integer(v);
+        }
+    }
+
+    let (a, b) = (l.to_f64(), r.to_f64());
+    match op {
+        BinaryOp::Add => Value::Real(a + b),
+        BinaryOp::Sub => Value::Real(a - b),
+        BinaryOp::Mul => Value::Real(a * b),
+        BinaryOp::Div => {
+            if b == 0.0 {
+                Value::Null
+            } else {
+                Value::Real(a / b)
+            }
+        }
+        BinaryOp::Rem => {
+            let (ai, bi) = (a as i64, b as i64);
+            if bi == 0 {
+                Value::Null
+            } else {
+                Value::Real((ai % bi) as f64)
+            }
+        }
+        _ => Value::Null,
+    }
+}
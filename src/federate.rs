@@ -0,0 +1,134 @@
+use anyhow::{bail, Result};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::header::DbHeader;
+use crate::pager::Pager;
+use crate::planner::{self, Input, Output};
+use crate::settings::Settings;
+use crate::sql::Select;
+use crate::value::Value;
+use crate::vm::Vm;
+use crate::{find_table_schema, QueryResult};
+
+/// The rows of one file's table that pass the WHERE clause, holding only
+/// the columns the query reads (see `Output::Matched`).
+struct MatchedRows {
+    table_sql: String,
+    rows: Vec<Vec<Value>>,
+}
+
+/// Runs a SELECT against every SQLite file in `dir` as if their copies of the
+/// table were one table (UNION ALL). Files are scanned in parallel; the
+/// schemas must match exactly.
+pub fn federate(dir: &str, select: &Select, settings: &Settings) -> Result<QueryResult> {
+    let files = database_files(Path::new(dir))?;
+    if files.is_empty() {
+        bail!("no database files in {}", dir);
+    }
+    let mut pager = Pager::open(&files[0].to_string_lossy(), settings)?;
+    let (_, table) = find_table_schema(&mut pager, &select.table)?;
+    let mut select = select.clone();
+    select.resolve_double_quoted(&|name| table.has_column(name));
+    let select = &select;
+
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(files.len());
+    let chunk_size = files.len().div_ceil(workers);
+
+    let results: Vec<Result<Vec<MatchedRows>>> = thread::scope(|scope| {
+        let handles: Vec<_> = files
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || scan_files(chunk, select, settings)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| match h.join() {
+                Ok(r) => r,
+                Err(_) => bail!("federated scan panicked"),
+            })
+            .collect()
+    });
+
+    let mut shards = Vec::with_capacity(files.len());
+    for r in results {
+        shards.extend(r?);
+    }
+
+    let first = &shards[0];
+    let mut matched = Vec::new();
+    for (shard, path) in shards.iter().zip(&files) {
+        if shard.table_sql != first.table_sql {
+            bail!(
+                "schema of {} in {} differs from {}",
+                select.table,
+                path.display(),
+                files[0].display()
+            );
+        }
+        matched.extend(shard.rows.iter().cloned());
+    }
+    // The same query projects the combined rows, stepping through them as
+    // it would through one table's.
+    let program = planner::compile(
+        &mut pager,
+        select,
+        Input::Matched,
+        Output::Results,
+        settings,
+    )?;
+    let mut rows = Vec::new();
+    for row in Vm::new(&mut pager, program, settings)?.with_rows(matched) {
+        rows.push(row?);
+        settings.check_row_count(rows.len())?;
+    }
+    Ok(QueryResult {
+        columns: select.column_names(&table),
+        rows,
+    })
+}
+
+fn scan_files(paths: &[PathBuf], select: &Select, settings: &Settings) -> Result<Vec<MatchedRows>> {
+    let mut out = Vec::with_capacity(paths.len());
+    for path in paths {
+        let scanned = Pager::open(&path.to_string_lossy(), settings)
+            .and_then(|mut pager| match_rows(&mut pager, select, settings));
+        match scanned {
+            Ok(matched) => out.push(matched),
+            Err(e) => bail!("{}: {}", path.display(), e),
+        }
+    }
+    Ok(out)
+}
+
+fn match_rows(pager: &mut Pager, select: &Select, settings: &Settings) -> Result<MatchedRows> {
+    let (table_schema, _) = find_table_schema(pager, &select.table)?;
+    let program = planner::compile(pager, select, Input::Table, Output::Matched, settings)?;
+    let rows = Vm::new(pager, program, settings)?.collect::<Result<_>>()?;
+    Ok(MatchedRows {
+        table_sql: table_schema.sql,
+        rows,
+    })
+}
+
+/// Regular files in `dir` with a valid SQLite header, sorted by name.
+fn database_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let is_db = File::open(&path)
+            .ok()
+            .is_some_and(|mut f| DbHeader::read(&mut f).is_ok());
+        if is_db {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
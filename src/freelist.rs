@@ -0,0 +1,68 @@
+use anyhow::Result;
+
+use crate::error::corrupt;
+use crate::header::DbHeader;
+use crate::page::PageBytes;
+use crate::pager::Pager;
+
+/// The unused pages of a database. Trunk pages hold the list itself; leaf
+/// pages are free for reuse.
+pub struct Freelist {
+    pub trunks: Vec<u32>,
+    pub leaves: Vec<u32>,
+}
+
+impl Freelist {
+    pub fn page_count(&self) -> usize {
+        self.trunks.len() + self.leaves.len()
+    }
+}
+
+/// Walks the trunk chain from the header and checks the result against the
+/// header's freelist page count.
+pub fn read_freelist(pager: &mut Pager, header: &DbHeader) -> Result<Freelist> {
+    let expected = header.freelist_pages as usize;
+    let max_leaves = header.usable_size() / 4 - 2;
+    let mut freelist = Freelist {
+        trunks: Vec::new(),
+        leaves: Vec::new(),
+    };
+
+    let mut trunk = header.first_freelist_trunk;
+    while trunk != 0 {
+        // Each trunk is itself a free page, so a longer chain means a cycle.
+        if freelist.trunks.len() >= expected {
+            return Err(corrupt(
+                trunk,
+                "freelist trunk chain longer than its page count",
+            ));
+        }
+        let page = pager.read_page(trunk, header)?;
+        let page = PageBytes::new(trunk, &page);
+        let next = page.u32(0)?;
+        let count = page.u32(4)? as usize;
+        if count > max_leaves {
+            return Err(corrupt(
+                trunk,
+                format!("freelist trunk lists {} leaf pages", count),
+            ));
+        }
+        for i in 0..count {
+            freelist.leaves.push(page.u32(8 + i * 4)?);
+        }
+        freelist.trunks.push(trunk);
+        trunk = next;
+    }
+
+    if freelist.page_count() != expected {
+        return Err(corrupt(
+            0,
+            format!(
+                "freelist: header counts {} pages, found {}",
+                expected,
+                freelist.page_count()
+            ),
+        ));
+    }
+    Ok(freelist)
+}
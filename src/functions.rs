@@ -0,0 +1,581 @@
+use anyhow::{bail, Result};
+use regex::{Regex, RegexBuilder};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::help;
+use crate::json;
+use crate::record::TextEncoding;
+use crate::value::{format_real, Value};
+
+pub struct FunctionDoc {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    pub usage: &'static str,
+    pub summary: &'static str,
+}
+
+/// Every function, scalar or aggregate. Arity checks and `.help` both read
+/// this table.
+pub const FUNCTIONS: &[FunctionDoc] = &[
+    FunctionDoc {
+        name: "abs",
+        min_args: 1,
+        max_args: 1,
+        usage: "abs(X)",
+        summary: "Absolute value of X; NULL for NULL",
+    },
+    FunctionDoc {
+        name: "approx_count_distinct",
+        min_args: 1,
+        max_args: 1,
+        usage: "approx_count_distinct(X)",
+        summary:
+            "Aggregate: about how many distinct non-NULL values X takes (HyperLogLog, ~0.8% error)",
+    },
+    FunctionDoc {
+        name: "approx_percentile",
+        min_args: 2,
+        max_args: 2,
+        usage: "approx_percentile(X, P)",
+        summary:
+            "Aggregate: about the P-th fraction (0 to 1) of the numeric values of X (t-digest)",
+    },
+    FunctionDoc {
+        name: "glob",
+        min_args: 2,
+        max_args: 2,
+        usage: "glob(PATTERN, X)",
+        summary: "1 if X matches the case-sensitive GLOB PATTERN",
+    },
+    FunctionDoc {
+        name: "help",
+        min_args: 0,
+        max_args: 1,
+        usage: "help([TOPIC])",
+        summary: "Usage text for a command or function, as printed by .help",
+    },
+    FunctionDoc {
+        name: "hex",
+        min_args: 1,
+        max_args: 1,
+        usage: "hex(X)",
+        summary: "Upper-case hexadecimal rendering of X's bytes",
+    },
+    FunctionDoc {
+        name: "json_array_length",
+        min_args: 1,
+        max_args: 2,
+        usage: "json_array_length(JSON[, PATH])",
+        summary: "Number of elements in the array at PATH",
+    },
+    FunctionDoc {
+        name: "json_extract",
+        min_args: 2,
+        max_args: usize::MAX,
+        usage: "json_extract(JSON, PATH, ...)",
+        summary: "Value at PATH; with several paths, a JSON array of values",
+    },
+    FunctionDoc {
+        name: "json_type",
+        min_args: 1,
+        max_args: 2,
+        usage: "json_type(JSON[, PATH])",
+        summary: "Type name of the value at PATH",
+    },
+    FunctionDoc {
+        name: "like",
+        min_args: 2,
+        max_args: 2,
+        usage: "like(PATTERN, X)",
+        summary: "1 if X matches the case-insensitive LIKE PATTERN",
+    },
+    FunctionDoc {
+        name: "lower",
+        min_args: 1,
+        max_args: 1,
+        usage: "lower(X)",
+        summary: "X as text with ASCII letters in lower case",
+    },
+    FunctionDoc {
+        name: "quote",
+        min_args: 1,
+        max_args: 1,
+        usage: "quote(X)",
+        summary: "X rendered as an SQL literal",
+    },
+    FunctionDoc {
+        name: "random",
+        min_args: 0,
+        max_args: 0,
+        usage: "random()",
+        summary: "A pseudo-random 64-bit integer",
+    },
+    FunctionDoc {
+        name: "randomblob",
+        min_args: 1,
+        max_args: 1,
+        usage: "randomblob(N)",
+        summary: "A blob of N pseudo-random bytes",
+    },
+    FunctionDoc {
+        name: "regexp",
+        min_args: 2,
+        max_args: 2,
+        usage: "regexp(PATTERN, X)",
+        summary: "1 if X matches the regular expression PATTERN",
+    },
+    FunctionDoc {
+        name: "round",
+        min_args: 1,
+        max_args: 2,
+        usage: "round(X[, DIGITS])",
+        summary: "X rounded to DIGITS decimal places (default 0)",
+    },
+    FunctionDoc {
+        name: "upper",
+        min_args: 1,
+        max_args: 1,
+        usage: "upper(X)",
+        summary: "X as text with ASCII letters in upper case",
+    },
+];
+
+/// Calls a scalar function. `encoding` is the database's text encoding,
+/// the bytes `hex()` shows of a text.
+pub fn call_scalar(name: &str, args: &[Value], encoding: TextEncoding) -> Result<Value> {
+    match name {
+        "hex" => {
+            check_arity(name, args)?;
+            Ok(hex(&args[0], encoding))
+        }
+        "quote" => {
+            check_arity(name, args)?;
+            Ok(quote(&args[0]))
+        }
+        "randomblob" => {
+            check_arity(name, args)?;
+            Ok(randomblob(&args[0]))
+        }
+        "random" => {
+            check_arity(name, args)?;
+            Ok(Value::Integer(next_random() as i64))
+        }
+        "abs" => {
+            check_arity(name, args)?;
+            abs(&args[0])
+        }
+        "round" => {
+            check_arity(name, args)?;
+            Ok(round(&args[0], args.get(1)))
+        }
+        "lower" => {
+            check_arity(name, args)?;
+            Ok(fold_case(&args[0], str::to_ascii_lowercase))
+        }
+        "upper" => {
+            check_arity(name, args)?;
+            Ok(fold_case(&args[0], str::to_ascii_uppercase))
+        }
+        "like" => {
+            check_arity(name, args)?;
+            Ok(pattern_result(&args[0], &args[1], like_match))
+        }
+        "glob" => {
+            check_arity(name, args)?;
+            Ok(pattern_result(&args[0], &args[1], glob_match))
+        }
+        "regexp" => {
+            check_arity(name, args)?;
+            if args[0].is_null() || args[1].is_null() {
+                return Ok(Value::Null);
+            }
+            let matched = regexp_match(&args[0].to_string(), &args[1].to_string())?;
+            Ok(Value::Integer(matched as i64))
+        }
+        "json_extract" => {
+            check_arity(name, args)?;
+            json::json_extract(args)
+        }
+        "json_array_length" => {
+            check_arity(name, args)?;
+            json::json_array_length(args)
+        }
+        "json_type" => {
+            check_arity(name, args)?;
+            json::json_type(args)
+        }
+        "help" => {
+            check_arity(name, args)?;
+            let topic = args.first().map(|v| v.to_string()).unwrap_or_default();
+            Ok(Value::Text(help::help_text(&topic)?))
+        }
+        _ => bail!("no such function: {}", name),
+    }
+}
+
+/// The functions in [`FUNCTIONS`] that aggregate over rows.
+pub const AGGREGATES: &[&str] = &["approx_count_distinct", "approx_percentile"];
+
+pub fn is_aggregate(name: &str) -> bool {
+    AGGREGATES.contains(&name)
+}
+
+/// Checks the argument count against the function's entry in [`FUNCTIONS`].
+pub fn check_arity(name: &str, args: &[Value]) -> Result<()> {
+    let Some(f) = FUNCTIONS.iter().find(|f| f.name == name) else {
+        bail!("no such function: {}", name);
+    };
+    if args.len() < f.min_args || args.len() > f.max_args {
+        bail!("wrong number of arguments to function {}()", name);
+    }
+    Ok(())
+}
+
+/// `lower` and `upper`: like SQLite built without ICU, only ASCII letters
+/// change case. Any other value is converted to text first.
+fn fold_case(v: &Value, fold: fn(&str) -> String) -> Value {
+    match v {
+        Value::Null => Value::Null,
+        v => Value::Text(fold(&v.to_string())),
+    }
+}
+
+/// SQLite hexes a text as it is stored, so in a UTF-16 database a text has
+/// two bytes per character; a number is rendered as UTF-8 text either way.
+fn hex(v: &Value, encoding: TextEncoding) -> Value {
+    let bytes = match v {
+        Value::Text(s) => encoding.encode(s),
+        other => other.to_bytes(),
+    };
+    let mut out = String::new();
+    for b in bytes {
+        out.push_str(&format!("{:02X}", b));
+    }
+    Value::Text(out)
+}
+
+/// Renders a value as an SQL literal, as SQLite's `quote()` does.
+pub fn quote(v: &Value) -> Value {
+    let text = match v {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(r) => {
+            let s = format_real(*r);
+            if s.parse::<f64>().ok() == Some(*r) {
+                s
+            } else {
+                format!("{:e}", r)
+            }
+        }
+        Value::Text(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Blob(b) => {
+            let mut out = String::from("X'");
+            for byte in b {
+                out.push_str(&format!("{:02X}", byte));
+            }
+            out.push('\'');
+            out
+        }
+    };
+    Value::Text(text)
+}
+
+fn randomblob(n: &Value) -> Value {
+    let n = n.to_i64().max(1) as usize;
+    let mut out = Vec::with_capacity(n);
+    while out.len() < n {
+        let bytes = next_random().to_le_bytes();
+        let take = (n - out.len()).min(8);
+        out.extend_from_slice(&bytes[..take]);
+    }
+    Value::Blob(out)
+}
+
+fn abs(v: &Value) -> Result<Value> {
+    match v {
+        Value::Null => Ok(Value::Null),
+        Value::Integer(i) => match i.checked_abs() {
+            Some(a) => Ok(Value::Integer(a)),
+            None => bail!("integer overflow"),
+        },
+        other => Ok(Value::Real(other.to_f64().abs())),
+    }
+}
+
+/// SQLite's round: halves go away from zero. With no digits that is done
+/// by adding or subtracting 0.5 and truncating; otherwise, as SQLite's
+/// printf does it, on the value's exact decimal expansion, so 2.675 (just
+/// under 2.675 in binary) rounds down and -2.25 (exact) rounds to -2.3.
+fn round(v: &Value, digits: Option<&Value>) -> Value {
+    if v.is_null() || digits.is_some_and(|d| d.is_null()) {
+        return Value::Null;
+    }
+    let x = v.to_f64();
+    let n = digits.map(|d| d.to_i64()).unwrap_or(0).clamp(0, 30) as usize;
+    // Beyond 2^52 a double has no fractional part to round.
+    let rounded = if x.abs() > 4503599627370496.0 {
+        x
+    } else if n == 0 {
+        (x + if x < 0.0 { -0.5 } else { 0.5 }).trunc()
+    } else {
+        round_decimal(x, n)
+    };
+    Value::Real(rounded)
+}
+
+fn round_decimal(x: f64, n: usize) -> f64 {
+    // 1074 places hold every digit of any double's fraction.
+    let exact = format!("{:.1074}", x.abs());
+    let point = exact.find('.').unwrap_or(exact.len());
+    let mut digits: Vec<u8> = exact[..point + 1 + n].bytes().collect();
+    if exact.as_bytes()[point + 1 + n] >= b'5' {
+        // Carry up through the kept digits, skipping the point.
+        let mut i = digits.len();
+        loop {
+            if i == 0 {
+                digits.insert(0, b'1');
+                break;
+            }
+            i -= 1;
+            match digits[i] {
+                b'.' => continue,
+                b'9' => digits[i] = b'0',
+                d => {
+                    digits[i] = d + 1;
+                    break;
+                }
+            }
+        }
+    }
+    let magnitude: f64 = String::from_utf8_lossy(&digits).parse().unwrap_or(x.abs());
+    magnitude.copysign(x)
+}
+
+fn pattern_result(pattern: &Value, text: &Value, matcher: fn(&str, &str) -> bool) -> Value {
+    if pattern.is_null() || text.is_null() {
+        return Value::Null;
+    }
+    Value::Integer(matcher(&pattern.to_string(), &text.to_string()) as i64)
+}
+
+/// SQL LIKE: `%` matches any run of characters, `_` exactly one, and ASCII
+/// letters compare case-insensitively.
+pub fn like_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    wildcard_match(&pattern, &text, '%', '_', |p, t| p.eq_ignore_ascii_case(&t))
+}
+
+/// SQLite GLOB: case-sensitive `*`, `?` and `[...]` character classes
+/// (with `^` negation and `a-z` ranges).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_at(&pattern, &text)
+}
+
+fn wildcard_match(
+    pattern: &[char],
+    text: &[char],
+    any: char,
+    one: char,
+    eq: impl Fn(char, char) -> bool,
+) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == any {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == one || eq(pattern[p], text[t])) {
+            p += 1;
+            t += 1;
+        } else if let Some((bp, bt)) = backtrack {
+            p = bp + 1;
+            t = bt + 1;
+            backtrack = Some((bp, bt + 1));
+        } else {
+            return false;
+        }
+    }
+    while p < pattern.len() && pattern[p] == any {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+fn glob_at(pattern: &[char], text: &[char]) -> bool {
+    let Some(&first) = pattern.first() else {
+        return text.is_empty();
+    };
+    match first {
+        '*' => (0..=text.len()).any(|skip| glob_at(&pattern[1..], &text[skip..])),
+        '?' => !text.is_empty() && glob_at(&pattern[1..], &text[1..]),
+        '[' => {
+            let Some(&c) = text.first() else {
+                return false;
+            };
+            match match_class(&pattern[1..], c) {
+                Some((matched, consumed)) => {
+                    matched && glob_at(&pattern[1 + consumed..], &text[1..])
+                }
+                None => text.first() == Some(&'[') && glob_at(&pattern[1..], &text[1..]),
+            }
+        }
+        c => text.first() == Some(&c) && glob_at(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Matches `c` against a character class whose opening `[` has already been
+/// consumed. Returns whether it matched and how many pattern chars the class
+/// used, or `None` if the class is unterminated.
+fn match_class(class: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let negated = class.first() == Some(&'^');
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < class.len() {
+        let ch = class[i];
+        if ch == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        if i + 2 < class.len() && class[i + 1] == '-' && class[i + 2] != ']' {
+            if class[i] <= c && c <= class[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if ch == c {
+                matched = true;
+            }
+            i += 1;
+        }
+        first = false;
+    }
+    None
+}
+
+thread_local! {
+    static REGEX_CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+/// Whether `pattern` matches anywhere in `text`. As in SQLite's regexp
+/// extension, `.` matches a newline too, and `^` and `$` anchor at the ends
+/// of the whole text.
+pub fn regexp_match(pattern: &str, text: &str) -> Result<bool> {
+    REGEX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if !cache.contains_key(pattern) {
+            let re = match RegexBuilder::new(pattern).dot_matches_new_line(true).build() {
+                Ok(re) => re,
+                Err(e) => bail!("invalid regular expression: {}", e),
+            };
+            cache.insert(pattern.to_string(), re);
+        }
+        Ok(cache[pattern].is_match(text))
+    })
+}
+
+thread_local! {
+    static RNG_STATE: Cell<u64> = Cell::new(seed());
+}
+
+fn seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    (nanos ^ ((std::process::id() as u64) << 32)) | 1
+}
+
+/// xorshift64* generator; good enough for random() and randomblob().
+fn next_random() -> u64 {
+    RNG_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regexp_dot_matches_a_newline() {
+        assert!(regexp_match("a.b", "a\nb").unwrap());
+        assert!(regexp_match("^a.*b$", "a\n\nb").unwrap());
+        assert!(!regexp_match("^b", "a\nb").unwrap());
+        assert!(!regexp_match("a$", "a\nb").unwrap());
+    }
+
+    /// Results as sqlite3 3.51 gives them.
+    #[test]
+    fn round_matches_sqlite() {
+        let cases: &[(f64, i64, f64)] = &[
+            // Ties, exact in binary, go away from zero.
+            (-2.25, 1, -2.3),
+            (2.25, 1, 2.3),
+            (0.125, 2, 0.13),
+            (-0.125, 2, -0.13),
+            (1234567.125, 2, 1234567.13),
+            (2.5, 0, 3.0),
+            (-2.5, 0, -3.0),
+            (0.5, 0, 1.0),
+            (-0.5, 0, -1.0),
+            // Values just below a tie in binary round down.
+            (2.675, 2, 2.67),
+            (0.15, 1, 0.1),
+            (1.005, 2, 1.0),
+            (5.55, 1, 5.5),
+            (-5.55, 1, -5.5),
+            (9.995, 2, 9.99),
+            // Carries and the other ends of the range.
+            (9.96, 1, 10.0),
+            (-99.99, 1, -100.0),
+            (123.456, -1, 123.0),
+            (1e20, 2, 1e20),
+            (2.5, 40, 2.5),
+        ];
+        for &(x, n, expected) in cases {
+            let got = round(&Value::Real(x), Some(&Value::Integer(n)));
+            assert_eq!(got, Value::Real(expected), "round({}, {})", x, n);
+        }
+    }
+
+    #[test]
+    fn round_without_digits_and_of_null() {
+        assert_eq!(round(&Value::Integer(3), None), Value::Real(3.0));
+        assert_eq!(round(&Value::Real(-3.5), None), Value::Real(-4.0));
+        assert_eq!(round(&Value::Null, None), Value::Null);
+        assert_eq!(round(&Value::Real(1.5), Some(&Value::Null)), Value::Null);
+    }
+
+    /// As sqlite3 gives them in a database of each encoding.
+    #[test]
+    fn hex_shows_text_in_the_database_encoding() {
+        let cases = [
+            (TextEncoding::Utf8, "61C3A9"),
+            (TextEncoding::Utf16Le, "6100E900"),
+            (TextEncoding::Utf16Be, "006100E9"),
+        ];
+        for (encoding, expected) in cases {
+            let got = hex(&Value::Text("aé".to_string()), encoding);
+            assert_eq!(got, Value::Text(expected.to_string()), "{:?}", encoding);
+            // Numbers and blobs are the same bytes in any encoding.
+            assert_eq!(hex(&Value::Integer(12), encoding), Value::Text("3132".to_string()));
+            assert_eq!(hex(&Value::Real(1.5), encoding), Value::Text("312E35".to_string()));
+            let blob = Value::Blob(vec![0x00, 0xFF]);
+            assert_eq!(hex(&blob, encoding), Value::Text("00FF".to_string()));
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use std::fs::File;
+use std::io::{prelude::*, SeekFrom};
+
+use crate::error::{corrupt, RqliteError};
+use crate::record::TextEncoding;
+
+pub const HEADER_SIZE: usize = 100;
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// The 100-byte database header at the start of page 1.
+#[derive(Debug, Clone)]
+pub struct DbHeader {
+    /// Page size in bytes; the on-disk value 1 means 65536.
+    pub page_size: usize,
+    pub write_version: u8,
+    pub read_version: u8,
+    /// Bytes at the end of every page set aside for extensions (checksums,
+    /// encryption nonces); cells never live there.
+    pub reserved_bytes: u8,
+    pub file_change_counter: u32,
+    /// Database size in pages, as last written by SQLite.
+    pub page_count: u32,
+    /// First freelist trunk page, or 0 if the freelist is empty.
+    pub first_freelist_trunk: u32,
+    /// Total number of pages on the freelist.
+    pub freelist_pages: u32,
+    pub schema_cookie: u32,
+    pub schema_format: u32,
+    pub default_cache_size: u32,
+    /// Largest root b-tree page; non-zero only in auto-vacuum databases.
+    pub largest_root_page: u32,
+    /// The raw encoding number (1 UTF-8, 2 UTF-16le, 3 UTF-16be).
+    pub text_encoding_code: u32,
+    pub user_version: u32,
+    pub incremental_vacuum: u32,
+    pub application_id: u32,
+    /// `SQLITE_VERSION_NUMBER` of the library that last wrote the file.
+    pub software_version: u32,
+    pub text_encoding: TextEncoding,
+}
+
+impl DbHeader {
+    pub fn parse(bytes: &[u8]) -> Result<DbHeader> {
+        if bytes.len() < HEADER_SIZE || &bytes[..16] != MAGIC {
+            return Err(RqliteError::NotADatabase.into());
+        }
+        let page_size = match u16::from_be_bytes([bytes[16], bytes[17]]) {
+            1 => 65536,
+            n if n >= 512 && n.is_power_of_two() => n as usize,
+            n => return Err(corrupt(1, format!("invalid page size: {}", n))),
+        };
+        if page_size - (bytes[20] as usize) < 480 {
+            return Err(corrupt(
+                1,
+                format!("invalid reserved space: {} bytes", bytes[20]),
+            ));
+        }
+        let be32 = |at: usize| {
+            u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        Ok(DbHeader {
+            page_size,
+            write_version: bytes[18],
+            read_version: bytes[19],
+            reserved_bytes: bytes[20],
+            file_change_counter: be32(24),
+            page_count: be32(28),
+            first_freelist_trunk: be32(32),
+            freelist_pages: be32(36),
+            schema_cookie: be32(40),
+            schema_format: be32(44),
+            default_cache_size: be32(48),
+            largest_root_page: be32(52),
+            text_encoding_code: be32(56),
+            user_version: be32(60),
+            incremental_vacuum: be32(64),
+            application_id: be32(68),
+            software_version: be32(96),
+            text_encoding: TextEncoding::from_header(bytes)?,
+        })
+    }
+
+    /// Reads and parses the header, leaving the file positioned just after it.
+    pub fn read(file: &mut File) -> Result<DbHeader> {
+        file.seek(SeekFrom::Start(0)).map_err(RqliteError::Io)?;
+        let mut bytes = [0u8; HEADER_SIZE];
+        if file.read_exact(&mut bytes).is_err() {
+            return Err(RqliteError::NotADatabase.into());
+        }
+        DbHeader::parse(&bytes)
+    }
+
+    /// The part of each page available to b-tree content.
+    pub fn usable_size(&self) -> usize {
+        self.page_size - self.reserved_bytes as usize
+    }
+
+    pub fn is_wal(&self) -> bool {
+        self.write_version == 2 || self.read_version == 2
+    }
+
+    /// Auto-vacuum (and incremental-vacuum) databases interleave pointer-map
+    /// pages with the b-tree pages.
+    pub fn auto_vacuum(&self) -> bool {
+        self.largest_root_page != 0
+    }
+
+    /// Whether `page_no` is a pointer-map page. The first is page 2, and each
+    /// one maps the `usable_size / 5` pages that follow it.
+    pub fn is_ptrmap_page(&self, page_no: u32) -> bool {
+        if !self.auto_vacuum() || page_no < 2 {
+            return false;
+        }
+        (page_no - 2).is_multiple_of(self.ptrmap_span())
+    }
+
+    /// Number of pointer-map pages in a file of `page_count` pages.
+    pub fn ptrmap_pages(&self, page_count: u64) -> u64 {
+        if !self.auto_vacuum() || page_count < 2 {
+            return 0;
+        }
+        (page_count - 1).div_ceil(self.ptrmap_span() as u64)
+    }
+
+    fn ptrmap_span(&self) -> u32 {
+        (self.usable_size() / 5) as u32 + 1
+    }
+}
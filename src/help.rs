@@ -0,0 +1,224 @@
+use anyhow::{bail, Result};
+
+use crate::functions::FUNCTIONS;
+
+pub struct CommandDoc {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub summary: &'static str,
+}
+
+/// Every dot-command and CLI mode. Dispatch takes its usage errors from here.
+pub const COMMANDS: &[CommandDoc] = &[
+    CommandDoc {
+        name: ".dbinfo",
+        usage: ".dbinfo",
+        summary: "Show database header fields and schema counts",
+    },
+    CommandDoc {
+        name: ".tables",
+        usage: ".tables [--views] [PATTERN]",
+        summary: "List tables and views, or those matching a LIKE pattern, or only views",
+    },
+    CommandDoc {
+        name: ".indexes",
+        usage: ".indexes [TABLE]",
+        summary: "List each index with its table and key columns",
+    },
+    CommandDoc {
+        name: ".dump",
+        usage: ".dump [TABLE...]",
+        summary: "Print SQL that recreates the database, or just the tables given as LIKE patterns",
+    },
+    CommandDoc {
+        name: ".recover",
+        usage: ".recover",
+        summary: "Print SQL that rebuilds what can still be read of a damaged database",
+    },
+    CommandDoc {
+        name: ".describe",
+        usage: ".describe SQL",
+        summary:
+            "Show each result column's declared type, origin table and column, and nullability",
+    },
+    CommandDoc {
+        name: ".save",
+        usage: ".save [NAME SQL]",
+        summary: "Save SQL as a named query for this database, or list saved queries",
+    },
+    CommandDoc {
+        name: ".run",
+        usage: ".run NAME",
+        summary: "Run a saved query",
+    },
+    CommandDoc {
+        name: ".set",
+        usage: ".set [NAME VALUE]",
+        summary: "Set a variable usable as $NAME, :NAME or @NAME, or list variables",
+    },
+    CommandDoc {
+        name: ".mode",
+        usage: ".mode [FORMAT] [TABLE]",
+        summary: "Set the output format, and for insert the TABLE to insert into, or show the current one",
+    },
+    CommandDoc {
+        name: ".headers",
+        usage: ".headers on|off",
+        summary: "Start each result with its column names",
+    },
+    CommandDoc {
+        name: ".separator",
+        usage: ".separator COL [ROW]",
+        summary: "Set the text between values, and after rows, in list, csv and tsv output",
+    },
+    CommandDoc {
+        name: ".nullvalue",
+        usage: ".nullvalue TEXT",
+        summary: "Set the text printed for NULL",
+    },
+    CommandDoc {
+        name: ".timeout",
+        usage: ".timeout MS",
+        summary: "Retry a locked database for up to MS milliseconds",
+    },
+    CommandDoc {
+        name: ".output",
+        usage: ".output [FILE]",
+        summary: "Send output to FILE, or back to stdout",
+    },
+    CommandDoc {
+        name: ".once",
+        usage: ".once FILE",
+        summary: "Send the next command's output to FILE",
+    },
+    CommandDoc {
+        name: ".read",
+        usage: ".read [--bail] FILE",
+        summary: "Run the SQL and commands in FILE; --bail stops at the first error",
+    },
+    CommandDoc {
+        name: ".show",
+        usage: ".show",
+        summary: "Show the current settings",
+    },
+    CommandDoc {
+        name: ".quit",
+        usage: ".quit",
+        summary: "Leave the interactive shell (also .exit)",
+    },
+    CommandDoc {
+        name: ".help",
+        usage: ".help [COMMAND|FUNCTION]",
+        summary: "Show help for all commands, one command, or one SQL function",
+    },
+    CommandDoc {
+        name: "ANALYZE",
+        usage: "ANALYZE [TABLE|INDEX]",
+        summary:
+            "Compute the sqlite_stat1 rows ANALYZE would write; the planner reads existing ones",
+    },
+    CommandDoc {
+        name: "EXPLAIN",
+        usage: "EXPLAIN SELECT ...",
+        summary: "List the bytecode program a query compiles to instead of running it",
+    },
+    CommandDoc {
+        name: "PRAGMA",
+        usage: "PRAGMA NAME | PRAGMA NAME(ARG)",
+        summary: "Report page_size, page_count, table_info(t), index_list(t) and other pragmas",
+    },
+    CommandDoc {
+        name: "--format",
+        usage: "--format NAME DATABASE COMMAND",
+        summary: "Print query results in a registered output format (default: list)",
+    },
+    CommandDoc {
+        name: "--pragma",
+        usage: "--pragma NAME=VALUE",
+        summary: "Change a setting by name (see .show for the list)",
+    },
+    CommandDoc {
+        name: "--busy-timeout",
+        usage: "--busy-timeout MS DATABASE COMMAND",
+        summary: "Retry for up to MS milliseconds while a writer holds the database locked",
+    },
+    CommandDoc {
+        name: "--redact",
+        usage: "--redact PROFILE.json DATABASE COMMAND",
+        summary: "Mask the columns a redaction profile names in query results and .dump",
+    },
+    CommandDoc {
+        name: "--params",
+        usage: "--params JSON DATABASE COMMAND",
+        summary: "Bind typed values (dates, base64 blobs, booleans) to $name variables",
+    },
+    CommandDoc {
+        name: "--why",
+        usage: "--why rowid=N DATABASE QUERY",
+        summary: "Trace one row through the query's plan instead of running it",
+    },
+    CommandDoc {
+        name: "--no-lock",
+        usage: "--no-lock DATABASE COMMAND",
+        summary: "Read without SQLite's SHARED lock, for files no process writes to",
+    },
+    CommandDoc {
+        name: "federate",
+        usage: "federate DIRECTORY SQL",
+        summary: "Query a table across every database file in DIRECTORY (UNION ALL)",
+    },
+    CommandDoc {
+        name: "analyze-space",
+        usage: "analyze-space DATABASE",
+        summary: "Report per-table and per-index space use, as sqlite3_analyzer does",
+    },
+    CommandDoc {
+        name: "schema",
+        usage: "schema export DATABASE [--json]",
+        summary: "Print the schema (tables, columns, constraints, indexes) as JSON",
+    },
+    CommandDoc {
+        name: "conformance",
+        usage: "conformance DIRECTORY",
+        summary: "Run golden-file cases (NAME.sql / NAME.expected) and report diffs",
+    },
+];
+
+pub fn usage_error(command: &str) -> anyhow::Error {
+    match COMMANDS.iter().find(|c| c.name == command) {
+        Some(c) => anyhow::anyhow!("Usage: {}", c.usage),
+        None => anyhow::anyhow!("unknown command: {}", command),
+    }
+}
+
+/// Help text for a command (with or without its leading dot) or an SQL
+/// function; with no topic, an overview of every command.
+pub fn help_text(topic: &str) -> Result<String> {
+    let topic = topic.trim();
+    if topic.is_empty() {
+        let width = COMMANDS.iter().map(|c| c.usage.len()).max().unwrap_or(0);
+        let mut out = String::new();
+        for c in COMMANDS {
+            out.push_str(&format!("{:width$}  {}\n", c.usage, c.summary));
+        }
+        let names: Vec<&str> = FUNCTIONS.iter().map(|f| f.name).collect();
+        out.push_str(&format!("\nSQL functions: {}\n", names.join(", ")));
+        out.push_str("Use .help NAME for details.");
+        return Ok(out);
+    }
+
+    let command = COMMANDS.iter().find(|c| {
+        c.name.eq_ignore_ascii_case(topic)
+            || c.name
+                .strip_prefix('.')
+                .is_some_and(|n| n.eq_ignore_ascii_case(topic))
+    });
+    if let Some(c) = command {
+        return Ok(format!("{}\n  {}", c.usage, c.summary));
+    }
+    let name = topic.trim_end_matches("()");
+    if let Some(f) = FUNCTIONS.iter().find(|f| f.name.eq_ignore_ascii_case(name)) {
+        return Ok(format!("{}\n  {}", f.usage, f.summary));
+    }
+    bail!("no help for {}", topic)
+}
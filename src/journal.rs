@@ -0,0 +1,193 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+
+const MAGIC: [u8; 8] = [0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+
+/// A hot rollback journal: the original images of pages a writer changed
+/// before it crashed. Rolling back means reading those images instead of the
+/// database file's copies, and treating the file as its original size.
+pub struct Journal {
+    pages: HashMap<u32, Vec<u8>>,
+    /// Database size in pages before the interrupted transaction.
+    pub db_pages: u32,
+}
+
+impl Journal {
+    /// Reads the journal at `path` if it is hot. A missing or empty journal,
+    /// or one whose header was zeroed or never finished (a committed or
+    /// abandoned transaction), leaves nothing to roll back.
+    pub fn open(path: &str) -> Result<Option<Journal>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if bytes.len() < 28 || bytes[..8] != MAGIC {
+            return Ok(None);
+        }
+
+        let mut journal = Journal {
+            pages: HashMap::new(),
+            db_pages: be32(&bytes[16..20]),
+        };
+        let mut offset = 0;
+        // A journal holds one or more segments, each a sector-aligned header
+        // followed by page records. Playback stops at the first record whose
+        // checksum fails, as SQLite does, since its write may have been torn.
+        while offset + 28 <= bytes.len() && bytes[offset..offset + 8] == MAGIC {
+            let header = &bytes[offset..];
+            let mut records = be32(&header[8..12]) as usize;
+            let nonce = be32(&header[12..16]);
+            let sector_size = be32(&header[20..24]) as usize;
+            let page_size = be32(&header[24..28]) as usize;
+            if !(32..=65536).contains(&sector_size)
+                || !page_size.is_power_of_two()
+                || !(512..=65536).contains(&page_size)
+            {
+                break;
+            }
+
+            let record_size = page_size + 8;
+            let start = offset + sector_size;
+            if records == 0xffff_ffff {
+                records = bytes.len().saturating_sub(start) / record_size;
+            }
+            let mut torn = false;
+            for i in 0..records {
+                let at = start + i * record_size;
+                let Some(record) = bytes.get(at..at + record_size) else {
+                    torn = true;
+                    break;
+                };
+                let page_no = be32(&record[0..4]);
+                let data = &record[4..4 + page_size];
+                if page_no == 0 || be32(&record[4 + page_size..]) != checksum(nonce, data) {
+                    torn = true;
+                    break;
+                }
+                // The first image of a page is its original content.
+                journal
+                    .pages
+                    .entry(page_no)
+                    .or_insert_with(|| data.to_vec());
+            }
+            if torn {
+                break;
+            }
+            offset = (start + records * record_size).next_multiple_of(sector_size);
+        }
+        Ok(Some(journal))
+    }
+
+    /// The original image of `page_no`, if the interrupted transaction
+    /// changed it.
+    pub fn page(&self, page_no: u32) -> Option<&[u8]> {
+        self.pages.get(&page_no).map(Vec::as_slice)
+    }
+}
+
+/// The journal's per-record checksum: the nonce plus every 200th byte of the
+/// page, counting down from 200 bytes before its end.
+fn checksum(nonce: u32, data: &[u8]) -> u32 {
+    let mut sum = nonce;
+    let mut i = data.len() as isize - 200;
+    while i > 0 {
+        sum = sum.wrapping_add(data[i as usize] as u32);
+        i -= 200;
+    }
+    sum
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 512;
+    const SECTOR_SIZE: usize = 512;
+    const NONCE: u32 = 0x1234_5678;
+
+    /// A journal segment header for `records` records of a database that was
+    /// `db_pages` pages long, padded to a sector.
+    fn segment(records: u32, db_pages: u32) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend(records.to_be_bytes());
+        out.extend(NONCE.to_be_bytes());
+        out.extend(db_pages.to_be_bytes());
+        out.extend((SECTOR_SIZE as u32).to_be_bytes());
+        out.extend((PAGE_SIZE as u32).to_be_bytes());
+        out.resize(SECTOR_SIZE, 0);
+        out
+    }
+
+    /// A page record: `page_no` filled with `fill`, and its checksum.
+    fn record(page_no: u32, fill: u8) -> Vec<u8> {
+        let page = vec![fill; PAGE_SIZE];
+        let mut out = page_no.to_be_bytes().to_vec();
+        out.extend(&page);
+        out.extend(checksum(NONCE, &page).to_be_bytes());
+        out
+    }
+
+    /// `bytes` opened as a journal, with its original size and the first
+    /// byte of each original page it has for pages 1 to 3.
+    fn open(name: &str, bytes: &[u8]) -> Option<(u32, [Option<u8>; 3])> {
+        let path = std::env::temp_dir().join(format!("rqlite-{}-{}", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        let journal = Journal::open(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        journal.map(|j| {
+            let first = |page_no| j.page(page_no).map(|p| p[0]);
+            (j.db_pages, [first(1), first(2), first(3)])
+        })
+    }
+
+    #[test]
+    fn a_hot_journal_keeps_each_pages_first_image() {
+        let mut bytes = segment(2, 3);
+        bytes.extend(record(2, 0xb1));
+        bytes.extend(record(1, 0xa1));
+        // A second segment, after the first's sector-aligned end, records a
+        // page the first already has; the earlier image is the original.
+        let second = (SECTOR_SIZE + 2 * (PAGE_SIZE + 8)).next_multiple_of(SECTOR_SIZE);
+        bytes.resize(second, 0);
+        bytes.extend(segment(2, 3));
+        bytes.extend(record(2, 0xb2));
+        bytes.extend(record(3, 0xc1));
+        assert_eq!(
+            open("hot", &bytes),
+            Some((3, [Some(0xa1), Some(0xb1), Some(0xc1)]))
+        );
+    }
+
+    #[test]
+    fn playback_stops_at_a_torn_record() {
+        let mut bytes = segment(3, 3);
+        bytes.extend(record(1, 0xa1));
+        let mut torn = record(2, 0xb1);
+        torn[4 + PAGE_SIZE - 200] ^= 1;
+        bytes.extend(torn);
+        bytes.extend(record(3, 0xc1));
+        assert_eq!(open("torn", &bytes), Some((3, [Some(0xa1), None, None])));
+        // So does a record count of -1 (records up to the end of the file)
+        // when the file ends partway through one.
+        let mut bytes = segment(0xffff_ffff, 2);
+        bytes.extend(record(1, 0xa1));
+        bytes.extend(&record(2, 0xb1)[..100]);
+        assert_eq!(open("short", &bytes), Some((2, [Some(0xa1), None, None])));
+    }
+
+    #[test]
+    fn a_journal_without_a_header_is_not_hot() {
+        let mut bytes = vec![0; SECTOR_SIZE];
+        bytes.extend(record(1, 0xa1));
+        assert_eq!(open("zeroed", &bytes), None);
+        assert_eq!(open("empty", &[]), None);
+        assert!(Journal::open("/nonexistent/db-journal").unwrap().is_none());
+    }
+}
@@ -0,0 +1,571 @@
+use anyhow::{bail, Result};
+
+use crate::value::Value;
+
+/// A parsed JSON document. Numbers and strings keep their source text (strings
+/// without the surrounding quotes, escapes intact) so that re-serialization is
+/// lossless, the way SQLite's JSON functions behave.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(true) => "true",
+            Json::Bool(false) => "false",
+            Json::Number(n) if is_integer_text(n) => "integer",
+            Json::Number(_) => "real",
+            Json::String(_) => "text",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// Converts a JSON value to the SQL value `json_extract()` returns.
+    pub fn to_value(&self) -> Value {
+        match self {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Integer(*b as i64),
+            Json::Number(n) => {
+                if is_integer_text(n) {
+                    match n.parse::<i64>() {
+                        Ok(i) => Value::Integer(i),
+                        Err(_) => Value::Real(n.parse::<f64>().unwrap_or(0.0)),
+                    }
+                } else {
+                    Value::Real(n.parse::<f64>().unwrap_or(0.0))
+                }
+            }
+            Json::String(raw) => Value::Text(unescape(raw)),
+            Json::Array(_) | Json::Object(_) => Value::Text(self.to_string()),
+        }
+    }
+
+    /// A JSON string holding `s`, escaped as needed.
+    pub fn text(s: &str) -> Json {
+        let mut raw = String::with_capacity(s.len());
+        for c in s.chars() {
+            match c {
+                '"' => raw.push_str("\\\""),
+                '\\' => raw.push_str("\\\\"),
+                '\n' => raw.push_str("\\n"),
+                '\r' => raw.push_str("\\r"),
+                '\t' => raw.push_str("\\t"),
+                c if (c as u32) < 0x20 => raw.push_str(&format!("\\u{:04x}", c as u32)),
+                c => raw.push(c),
+            }
+        }
+        Json::String(raw)
+    }
+
+    /// Serializes with two-space indentation, one member or element per line.
+    pub fn to_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, depth: usize) {
+        let indent = |out: &mut String, depth: usize| {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth));
+        };
+        match self {
+            Json::Array(items) if !items.is_empty() => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    indent(out, depth + 1);
+                    item.write_pretty(out, depth + 1);
+                }
+                indent(out, depth);
+                out.push(']');
+            }
+            Json::Object(members) if !members.is_empty() => {
+                out.push('{');
+                for (i, (key, item)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    indent(out, depth + 1);
+                    out.push('"');
+                    out.push_str(key);
+                    out.push_str("\": ");
+                    item.write_pretty(out, depth + 1);
+                }
+                indent(out, depth);
+                out.push('}');
+            }
+            _ => self.write(out),
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Number(n) => out.push_str(n),
+            Json::String(raw) => {
+                out.push('"');
+                out.push_str(raw);
+                out.push('"');
+            }
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    item.write(out);
+                }
+                out.push(']');
+            }
+            Json::Object(members) => {
+                out.push('{');
+                for (i, (key, item)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    out.push('"');
+                    out.push_str(key);
+                    out.push_str("\":");
+                    item.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out);
+        f.write_str(&out)
+    }
+}
+
+fn is_integer_text(n: &str) -> bool {
+    !n.contains(['.', 'e', 'E'])
+}
+
+/// Decodes the escapes of a raw JSON string body. The parser has already
+/// validated the escapes, so malformed sequences cannot occur here.
+fn unescape(raw: &str) -> String {
+    if !raw.contains('\\') {
+        return raw.to_string();
+    }
+    let mut parser = JsonParser {
+        bytes: raw.as_bytes(),
+        pos: 0,
+    };
+    let mut out = Vec::new();
+    while parser.pos < parser.bytes.len() {
+        let b = parser.bytes[parser.pos];
+        parser.pos += 1;
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        let esc = parser.bytes[parser.pos];
+        parser.pos += 1;
+        match esc {
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0c),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'u' => {
+                let c = parser.parse_unicode_escape().unwrap_or('\u{FFFD}');
+                let mut buf = [0u8; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            other => out.push(other),
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+pub fn parse(text: &str) -> Result<Json> {
+    let mut parser = JsonParser {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    parser.skip_ws();
+    let value = parser.parse_value(0)?;
+    parser.skip_ws();
+    if parser.pos != parser.bytes.len() {
+        bail!("malformed JSON");
+    }
+    Ok(value)
+}
+
+const MAX_DEPTH: usize = 1000;
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_ws(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.bytes.get(self.pos) == Some(&b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_literal(&mut self, lit: &str) -> bool {
+        if self.bytes[self.pos..].starts_with(lit.as_bytes()) {
+            self.pos += lit.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<Json> {
+        if depth > MAX_DEPTH {
+            bail!("malformed JSON");
+        }
+        match self.bytes.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_ws();
+                if self.eat(b'}') {
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.parse_string()?;
+                    self.skip_ws();
+                    if !self.eat(b':') {
+                        bail!("malformed JSON");
+                    }
+                    self.skip_ws();
+                    let value = self.parse_value(depth + 1)?;
+                    members.push((key, value));
+                    self.skip_ws();
+                    if self.eat(b'}') {
+                        return Ok(Json::Object(members));
+                    }
+                    if !self.eat(b',') {
+                        bail!("malformed JSON");
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_ws();
+                if self.eat(b']') {
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    self.skip_ws();
+                    items.push(self.parse_value(depth + 1)?);
+                    self.skip_ws();
+                    if self.eat(b']') {
+                        return Ok(Json::Array(items));
+                    }
+                    if !self.eat(b',') {
+                        bail!("malformed JSON");
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.parse_string()?)),
+            Some(b't') if self.eat_literal("true") => Ok(Json::Bool(true)),
+            Some(b'f') if self.eat_literal("false") => Ok(Json::Bool(false)),
+            Some(b'n') if self.eat_literal("null") => Ok(Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            _ => bail!("malformed JSON"),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Json> {
+        let start = self.pos;
+        self.eat(b'-');
+        let int_start = self.pos;
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_digit() {
+            self.pos += 1;
+        }
+        let int_len = self.pos - int_start;
+        if int_len == 0 || (int_len > 1 && self.bytes[int_start] == b'0') {
+            bail!("malformed JSON");
+        }
+        if self.eat(b'.') {
+            let frac_start = self.pos;
+            while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_digit() {
+                self.pos += 1;
+            }
+            if self.pos == frac_start {
+                bail!("malformed JSON");
+            }
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            if !self.eat(b'+') {
+                self.eat(b'-');
+            }
+            let exp_start = self.pos;
+            while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_digit() {
+                self.pos += 1;
+            }
+            if self.pos == exp_start {
+                bail!("malformed JSON");
+            }
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos])?;
+        Ok(Json::Number(text.to_string()))
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        if !self.eat(b'"') {
+            bail!("malformed JSON");
+        }
+        let start = self.pos;
+        loop {
+            let b = match self.bytes.get(self.pos) {
+                Some(b) => *b,
+                None => bail!("malformed JSON"),
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let esc = match self.bytes.get(self.pos) {
+                        Some(e) => *e,
+                        None => bail!("malformed JSON"),
+                    };
+                    self.pos += 1;
+                    match esc {
+                        b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => {}
+                        b'u' => {
+                            self.parse_unicode_escape()?;
+                        }
+                        _ => bail!("malformed JSON"),
+                    }
+                }
+                b if b < 0x20 => bail!("malformed JSON"),
+                _ => {}
+            }
+        }
+        Ok(std::str::from_utf8(&self.bytes[start..self.pos - 1])?.to_string())
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32> {
+        let digits = match self.bytes.get(self.pos..self.pos + 4) {
+            Some(d) => std::str::from_utf8(d)?,
+            None => bail!("malformed JSON"),
+        };
+        self.pos += 4;
+        match u32::from_str_radix(digits, 16) {
+            Ok(v) => Ok(v),
+            Err(_) => bail!("malformed JSON"),
+        }
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char> {
+        let hi = self.parse_hex4()?;
+        let code = if (0xD800..0xDC00).contains(&hi) && self.eat_literal("\\u") {
+            let lo = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&lo) {
+                bail!("malformed JSON");
+            }
+            0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00)
+        } else {
+            hi
+        };
+        Ok(char::from_u32(code).unwrap_or('\u{FFFD}'))
+    }
+}
+
+enum PathStep {
+    Key(String),
+    Index(usize),
+    FromEnd(usize),
+}
+
+fn parse_path(path: &str) -> Result<Vec<PathStep>> {
+    let bytes = path.as_bytes();
+    if bytes.first() != Some(&b'$') {
+        bail!("bad JSON path: '{}'", path);
+    }
+    let mut steps = Vec::new();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'.' => {
+                i += 1;
+                if bytes.get(i) == Some(&b'"') {
+                    let start = i + 1;
+                    let end = match path[start..].find('"') {
+                        Some(e) => start + e,
+                        None => bail!("bad JSON path: '{}'", path),
+                    };
+                    steps.push(PathStep::Key(path[start..end].to_string()));
+                    i = end + 1;
+                } else {
+                    let start = i;
+                    while i < bytes.len() && bytes[i] != b'.' && bytes[i] != b'[' {
+                        i += 1;
+                    }
+                    if i == start {
+                        bail!("bad JSON path: '{}'", path);
+                    }
+                    steps.push(PathStep::Key(path[start..i].to_string()));
+                }
+            }
+            b'[' => {
+                let end = match path[i..].find(']') {
+                    Some(e) => i + e,
+                    None => bail!("bad JSON path: '{}'", path),
+                };
+                let inner = path[i + 1..end].trim();
+                let step = if let Some(rest) = inner.strip_prefix('#') {
+                    let rest = rest.trim();
+                    if rest.is_empty() {
+                        PathStep::FromEnd(0)
+                    } else if let Some(n) = rest.strip_prefix('-') {
+                        match n.trim().parse::<usize>() {
+                            Ok(n) => PathStep::FromEnd(n),
+                            Err(_) => bail!("bad JSON path: '{}'", path),
+                        }
+                    } else {
+                        bail!("bad JSON path: '{}'", path);
+                    }
+                } else {
+                    match inner.parse::<usize>() {
+                        Ok(n) => PathStep::Index(n),
+                        Err(_) => bail!("bad JSON path: '{}'", path),
+                    }
+                };
+                steps.push(step);
+                i = end + 1;
+            }
+            _ => bail!("bad JSON path: '{}'", path),
+        }
+    }
+    Ok(steps)
+}
+
+/// Resolves a `$.a.b[2]` style path, returning `None` when it does not exist.
+pub fn lookup<'a>(json: &'a Json, path: &str) -> Result<Option<&'a Json>> {
+    let mut current = json;
+    for step in parse_path(path)? {
+        let next = match (step, current) {
+            (PathStep::Key(key), Json::Object(members)) => members
+                .iter()
+                .find(|(k, _)| unescape(k) == key)
+                .map(|(_, v)| v),
+            (PathStep::Index(n), Json::Array(items)) => items.get(n),
+            (PathStep::FromEnd(n), Json::Array(items)) => {
+                items.len().checked_sub(n).and_then(|i| items.get(i))
+            }
+            _ => None,
+        };
+        match next {
+            Some(v) => current = v,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current))
+}
+
+fn parse_arg(v: &Value) -> Result<Option<Json>> {
+    match v {
+        Value::Null => Ok(None),
+        Value::Blob(_) => bail!("JSON cannot hold BLOB values"),
+        Value::Text(s) => Ok(Some(parse(s)?)),
+        other => Ok(Some(parse(&other.to_string())?)),
+    }
+}
+
+fn path_arg(v: &Value) -> Option<String> {
+    match v {
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+pub fn json_extract(args: &[Value]) -> Result<Value> {
+    let json = match parse_arg(&args[0])? {
+        Some(j) => j,
+        None => return Ok(Value::Null),
+    };
+    if args.len() == 2 {
+        let path = match path_arg(&args[1]) {
+            Some(p) => p,
+            None => return Ok(Value::Null),
+        };
+        return Ok(match lookup(&json, &path)? {
+            Some(found) => found.to_value(),
+            None => Value::Null,
+        });
+    }
+
+    let mut results = Vec::new();
+    for arg in &args[1..] {
+        let path = match path_arg(arg) {
+            Some(p) => p,
+            None => return Ok(Value::Null),
+        };
+        results.push(lookup(&json, &path)?.cloned().unwrap_or(Json::Null));
+    }
+    Ok(Value::Text(Json::Array(results).to_string()))
+}
+
+pub fn json_array_length(args: &[Value]) -> Result<Value> {
+    let json = match parse_arg(&args[0])? {
+        Some(j) => j,
+        None => return Ok(Value::Null),
+    };
+    let path = match args.get(1) {
+        Some(arg) => match path_arg(arg) {
+            Some(p) => p,
+            None => return Ok(Value::Null),
+        },
+        None => "$".to_string(),
+    };
+    Ok(match lookup(&json, &path)? {
+        Some(Json::Array(items)) => Value::Integer(items.len() as i64),
+        Some(_) => Value::Integer(0),
+        None => Value::Null,
+    })
+}
+
+pub fn json_type(args: &[Value]) -> Result<Value> {
+    let json = match parse_arg(&args[0])? {
+        Some(j) => j,
+        None => return Ok(Value::Null),
+    };
+    let path = match args.get(1) {
+        Some(arg) => match path_arg(arg) {
+            Some(p) => p,
+            None => return Ok(Value::Null),
+        },
+        None => "$".to_string(),
+    };
+    Ok(match lookup(&json, &path)? {
+        Some(found) => Value::Text(found.type_name().to_string()),
+        None => Value::Null,
+    })
+}
@@ -0,0 +1,121 @@
+use anyhow::Result;
+use std::fs::File;
+
+/// SQLite's lock bytes: 512 bytes just past the first gigabyte, on a page
+/// SQLite never stores data in, so locks never get in the way of I/O.
+#[cfg(unix)]
+const PENDING_BYTE: i64 = 0x4000_0000;
+#[cfg(unix)]
+const RESERVED_BYTE: i64 = PENDING_BYTE + 1;
+#[cfg(unix)]
+const SHARED_FIRST: i64 = PENDING_BYTE + 2;
+#[cfg(unix)]
+const SHARED_SIZE: i64 = 510;
+
+/// Takes a SHARED lock the way SQLite's unix VFS does: a read lock on the
+/// PENDING byte (failing while a writer waits for EXCLUSIVE), then a read
+/// lock on the shared range (failing while a writer holds EXCLUSIVE), then
+/// the PENDING lock is dropped. Returns false if the database is locked.
+///
+/// These are POSIX record locks, so they belong to the process and are all
+/// released when any descriptor for the file is closed.
+#[cfg(unix)]
+pub fn lock_shared(file: &File) -> Result<bool> {
+    if !set_lock(file, libc::F_RDLCK, PENDING_BYTE, 1)? {
+        return Ok(false);
+    }
+    let locked = set_lock(file, libc::F_RDLCK, SHARED_FIRST, SHARED_SIZE)?;
+    set_lock(file, libc::F_UNLCK, PENDING_BYTE, 1)?;
+    Ok(locked)
+}
+
+/// Whether another process holds the RESERVED lock, i.e. a writer is
+/// mid-transaction and its journal is live rather than hot.
+#[cfg(unix)]
+pub fn is_reserved(file: &File) -> Result<bool> {
+    Ok(held_by_other(file, RESERVED_BYTE)? != ByteLock::Unlocked)
+}
+
+/// A lock on one byte of a file, as another process may hold it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteLock {
+    Unlocked,
+    Read,
+    Write,
+}
+
+/// Takes a read (shared) or write (exclusive) lock on byte `at` without
+/// waiting, or changes the kind this process already holds there. Returns
+/// false if another process holds a conflicting lock. This is all the
+/// `-shm` file's locks need: see `WalIndex`.
+#[cfg(unix)]
+pub fn try_lock_byte(file: &File, at: i64, kind: ByteLock) -> Result<bool> {
+    let kind = match kind {
+        ByteLock::Unlocked => libc::F_UNLCK,
+        ByteLock::Read => libc::F_RDLCK,
+        ByteLock::Write => libc::F_WRLCK,
+    };
+    set_lock(file, kind, at, 1)
+}
+
+/// The strongest lock another process holds on byte `at`.
+#[cfg(unix)]
+pub fn held_by_other(file: &File, at: i64) -> Result<ByteLock> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut lock = flock(libc::F_WRLCK, at, 1);
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(match lock.l_type as libc::c_int {
+        libc::F_UNLCK => ByteLock::Unlocked,
+        libc::F_RDLCK => ByteLock::Read,
+        _ => ByteLock::Write,
+    })
+}
+
+#[cfg(unix)]
+fn set_lock(file: &File, kind: libc::c_int, start: i64, len: i64) -> Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let lock = flock(kind, start, len);
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) } == -1 {
+        let err = std::io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EAGAIN) | Some(libc::EACCES) => Ok(false),
+            _ => Err(err.into()),
+        };
+    }
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn flock(kind: libc::c_int, start: i64, len: i64) -> libc::flock {
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = kind as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    lock.l_start = start as libc::off_t;
+    lock.l_len = len as libc::off_t;
+    lock
+}
+
+/// Other platforms read without locking, as with `--no-lock`.
+#[cfg(not(unix))]
+pub fn lock_shared(_file: &File) -> Result<bool> {
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+pub fn is_reserved(_file: &File) -> Result<bool> {
+    Ok(false)
+}
+
+#[cfg(not(unix))]
+pub fn try_lock_byte(_file: &File, _at: i64, _kind: ByteLock) -> Result<bool> {
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+pub fn held_by_other(_file: &File, _at: i64) -> Result<ByteLock> {
+    Ok(ByteLock::Unlocked)
+}
@@ -1,979 +1,1195 @@
+mod affinity;
+mod aggregate;
+mod analyze;
+mod collation;
+mod conformance;
+mod cursor;
+mod database;
+mod dump;
+mod error;
+mod eval;
+mod federate;
+mod freelist;
+mod functions;
+mod header;
+mod help;
+mod journal;
+mod json;
+mod lock;
+mod output;
+mod page;
+mod pager;
+mod params;
+mod planner;
+mod pragma;
+mod record;
+mod recover;
+mod redact;
+mod repl;
+mod schema;
+mod session;
+mod settings;
+mod sql;
+mod stat;
+mod statement;
+mod support;
+mod value;
+mod vm;
+mod wal;
+mod wal_index;
+mod why;
+
 use anyhow::{bail, Result};
+use std::cmp::Ordering;
 use std::fs::File;
-use std::io::{prelude::*, Seek, SeekFrom};
-
-fn main() -> Result<()> {
-    let args = std::env::args().collect::<Vec<_>>();
-    match args.len() {
-        0 | 1 => bail!("Missing <database path> and <command>"),
-        2 => bail!("Missing <command>"),
-        _ => {}
+use std::io::{BufWriter, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use collation::Collation;
+use cursor::TableCursor;
+use database::Database;
+use error::{corrupt, unsupported, RqliteError};
+use functions::like_match;
+use header::DbHeader;
+use output::{FormatRegistry, OutputFormatter};
+use page::{Page, PageBytes, MAX_DEPTH};
+use pager::Pager;
+use params::Params;
+use planner::KeyIndex;
+use record::{
+    cell_payload, check_local_payload, decode_value, extract_row_from_table_cell, record_fields,
+    ColumnSource, TextEncoding,
+};
+use session::Session;
+use settings::Settings;
+use sql::TableSchema;
+use sql::{is_blank, is_rowid_name, parse_create_table, parse_select, split_statements};
+use statement::Statement;
+use support::{check_definition, check_table};
+use value::Value;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report(&e, None);
+            ExitCode::from(exit_code(&e))
+        }
     }
+}
 
-    let db_path = &args[1];
-    let command = &args[2];
-
-    match command.as_str() {
-        ".dbinfo" => {
-            let mut file = File::open(db_path)?;
-            let mut header = [0u8; 100];
-            file.read_exact(&mut header)?;
-
-            let page_size = u16::from_be_bytes([header[16], header[17]]);
-            let table_count = read_number_of_tables(&mut file)?;
+/// Prints a failure to stderr; a syntax error also shows where in the SQL
+/// it is.
+fn report(e: &anyhow::Error, at: Option<&sql::Location>) {
+    match e.chain().find_map(|c| c.downcast_ref::<RqliteError>()) {
+        Some(RqliteError::SyntaxError { position, sql, .. }) => {
+            eprint!("Error: {}\n{}", e, sql::point_at(sql, *position, at));
+        }
+        _ => match at {
+            Some(at) => eprintln!("Error: {:?}\n --> {}, line {}", e, at.file, at.line),
+            None => eprintln!("Error: {:?}", e),
+        },
+    }
+}
 
-            eprintln!("Logs from your program will appear here!");
+/// The CLI's exit status for a failure: 1, SQLite's generic error code,
+/// unless it is an `RqliteError` with a code of its own.
+fn exit_code(e: &anyhow::Error) -> u8 {
+    e.chain()
+        .find_map(|c| c.downcast_ref::<RqliteError>())
+        .map_or(1, RqliteError::exit_code)
+}
 
-            println!("database page size: {}", page_size);
-            println!("number of tables: {}", table_count);
+fn run() -> Result<()> {
+    let mut args = std::env::args().collect::<Vec<_>>();
+    let mut format = String::from("list");
+    let mut settings = Settings::default();
+    let mut params = None;
+    let mut why = None;
+    while let Some(option) = args.get(1).filter(|a| a.starts_with("--")).cloned() {
+        if option == "--no-lock" {
+            settings.no_lock = true;
+            args.remove(1);
+            continue;
         }
-        ".tables" => {
-            let mut file = File::open(db_path)?;
-            let table_names = read_table_names(&mut file)?;
-            if !table_names.is_empty() {
-                println!("{}", table_names.join(" "));
-            }
+        if args.len() < 3 {
+            return Err(help::usage_error(&option));
         }
-        _ => {
-            let mut file = File::open(db_path)?;
-            let upper = command.to_uppercase();
-
-            if upper.starts_with("SELECT") && upper.contains("COUNT(*)") {
-                let table_name = parse_table_name(command);
-                let count = count_rows_in_table(&mut file, &table_name)?;
-                println!("{}", count);
-            } else if upper.starts_with("SELECT") {
-                if upper.contains("WHERE") {
-                    let (cols, table, where_col, where_val) =
-                        parse_select_columns_where_query(command);
-                    let rows =
-                        select_columns_from_table_where(&mut file, &table, &cols, &where_col, &where_val)?;
-                    for row in rows {
-                        println!("{}", row.join("|"));
-                    }
-                } else {
-                    let (cols, table) = parse_select_columns_query(command);
-                    if cols.len() == 1 {
-                        let values = select_column_from_table(&mut file, &table, &cols[0])?;
-                        for v in values {
-                            println!("{}", v);
-                        }
-                    } else {
-                        let rows = select_columns_from_table(&mut file, &table, &cols)?;
-                        for row in rows {
-                            println!("{}", row.join("|"));
-                        }
-                    }
-                }
-            } else {
-                bail!("Missing or invalid command passed: {}", command)
-            }
+        let value = args.remove(2);
+        args.remove(1);
+        match option.as_str() {
+            "--format" => format = value,
+            "--busy-timeout" => settings.set("busy_timeout", &value)?,
+            "--redact" => settings.set("redact", &value)?,
+            "--params" => params = Some(value),
+            "--why" => match value.split_once('=') {
+                Some((name, n)) if is_rowid_name(name.trim()) => match n.trim().parse::<i64>() {
+                    Ok(rowid) => why = Some(rowid),
+                    Err(_) => return Err(help::usage_error("--why")),
+                },
+                _ => return Err(help::usage_error("--why")),
+            },
+            "--pragma" => match value.split_once('=') {
+                Some((name, v)) => settings.set(name.trim(), v.trim())?,
+                None => return Err(help::usage_error("--pragma")),
+            },
+            _ => bail!("unknown option: {}", option),
         }
     }
+    // Parsed once every option is read, as date_storage may come later.
+    let params = match params {
+        Some(json) => Some(Params::from_json(&json, settings.date_storage)?),
+        None => None,
+    };
+    let formats = FormatRegistry::builtin();
+    let mut formatter = formats.create(&format, &settings)?;
 
-    Ok(())
-}
-
-fn read_number_of_tables(file: &mut File) -> Result<u16> {
-    let mut page_header = [0u8; 8];
-    file.read_exact(&mut page_header)?;
-    let cell_count = u16::from_be_bytes([page_header[3], page_header[4]]);
-    Ok(cell_count)
-}
+    if let Some(topic) = args.get(1).and_then(|a| a.strip_prefix(".help")) {
+        println!("{}", help::help_text(topic)?);
+        return Ok(());
+    }
+    const SUBCOMMANDS: [&str; 4] = ["federate", "analyze-space", "schema", "conformance"];
+    match args.len() {
+        0 | 1 => bail!("Missing <database path> and <command>"),
+        2 if SUBCOMMANDS.contains(&args[1].as_str()) => bail!("Missing <command>"),
+        _ => {}
+    }
 
-fn read_table_names(file: &mut File) -> Result<Vec<String>> {
-    let mut header = [0u8; 100];
-    file.read_exact(&mut header)?;
-    let page_size = u16::from_be_bytes([header[16], header[17]]) as usize;
+    if args[1] == "federate" {
+        if args.len() < 4 {
+            return Err(help::usage_error("federate"));
+        }
+        let mut select = parse_select(&args[3])?;
+        select.bind(&|_| None)?;
+        let result = federate::federate(&args[2], &select, &settings)?;
+        print_result(formatter.as_mut(), &mut std::io::stdout().lock(), &result)?;
+        return Ok(());
+    }
 
-    let mut page = vec![0u8; page_size];
-    page[..100].copy_from_slice(&header);
-    file.read_exact(&mut page[100..])?;
+    if args[1] == "analyze-space" {
+        print!("{}", analyze::analyze_space(&args[2], &settings)?);
+        return Ok(());
+    }
 
-    let page_header_offset = 100;
-    let cell_count =
-        u16::from_be_bytes([page[page_header_offset + 3], page[page_header_offset + 4]]) as usize;
-    let cell_ptr_array_offset = page_header_offset + 8;
+    if args[1] == "schema" {
+        return match (args[2].as_str(), args.get(3)) {
+            ("export", Some(db)) if args.len() == 4 || args[4] == "--json" => {
+                println!("{}", schema::export_json(db, &settings)?);
+                Ok(())
+            }
+            // Applying means executing the DDL, and there is no write path.
+            ("apply", Some(_)) => Err(unsupported("schema apply (no write support yet)")),
+            _ => Err(help::usage_error("schema")),
+        };
+    }
 
-    let mut names = Vec::new();
-    for i in 0..cell_count {
-        let idx = cell_ptr_array_offset + i * 2;
-        let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-        let name = extract_tbl_name_from_cell(&page, cell_offset)?;
-        names.push(name);
+    if args[1] == "conformance" {
+        return conformance::run(&args[2]);
     }
 
-    Ok(names)
+    let mut context = Context {
+        db: Database::open(&args[1], &settings),
+        formats,
+        mode: format.to_ascii_lowercase(),
+        formatter,
+        params,
+        why,
+        output: None,
+        once: false,
+        scripts: 0,
+    };
+    if args.len() == 2 {
+        // Opened up front, so that a path that isn't a database fails here
+        // rather than at the first command.
+        context.db.snapshot()?;
+        return repl::run(&mut context);
+    }
+    // SQL may hold several statements, run in turn until one fails.
+    let command = args[2].trim();
+    if command.starts_with('.') {
+        return execute(&mut context, command);
+    }
+    let (statements, rest) = split_statements(command);
+    let last = Some(rest.trim()).filter(|rest| !is_blank(rest));
+    for statement in statements.into_iter().chain(last) {
+        execute(&mut context, statement)?;
+    }
+    Ok(())
 }
 
-fn extract_tbl_name_from_cell(page: &[u8], cell_offset: usize) -> Result<String> {
-    let (_payload_size, len1) = read_varint(page, cell_offset);
-    let (_rowid, len2) = read_varint(page, cell_offset + len1);
-    let header_start = cell_offset + len1 + len2;
-    let (header_size, len3) = read_varint(page, header_start);
-    let mut header_pos = header_start + len3;
+/// How deep `.read` scripts may nest, so that one reading itself stops.
+const MAX_SCRIPTS: usize = 16;
+
+/// What a command runs against: the database and the options the CLI was
+/// started with. The interactive shell keeps one for its whole session, in
+/// which `.mode` changes the output format.
+struct Context {
+    db: Database,
+    formats: FormatRegistry,
+    /// The name of the output format `formatter` writes.
+    mode: String,
+    formatter: Box<dyn OutputFormatter>,
+    params: Option<Params>,
+    why: Option<i64>,
+    /// The file `.output` or `.once` sends output to, instead of stdout;
+    /// with `once`, for the next command only.
+    output: Option<BufWriter<File>>,
+    once: bool,
+    /// How many `.read` scripts are running, each read from the last.
+    scripts: usize,
+}
 
-    let mut serials = [0u64; 5];
-    for i in 0..5 {
-        let (st, l) = read_varint(page, header_pos);
-        serials[i] = st;
-        header_pos += l;
+impl Context {
+    /// Makes the formatter anew, after a change to the mode or the settings
+    /// it was made with.
+    fn reformat(&mut self) -> Result<()> {
+        self.formatter = self.formats.create(&self.mode, self.db.settings())?;
+        Ok(())
     }
 
-    let body_start = header_start + header_size as usize;
-    let mut body_pos = body_start;
+    /// Sends output to `path`, created anew, or back to stdout for None.
+    fn redirect(&mut self, path: Option<&str>, once: bool) -> Result<()> {
+        self.output = match path {
+            Some(path) => match File::create(path) {
+                Ok(file) => Some(BufWriter::new(file)),
+                Err(e) => bail!("cannot open \"{}\": {}", path, e),
+            },
+            None => None,
+        };
+        self.once = once && self.output.is_some();
+        Ok(())
+    }
+}
 
-    for col in 0..5 {
-        let size = serial_type_size(serials[col]);
-        if col == 2 {
-            let start = body_pos;
-            let end = start + size;
-            let bytes = &page[start..end];
-            let s = String::from_utf8(bytes.to_vec())?;
-            return Ok(s);
+/// Runs one SQL statement or dot-command and prints its output, to stdout
+/// or to the file `.output` or `.once` named.
+fn execute(context: &mut Context, command: &str) -> Result<()> {
+    let (name, rest) = match command.trim().split_once(char::is_whitespace) {
+        Some((name, rest)) => (name, rest.trim()),
+        None => (command.trim(), ""),
+    };
+    if name == ".output" || name == ".once" {
+        let args = dot_args(rest);
+        if args.len() > 1 {
+            return Err(help::usage_error(name));
         }
-        body_pos += size;
+        return context.redirect(args.first().map(String::as_str), name == ".once");
+    }
+    // Before the output is taken, so that the script's commands write
+    // where it would.
+    if name == ".read" {
+        let args = dot_args(rest);
+        let (path, bail) = match args.as_slice() {
+            [path] => (path, false),
+            [flag, path] if flag == "--bail" => (path, true),
+            _ => return Err(help::usage_error(name)),
+        };
+        if context.scripts >= MAX_SCRIPTS {
+            bail!(
+                "scripts read from each other more than {} deep",
+                MAX_SCRIPTS
+            );
+        }
+        context.scripts += 1;
+        let result = repl::read(context, path, bail);
+        context.scripts -= 1;
+        return result;
     }
 
-    Ok(String::new())
+    let mut file = context.output.take();
+    let once = std::mem::take(&mut context.once);
+    let mut stdout = BufWriter::new(std::io::stdout().lock());
+    let out: &mut dyn Write = match &mut file {
+        Some(file) => file,
+        None => &mut stdout,
+    };
+    let result = dispatch(context, out, command, name, rest);
+    // Flushed even after a failure, so that what was written is kept.
+    out.flush()?;
+    if !once {
+        context.output = file;
+    }
+    result
 }
 
-fn read_varint(buf: &[u8], offset: usize) -> (u64, usize) {
-    let mut result: u64 = 0;
-    let mut i = 0;
-
-    loop {
-        let b = buf[offset + i];
-        if i == 8 {
-            result = (result << 8) | b as u64;
-            i += 1;
-            break;
-        } else {
-            result = (result << 7) | (b & 0x7F) as u64;
-            if (b & 0x80) == 0 {
-                i += 1;
-                break;
+fn dispatch(
+    context: &mut Context,
+    out: &mut dyn Write,
+    command: &str,
+    name: &str,
+    rest: &str,
+) -> Result<()> {
+    let db_path = &context.db.path().to_string();
+    let settings = context.db.settings().clone();
+    match name {
+        ".dbinfo" => {
+            let mut pager = context.db.snapshot()?;
+            print_dbinfo(&mut pager, out)?;
+        }
+        ".tables" => {
+            let mut pager = context.db.snapshot()?;
+            let (views_only, pattern) = match rest.strip_prefix("--views") {
+                Some(pattern) => (true, pattern.trim()),
+                None => (false, rest),
+            };
+            let pattern = Some(pattern).filter(|p| !p.is_empty());
+            let names = read_table_names(&mut pager, pattern, views_only)?;
+            write!(out, "{}", name_columns(&names))?;
+        }
+        ".indexes" => {
+            let mut pager = context.db.snapshot()?;
+            let table = Some(rest).filter(|t| !t.is_empty());
+            let result = pragma::index_summary(&mut pager, table)?;
+            print_result(context.formatter.as_mut(), out, &result)?;
+        }
+        ".dump" => {
+            dump::dump(db_path, &settings, &dot_args(rest), out)?;
+        }
+        ".recover" => {
+            recover::recover(db_path, &settings, out)?;
+        }
+        ".save" => {
+            let mut session = Session::load(db_path)?;
+            if rest.is_empty() {
+                for (name, sql) in &session.queries {
+                    writeln!(out, "{}: {}", name, sql)?;
+                }
+            } else {
+                let (query_name, sql) = match rest.split_once(char::is_whitespace) {
+                    Some((n, sql)) => (n, sql.trim()),
+                    None => return Err(help::usage_error(".save")),
+                };
+                session.save_query(query_name, sql)?;
+            }
+        }
+        ".run" => {
+            let session = Session::load(db_path)?;
+            if rest.is_empty() {
+                return Err(help::usage_error(".run"));
             }
+            let sql = session.query(rest)?.to_string();
+            run_sql(context, out, &sql, Some(&session))?;
+        }
+        ".set" => {
+            let mut session = Session::load(db_path)?;
+            if rest.is_empty() {
+                for (name, value) in &session.vars {
+                    writeln!(out, "{} = {}", name, value)?;
+                }
+            } else {
+                let (var_name, value) = match rest.split_once(char::is_whitespace) {
+                    Some((n, v)) => (n, v.trim()),
+                    None => return Err(help::usage_error(".set")),
+                };
+                session.set_var(var_name, value)?;
+            }
+        }
+        ".describe" => {
+            if rest.is_empty() {
+                return Err(help::usage_error(".describe"));
+            }
+            let session = Session::load(db_path).ok();
+            let mut pager = context.db.snapshot()?;
+            let rows = prepare(rest, context.params.as_ref(), session.as_ref())?
+                .query(&mut pager, &settings)?;
+            let text = |s: &Option<String>| s.clone().map_or(Value::Null, Value::Text);
+            let result = QueryResult {
+                columns: ["name", "type", "table", "column", "notnull"]
+                    .map(String::from)
+                    .to_vec(),
+                rows: rows
+                    .column_meta()
+                    .iter()
+                    .map(|m| {
+                        vec![
+                            Value::Text(m.name.clone()),
+                            text(&m.declared_type),
+                            text(&m.table),
+                            text(&m.column),
+                            m.not_null.map_or(Value::Null, |n| Value::Integer(n as i64)),
+                        ]
+                    })
+                    .collect(),
+            };
+            print_result(context.formatter.as_mut(), out, &result)?;
+        }
+        ".mode" => {
+            let args = dot_args(rest);
+            let (mode, table) = match args.as_slice() {
+                [] if context.mode == "insert" => {
+                    let table = dump::quote_name(&settings.insert_table);
+                    writeln!(out, "current output mode: insert {}", table)?;
+                    return Ok(());
+                }
+                [] => {
+                    writeln!(out, "current output mode: {}", context.mode)?;
+                    return Ok(());
+                }
+                [mode] => (mode, None),
+                // Only insert mode takes more: the table to insert into.
+                [mode, table] if mode.eq_ignore_ascii_case("insert") => (mode, Some(table)),
+                _ => return Err(help::usage_error(".mode")),
+            };
+            context.formats.create(mode, &settings)?;
+            // As in sqlite3, a new mode brings its own separators, column
+            // mode starts showing the names, and insert mode inserts into
+            // "table" unless told otherwise.
+            let settings = context.db.settings_mut();
+            settings.separator = None;
+            settings.row_separator = None;
+            if mode.eq_ignore_ascii_case("column") {
+                settings.headers = true;
+            }
+            if mode.eq_ignore_ascii_case("insert") {
+                settings.insert_table = table.map_or("table", |t| t.as_str()).to_string();
+            }
+            context.mode = mode.to_ascii_lowercase();
+            context.reformat()?;
+        }
+        ".separator" => {
+            let args = dot_args(rest);
+            let settings = context.db.settings_mut();
+            match args.as_slice() {
+                [column] => settings.separator = Some(column.clone()),
+                [column, row] => {
+                    settings.separator = Some(column.clone());
+                    settings.row_separator = Some(row.clone());
+                }
+                _ => return Err(help::usage_error(".separator")),
+            }
+            context.reformat()?;
+        }
+        ".timeout" => match dot_args(rest).as_slice() {
+            [ms] => match ms.parse::<u64>() {
+                Ok(ms) => context.db.busy_timeout(Duration::from_millis(ms)),
+                Err(_) => bail!("invalid value for busy_timeout: {}", ms),
+            },
+            _ => return Err(help::usage_error(".timeout")),
+        },
+        ".nullvalue" => {
+            match dot_args(rest).as_slice() {
+                [text] => context.db.settings_mut().null_value = text.clone(),
+                _ => return Err(help::usage_error(".nullvalue")),
+            }
+            context.reformat()?;
+        }
+        ".headers" => {
+            if rest.is_empty() {
+                return Err(help::usage_error(".headers"));
+            }
+            context.db.settings_mut().set("headers", rest)?;
+            context.reformat()?;
+        }
+        ".show" => {
+            let entries = settings.entries();
+            let width = entries
+                .iter()
+                .map(|(name, _)| name.len())
+                .max()
+                .unwrap_or(0);
+            for (name, value) in entries {
+                writeln!(out, "{:>width$}: {}", name, value, width = width)?;
+            }
+        }
+        ".help" => writeln!(out, "{}", help::help_text(rest)?)?,
+        _ if name.eq_ignore_ascii_case("ANALYZE") => {
+            let mut pager = context.db.snapshot()?;
+            let target = Some(rest.trim_end_matches(';').trim()).filter(|t| !t.is_empty());
+            let result = QueryResult {
+                columns: ["tbl", "idx", "stat"].map(String::from).to_vec(),
+                rows: stat::analyze(&mut pager, target, &settings)?,
+            };
+            print_result(context.formatter.as_mut(), out, &result)?;
+        }
+        _ if name.eq_ignore_ascii_case("PRAGMA") => {
+            let mut pager = context.db.snapshot()?;
+            let result = pragma::pragma(&mut pager, command)?;
+            print_result(context.formatter.as_mut(), out, &result)?;
+        }
+        _ => {
+            let session = Session::load(db_path).ok();
+            run_sql(context, out, command, session.as_ref())?;
         }
-        i += 1;
     }
 
-    (result, i)
+    Ok(())
 }
 
-fn serial_type_size(serial: u64) -> usize {
-    match serial {
-        0 => 0,
-        1 => 1,
-        2 => 2,
-        3 => 3,
-        4 => 4,
-        5 => 6,
-        6 => 8,
-        7 => 8,
-        8 => 0,
-        9 => 0,
-        10 | 11 => 0,
-        s if s >= 12 && s % 2 == 0 => ((s - 12) / 2) as usize,
-        s if s >= 13 && s % 2 == 1 => ((s - 13) / 2) as usize,
-        _ => 0,
+/// A dot-command's arguments, split at whitespace as sqlite3 splits them:
+/// text in single quotes is taken as is, and text in double quotes may
+/// hold `\t`, `\n`, `\r`, `\"` and `\\` escapes.
+fn dot_args(rest: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut arg = String::new();
+        match c {
+            '\'' => {
+                chars.next();
+                arg.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('t') => arg.push('\t'),
+                            Some('n') => arg.push('\n'),
+                            Some('r') => arg.push('\r'),
+                            Some(c) => arg.push(c),
+                            None => arg.push('\\'),
+                        },
+                        c => arg.push(c),
+                    }
+                }
+            }
+            _ => {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    arg.push(c);
+                }
+            }
+        }
+        args.push(arg);
     }
+    args
 }
 
-struct SchemaRow {
-    tbl_name: String,
-    rootpage: u32,
-    sql: String,
-}
-
-fn parse_table_name(query: &str) -> String {
-    let parts: Vec<&str> = query.split_whitespace().collect();
-    let last = parts.last().unwrap_or(&"");
-    last.trim_end_matches(';').to_string()
+/// Result rows together with the name of each column.
+struct QueryResult {
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
 }
 
-fn count_rows_in_table(file: &mut File, table_name: &str) -> Result<usize> {
-    file.seek(SeekFrom::Start(0))?;
-    let mut header = [0u8; 100];
-    file.read_exact(&mut header)?;
-    let page_size = u16::from_be_bytes([header[16], header[17]]) as usize;
-
-    let mut page = vec![0u8; page_size];
-    page[..100].copy_from_slice(&header);
-    file.read_exact(&mut page[100..])?;
-
-    let page_header_offset = 100;
-    let cell_count =
-        u16::from_be_bytes([page[page_header_offset + 3], page[page_header_offset + 4]]) as usize;
-    let cell_ptr_array_offset = page_header_offset + 8;
-
-    let mut rootpage: Option<u32> = None;
-
-    for i in 0..cell_count {
-        let idx = cell_ptr_array_offset + i * 2;
-        let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-        let row = extract_schema_row_from_cell(&page, cell_offset)?;
-        if row.tbl_name == table_name {
-            rootpage = Some(row.rootpage);
-            break;
+/// Runs a query and prints its rows, or with `why` set, the trace of that
+/// rowid through the query instead.
+fn run_sql(
+    context: &mut Context,
+    out: &mut dyn Write,
+    command: &str,
+    session: Option<&Session>,
+) -> Result<()> {
+    let settings = &context.db.settings().clone();
+    let mut pager = context.db.snapshot()?;
+    let statement = prepare(command, context.params.as_ref(), session)?;
+    if let Some(rowid) = context.why {
+        for line in statement.why(&mut pager, rowid, settings)? {
+            writeln!(out, "{}", line)?;
         }
+        return Ok(());
     }
+    let compiled = statement.compile(&mut pager, settings)?;
+    context.db.remember(&pager);
+    let mut rows = compiled.run(&mut pager, settings)?;
+    let columns = rows.columns().to_vec();
+    if settings.max_rows.is_some() {
+        // Held back so that a result over the limit fails before any of it
+        // is printed; the limit bounds what is held.
+        let rows = rows.collect::<Result<Vec<_>>>()?;
+        output::write_rows(context.formatter.as_mut(), out, &columns, &rows)
+    } else {
+        output::write_row_stream(context.formatter.as_mut(), out, &columns, &mut rows)
+    }
+}
 
-    let rootpage = match rootpage {
-        Some(r) => r,
-        None => bail!("table not found"),
-    };
-
-    let mut count = 0usize;
-    scan_table_btree_count(file, rootpage, page_size, &mut count)?;
-    Ok(count)
+/// Parses a statement and fills in its variables, from `--params` first and
+/// then from the session.
+fn prepare(command: &str, params: Option<&Params>, session: Option<&Session>) -> Result<Statement> {
+    let mut statement = Statement::prepare(command)?;
+    statement.bind(&|name| {
+        params
+            .and_then(|p| p.get(name))
+            .or_else(|| session.and_then(|s| s.var(name)))
+    })?;
+    Ok(statement)
 }
 
-fn scan_table_btree_count(
-    file: &mut File,
-    page_no: u32,
-    page_size: usize,
-    count: &mut usize,
+fn print_result(
+    formatter: &mut dyn OutputFormatter,
+    out: &mut dyn Write,
+    result: &QueryResult,
 ) -> Result<()> {
-    let page_start: u64 = (page_no as u64 - 1) * page_size as u64;
-    file.seek(SeekFrom::Start(page_start))?;
-    let mut page = vec![0u8; page_size];
-    file.read_exact(&mut page)?;
+    output::write_rows(formatter, out, &result.columns, &result.rows)
+}
 
-    let header_offset = if page_no == 1 { 100 } else { 0 };
-    let page_type = page[header_offset];
-    let cell_count =
-        u16::from_be_bytes([page[header_offset + 3], page[header_offset + 4]]) as usize;
+fn format_row(row: &[Value]) -> String {
+    let line: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+    line.join("|")
+}
 
-    if page_type == 0x0D {
-        *count += cell_count;
-    } else if page_type == 0x05 {
-        let right_child = u32::from_be_bytes([
-            page[header_offset + 8],
-            page[header_offset + 9],
-            page[header_offset + 10],
-            page[header_offset + 11],
-        ]);
-        let cell_ptr_array_offset = header_offset + 12;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-            let child_page = u32::from_be_bytes([
-                page[cell_offset],
-                page[cell_offset + 1],
-                page[cell_offset + 2],
-                page[cell_offset + 3],
-            ]);
-            scan_table_btree_count(file, child_page, page_size, count)?;
-        }
-        scan_table_btree_count(file, right_child, page_size, count)?;
+/// Prints the header fields and schema summary in the layout of sqlite3's
+/// `.dbinfo`.
+fn print_dbinfo(pager: &mut Pager, out: &mut dyn Write) -> Result<()> {
+    let header = pager.header()?;
+    let freelist = freelist::read_freelist(pager, &header)?;
+    let schema = read_schema_rows(pager)?;
+    let count = |kind: &str| schema.iter().filter(|r| r.kind == kind).count();
+    let encoding = match header.text_encoding_code {
+        1 => "1 (utf8)".to_string(),
+        2 => "2 (utf16le)".to_string(),
+        3 => "3 (utf16be)".to_string(),
+        n => n.to_string(),
+    };
+    let schema_size: usize = schema.iter().map(|r| r.sql.chars().count()).sum();
+
+    let fields: [(&str, String); 21] = [
+        ("database page size:", header.page_size.to_string()),
+        ("write format:", header.write_version.to_string()),
+        ("read format:", header.read_version.to_string()),
+        ("reserved bytes:", header.reserved_bytes.to_string()),
+        (
+            "file change counter:",
+            header.file_change_counter.to_string(),
+        ),
+        ("database page count:", header.page_count.to_string()),
+        ("freelist page count:", freelist.page_count().to_string()),
+        ("schema cookie:", header.schema_cookie.to_string()),
+        ("schema format:", header.schema_format.to_string()),
+        ("default cache size:", header.default_cache_size.to_string()),
+        ("autovacuum top root:", header.largest_root_page.to_string()),
+        ("incremental vacuum:", header.incremental_vacuum.to_string()),
+        ("text encoding:", encoding),
+        ("user version:", header.user_version.to_string()),
+        ("application id:", header.application_id.to_string()),
+        ("software version:", header.software_version.to_string()),
+        ("number of tables:", count("table").to_string()),
+        ("number of indexes:", count("index").to_string()),
+        ("number of triggers:", count("trigger").to_string()),
+        ("number of views:", count("view").to_string()),
+        ("schema size:", schema_size.to_string()),
+    ];
+    for (label, value) in fields {
+        writeln!(out, "{:<21}{}", label, value)?;
     }
-
+    // sqlite3 prints this label without a colon. Its pager starts at the
+    // default 4096-byte page size and counts switching to the file's own
+    // size as a change, so any other page size reports 2.
+    let data_version = if header.page_size == 4096 { 1 } else { 2 };
+    writeln!(out, "{:<21}{}", "data version", data_version)?;
     Ok(())
 }
 
-fn extract_schema_row_from_cell(page: &[u8], cell_offset: usize) -> Result<SchemaRow> {
-    let (_payload_size, len1) = read_varint(page, cell_offset);
-    let (_rowid, len2) = read_varint(page, cell_offset + len1);
-    let header_start = cell_offset + len1 + len2;
-    let (header_size, len3) = read_varint(page, header_start);
-    let mut header_pos = header_start + len3;
+/// The tables and views `.tables` lists, sorted: those whose names match
+/// the LIKE `pattern`, if given, or only the views with `views_only`.
+/// SQLite's internal `sqlite_` tables are left out unless the pattern asks
+/// for them by name.
+fn read_table_names(
+    pager: &mut Pager,
+    pattern: Option<&str>,
+    views_only: bool,
+) -> Result<Vec<String>> {
+    let internal = pattern.is_some_and(|p| {
+        p.get(..7)
+            .is_some_and(|p| p.eq_ignore_ascii_case("sqlite_"))
+    });
+    let mut names: Vec<String> = read_schema_rows(pager)?
+        .into_iter()
+        .filter(|r| r.kind == "view" || (r.kind == "table" && !views_only))
+        .filter(|r| internal || !r.name.starts_with("sqlite_"))
+        .filter(|r| pattern.is_none_or(|p| like_match(p, &r.name)))
+        .map(|r| r.name)
+        .collect();
+    names.sort();
+    Ok(names)
+}
 
-    let mut serials = [0u64; 5];
-    for i in 0..5 {
-        let (st, l) = read_varint(page, header_pos);
-        serials[i] = st;
-        header_pos += l;
-    }
-
-    let body_start = header_start + header_size as usize;
-    let mut body_pos = body_start;
-
-    let mut tbl_name = String::new();
-    let mut rootpage: u32 = 0;
-    let mut sql = String::new();
-
-    for col in 0..5 {
-        let size = serial_type_size(serials[col]);
-        if col == 2 {
-            let bytes = &page[body_pos..body_pos + size];
-            tbl_name = String::from_utf8(bytes.to_vec())?;
-        } else if col == 3 {
-            let bytes = &page[body_pos..body_pos + size];
-            let mut v: u64 = 0;
-            for b in bytes {
-                v = (v << 8) | (*b as u64);
-            }
-            rootpage = v as u32;
-        } else if col == 4 {
-            let bytes = &page[body_pos..body_pos + size];
-            sql = String::from_utf8(bytes.to_vec())?;
+/// Names laid out as sqlite3's `.tables` prints them: down columns as wide
+/// as the longest name, two spaces apart, as many as fit in 80 characters.
+fn name_columns(names: &[String]) -> String {
+    let width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+    let columns = (80 / (width + 2)).max(1);
+    let rows = names.len().div_ceil(columns);
+    let mut out = String::new();
+    for row in 0..rows {
+        for (i, name) in names.iter().enumerate().skip(row).step_by(rows) {
+            let gap = if i < rows { "" } else { "  " };
+            out.push_str(&format!("{}{:<width$}", gap, name, width = width));
         }
-        body_pos += size;
+        out.push('\n');
     }
-
-    Ok(SchemaRow { tbl_name, rootpage, sql })
+    out
 }
 
-fn parse_select_columns_query(query: &str) -> (Vec<String>, String) {
-    let upper = query.to_uppercase();
-    let select_pos = upper.find("SELECT").unwrap_or(0);
-    let from_pos = upper.find("FROM").unwrap_or(query.len());
-    let cols_part = &query[select_pos + 6..from_pos];
-    let cols: Vec<String> = cols_part
-        .split(',')
-        .map(|c| c.trim().trim_end_matches(',').to_string())
-        .filter(|c| !c.is_empty())
-        .collect();
-
-    let after_from = &query[from_pos + 4..];
-    let table = after_from
-        .split_whitespace()
-        .next()
-        .unwrap_or("")
-        .trim_end_matches(';')
-        .to_string();
-
-    (cols, table)
+#[derive(Clone)]
+pub struct SchemaRow {
+    kind: String,
+    name: String,
+    tbl_name: String,
+    rootpage: u32,
+    sql: String,
 }
 
-fn parse_select_columns_where_query(query: &str) -> (Vec<String>, String, String, String) {
-    let upper = query.to_uppercase();
-    let select_pos = upper.find("SELECT").unwrap_or(0);
-    let from_pos = upper.find("FROM").unwrap_or(query.len());
-    let where_pos = upper.find("WHERE").unwrap_or(query.len());
-
-    let cols_part = &query[select_pos + 6..from_pos];
-    let cols: Vec<String> = cols_part
-        .split(',')
-        .map(|c| c.trim().trim_end_matches(',').to_string())
-        .filter(|c| !c.is_empty())
-        .collect();
-
-    let table_part = if where_pos < query.len() {
-        &query[from_pos + 4..where_pos]
-    } else {
-        &query[from_pos + 4..]
-    };
-    let table = table_part
-        .split_whitespace()
-        .next()
-        .unwrap_or("")
-        .trim_end_matches(';')
-        .to_string();
-
-    let where_part = if where_pos < query.len() {
-        &query[where_pos + 5..]
-    } else {
-        ""
-    };
+/// Every row of sqlite_schema, in storage order: the whole b-tree rooted at
+/// page 1, read like any table, including records that spill onto overflow
+/// pages as a long CREATE statement's can. The pager keeps them, so the
+/// schema is read once a snapshot.
+fn read_schema_rows(pager: &mut Pager) -> Result<Vec<SchemaRow>> {
+    if let Some(rows) = pager.schema() {
+        return Ok(rows.to_vec());
+    }
+    let header = pager.header()?;
+    let mut rows = Vec::new();
+    collect_schema_rows(pager, &header, 1, 0, &mut rows)?;
+    pager.cache_schema(rows.clone());
+    Ok(rows)
+}
 
-    let mut where_col = String::new();
-    let mut where_val = String::new();
-
-    if !where_part.trim().is_empty() {
-        let eq_parts: Vec<&str> = where_part.splitn(2, '=').collect();
-        if eq_parts.len() == 2 {
-            where_col = eq_parts[0].trim().to_string();
-            let mut v = eq_parts[1].trim().trim_end_matches(';').trim().to_string();
-            if v.starts_with('\'') && v.ends_with('\'') && v.len() >= 2 {
-                v = v[1..v.len() - 1].to_string();
-            } else if v.starts_with('"') && v.ends_with('"') && v.len() >= 2 {
-                v = v[1..v.len() - 1].to_string();
+fn collect_schema_rows(
+    pager: &mut Pager,
+    header: &DbHeader,
+    page_no: u32,
+    depth: usize,
+    rows: &mut Vec<SchemaRow>,
+) -> Result<()> {
+    let page = pager.read_page(page_no, header)?;
+    let page = PageBytes::new(page_no, &page[..header.usable_size()]);
+    if depth > MAX_DEPTH {
+        return Err(page.corrupt("b-tree is deeper than any SQLite writes"));
+    }
+    let cell_count = page.cell_count()?;
+    match page.page_type()? {
+        0x05 => {
+            for i in 0..=cell_count {
+                collect_schema_rows(pager, header, page.child(i)?, depth + 1, rows)?;
             }
-            where_val = v;
+        }
+        0x0D => {
+            for i in 0..cell_count {
+                rows.push(extract_schema_row_from_cell(
+                    pager,
+                    header,
+                    page,
+                    page.cell_offset(i)?,
+                )?);
+            }
+        }
+        page_type => {
+            return Err(page.corrupt(format!("page type {:#04x} in a table b-tree", page_type)));
         }
     }
-
-    (cols, table, where_col, where_val)
+    Ok(())
 }
 
-fn select_column_from_table(file: &mut File, table_name: &str, column_name: &str) -> Result<Vec<String>> {
-    file.seek(SeekFrom::Start(0))?;
-    let mut header = [0u8; 100];
-    file.read_exact(&mut header)?;
-    let page_size = u16::from_be_bytes([header[16], header[17]]) as usize;
-
-    let mut page = vec![0u8; page_size];
-    page[..100].copy_from_slice(&header);
-    file.read_exact(&mut page[100..])?;
-
-    let page_header_offset = 100;
-    let cell_count =
-        u16::from_be_bytes([page[page_header_offset + 3], page[page_header_offset + 4]]) as usize;
-    let cell_ptr_array_offset = page_header_offset + 8;
-
-    let mut target_row: Option<SchemaRow> = None;
-    for i in 0..cell_count {
-        let idx = cell_ptr_array_offset + i * 2;
-        let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-        let row = extract_schema_row_from_cell(&page, cell_offset)?;
-        if row.tbl_name == table_name {
-            target_row = Some(row);
-            break;
+/// Finds a table's schema row, matching the name case-insensitively, and
+/// parses its definition. Tables the reader can't scan are rejected here.
+fn find_table_schema(pager: &mut Pager, table_name: &str) -> Result<(SchemaRow, TableSchema)> {
+    for row in read_schema_rows(pager)? {
+        if row.tbl_name.eq_ignore_ascii_case(table_name)
+            && (row.kind == "table" || row.kind == "view")
+        {
+            check_table(&row.kind, &row.tbl_name, &row.sql)?;
+            let definition = parse_create_table(&row.sql)?;
+            check_definition(&definition)?;
+            return Ok((row, definition));
         }
     }
-
-    let schema_row = match target_row {
-        Some(r) => r,
-        None => bail!("table not found"),
-    };
-
-    let col_index = get_column_index_from_sql(&schema_row.sql, column_name)?;
-
-    let rows = scan_table_btree_column(file, schema_row.rootpage, page_size, col_index)?;
-    Ok(rows)
+    Err(RqliteError::TableNotFound {
+        name: table_name.to_string(),
+    }
+    .into())
 }
 
-fn scan_table_btree_column(
-    file: &mut File,
+fn scan_table_btree_count(
+    pager: &mut Pager,
     page_no: u32,
-    page_size: usize,
-    col_index: usize,
-) -> Result<Vec<String>> {
-    let page_start: u64 = (page_no as u64 - 1) * page_size as u64;
-    file.seek(SeekFrom::Start(page_start))?;
-    let mut page = vec![0u8; page_size];
-    file.read_exact(&mut page)?;
-
-    let header_offset = if page_no == 1 { 100 } else { 0 };
-    let page_type = page[header_offset];
-    let cell_count =
-        u16::from_be_bytes([page[header_offset + 3], page[header_offset + 4]]) as usize;
+    header: &DbHeader,
+    count: &mut usize,
+) -> Result<()> {
+    count_table_cells(pager, page_no, header, count, 0)
+}
 
-    let mut result = Vec::new();
+fn count_table_cells(
+    pager: &mut Pager,
+    page_no: u32,
+    header: &DbHeader,
+    count: &mut usize,
+    depth: usize,
+) -> Result<()> {
+    let page = pager.read_page(page_no, header)?;
+    let page = PageBytes::new(page_no, &page[..header.usable_size()]);
+    if depth > MAX_DEPTH {
+        return Err(page.corrupt("b-tree is deeper than any SQLite writes"));
+    }
 
+    let page_type = page.page_type()?;
+    let cell_count = page.cell_count()?;
     if page_type == 0x0D {
-        let cell_ptr_array_offset = header_offset + 8;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-            if let Some(val) = extract_column_from_table_cell(&page, cell_offset, col_index)? {
-                result.push(val);
-            }
-        }
+        *count += cell_count;
     } else if page_type == 0x05 {
-        let right_child = u32::from_be_bytes([
-            page[header_offset + 8],
-            page[header_offset + 9],
-            page[header_offset + 10],
-            page[header_offset + 11],
-        ]);
-        let cell_ptr_array_offset = header_offset + 12;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-            let child_page = u32::from_be_bytes([
-                page[cell_offset],
-                page[cell_offset + 1],
-                page[cell_offset + 2],
-                page[cell_offset + 3],
-            ]);
-            let mut child_vals =
-                scan_table_btree_column(file, child_page, page_size, col_index)?;
-            result.append(&mut child_vals);
-        }
-        let mut right_vals = scan_table_btree_column(file, right_child, page_size, col_index)?;
-        result.append(&mut right_vals);
-    }
-
-    Ok(result)
-}
-
-fn select_columns_from_table(
-    file: &mut File,
-    table_name: &str,
-    columns: &[String],
-) -> Result<Vec<Vec<String>>> {
-    file.seek(SeekFrom::Start(0))?;
-    let mut header = [0u8; 100];
-    file.read_exact(&mut header)?;
-    let page_size = u16::from_be_bytes([header[16], header[17]]) as usize;
-
-    let mut page = vec![0u8; page_size];
-    page[..100].copy_from_slice(&header);
-    file.read_exact(&mut page[100..])?;
-
-    let page_header_offset = 100;
-    let cell_count =
-        u16::from_be_bytes([page[page_header_offset + 3], page[page_header_offset + 4]]) as usize;
-    let cell_ptr_array_offset = page_header_offset + 8;
-
-    let mut target_row: Option<SchemaRow> = None;
-    for i in 0..cell_count {
-        let idx = cell_ptr_array_offset + i * 2;
-        let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-        let row = extract_schema_row_from_cell(&page, cell_offset)?;
-        if row.tbl_name == table_name {
-            target_row = Some(row);
-            break;
+        for i in 0..=cell_count {
+            count_table_cells(pager, page.child(i)?, header, count, depth + 1)?;
         }
+    } else {
+        return Err(page.corrupt(format!("page type {:#04x} in a table b-tree", page_type)));
     }
 
-    let schema_row = match target_row {
-        Some(r) => r,
-        None => bail!("table not found"),
-    };
+    Ok(())
+}
 
-    let mut indexes = Vec::new();
-    for col in columns {
-        let idx = get_column_index_from_sql(&schema_row.sql, col)?;
-        indexes.push(idx);
+fn extract_schema_row_from_cell(
+    pager: &mut Pager,
+    header: &DbHeader,
+    page: PageBytes,
+    cell_offset: usize,
+) -> Result<SchemaRow> {
+    let (payload_size, len1) = page.varint(cell_offset)?;
+    let (_rowid, len2) = page.varint(cell_offset + len1)?;
+    let record = cell_payload(pager, header, page, cell_offset + len1 + len2, payload_size)?;
+    let fields = record_fields(page, &record)?;
+    let mut values = Vec::with_capacity(5);
+    for i in 0..5 {
+        values.push(match fields.get(i) {
+            Some(&(serial, bytes)) => decode_value(serial, bytes, header.text_encoding, false)?,
+            None => Value::Null,
+        });
     }
+    // Views and triggers have root page 0, and automatic indexes no SQL.
+    let text = |v: &Value| match v {
+        Value::Null => String::new(),
+        v => v.to_string(),
+    };
+    Ok(SchemaRow {
+        kind: text(&values[0]),
+        name: text(&values[1]),
+        tbl_name: text(&values[2]),
+        rootpage: match values[3] {
+            Value::Integer(n) => n as u32,
+            _ => 0,
+        },
+        sql: text(&values[4]),
+    })
+}
 
-    let rows = scan_table_btree_all_columns(file, schema_row.rootpage, page_size, &indexes)?;
-    Ok(rows)
+/// Rows produced one at a time, each read only when asked for.
+type RowIter<'a> = Box<dyn Iterator<Item = Result<Vec<Value>>> + 'a>;
+
+fn scan_table_btree_all_columns(
+    pager: &mut Pager,
+    page_no: u32,
+    header: &DbHeader,
+    indexes: &[ColumnSource],
+    settings: &Settings,
+) -> Result<Vec<Vec<Value>>> {
+    TableCursor::new(pager, page_no, header, indexes, settings)?.collect()
 }
 
-fn select_columns_from_table_where(
-    file: &mut File,
-    table_name: &str,
-    columns: &[String],
-    where_col: &str,
-    where_val: &str,
-) -> Result<Vec<Vec<String>>> {
-    file.seek(SeekFrom::Start(0))?;
-    let mut header = [0u8; 100];
-    file.read_exact(&mut header)?;
-    let page_size = u16::from_be_bytes([header[16], header[17]]) as usize;
-
-    let mut page = vec![0u8; page_size];
-    page[..100].copy_from_slice(&header);
-    file.read_exact(&mut page[100..])?;
-
-    let page_header_offset = 100;
-    let cell_count =
-        u16::from_be_bytes([page[page_header_offset + 3], page[page_header_offset + 4]]) as usize;
-    let cell_ptr_array_offset = page_header_offset + 8;
-
-    let mut table_row: Option<SchemaRow> = None;
-    let mut index_row: Option<SchemaRow> = None;
-    let where_lower = where_col.to_lowercase();
-
-    for i in 0..cell_count {
-        let idx = cell_ptr_array_offset + i * 2;
-        let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-        let row = extract_schema_row_from_cell(&page, cell_offset)?;
-        if row.tbl_name == table_name {
-            let sql_lower = row.sql.to_lowercase();
-            if sql_lower.contains("create table") && table_row.is_none() {
-                table_row = Some(row);
-            } else if sql_lower.contains("create index")
-                && sql_lower.contains(&where_lower)
-                && index_row.is_none()
-            {
-                index_row = Some(row);
+/// Finds a row by rowid, descending from `page_no` straight to the one leaf
+/// that can hold it. Cells on every page are sorted by rowid, so each page
+/// is binary-searched rather than scanned.
+fn scan_table_btree_for_rowid(
+    pager: &mut Pager,
+    page_no: u32,
+    header: &DbHeader,
+    target_rowid: u64,
+    indexes: &[ColumnSource],
+    settings: &Settings,
+) -> Result<Option<Vec<Value>>> {
+    // Rowids are signed, though stored as varints.
+    let target = target_rowid as i64;
+    let mut page_no = page_no;
+    for _ in 0..=MAX_DEPTH {
+        let page = pager.read_page(page_no, header)?;
+        let page = PageBytes::new(page_no, &page[..header.usable_size()]);
+
+        let page_type = page.page_type()?;
+        let cell_count = page.cell_count()?;
+        let is_leaf = page_type == 0x0D;
+        if !is_leaf && page_type != 0x05 {
+            return Ok(None);
+        }
+        // A leaf cell's rowid follows its payload size; an interior cell's
+        // key follows its 4-byte child pointer.
+        let key = |i: usize| -> Result<i64> {
+            let offset = page.cell_offset(i)?;
+            let key_offset = if is_leaf {
+                offset + page.varint(offset)?.1
+            } else {
+                offset + 4
+            };
+            Ok(page.varint(key_offset)?.0 as i64)
+        };
+
+        // The first cell whose key is at least the target.
+        let (mut lo, mut hi) = (0, cell_count);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if key(mid)? < target {
+                lo = mid + 1;
+            } else {
+                hi = mid;
             }
         }
-    }
-
-    let table_schema = match table_row {
-        Some(r) => r,
-        None => bail!("table not found"),
-    };
 
-    let mut indexes = Vec::new();
-    for col in columns {
-        let idx = get_column_index_from_sql(&table_schema.sql, col)?;
-        indexes.push(idx);
-    }
-    let where_index = get_column_index_from_sql(&table_schema.sql, where_col)?;
-
-    if let Some(index_schema) = index_row {
-        let rowids =
-            scan_index_btree_for_value(file, index_schema.rootpage, page_size, where_val)?;
-        let mut rows = Vec::new();
-        for rid in rowids {
-            if let Some(row_vals) =
-                scan_table_btree_for_rowid(file, table_schema.rootpage, page_size, rid, &indexes)?
-            {
-                rows.push(row_vals);
+        if is_leaf {
+            if lo == cell_count || key(lo)? != target {
+                return Ok(None);
             }
+            return Ok(Some(extract_row_from_table_cell(
+                page,
+                page.cell_offset(lo)?,
+                indexes,
+                header.text_encoding,
+                settings,
+            )?));
         }
-        Ok(rows)
-    } else {
-        let rows = scan_table_btree_where(
-            file,
-            table_schema.rootpage,
-            page_size,
-            &indexes,
-            where_index,
-            where_val,
-        )?;
-        Ok(rows)
+        // An interior key is the largest rowid in its left subtree; rowids
+        // above every key are under the right child.
+        page_no = page.child(lo)?;
     }
+    Err(corrupt(page_no, "b-tree is deeper than any SQLite writes"))
 }
 
-fn scan_table_btree_where(
-    file: &mut File,
-    page_no: u32,
-    page_size: usize,
-    indexes: &[usize],
-    where_index: usize,
-    where_val: &str,
-) -> Result<Vec<Vec<String>>> {
-    let page_start: u64 = (page_no as u64 - 1) * page_size as u64;
-    file.seek(SeekFrom::Start(page_start))?;
-    let mut page = vec![0u8; page_size];
-    file.read_exact(&mut page)?;
-
-    let header_offset = if page_no == 1 { 100 } else { 0 };
-    let page_type = page[header_offset];
-    let cell_count =
-        u16::from_be_bytes([page[header_offset + 3], page[header_offset + 4]]) as usize;
+/// The index entries whose first key equals `target_val` under the index's
+/// collation, in index order. Keys are stored with the column's affinity
+/// applied, so the caller applies it to the value first (see
+/// `planner::KeyTerm`). The search descends only into the subtrees whose
+/// key range can hold the target.
+fn scan_index_btree_for_value(
+    pager: &mut Pager,
+    index: &KeyIndex,
+    header: &DbHeader,
+    target_val: &Value,
+    settings: &Settings,
+) -> Result<Vec<Vec<Value>>> {
+    let mut entries = Vec::new();
+    let bounds = KeyBounds {
+        lower: target_val,
+        upper: Some(target_val),
+        descending: index.descending,
+        collation: index.collation,
+    };
+    scan_index_range(pager, index.root, header, &bounds, settings, &mut entries)?;
+    Ok(entries)
+}
 
-    let mut rows = Vec::new();
+/// Inclusive bounds on an index's first key (no upper bound if None), and
+/// how the index orders its keys.
+struct KeyBounds<'v> {
+    lower: &'v Value,
+    upper: Option<&'v Value>,
+    descending: bool,
+    collation: Collation,
+}
 
-    if page_type == 0x0D {
-        let cell_ptr_array_offset = header_offset + 8;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-
-            let where_v = extract_column_from_table_cell(&page, cell_offset, where_index)?;
-            if let Some(ref w) = where_v {
-                if w == where_val {
-                    let mut row_vals = Vec::new();
-                    for &col_idx in indexes {
-                        let v = extract_column_from_table_cell(&page, cell_offset, col_idx)?;
-                        row_vals.push(v.unwrap_or_default());
-                    }
-                    rows.push(row_vals);
-                }
-            }
+impl KeyBounds<'_> {
+    /// Whether `key` comes before every key in bounds, in index order.
+    fn before(&self, key: &Value) -> bool {
+        let compare = |bound| self.collation.compare(key, bound);
+        if self.descending {
+            self.upper.is_some_and(|u| compare(u) == Ordering::Greater)
+        } else {
+            compare(self.lower) == Ordering::Less
         }
-    } else if page_type == 0x05 {
-        let right_child = u32::from_be_bytes([
-            page[header_offset + 8],
-            page[header_offset + 9],
-            page[header_offset + 10],
-            page[header_offset + 11],
-        ]);
-        let cell_ptr_array_offset = header_offset + 12;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-            let child_page = u32::from_be_bytes([
-                page[cell_offset],
-                page[cell_offset + 1],
-                page[cell_offset + 2],
-                page[cell_offset + 3],
-            ]);
-            let mut child_rows = scan_table_btree_where(
-                file,
-                child_page,
-                page_size,
-                indexes,
-                where_index,
-                where_val,
-            )?;
-            rows.append(&mut child_rows);
+    }
+
+    /// Whether `key` comes after every key in bounds, in index order.
+    fn after(&self, key: &Value) -> bool {
+        let compare = |bound| self.collation.compare(key, bound);
+        if self.descending {
+            compare(self.lower) == Ordering::Less
+        } else {
+            self.upper.is_some_and(|u| compare(u) == Ordering::Greater)
         }
-        let mut right_rows =
-            scan_table_btree_where(file, right_child, page_size, indexes, where_index, where_val)?;
-        rows.append(&mut right_rows);
     }
+}
 
-    Ok(rows)
+/// Index cells, leaf or interior, each hold one entry; an interior cell also
+/// points to the subtree of entries ordered before it.
+struct IndexPage {
+    no: u32,
+    page: Page,
+    usable_size: usize,
+    is_leaf: bool,
+    cell_count: usize,
 }
 
-fn scan_table_btree_all_columns(
-    file: &mut File,
-    page_no: u32,
-    page_size: usize,
-    indexes: &[usize],
-) -> Result<Vec<Vec<String>>> {
-    let page_start: u64 = (page_no as u64 - 1) * page_size as u64;
-    file.seek(SeekFrom::Start(page_start))?;
-    let mut page = vec![0u8; page_size];
-    file.read_exact(&mut page)?;
-
-    let header_offset = if page_no == 1 { 100 } else { 0 };
-    let page_type = page[header_offset];
-    let cell_count =
-        u16::from_be_bytes([page[header_offset + 3], page[header_offset + 4]]) as usize;
+impl IndexPage {
+    /// Reads page `page_no`, `depth` levels below the root; None if it isn't
+    /// an index page.
+    fn read(
+        pager: &mut Pager,
+        page_no: u32,
+        header: &DbHeader,
+        depth: usize,
+    ) -> Result<Option<IndexPage>> {
+        if depth > MAX_DEPTH {
+            return Err(corrupt(page_no, "b-tree is deeper than any SQLite writes"));
+        }
+        let page = pager.read_page(page_no, header)?;
+        let bytes = PageBytes::new(page_no, &page);
+        let is_leaf = match bytes.page_type()? {
+            0x0A => true,
+            0x02 => false,
+            _ => return Ok(None),
+        };
+        let cell_count = bytes.cell_count()?;
+        Ok(Some(IndexPage {
+            no: page_no,
+            page,
+            usable_size: header.usable_size(),
+            is_leaf,
+            cell_count,
+        }))
+    }
 
-    let mut rows = Vec::new();
+    fn bytes(&self) -> PageBytes<'_> {
+        PageBytes::new(self.no, &self.page[..self.usable_size])
+    }
 
-    if page_type == 0x0D {
-        let cell_ptr_array_offset = header_offset + 8;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-
-            let mut row_vals = Vec::new();
-            for &col_idx in indexes {
-                let v = extract_column_from_table_cell(&page, cell_offset, col_idx)?;
-                row_vals.push(v.unwrap_or_default());
-            }
-            rows.push(row_vals);
-        }
-    } else if page_type == 0x05 {
-        let right_child = u32::from_be_bytes([
-            page[header_offset + 8],
-            page[header_offset + 9],
-            page[header_offset + 10],
-            page[header_offset + 11],
-        ]);
-        let cell_ptr_array_offset = header_offset + 12;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-            let child_page = u32::from_be_bytes([
-                page[cell_offset],
-                page[cell_offset + 1],
-                page[cell_offset + 2],
-                page[cell_offset + 3],
-            ]);
-            let mut child_rows =
-                scan_table_btree_all_columns(file, child_page, page_size, indexes)?;
-            rows.append(&mut child_rows);
-        }
-        let mut right_rows =
-            scan_table_btree_all_columns(file, right_child, page_size, indexes)?;
-        rows.append(&mut right_rows);
+    /// The keys and rowid of cell `i`.
+    fn entry(&self, i: usize, header: &DbHeader, settings: &Settings) -> Result<Vec<Value>> {
+        let skip = if self.is_leaf { 0 } else { 4 };
+        let page = self.bytes();
+        extract_index_entry_from_cell(
+            page,
+            page.cell_offset(i)? + skip,
+            header.text_encoding,
+            settings,
+        )
     }
 
-    Ok(rows)
+    /// The child page left of cell `i`, or the right-most child for
+    /// `i == cell_count`.
+    fn child(&self, i: usize) -> Result<u32> {
+        self.bytes().child(i)
+    }
 }
 
-fn scan_table_btree_for_rowid(
-    file: &mut File,
-    page_no: u32,
-    page_size: usize,
-    target_rowid: u64,
-    indexes: &[usize],
-) -> Result<Option<Vec<String>>> {
-    let page_start: u64 = (page_no as u64 - 1) * page_size as u64;
-    file.seek(SeekFrom::Start(page_start))?;
-    let mut page = vec![0u8; page_size];
-    file.read_exact(&mut page)?;
-
-    let header_offset = if page_no == 1 { 100 } else { 0 };
-    let page_type = page[header_offset];
-    let cell_count =
-        u16::from_be_bytes([page[header_offset + 3], page[header_offset + 4]]) as usize;
-
-    if page_type == 0x0D {
-        let cell_ptr_array_offset = header_offset + 8;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-
-            let (_payload_size, len1) = read_varint(&page, cell_offset);
-            let (rowid, _len2) = read_varint(&page, cell_offset + len1);
-            if rowid == target_rowid {
-                let mut row_vals = Vec::new();
-                for &col_idx in indexes {
-                    let v = extract_column_from_table_cell(&page, cell_offset, col_idx)?;
-                    row_vals.push(v.unwrap_or_default());
-                }
-                return Ok(Some(row_vals));
-            }
+/// Roughly how many entries the b-tree rooted at `page_no` holds, from one
+/// descent down its left edge: each level's fan-out times the leaf's cell
+/// count, as if every page were as full as those.
+fn estimate_entries(pager: &mut Pager, page_no: u32, header: &DbHeader) -> Result<f64> {
+    let mut page_no = page_no;
+    let mut estimate = 1.0;
+    for _ in 0..=MAX_DEPTH {
+        let page = pager.read_page(page_no, header)?;
+        let page = PageBytes::new(page_no, &page[..header.usable_size()]);
+        let cells = page.cell_count()?;
+        if !matches!(page.page_type()?, 0x02 | 0x05) {
+            return Ok(estimate * cells as f64);
         }
-        Ok(None)
-    } else if page_type == 0x05 {
-        let right_child = u32::from_be_bytes([
-            page[header_offset + 8],
-            page[header_offset + 9],
-            page[header_offset + 10],
-            page[header_offset + 11],
-        ]);
-        let cell_ptr_array_offset = header_offset + 12;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-            let child_page = u32::from_be_bytes([
-                page[cell_offset],
-                page[cell_offset + 1],
-                page[cell_offset + 2],
-                page[cell_offset + 3],
-            ]);
-            if let Some(vals) =
-                scan_table_btree_for_rowid(file, child_page, page_size, target_rowid, indexes)?
-            {
-                return Ok(Some(vals));
-            }
-        }
-        if let Some(vals) =
-            scan_table_btree_for_rowid(file, right_child, page_size, target_rowid, indexes)?
-        {
-            return Ok(Some(vals));
-        }
-        Ok(None)
-    } else {
-        Ok(None)
+        estimate *= cells as f64 + 1.0;
+        // Every interior cell starts with its left child's page number; with
+        // no cells, only the right-most child is left.
+        page_no = page.child(0)?;
     }
+    Err(corrupt(page_no, "b-tree is deeper than any SQLite writes"))
 }
 
-fn scan_index_btree_for_value(
-    file: &mut File,
-    page_no: u32,
-    page_size: usize,
-    target_val: &str,
-) -> Result<Vec<u64>> {
-    let page_start: u64 = (page_no as u64 - 1) * page_size as u64;
-    file.seek(SeekFrom::Start(page_start))?;
-    let mut page = vec![0u8; page_size];
-    file.read_exact(&mut page)?;
-
-    let header_offset = if page_no == 1 { 100 } else { 0 };
-    let page_type = page[header_offset];
-    let cell_count =
-        u16::from_be_bytes([page[header_offset + 3], page[header_offset + 4]]) as usize;
-
-    let mut rowids = Vec::new();
-
-    if page_type == 0x0A {
-        let cell_ptr_array_offset = header_offset + 8;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-            let (val, rid) = extract_index_key_and_rowid_from_cell(&page, cell_offset)?;
-            if val == target_val {
-                rowids.push(rid);
+/// The share of an index's entries, from 0 to 1, for which `before` holds
+/// of the first key; it must hold for a prefix of them in index order. One
+/// descent finds it: at each level the cells ahead of the split narrow the
+/// share, as if every subtree held as many entries as its siblings.
+fn index_fraction(
+    pager: &mut Pager,
+    root: u32,
+    header: &DbHeader,
+    settings: &Settings,
+    before: &dyn Fn(&Value) -> bool,
+) -> Result<f64> {
+    let (mut start, mut width) = (0.0, 1.0);
+    let mut page_no = root;
+    let mut depth = 0;
+    loop {
+        let Some(page) = IndexPage::read(pager, page_no, header, depth)? else {
+            return Ok(start);
+        };
+        let (mut lo, mut hi) = (0, page.cell_count);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if before(&page.entry(mid, header, settings)?[0]) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
             }
         }
-    } else if page_type == 0x02 {
-        let right_child = u32::from_be_bytes([
-            page[header_offset + 8],
-            page[header_offset + 9],
-            page[header_offset + 10],
-            page[header_offset + 11],
-        ]);
-        let cell_ptr_array_offset = header_offset + 12;
-        for i in 0..cell_count {
-            let idx = cell_ptr_array_offset + i * 2;
-            let cell_offset = u16::from_be_bytes([page[idx], page[idx + 1]]) as usize;
-            let child_page = u32::from_be_bytes([
-                page[cell_offset],
-                page[cell_offset + 1],
-                page[cell_offset + 2],
-                page[cell_offset + 3],
-            ]);
-            let mut child_rowids =
-                scan_index_btree_for_value(file, child_page, page_size, target_val)?;
-            rowids.append(&mut child_rowids);
-        }
-        let mut right_rowids =
-            scan_index_btree_for_value(file, right_child, page_size, target_val)?;
-        rowids.append(&mut right_rowids);
-    }
-
-    Ok(rowids)
-}
-
-fn extract_index_key_and_rowid_from_cell(
-    page: &[u8],
-    cell_offset: usize,
-) -> Result<(String, u64)> {
-    let (_payload_size, len1) = read_varint(page, cell_offset);
-    let record_start = cell_offset + len1;
-
-    let (header_size, len2) = read_varint(page, record_start);
-    let mut header_pos = record_start + len2;
-
-    let mut serials = Vec::new();
-    while header_pos < record_start + header_size as usize {
-        let (st, l) = read_varint(page, header_pos);
-        serials.push(st);
-        header_pos += l;
-    }
-
-    let body_start = record_start + header_size as usize;
-    let mut body_pos = body_start;
-
-    let mut key = String::new();
-    let mut rowid: u64 = 0;
-
-    for (idx, st) in serials.iter().enumerate() {
-        let size = serial_type_size(*st);
-        if idx == 0 {
-            let bytes = &page[body_pos..body_pos + size];
-            key = String::from_utf8(bytes.to_vec())?;
-        } else if idx == serials.len() - 1 {
-            let bytes = &page[body_pos..body_pos + size];
-            let mut v: u64 = 0;
-            for b in bytes {
-                v = (v << 8) | (*b as u64);
-            }
-            rowid = v;
+        if page.is_leaf {
+            return Ok(start + width * lo as f64 / page.cell_count.max(1) as f64);
         }
-        body_pos += size;
+        // The first `lo` cells and their subtrees come before the split,
+        // which falls inside child `lo`.
+        let slots = (page.cell_count + 1) as f64;
+        start += width * lo as f64 / slots;
+        width /= slots;
+        page_no = page.child(lo)?;
+        depth += 1;
     }
+}
 
-    Ok((key, rowid))
+/// Collects, in index order, the entries with first keys in `bounds`. Only
+/// the pages whose key range overlaps them are visited: the search seeks to
+/// the first key in bounds and stops at the first one after them.
+fn scan_index_range(
+    pager: &mut Pager,
+    page_no: u32,
+    header: &DbHeader,
+    bounds: &KeyBounds,
+    settings: &Settings,
+    entries: &mut Vec<Vec<Value>>,
+) -> Result<()> {
+    scan_index_subtree(pager, page_no, header, bounds, settings, entries, 0)
 }
 
-fn get_column_index_from_sql(sql: &str, column_name: &str) -> Result<usize> {
-    let lower_sql = sql.to_lowercase();
-    let start = match lower_sql.find('(') {
-        Some(i) => i + 1,
-        None => bail!("invalid sql"),
-    };
-    let end = match lower_sql.rfind(')') {
-        Some(i) => i,
-        None => bail!("invalid sql"),
+fn scan_index_subtree(
+    pager: &mut Pager,
+    page_no: u32,
+    header: &DbHeader,
+    bounds: &KeyBounds,
+    settings: &Settings,
+    entries: &mut Vec<Vec<Value>>,
+    depth: usize,
+) -> Result<()> {
+    let Some(page) = IndexPage::read(pager, page_no, header, depth)? else {
+        return Ok(());
     };
-    let cols_str = &sql[start..end];
-    let parts: Vec<&str> = cols_str.split(',').collect();
-    for (i, part) in parts.iter().enumerate() {
-        let trimmed = part.trim();
-        if trimmed.is_empty() {
-            continue;
+    // The first cell whose key is not before the bounds. Everything ahead of
+    // it, and in the subtrees left of it, comes before them too.
+    let (mut lo, mut hi) = (0, page.cell_count);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if bounds.before(&page.entry(mid, header, settings)?[0]) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
         }
-        let mut iter = trimmed.split_whitespace();
-        if let Some(name) = iter.next() {
-            let clean = name.trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']');
-            if clean.eq_ignore_ascii_case(column_name) {
-                return Ok(i);
-            }
+    }
+    for i in lo..=page.cell_count {
+        if !page.is_leaf {
+            let child = page.child(i)?;
+            scan_index_subtree(pager, child, header, bounds, settings, entries, depth + 1)?;
         }
+        if i == page.cell_count {
+            break;
+        }
+        let entry = page.entry(i, header, settings)?;
+        if bounds.after(&entry[0]) {
+            break;
+        }
+        entries.push(entry);
     }
-    bail!("column not found")
+    Ok(())
 }
 
-fn extract_column_from_table_cell(
-    page: &[u8],
+fn extract_index_entry_from_cell(
+    page: PageBytes,
     cell_offset: usize,
-    col_index: usize,
-) -> Result<Option<String>> {
-    let (_payload_size, len1) = read_varint(page, cell_offset);
-    let (rowid, len2) = read_varint(page, cell_offset + len1);
-    let header_start = cell_offset + len1 + len2;
-    let (header_size, len3) = read_varint(page, header_start);
-    let mut header_pos = header_start + len3;
-
-    let mut serials = Vec::new();
-    while header_pos < header_start + header_size as usize {
-        let (st, l) = read_varint(page, header_pos);
-        serials.push(st);
-        header_pos += l;
-    }
-
-    if col_index >= serials.len() {
-        return Ok(None);
-    }
-
-    let body_start = header_start + header_size as usize;
-    let mut body_pos = body_start;
-
-    for (idx, st) in serials.iter().enumerate() {
-        let size = serial_type_size(*st);
-        if idx == col_index {
-            if size == 0 {
-                if col_index == 0 {
-                    return Ok(Some(rowid.to_string()));
-                } else {
-                    return Ok(None);
-                }
-            }
-            let bytes = &page[body_pos..body_pos + size];
-            let s = String::from_utf8(bytes.to_vec())?;
-            return Ok(Some(s));
-        }
-        body_pos += size;
+    encoding: TextEncoding,
+    settings: &Settings,
+) -> Result<Vec<Value>> {
+    let (payload_size, len1) = page.varint(cell_offset)?;
+    check_local_payload(payload_size, (page.bytes.len() - 12) * 64 / 255 - 23)?;
+    let record = page.slice(cell_offset + len1, payload_size as usize)?;
+    let fields = record_fields(page, record)?;
+    if fields.len() < 2 {
+        bail!("malformed index record");
     }
-
-    Ok(None)
+    fields
+        .into_iter()
+        .map(|(serial, bytes)| decode_value(serial, bytes, encoding, settings.lossy_text))
+        .collect()
 }
@@ -0,0 +1,633 @@
+use anyhow::{bail, Result};
+use std::io::Write;
+
+use crate::dump::{quote_name, sql_literal};
+#[cfg(feature = "json-output")]
+use crate::params::encode_base64;
+use crate::settings::Settings;
+use crate::value::Value;
+
+/// Writes a result set in one output format. `begin` and `end` are called
+/// once per result set, even an empty one; `row` once per row.
+pub trait OutputFormatter {
+    fn begin(&mut self, _out: &mut dyn Write, _columns: &[String]) -> Result<()> {
+        Ok(())
+    }
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> Result<()>;
+    fn end(&mut self, _out: &mut dyn Write) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub type FormatterFactory = fn(&Settings) -> Box<dyn OutputFormatter>;
+
+/// Output formats by name. `builtin` holds the formats compiled in (some
+/// behind cargo features); more can be added with `register`.
+pub struct FormatRegistry {
+    formats: Vec<(&'static str, FormatterFactory)>,
+}
+
+impl FormatRegistry {
+    pub fn builtin() -> FormatRegistry {
+        let mut registry = FormatRegistry {
+            formats: Vec::new(),
+        };
+        registry.register("list", |s| Box::new(List(Header::new(s, "|", "\n"))));
+        registry.register("csv", |s| Box::new(Csv(Header::new(s, ",", "\r\n"))));
+        registry.register("tsv", |s| Box::new(List(Header::new(s, "\t", "\n"))));
+        registry.register("tabs", |s| Box::new(List(Header::new(s, "\t", "\n"))));
+        registry.register("column", |s| Box::new(Columns::new(s, false)));
+        registry.register("box", |s| Box::new(Columns::new(s, true)));
+        registry.register("insert", |s| Box::new(Insert::new(s)));
+        #[cfg(feature = "json-output")]
+        registry.register("json", |_| Box::<JsonRows>::default());
+        #[cfg(feature = "markdown-output")]
+        registry.register("markdown", |s| {
+            Box::new(Markdown {
+                null_value: s.null_value.clone(),
+                ..Default::default()
+            })
+        });
+        #[cfg(feature = "html-output")]
+        registry.register("html", |s| Box::new(Html(Header::new(s, "", "\n"))));
+        registry
+    }
+
+    /// Adds a format, replacing any existing one with the same name.
+    pub fn register(&mut self, name: &'static str, factory: FormatterFactory) {
+        self.formats.retain(|(n, _)| *n != name);
+        self.formats.push((name, factory));
+    }
+
+    pub fn create(&self, name: &str, settings: &Settings) -> Result<Box<dyn OutputFormatter>> {
+        match self
+            .formats
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            Some((_, factory)) => Ok(factory(settings)),
+            None => bail!(
+                "unknown output format: {} (available: {})",
+                name,
+                self.names().join(", ")
+            ),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.formats.iter().map(|(n, _)| *n).collect()
+    }
+}
+
+/// Writes a whole result set through `formatter`.
+pub fn write_rows(
+    formatter: &mut dyn OutputFormatter,
+    out: &mut dyn Write,
+    columns: &[String],
+    rows: &[Vec<Value>],
+) -> Result<()> {
+    formatter.begin(out, columns)?;
+    for row in rows {
+        formatter.row(out, row)?;
+    }
+    formatter.end(out)
+}
+
+/// Writes rows through `formatter` as they are produced, so a large result
+/// is never held in memory by the writer (though a format may buffer it).
+pub fn write_row_stream(
+    formatter: &mut dyn OutputFormatter,
+    out: &mut dyn Write,
+    columns: &[String],
+    rows: &mut dyn Iterator<Item = Result<Vec<Value>>>,
+) -> Result<()> {
+    formatter.begin(out, columns)?;
+    for row in rows {
+        formatter.row(out, &row?)?;
+    }
+    formatter.end(out)
+}
+
+/// The text a formatter prints for `value`, with NULL shown as `null_value`.
+fn render(value: &Value, null_value: &str) -> String {
+    match value {
+        Value::Null => null_value.to_string(),
+        v => v.to_string(),
+    }
+}
+
+/// The settings the line-based formats share, and their column names until
+/// the first row, before which they are printed as a header when headers
+/// are on. A result with no rows has no header, as in sqlite3.
+struct Header {
+    null_value: String,
+    /// Between values and after each row: the format's own unless the
+    /// settings give others.
+    separator: String,
+    row_end: String,
+    headers: bool,
+    pending: Option<Vec<String>>,
+}
+
+impl Header {
+    fn new(settings: &Settings, separator: &str, row_end: &str) -> Header {
+        Header {
+            null_value: settings.null_value.clone(),
+            separator: settings
+                .separator
+                .as_deref()
+                .unwrap_or(separator)
+                .to_string(),
+            row_end: settings
+                .row_separator
+                .as_deref()
+                .unwrap_or(row_end)
+                .to_string(),
+            headers: settings.headers,
+            pending: None,
+        }
+    }
+
+    /// Writes one line of fields.
+    fn line(&self, out: &mut dyn Write, fields: &[impl AsRef<[u8]>]) -> Result<()> {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                out.write_all(self.separator.as_bytes())?;
+            }
+            out.write_all(field.as_ref())?;
+        }
+        out.write_all(self.row_end.as_bytes())?;
+        Ok(())
+    }
+
+    fn begin(&mut self, columns: &[String]) {
+        self.pending = self.headers.then(|| columns.to_vec());
+    }
+
+    /// The column names, the first time this is called for a result.
+    fn take(&mut self) -> Option<Vec<String>> {
+        self.pending.take()
+    }
+}
+
+/// sqlite3's default: values separated by `|`, one row per line. With tabs
+/// for the separator it is sqlite3's tabs mode.
+struct List(Header);
+
+impl OutputFormatter for List {
+    fn begin(&mut self, _out: &mut dyn Write, columns: &[String]) -> Result<()> {
+        self.0.begin(columns);
+        Ok(())
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> Result<()> {
+        if let Some(columns) = self.0.take() {
+            self.0.line(out, &columns)?;
+        }
+        let line: Vec<String> = row.iter().map(|v| render(v, &self.0.null_value)).collect();
+        self.0.line(out, &line)
+    }
+}
+
+/// sqlite3's csv mode: comma-separated values, each row ended by CRLF. Text
+/// is quoted, with `"` doubled, when it is empty or holds the separator, a
+/// quote, space, control character or non-ASCII character; numbers and
+/// NULL never are. A blob is written as its raw bytes, quoted by the same
+/// rules, so it reads back byte for byte.
+struct Csv(Header);
+
+impl OutputFormatter for Csv {
+    fn begin(&mut self, _out: &mut dyn Write, columns: &[String]) -> Result<()> {
+        self.0.begin(columns);
+        Ok(())
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> Result<()> {
+        let pending = self.0.take();
+        let separator = &self.0.separator;
+        if let Some(columns) = pending {
+            let names: Vec<Vec<u8>> = columns
+                .iter()
+                .map(|c| csv_quote(c.as_bytes(), separator))
+                .collect();
+            self.0.line(out, &names)?;
+        }
+        let line: Vec<Vec<u8>> = row
+            .iter()
+            .map(|value| match value {
+                Value::Null => self.0.null_value.clone().into_bytes(),
+                Value::Integer(_) | Value::Real(_) => value.to_string().into_bytes(),
+                Value::Text(s) => csv_quote(s.as_bytes(), separator),
+                Value::Blob(b) => csv_quote(b, separator),
+            })
+            .collect();
+        self.0.line(out, &line)
+    }
+}
+
+/// sqlite3's insert mode: each row as an INSERT statement that adds it to
+/// the `insert_table` setting's table, values written as SQL literals that
+/// read back with the same type. With headers on, the statements name the
+/// columns.
+struct Insert {
+    table: String,
+    headers: bool,
+    /// The current result's column list, when headers are on.
+    columns: String,
+}
+
+impl Insert {
+    fn new(settings: &Settings) -> Insert {
+        Insert {
+            table: quote_name(&settings.insert_table),
+            headers: settings.headers,
+            columns: String::new(),
+        }
+    }
+}
+
+impl OutputFormatter for Insert {
+    fn begin(&mut self, _out: &mut dyn Write, columns: &[String]) -> Result<()> {
+        self.columns = if self.headers {
+            let names: Vec<String> = columns.iter().map(|c| quote_name(c)).collect();
+            format!("({})", names.join(","))
+        } else {
+            String::new()
+        };
+        Ok(())
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> Result<()> {
+        let literals: Vec<String> = row.iter().map(sql_literal).collect();
+        writeln!(
+            out,
+            "INSERT INTO {}{} VALUES({});",
+            self.table,
+            self.columns,
+            literals.join(",")
+        )?;
+        Ok(())
+    }
+}
+
+/// `s` as a CSV field. Going by bytes rather than chars, any byte of a
+/// non-ASCII character calls for quotes, as the character does.
+fn csv_quote(s: &[u8], separator: &str) -> Vec<u8> {
+    let separator = separator.as_bytes();
+    let quote = s.is_empty()
+        || (!separator.is_empty() && s.windows(separator.len()).any(|w| w == separator))
+        || s.iter()
+            .any(|&b| matches!(b, b'"' | b'\'' | b' ') || !(b' '..0x7f).contains(&b));
+    if !quote {
+        return s.to_vec();
+    }
+    let mut out = vec![b'"'];
+    for &b in s {
+        if b == b'"' {
+            out.push(b'"');
+        }
+        out.push(b);
+    }
+    out.push(b'"');
+    out
+}
+
+/// sqlite3's column and box modes: values left-aligned in columns as wide as
+/// the widest value or name, a value with newlines taking a line for each.
+/// `column` puts two spaces between columns, with the names and a rule of
+/// dashes first when headers are on; `box` draws Unicode borders and always
+/// shows the names, centred.
+struct Columns {
+    boxed: bool,
+    headers: bool,
+    null_value: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Columns {
+    fn new(settings: &Settings, boxed: bool) -> Columns {
+        Columns {
+            boxed,
+            headers: settings.headers,
+            null_value: settings.null_value.clone(),
+            columns: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+}
+
+impl OutputFormatter for Columns {
+    fn begin(&mut self, _out: &mut dyn Write, columns: &[String]) -> Result<()> {
+        self.columns = columns.to_vec();
+        self.rows.clear();
+        Ok(())
+    }
+
+    fn row(&mut self, _out: &mut dyn Write, row: &[Value]) -> Result<()> {
+        let cells = row.iter().map(|v| render(v, &self.null_value));
+        self.rows.push(cells.collect());
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let width = |s: &str| s.chars().count();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let lines = self.rows.iter().filter_map(|r| r.get(i));
+                let lines = lines.flat_map(|v| v.split('\n'));
+                lines.map(width).fold(width(name), usize::max)
+            })
+            .collect();
+        let pad = |s: &str, w: usize| format!("{}{}", s, " ".repeat(w.saturating_sub(width(s))));
+        // Each line a row takes, its cells padded to their widths.
+        let lines = |row: &[String]| -> Vec<Vec<String>> {
+            let parts: Vec<Vec<&str>> = row.iter().map(|c| c.split('\n').collect()).collect();
+            let height = parts.iter().map(Vec::len).max().unwrap_or(1);
+            (0..height)
+                .map(|n| {
+                    parts
+                        .iter()
+                        .zip(&widths)
+                        .map(|(p, w)| pad(p.get(n).copied().unwrap_or(""), *w))
+                        .collect()
+                })
+                .collect()
+        };
+
+        if !self.boxed {
+            if self.headers {
+                let names: Vec<String> = lines(&self.columns).concat();
+                writeln!(out, "{}", names.join("  "))?;
+                let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+                writeln!(out, "{}", rule.join("  "))?;
+            }
+            for row in &self.rows {
+                for line in lines(row) {
+                    writeln!(out, "{}", line.join("  "))?;
+                }
+            }
+            return Ok(());
+        }
+
+        let rule = |left: &str, middle: &str, right: &str| {
+            let parts: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            format!("{}{}{}", left, parts.join(middle), right)
+        };
+        writeln!(out, "{}", rule("┌", "┬", "┐"))?;
+        let names: Vec<String> = self
+            .columns
+            .iter()
+            .zip(&widths)
+            .map(|(name, w)| {
+                let left = (w - width(name)) / 2;
+                pad(&format!("{}{}", " ".repeat(left), name), *w)
+            })
+            .collect();
+        writeln!(out, "│ {} │", names.join(" │ "))?;
+        writeln!(out, "{}", rule("├", "┼", "┤"))?;
+        for row in &self.rows {
+            for line in lines(row) {
+                writeln!(out, "│ {} │", line.join(" │ "))?;
+            }
+        }
+        writeln!(out, "{}", rule("└", "┴", "┘"))?;
+        Ok(())
+    }
+}
+
+/// sqlite3's `-json` mode: an array with one object per row. Two kinds of
+/// value JSON has no form for are written differently: a blob as
+/// `{"$blob": "<base64>"}`, the form `--params` reads back, and an infinite
+/// or NaN real as `null`.
+#[cfg(feature = "json-output")]
+#[derive(Default)]
+struct JsonRows {
+    columns: Vec<String>,
+    rows: usize,
+}
+
+#[cfg(feature = "json-output")]
+impl OutputFormatter for JsonRows {
+    fn begin(&mut self, _out: &mut dyn Write, columns: &[String]) -> Result<()> {
+        self.columns = columns.to_vec();
+        self.rows = 0;
+        Ok(())
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> Result<()> {
+        write!(out, "{}{{", if self.rows == 0 { "[" } else { ",\n" })?;
+        self.rows += 1;
+        for (i, value) in row.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            let name = self.columns.get(i).map(String::as_str).unwrap_or("");
+            write!(out, "{}:", json_string(name))?;
+            match value {
+                Value::Null => write!(out, "null")?,
+                Value::Real(r) if !r.is_finite() => write!(out, "null")?,
+                Value::Integer(_) | Value::Real(_) => write!(out, "{}", value)?,
+                Value::Text(s) => write!(out, "{}", json_string(s))?,
+                Value::Blob(b) => write!(out, "{{\"$blob\":\"{}\"}}", encode_base64(b))?,
+            }
+        }
+        write!(out, "}}")?;
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> Result<()> {
+        if self.rows > 0 {
+            writeln!(out, "]")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "json-output")]
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A GitHub-flavoured markdown table, laid out like sqlite3's markdown mode:
+/// columns padded to their widest value, headers centred. `|` is escaped and
+/// newlines become `<br>` so that every value stays in its cell.
+#[cfg(feature = "markdown-output")]
+#[derive(Default)]
+struct Markdown {
+    null_value: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+#[cfg(feature = "markdown-output")]
+impl OutputFormatter for Markdown {
+    fn begin(&mut self, _out: &mut dyn Write, columns: &[String]) -> Result<()> {
+        self.columns = columns.iter().map(|c| markdown_escape(c)).collect();
+        self.rows.clear();
+        Ok(())
+    }
+
+    fn row(&mut self, _out: &mut dyn Write, row: &[Value]) -> Result<()> {
+        let cells = row
+            .iter()
+            .map(|v| markdown_escape(&render(v, &self.null_value)));
+        self.rows.push(cells.collect());
+        Ok(())
+    }
+
+    fn end(&mut self, out: &mut dyn Write) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let width = |s: &str| s.chars().count();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let values = self.rows.iter().filter_map(|r| r.get(i));
+                values.map(|v| width(v)).fold(width(name), usize::max)
+            })
+            .collect();
+
+        let mut line = String::from("|");
+        for (name, w) in self.columns.iter().zip(&widths) {
+            let left = (w - width(name)) / 2;
+            let right = w - width(name) - left;
+            line += &format!(" {}{}{} |", " ".repeat(left), name, " ".repeat(right));
+        }
+        writeln!(out, "{}", line)?;
+        let rule: Vec<String> = widths.iter().map(|w| "-".repeat(w + 2)).collect();
+        writeln!(out, "|{}|", rule.join("|"))?;
+        for row in &self.rows {
+            let mut line = String::from("|");
+            for (cell, w) in row.iter().zip(&widths) {
+                line += &format!(" {}{} |", cell, " ".repeat(w - width(cell)));
+            }
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "markdown-output")]
+fn markdown_escape(s: &str) -> String {
+    s.replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+/// sqlite3's html mode: `<TR>` rows for pasting inside a `<TABLE>`, after a
+/// row of `<TH>` names when headers are on.
+#[cfg(feature = "html-output")]
+struct Html(Header);
+
+#[cfg(feature = "html-output")]
+impl OutputFormatter for Html {
+    fn begin(&mut self, _out: &mut dyn Write, columns: &[String]) -> Result<()> {
+        self.0.begin(columns);
+        Ok(())
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> Result<()> {
+        if let Some(columns) = self.0.take() {
+            for (i, name) in columns.iter().enumerate() {
+                let open = if i == 0 { "<TR>" } else { "" };
+                writeln!(out, "{}<TH>{}</TH>", open, html_escape(name))?;
+            }
+            writeln!(out, "</TR>")?;
+        }
+        for (i, value) in row.iter().enumerate() {
+            let open = if i == 0 { "<TR>" } else { "" };
+            writeln!(
+                out,
+                "{}<TD>{}</TD>",
+                open,
+                html_escape(&render(value, &self.0.null_value))
+            )?;
+        }
+        writeln!(out, "</TR>")?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "html-output")]
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(format: &str, row: Vec<Value>) -> Vec<u8> {
+        let mut formatter = FormatRegistry::builtin()
+            .create(format, &Settings::default())
+            .unwrap();
+        let columns: Vec<String> = (0..row.len()).map(|i| format!("c{}", i)).collect();
+        let mut out = Vec::new();
+        write_rows(formatter.as_mut(), &mut out, &columns, &[row]).unwrap();
+        out
+    }
+
+    #[test]
+    fn csv_writes_blobs_byte_for_byte() {
+        let blob = vec![0x00, 0xff, b'"', b'A', 0xc3];
+        let out = output("csv", vec![Value::Blob(blob), Value::Blob(b"AB".to_vec())]);
+        assert_eq!(out, b"\"\x00\xff\"\"A\xc3\",AB\r\n");
+    }
+
+    #[cfg(feature = "json-output")]
+    #[test]
+    fn json_has_a_form_for_every_value() {
+        let out = output(
+            "json",
+            vec![
+                Value::Blob(vec![0x00, 0xff, b'A']),
+                Value::Blob(b"AB".to_vec()),
+                Value::Real(f64::INFINITY),
+                Value::Real(f64::NEG_INFINITY),
+                Value::Real(f64::NAN),
+                Value::Real(1.5),
+            ],
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[{\"c0\":{\"$blob\":\"AP9B\"},\"c1\":{\"$blob\":\"QUI=\"},\"c2\":null,\
+             \"c3\":null,\"c4\":null,\"c5\":1.5}]\n"
+        );
+    }
+}
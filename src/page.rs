@@ -0,0 +1,143 @@
+use anyhow::Result;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::error::corrupt;
+use crate::header::{DbHeader, HEADER_SIZE};
+use crate::record::read_varint;
+
+/// The bytes of one page. Cloning is cheap: a page is shared with the cache,
+/// or with the mapped file in mmap mode, rather than copied.
+#[derive(Clone)]
+pub enum Page {
+    Owned(Rc<[u8]>),
+    #[cfg(feature = "mmap")]
+    Mapped {
+        map: Rc<memmap2::Mmap>,
+        start: usize,
+        len: usize,
+    },
+}
+
+impl Deref for Page {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Page::Owned(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Page::Mapped { map, start, len } => &map[*start..*start + *len],
+        }
+    }
+}
+
+/// How many levels a b-tree may have, as in SQLite. A deeper descent means
+/// the child pointers form a cycle.
+pub const MAX_DEPTH: usize = 20;
+
+/// One page's bytes, read with bounds checks. Offsets and sizes on a page
+/// come from the file itself, so any of them may be wrong: a read outside
+/// the page fails with `RqliteError::Corrupt` naming it rather than panicking.
+#[derive(Clone, Copy)]
+pub struct PageBytes<'a> {
+    pub no: u32,
+    pub bytes: &'a [u8],
+}
+
+impl<'a> PageBytes<'a> {
+    pub fn new(no: u32, bytes: &'a [u8]) -> PageBytes<'a> {
+        PageBytes { no, bytes }
+    }
+
+    pub fn corrupt(&self, detail: impl Into<String>) -> anyhow::Error {
+        corrupt(self.no, detail)
+    }
+
+    pub fn slice(&self, at: usize, len: usize) -> Result<&'a [u8]> {
+        at.checked_add(len)
+            .and_then(|end| self.bytes.get(at..end))
+            .ok_or_else(|| {
+                self.corrupt(format!("{} bytes at offset {} run past the page", len, at))
+            })
+    }
+
+    pub fn u8(&self, at: usize) -> Result<u8> {
+        Ok(self.slice(at, 1)?[0])
+    }
+
+    pub fn u16(&self, at: usize) -> Result<u16> {
+        let b = self.slice(at, 2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&self, at: usize) -> Result<u32> {
+        let b = self.slice(at, 4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// The varint at `at` and its length in bytes.
+    pub fn varint(&self, at: usize) -> Result<(u64, usize)> {
+        read_varint(self.bytes, at)
+            .ok_or_else(|| self.corrupt(format!("varint at offset {} runs past the page", at)))
+    }
+
+    /// Where the b-tree page header starts: after the database header on
+    /// page 1.
+    pub fn header_offset(&self) -> usize {
+        if self.no == 1 {
+            HEADER_SIZE
+        } else {
+            0
+        }
+    }
+
+    pub fn page_type(&self) -> Result<u8> {
+        self.u8(self.header_offset())
+    }
+
+    /// The number of cells, which the cell pointer array must have room
+    /// for.
+    pub fn cell_count(&self) -> Result<usize> {
+        let count = self.u16(self.header_offset() + 3)? as usize;
+        if self.cell_pointers()? + 2 * count > self.bytes.len() {
+            return Err(self.corrupt(format!("{} cells don't fit in the page", count)));
+        }
+        Ok(count)
+    }
+
+    /// Where the cell pointer array starts: after the 8-byte header of a
+    /// leaf or the 12-byte header of an interior page.
+    fn cell_pointers(&self) -> Result<usize> {
+        let interior = matches!(self.page_type()?, 0x02 | 0x05);
+        Ok(self.header_offset() + if interior { 12 } else { 8 })
+    }
+
+    /// The offset of cell `i`, from the cell pointer array.
+    pub fn cell_offset(&self, i: usize) -> Result<usize> {
+        let array = self.cell_pointers()?;
+        let offset = self.u16(array + i * 2)? as usize;
+        if offset < array || offset >= self.bytes.len() {
+            return Err(self.corrupt(format!(
+                "cell {} at offset {} is outside the page",
+                i, offset
+            )));
+        }
+        Ok(offset)
+    }
+
+    /// The child page left of cell `i` on an interior page, or the right-most
+    /// child for `i == cell_count`.
+    pub fn child(&self, i: usize) -> Result<u32> {
+        if i < self.cell_count()? {
+            self.u32(self.cell_offset(i)?)
+        } else {
+            self.u32(self.header_offset() + 8)
+        }
+    }
+}
+
+/// Where the b-tree code gets its pages. The `Pager` is the one source; the
+/// b-tree code sees only this trait, never the file, its locks or the WAL.
+pub trait PageSource {
+    fn read_page(&mut self, page_no: u32, header: &DbHeader) -> Result<Page>;
+}
@@ -0,0 +1,305 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{prelude::*, ErrorKind, SeekFrom};
+#[cfg(feature = "mmap")]
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{corrupt, RqliteError};
+use crate::header::{DbHeader, HEADER_SIZE};
+use crate::journal::Journal;
+use crate::lock::{is_reserved, lock_shared};
+use crate::page::{Page, PageSource};
+use crate::settings::Settings;
+use crate::wal::{Snapshot, Wal};
+use crate::wal_index::WalIndex;
+use crate::SchemaRow;
+
+/// The committed state of a database, as far as the files show it: the
+/// header's schema cookie, which every schema change bumps, and its change
+/// counter, which every commit in rollback mode bumps. In WAL mode commits
+/// leave the counter alone, so the checksum of the WAL's last commit stands
+/// in for it. Two snapshots of the same version hold the same schema.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Version {
+    pub schema_cookie: u32,
+    pub change_counter: u32,
+    pub wal_commit: Option<(u32, u32)>,
+}
+
+/// Reads the pages of one database. In WAL mode a page comes from the WAL
+/// when it holds a committed image newer than the database file's copy.
+/// After a crashed write, a page comes from the hot rollback journal when
+/// the interrupted transaction changed it, which rolls the transaction back
+/// in memory without touching either file.
+///
+/// The pager reads one snapshot, so the header, the schema and recently used
+/// pages are kept in memory and never need invalidating. Its locks keep the
+/// snapshot intact: in WAL mode, that includes a reader slot whose mark caps
+/// the frames it reads.
+pub struct Pager {
+    file: File,
+    wal: Option<Wal>,
+    /// In WAL mode, the `-shm` file whose reader slot this pager holds.
+    wal_index: Option<WalIndex>,
+    journal: Option<Journal>,
+    header: Option<DbHeader>,
+    schema: Option<Vec<SchemaRow>>,
+    cache: PageCache,
+    #[cfg(feature = "mmap")]
+    map: Option<Rc<memmap2::Mmap>>,
+}
+
+impl Pager {
+    /// Opens `path` holding a SHARED lock, and in WAL mode a reader slot in
+    /// the `-shm` file, unless `no_lock` is set, for as long as the pager
+    /// lives.
+    pub fn open(path: &str, settings: &Settings) -> Result<Pager> {
+        let file = File::open(path).map_err(RqliteError::Io)?;
+        let no_lock = settings.no_lock;
+        if !no_lock {
+            wait_for(settings.busy_timeout, || lock_shared(&file))?;
+        }
+        // A journal is only hot if no writer still holds RESERVED; a live
+        // one means the database file hasn't been touched yet. Read it
+        // first, since page 1 itself may be half-written.
+        let journal = if !no_lock && is_reserved(&file)? {
+            None
+        } else {
+            Journal::open(&format!("{}-journal", path))?
+        };
+        let mut pager = Pager {
+            file,
+            wal: None,
+            wal_index: None,
+            journal,
+            header: None,
+            schema: None,
+            cache: PageCache::new(settings.cache_size),
+            #[cfg(feature = "mmap")]
+            map: None,
+        };
+        if pager.header()?.is_wal() {
+            let mut snapshot = Snapshot::Whole;
+            if !no_lock {
+                let mut index = WalIndex::open(path)?;
+                wait_for(settings.busy_timeout, || {
+                    index.begin_read().map(|read| {
+                        snapshot = read.unwrap_or(snapshot);
+                        read.is_some()
+                    })
+                })?;
+                pager.wal_index = Some(index);
+            }
+            pager.wal = Wal::open(&format!("{}-wal", path), snapshot)?;
+        }
+        // Only now that the WAL is open is page 1's newest copy known.
+        pager.header = Some(pager.header()?);
+        // A mapping faults if the file shrinks underneath it, so it is only
+        // safe while the SHARED lock keeps writers from truncating.
+        #[cfg(feature = "mmap")]
+        if settings.mmap && !no_lock {
+            // SAFETY: the lock stops other SQLite processes from changing
+            // the pages this pager reads; see above.
+            pager.map = Some(Rc::new(unsafe { memmap2::Mmap::map(&pager.file)? }));
+        }
+        Ok(pager)
+    }
+
+    /// The database header, from the newest committed copy of page 1.
+    pub fn header(&mut self) -> Result<DbHeader> {
+        if let Some(header) = &self.header {
+            return Ok(header.clone());
+        }
+        let header = if let Some(page) = self.journal.as_ref().and_then(|j| j.page(1)) {
+            DbHeader::parse(&page[..HEADER_SIZE])?
+        } else if let Some(wal) = &mut self.wal
+            && let Some(page) = wal.read_page(1)?
+        {
+            DbHeader::parse(&page[..HEADER_SIZE])?
+        } else {
+            DbHeader::read(&mut self.file)?
+        };
+        Ok(header)
+    }
+
+    /// What identifies the snapshot's committed state: see `Version`.
+    pub fn version(&mut self) -> Result<Version> {
+        let header = self.header()?;
+        Ok(Version {
+            schema_cookie: header.schema_cookie,
+            change_counter: header.file_change_counter,
+            wal_commit: self.wal.as_ref().map(|w| w.commit),
+        })
+    }
+
+    /// The schema rows read so far, if any.
+    pub fn schema(&self) -> Option<&[SchemaRow]> {
+        self.schema.as_deref()
+    }
+
+    /// Keeps the schema rows for the rest of the snapshot: read from it, or
+    /// from an earlier snapshot of the same `version`.
+    pub fn cache_schema(&mut self, rows: Vec<SchemaRow>) {
+        self.schema = Some(rows);
+    }
+
+    /// Reads a whole page; page 1 includes the database header. Pointer-map
+    /// pages are never reachable from a b-tree, so a request for one means the
+    /// file is corrupt.
+    pub fn read_page(&mut self, page_no: u32, header: &DbHeader) -> Result<Page> {
+        if page_no == 0 {
+            return Err(corrupt(0, "reference to page 0"));
+        }
+        if header.is_ptrmap_page(page_no) {
+            return Err(corrupt(
+                page_no,
+                "a pointer-map page, read as a b-tree page",
+            ));
+        }
+        #[cfg(feature = "mmap")]
+        if let Some(page) = self.mapped_page(page_no, header) {
+            return Ok(page);
+        }
+        if let Some(page) = self.cache.get(page_no) {
+            return Ok(page);
+        }
+        let page = Page::Owned(self.read_uncached(page_no, header)?.into());
+        self.cache.insert(page_no, page.clone());
+        Ok(page)
+    }
+
+    /// The page straight from the mapped file, unless the journal or WAL
+    /// has a newer image of it or it lies past the end of the file.
+    #[cfg(feature = "mmap")]
+    fn mapped_page(&self, page_no: u32, header: &DbHeader) -> Option<Page> {
+        let map = self.map.as_ref()?;
+        if self
+            .journal
+            .as_ref()
+            .is_some_and(|j| j.page(page_no).is_some())
+            || self.wal.as_ref().is_some_and(|w| w.has_page(page_no))
+        {
+            return None;
+        }
+        let len = header.page_size;
+        let start = (page_no as usize - 1) * len;
+        if start + len > map.len() {
+            return None;
+        }
+        Some(Page::Mapped {
+            map: Rc::clone(map),
+            start,
+            len,
+        })
+    }
+
+    fn read_uncached(&mut self, page_no: u32, header: &DbHeader) -> Result<Vec<u8>> {
+        if let Some(page) = self.journal.as_ref().and_then(|j| j.page(page_no)) {
+            return Ok(page.to_vec());
+        }
+        if let Some(wal) = &mut self.wal
+            && let Some(page) = wal.read_page(page_no)?
+        {
+            return Ok(page);
+        }
+        let page_size = header.page_size;
+        self.file
+            .seek(SeekFrom::Start((page_no as u64 - 1) * page_size as u64))
+            .map_err(RqliteError::Io)?;
+        let mut page = vec![0u8; page_size];
+        match self.file.read_exact(&mut page) {
+            Ok(()) => Ok(page),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                Err(corrupt(page_no, "past the end of the file"))
+            }
+            Err(e) => Err(RqliteError::Io(e).into()),
+        }
+    }
+
+    /// Pages in the database, including any the WAL adds past the end of the
+    /// database file and excluding any a rolled-back transaction appended.
+    pub fn page_count(&self, header: &DbHeader) -> Result<u64> {
+        if let Some(journal) = &self.journal {
+            return Ok(journal.db_pages as u64);
+        }
+        match &self.wal {
+            Some(wal) if wal.db_pages > 0 => Ok(wal.db_pages as u64),
+            _ => {
+                let len = self.file.metadata().map_err(RqliteError::Io)?.len();
+                Ok(len / header.page_size as u64)
+            }
+        }
+    }
+}
+
+impl PageSource for Pager {
+    fn read_page(&mut self, page_no: u32, header: &DbHeader) -> Result<Page> {
+        Pager::read_page(self, page_no, header)
+    }
+}
+
+/// The most recently used pages, up to a fixed number. Each use stamps a
+/// page with the next tick, and the page with the oldest stamp is evicted.
+struct PageCache {
+    capacity: usize,
+    tick: u64,
+    pages: HashMap<u32, (u64, Page)>,
+    by_use: BTreeMap<u64, u32>,
+}
+
+impl PageCache {
+    fn new(capacity: usize) -> PageCache {
+        PageCache {
+            capacity,
+            tick: 0,
+            pages: HashMap::new(),
+            by_use: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, page_no: u32) -> Option<Page> {
+        self.tick += 1;
+        let (used, page) = self.pages.get_mut(&page_no)?;
+        self.by_use.remove(used);
+        self.by_use.insert(self.tick, page_no);
+        *used = self.tick;
+        Some(page.clone())
+    }
+
+    fn insert(&mut self, page_no: u32, page: Page) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.pages.len() >= self.capacity
+            && let Some((_, oldest)) = self.by_use.pop_first()
+        {
+            self.pages.remove(&oldest);
+        }
+        self.tick += 1;
+        self.by_use.insert(self.tick, page_no);
+        self.pages.insert(page_no, (self.tick, page));
+    }
+}
+
+/// Takes a lock with `lock`, retrying for up to `timeout` while a writer
+/// holds the database. The delays follow SQLite's default busy handler:
+/// short at first, then backing off to 100ms.
+fn wait_for(timeout: Duration, mut lock: impl FnMut() -> Result<bool>) -> Result<()> {
+    const DELAYS_MS: [u64; 12] = [1, 2, 5, 10, 15, 20, 25, 25, 25, 50, 50, 100];
+    let start = Instant::now();
+    for attempt in 0.. {
+        if lock()? {
+            return Ok(());
+        }
+        let waited = start.elapsed();
+        if waited >= timeout {
+            break;
+        }
+        let delay = Duration::from_millis(DELAYS_MS[attempt.min(DELAYS_MS.len() - 1)]);
+        thread::sleep(delay.min(timeout - waited));
+    }
+    Err(RqliteError::Busy.into())
+}
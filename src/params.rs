@@ -0,0 +1,274 @@
+use anyhow::{bail, Result};
+
+use crate::json::{self, Json};
+use crate::value::Value;
+
+/// How a `{"$date": ...}` parameter is stored, following the three
+/// conventions SQLite's date functions understand.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DateStorage {
+    /// `YYYY-MM-DD HH:MM:SS[.SSS]` text in UTC, as `datetime()` returns.
+    #[default]
+    Text,
+    /// Seconds since 1970-01-01 UTC: an integer, or a real with fractions.
+    UnixEpoch,
+    /// Fractional days since noon UTC on 24 November 4714 BC, as a real.
+    JulianDay,
+}
+
+impl DateStorage {
+    pub fn parse(name: &str) -> Result<DateStorage> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Ok(DateStorage::Text),
+            "unixepoch" => Ok(DateStorage::UnixEpoch),
+            "julianday" => Ok(DateStorage::JulianDay),
+            _ => bail!("expected text, unixepoch or julianday, got {}", name),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DateStorage::Text => "text",
+            DateStorage::UnixEpoch => "unixepoch",
+            DateStorage::JulianDay => "julianday",
+        }
+    }
+}
+
+/// Query parameters given as one JSON object, `{"name": value, ...}`, bound
+/// to `$name`, `:name` or `@name`. Each JSON value maps to a storage class:
+///
+/// - `null` to NULL, `true`/`false` to 1/0, numbers to INTEGER when written
+///   without a fraction or exponent and in range, otherwise REAL
+/// - strings to TEXT
+/// - `{"$blob": "<base64>"}` to a BLOB
+/// - `{"$date": "<ISO-8601>"}` to a date stored as `DateStorage` says
+pub struct Params(Vec<(String, Value)>);
+
+impl Params {
+    pub fn from_json(text: &str, dates: DateStorage) -> Result<Params> {
+        let Json::Object(members) = json::parse(text)? else {
+            bail!("parameters must be a JSON object");
+        };
+        let mut params = Vec::with_capacity(members.len());
+        for (name, value) in members {
+            let value = param_value(&name, &value, dates)?;
+            params.push((name, value));
+        }
+        Ok(Params(params))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+    }
+}
+
+fn param_value(name: &str, json: &Json, dates: DateStorage) -> Result<Value> {
+    match json {
+        Json::Object(members) => match members.as_slice() {
+            [(tag, text @ Json::String(_))] if tag == "$blob" => {
+                Ok(Value::Blob(decode_base64(&text.to_value().to_string())?))
+            }
+            [(tag, text @ Json::String(_))] if tag == "$date" => {
+                store_date(&text.to_value().to_string(), dates)
+            }
+            _ => bail!(
+                "unsupported value for parameter {}: expected {{\"$blob\": ...}} or {{\"$date\": ...}}",
+                name
+            ),
+        },
+        Json::Array(_) => bail!("unsupported value for parameter {}: array", name),
+        other => Ok(other.to_value()),
+    }
+}
+
+/// Encodes `bytes` as standard base64, padded.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let mut padded = [0u8; 4];
+        padded[1..=chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes(padded);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(DIGITS[(bits >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes standard base64, with or without `=` padding.
+fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let digit = |c: u8| -> Result<u32> {
+        Ok(match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => bail!("invalid base64 character: {:?}", c as char),
+        } as u32)
+    };
+    let trimmed = text.trim_end_matches('=').as_bytes();
+    if trimmed.len() % 4 == 1 {
+        bail!("invalid base64 length");
+    }
+    let mut out = Vec::with_capacity(trimmed.len() * 3 / 4);
+    for chunk in trimmed.chunks(4) {
+        let mut bits = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            bits |= digit(c)? << (18 - 6 * i);
+        }
+        let bytes = bits.to_be_bytes();
+        out.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Ok(out)
+}
+
+/// Parses an ISO-8601 date or date-time and stores it as `dates` says. A
+/// time without an offset is taken as UTC, like SQLite's date functions do.
+fn store_date(text: &str, dates: DateStorage) -> Result<Value> {
+    let Some((days, millis)) = parse_iso8601(text) else {
+        bail!("invalid ISO-8601 date: {}", text);
+    };
+    let unix_millis = days * 86_400_000 + millis;
+    Ok(match dates {
+        DateStorage::Text => {
+            let day_millis = unix_millis.rem_euclid(86_400_000);
+            let (y, m, d) = civil_from_days(unix_millis.div_euclid(86_400_000));
+            let secs = day_millis / 1000;
+            let mut out = format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                y,
+                m,
+                d,
+                secs / 3600,
+                secs / 60 % 60,
+                secs % 60
+            );
+            if day_millis % 1000 != 0 {
+                out.push_str(&format!(".{:03}", day_millis % 1000));
+            }
+            Value::Text(out)
+        }
+        DateStorage::UnixEpoch if unix_millis % 1000 == 0 => Value::Integer(unix_millis / 1000),
+        DateStorage::UnixEpoch => Value::Real(unix_millis as f64 / 1000.0),
+        DateStorage::JulianDay => Value::Real(unix_millis as f64 / 86_400_000.0 + 2_440_587.5),
+    })
+}
+
+/// Days since 1970-01-01 and milliseconds into that day (which may fall
+/// outside 0..86400000 once an offset is applied) for `YYYY-MM-DD`,
+/// optionally followed by `T` or a space, `HH:MM[:SS[.fff]]` and `Z` or
+/// `±HH:MM`.
+fn parse_iso8601(text: &str) -> Option<(i64, i64)> {
+    let text = text.trim();
+    let number = |s: &str| -> Option<i64> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    };
+
+    let (date, time) = match text.find(['T', 't', ' ']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    let mut parts = date.split('-');
+    let (y, m, d) = (
+        number(parts.next()?)?,
+        number(parts.next()?)?,
+        number(parts.next()?)?,
+    );
+    if parts.next().is_some() || date.len() != 10 || !(1..=12).contains(&m) {
+        return None;
+    }
+    if d < 1 || d > days_in_month(y, m) {
+        return None;
+    }
+    let days = days_from_civil(y, m, d);
+
+    let Some(time) = time else {
+        return Some((days, 0));
+    };
+    let (clock, offset_minutes) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        (clock, 0)
+    } else if let Some(i) = time.rfind(['+', '-']) {
+        let (h, m) = time[i + 1..].split_once(':')?;
+        let minutes = number(h)? * 60 + number(m)?;
+        let sign = if time.as_bytes()[i] == b'-' { -1 } else { 1 };
+        (&time[..i], sign * minutes)
+    } else {
+        (time, 0)
+    };
+
+    let mut fields = clock.split(':');
+    let hours = number(fields.next()?)?;
+    let minutes = number(fields.next()?)?;
+    let millis = match fields.next() {
+        Some(s) => {
+            let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+            let mut ms = number(whole)? * 1000;
+            if !fraction.is_empty() {
+                let digits = format!("{:0<3}", &fraction[..fraction.len().min(3)]);
+                number(fraction)?;
+                ms += number(&digits)?;
+            }
+            ms
+        }
+        None => 0,
+    };
+    if fields.next().is_some() || hours > 23 || minutes > 59 || millis >= 60_000 {
+        return None;
+    }
+    let millis = (hours * 60 + minutes - offset_minutes) * 60_000 + millis;
+    Some((days, millis))
+}
+
+fn days_in_month(y: i64, m: i64) -> i64 {
+    match m {
+        2 if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar (Howard
+/// Hinnant's algorithm).
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (
+        if m <= 2 {
+            yoe + era * 400 + 1
+        } else {
+            yoe + era * 400
+        },
+        m,
+        d,
+    )
+}
@@ -0,0 +1,1434 @@
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+
+use crate::affinity::Affinity;
+use crate::collation::Collation;
+use crate::error::{unsupported, RqliteError};
+use crate::eval::{comparison_affinity, comparison_collation};
+use crate::functions::is_aggregate;
+use crate::header::DbHeader;
+use crate::pager::Pager;
+use crate::record::ColumnSource;
+use crate::redact::Mask;
+use crate::settings::Settings;
+use crate::sql::{
+    is_rowid_name, parse_create_index, BinaryOp, Expr, IndexedColumn, Select, TableSchema, UnaryOp,
+};
+use crate::stat::Stats;
+use crate::value::Value;
+use crate::vm::{Addr, Comparison, CursorSpec, Op, Program, Reg};
+use crate::{estimate_entries, find_table_schema, index_fraction, read_schema_rows, KeyBounds};
+
+/// One way to reach the rows a query filters.
+pub enum AccessPath {
+    /// Every row of the table.
+    FullScan,
+    /// The one row a `rowid = N` term names.
+    Rowid(i64),
+    /// The entries of an index whose first key equals a value.
+    IndexEq { index: KeyIndex, value: Value },
+    /// The entries of an index whose first key lies within inclusive bounds.
+    IndexRange {
+        index: KeyIndex,
+        lower: Option<Value>,
+        upper: Option<Value>,
+    },
+}
+
+/// The access path chosen for a query, with the estimates it was chosen on.
+pub struct Plan {
+    pub path: AccessPath,
+    /// Whether an index path holds every column the query reads, so the
+    /// table is never read.
+    pub covering: bool,
+    /// Estimated rows, or index entries, the path reads.
+    pub rows: f64,
+    /// Estimated cost, in rows read by a full scan.
+    pub cost: f64,
+}
+
+impl Plan {
+    pub fn describe(&self) -> String {
+        let covering = if self.covering { ", covering" } else { "" };
+        match &self.path {
+            AccessPath::FullScan => "full table scan".to_string(),
+            AccessPath::Rowid(rowid) => format!("rowid lookup for rowid {}", rowid),
+            AccessPath::IndexEq { index, .. } => {
+                format!("index {} (equality{})", index.name, covering)
+            }
+            AccessPath::IndexRange { index, .. } => {
+                format!("index {} (range{})", index.name, covering)
+            }
+        }
+    }
+}
+
+/// Chooses the cheapest way to reach the rows of `table` (rooted at `root`)
+/// that a WHERE clause made of `terms` can match, for a query reading the
+/// columns in `sources`. The candidates are a full scan, a rowid lookup, and
+/// an equality or range lookup on every index that fits a term.
+///
+/// Costs are in rows read by a full scan. Reading a row by rowid costs a
+/// seek, which timing puts at about `log2(n) / 8` rows for a table of `n`;
+/// an index entry costs one row, plus a seek and a row more unless the index
+/// covers the query. The share of an index a lookup reads is estimated by
+/// descending it to each bound (see `index_fraction`). Table and index
+/// sizes come from sqlite_stat1 when ANALYZE has filled it, and are
+/// estimated from the b-trees' shape otherwise.
+pub fn plan(
+    pager: &mut Pager,
+    header: &DbHeader,
+    table: &TableSchema,
+    root: u32,
+    terms: &[&Expr],
+    sources: &[ColumnSource],
+    settings: &Settings,
+) -> Result<Plan> {
+    let key_terms: Vec<KeyTerm> = terms.iter().filter_map(|t| key_term(t, table)).collect();
+    let rowid_alias = table.rowid_alias();
+    let rowid_lookup = key_terms.iter().find_map(|term| {
+        let (Expr::Column(col), BinaryOp::Eq) = (term.key, term.op) else {
+            return None;
+        };
+        let is_rowid = match table.column_index(col) {
+            Some(i) => Some(i) == rowid_alias,
+            None => is_rowid_name(col),
+        };
+        if is_rowid {
+            rowid_literal(&term.value)
+        } else {
+            None
+        }
+    });
+
+    let stats = Stats::read(pager, settings)?;
+    let table_rows = match stats.table_rows(&table.name) {
+        Some(rows) => rows,
+        None => estimate_entries(pager, root, header)?,
+    };
+    let seek = table_rows.max(2.0).log2() / 8.0;
+    if let Some(rowid) = rowid_lookup {
+        return Ok(Plan {
+            path: AccessPath::Rowid(rowid),
+            covering: false,
+            rows: 1.0,
+            cost: seek + 1.0,
+        });
+    }
+    let mut best = Plan {
+        path: AccessPath::FullScan,
+        covering: false,
+        rows: table_rows,
+        cost: table_rows,
+    };
+    let mut consider =
+        |index: KeyIndex, seeks: usize, rows: f64, path: &dyn Fn(KeyIndex) -> AccessPath| {
+            let covering = covering_fields(&index.columns, sources, table).is_some();
+            let per_entry = if covering { 1.0 } else { 2.0 + seek };
+            let cost = seeks as f64 * seek + rows * per_entry;
+            if cost < best.cost {
+                best = Plan {
+                    path: path(index),
+                    covering,
+                    rows,
+                    cost,
+                };
+            }
+        };
+
+    for term in key_terms.iter().filter(|t| t.op == BinaryOp::Eq) {
+        // Keys equal bytewise are equal under every collation, so a BINARY
+        // equality can use any index and let the WHERE clause narrow it.
+        let usable = |c: Collation| term.collation == Collation::Binary || c == term.collation;
+        for index in key_indexes(pager, table, term.key, terms)? {
+            if !usable(index.collation) {
+                continue;
+            }
+            let stat = stats.index(&index.name);
+            let entries = index_entries(pager, header, &index, stat)?;
+            let value = &term.value;
+            let share = index_share(pager, header, &index, value, Some(value), settings)?;
+            let mut rows = share * entries;
+            // ANALYZE counts an index as unique when (nearly) every first
+            // key is distinct.
+            if index.unique || stat.and_then(|s| s.get(1)) == Some(&1.0) {
+                rows = rows.min(1.0);
+            }
+            consider(index, 1, rows, &|index| AccessPath::IndexEq {
+                index,
+                value: value.clone(),
+            });
+        }
+    }
+    for range in key_ranges(&key_terms) {
+        for index in key_indexes(pager, table, &range.key, terms)? {
+            if index.collation != range.collation {
+                continue;
+            }
+            let lower = range
+                .lower
+                .clone()
+                .unwrap_or(Value::Real(f64::NEG_INFINITY));
+            let entries = index_entries(pager, header, &index, stats.index(&index.name))?;
+            let share = index_share(
+                pager,
+                header,
+                &index,
+                &lower,
+                range.upper.as_ref(),
+                settings,
+            )?;
+            let rows = share * entries;
+            consider(index, 1, rows, &|index| AccessPath::IndexRange {
+                index,
+                lower: range.lower.clone(),
+                upper: range.upper.clone(),
+            });
+        }
+    }
+    Ok(best)
+}
+
+/// How many entries `index` holds: the count ANALYZE recorded in `stat`, or
+/// else an estimate from its shape.
+fn index_entries(
+    pager: &mut Pager,
+    header: &DbHeader,
+    index: &KeyIndex,
+    stat: Option<&[f64]>,
+) -> Result<f64> {
+    match stat {
+        Some(stat) => Ok(stat[0]),
+        None => estimate_entries(pager, index.root, header),
+    }
+}
+
+/// The share of `index`'s entries, from 0 to 1, with first keys from
+/// `lower` to `upper`.
+fn index_share(
+    pager: &mut Pager,
+    header: &DbHeader,
+    index: &KeyIndex,
+    lower: &Value,
+    upper: Option<&Value>,
+    settings: &Settings,
+) -> Result<f64> {
+    let bounds = KeyBounds {
+        lower,
+        upper,
+        descending: index.descending,
+        collation: index.collation,
+    };
+    let before = index_fraction(pager, index.root, header, settings, &|k| bounds.before(k))?;
+    let through = index_fraction(pager, index.root, header, settings, &|k| !bounds.after(k))?;
+    Ok((through - before).max(0.0))
+}
+
+/// The rowid a `rowid = literal` comparison can match, if any.
+pub fn rowid_literal(v: &Value) -> Option<i64> {
+    match v.as_numeric_text().as_ref().unwrap_or(v) {
+        Value::Integer(i) => Some(*i),
+        Value::Real(r) if r.fract() == 0.0 && r.abs() < 9.2e18 => Some(*r as i64),
+        _ => None,
+    }
+}
+
+/// The terms of a WHERE clause that are joined by AND at the top level; each
+/// must hold for a row to match.
+pub fn conjuncts<'e>(expr: &'e Expr, terms: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::Binary {
+            op: BinaryOp::And,
+            left,
+            right,
+        } => {
+            conjuncts(left, terms);
+            conjuncts(right, terms);
+        }
+        _ => terms.push(expr),
+    }
+}
+
+/// A WHERE term comparing a key, a column or an expression an index could
+/// be built on, with a literal.
+struct KeyTerm<'e> {
+    /// The key without any COLLATE.
+    key: &'e Expr,
+    /// The comparison, written with the key on the left.
+    op: BinaryOp,
+    /// The literal as the comparison sees it, with its affinity applied:
+    /// the key the index holds for the values the term matches.
+    value: Value,
+    collation: Collation,
+}
+
+/// `term` as a key compared with a literal, if it is one. `5 < x` becomes
+/// `x > 5`.
+fn key_term<'e>(term: &'e Expr, table: &TableSchema) -> Option<KeyTerm<'e>> {
+    let Expr::Binary { op, left, right } = term else {
+        return None;
+    };
+    let (op, key, value) = match (left.uncollated(), right.uncollated()) {
+        (Expr::Literal(_), Expr::Literal(_)) => return None,
+        (key, Expr::Literal(v)) => (*op, key, v),
+        (Expr::Literal(v), key) => match op {
+            BinaryOp::Lt => (BinaryOp::Gt, key, v),
+            BinaryOp::LtEq => (BinaryOp::GtEq, key, v),
+            BinaryOp::Gt => (BinaryOp::Lt, key, v),
+            BinaryOp::GtEq => (BinaryOp::LtEq, key, v),
+            op => (*op, key, v),
+        },
+        _ => return None,
+    };
+    // An unknown collation fails the query anyway, when the columns it is
+    // declared on are resolved.
+    let collation = comparison_collation(left, right, &|name| {
+        table.column_collation(name).unwrap_or_default()
+    });
+    let affinity = comparison_affinity(left, right, &|name| table.column_affinity(name));
+    Some(KeyTerm {
+        key,
+        op,
+        value: affinity.apply(value).unwrap_or_else(|| value.clone()),
+        collation,
+    })
+}
+
+/// Whether a WHERE term guarantees a term of a partial index's WHERE clause:
+/// it is the same expression (see `same_expr`), or the required term is `x IS NOT NULL` and
+/// the query compares `x` with a value, which no NULL satisfies. This is the
+/// subset of SQLite's own implication test that ordinary partial indexes
+/// rely on.
+fn implies(term: &Expr, required: &Expr) -> bool {
+    if same_expr(term, required) {
+        return true;
+    }
+    let Expr::IsNull {
+        expr,
+        negated: true,
+    } = required
+    else {
+        return false;
+    };
+    let Expr::Column(column) = expr.as_ref() else {
+        return false;
+    };
+    let Expr::Binary { op, left, right } = term else {
+        return false;
+    };
+    let compares = matches!(
+        op,
+        BinaryOp::Eq
+            | BinaryOp::NotEq
+            | BinaryOp::Lt
+            | BinaryOp::LtEq
+            | BinaryOp::Gt
+            | BinaryOp::GtEq
+    );
+    let operand_is_column =
+        |e: &Expr| matches!(e.uncollated(), Expr::Column(c) if c.eq_ignore_ascii_case(column));
+    compares && (operand_is_column(left) || operand_is_column(right))
+}
+
+/// Whether two expressions are written the same way, up to the case of
+/// column names (function names are already lowercase).
+fn same_expr(a: &Expr, b: &Expr) -> bool {
+    match (a, b) {
+        (Expr::Column(x), Expr::Column(y)) => x.eq_ignore_ascii_case(y),
+        (
+            Expr::Unary { op, expr },
+            Expr::Unary {
+                op: op2,
+                expr: expr2,
+            },
+        ) => op == op2 && same_expr(expr, expr2),
+        (
+            Expr::Binary { op, left, right },
+            Expr::Binary {
+                op: op2,
+                left: left2,
+                right: right2,
+            },
+        ) => op == op2 && same_expr(left, left2) && same_expr(right, right2),
+        (
+            Expr::IsNull { expr, negated },
+            Expr::IsNull {
+                expr: expr2,
+                negated: negated2,
+            },
+        ) => negated == negated2 && same_expr(expr, expr2),
+        (
+            Expr::Collate { expr, collation },
+            Expr::Collate {
+                expr: expr2,
+                collation: collation2,
+            },
+        ) => collation == collation2 && same_expr(expr, expr2),
+        (
+            Expr::Function { name, args },
+            Expr::Function {
+                name: name2,
+                args: args2,
+            },
+        ) => {
+            name == name2
+                && args.len() == args2.len()
+                && args.iter().zip(args2).all(|(x, y)| same_expr(x, y))
+        }
+        _ => a == b,
+    }
+}
+
+/// Where each of `sources` sits in an index entry, if the index holds them
+/// all; the rowid is the entry's last field.
+pub fn covering_fields(
+    columns: &[Option<String>],
+    sources: &[ColumnSource],
+    table: &TableSchema,
+) -> Option<Vec<usize>> {
+    sources
+        .iter()
+        .map(|source| match *source {
+            ColumnSource::Rowid => Some(columns.len()),
+            ColumnSource::Field(i) | ColumnSource::Real(i) => columns.iter().position(|c| {
+                c.as_ref()
+                    .is_some_and(|c| c.eq_ignore_ascii_case(&table.columns[i].name))
+            }),
+        })
+        .collect()
+}
+
+/// Bounds on the values of one column or expression, inclusive, under the
+/// collation its comparisons use.
+struct KeyRange {
+    key: Expr,
+    collation: Collation,
+    lower: Option<Value>,
+    upper: Option<Value>,
+}
+
+/// The range each column or expression is restricted to by comparisons with
+/// literals, bounded by each literal with the comparison's affinity applied
+/// (see `KeyTerm::value`).
+fn key_ranges(key_terms: &[KeyTerm]) -> Vec<KeyRange> {
+    let mut ranges: Vec<KeyRange> = Vec::new();
+    for term in key_terms {
+        if term.value.is_null() {
+            continue;
+        }
+        let collation = term.collation;
+        let compare = |a: &Value, b: &Value| collation.compare(a, b);
+        let bound = Some(term.value.clone());
+        let (lower, upper) = match term.op {
+            BinaryOp::Gt | BinaryOp::GtEq => (bound, None),
+            BinaryOp::Lt | BinaryOp::LtEq => (None, bound),
+            _ => continue,
+        };
+        let range = match ranges
+            .iter_mut()
+            .find(|r| r.collation == collation && same_expr(&r.key, term.key))
+        {
+            Some(range) => range,
+            None => {
+                ranges.push(KeyRange {
+                    key: term.key.clone(),
+                    collation,
+                    lower: None,
+                    upper: None,
+                });
+                ranges.last_mut().unwrap()
+            }
+        };
+        // Every term must hold, so the range narrows to the tightest bounds.
+        if let Some(lower) = lower
+            && range
+                .lower
+                .as_ref()
+                .is_none_or(|l| compare(&lower, l) == Ordering::Greater)
+        {
+            range.lower = Some(lower);
+        }
+        if let Some(upper) = upper
+            && range
+                .upper
+                .as_ref()
+                .is_none_or(|u| compare(&upper, u) == Ordering::Less)
+        {
+            range.upper = Some(upper);
+        }
+    }
+    ranges
+}
+
+/// An index usable for lookups on one column or expression.
+#[derive(Clone)]
+pub struct KeyIndex {
+    pub name: String,
+    pub root: u32,
+    /// The collation its keys are sorted by.
+    pub collation: Collation,
+    /// Whether that order is descending.
+    pub descending: bool,
+    /// Whether no two entries share a first key: a UNIQUE index on one
+    /// column.
+    unique: bool,
+    /// The table column behind each key, or None for an expression.
+    pub columns: Vec<Option<String>>,
+}
+
+/// The collation an index column sorts by: its own COLLATE, or else the
+/// table column's.
+pub fn index_column_collation(column: &IndexedColumn, table: &TableSchema) -> Result<Collation> {
+    let declared = table
+        .columns
+        .iter()
+        .find(|c| !column.is_expression && c.name.eq_ignore_ascii_case(&column.name))
+        .and_then(|c| c.collation());
+    match column.collation.as_deref().or(declared) {
+        Some(name) => Collation::parse(name),
+        None => Ok(Collation::Binary),
+    }
+}
+
+/// The indexes on `key`, a column or an expression, for a query whose WHERE
+/// clause is the conjunction of `terms`. The key must be an index's first
+/// column; an expression matches when it is written the same way as the
+/// index's. A partial index lacks the rows its WHERE clause excludes, so it
+/// is only offered when the query's terms imply that clause.
+fn key_indexes(
+    pager: &mut Pager,
+    table: &TableSchema,
+    key: &Expr,
+    terms: &[&Expr],
+) -> Result<Vec<KeyIndex>> {
+    let mut found = Vec::new();
+    for row in read_schema_rows(pager)? {
+        if row.kind != "index" || !row.tbl_name.eq_ignore_ascii_case(&table.name) {
+            continue;
+        }
+        // Automatic indexes have no SQL to say what they cover.
+        let Ok(index) = parse_create_index(&row.sql) else {
+            continue;
+        };
+        let Some(first) = index.columns.first() else {
+            continue;
+        };
+        let matches = match (&first.expr, key) {
+            (Some(expr), _) => {
+                let mut expr = expr.clone();
+                expr.resolve_double_quoted(&|name| table.has_column(name));
+                same_expr(&expr, key)
+            }
+            (None, Expr::Column(column)) => {
+                !first.is_expression && first.name.eq_ignore_ascii_case(column)
+            }
+            (None, _) => false,
+        };
+        if !matches {
+            continue;
+        }
+        if index.where_sql.is_some() {
+            let Some(mut predicate) = index.predicate else {
+                continue;
+            };
+            predicate.resolve_double_quoted(&|name| table.has_column(name));
+            let mut required = Vec::new();
+            conjuncts(&predicate, &mut required);
+            if !required.iter().all(|r| terms.iter().any(|t| implies(t, r))) {
+                continue;
+            }
+        }
+        // An application-defined collation can't be reproduced.
+        let Ok(collation) = index_column_collation(first, table) else {
+            continue;
+        };
+        let columns = index
+            .columns
+            .iter()
+            .map(|c| (!c.is_expression).then(|| c.name.clone()))
+            .collect();
+        found.push(KeyIndex {
+            name: row.name,
+            root: row.rootpage,
+            collation,
+            descending: first.descending,
+            unique: index.unique && index.columns.len() == 1,
+            columns,
+        });
+    }
+    Ok(found)
+}
+
+/// The columns a query reads from its table, in the order first referenced.
+pub struct QueryColumns {
+    pub names: Vec<String>,
+    pub collations: Vec<Collation>,
+    pub affinities: Vec<Affinity>,
+    pub sources: Vec<ColumnSource>,
+    /// Each column's redaction mask, applied before the WHERE clause.
+    pub masks: Vec<Option<Mask>>,
+}
+
+pub fn query_columns(
+    definition: &TableSchema,
+    columns: &[Expr],
+    where_clause: Option<&Expr>,
+    settings: &Settings,
+) -> Result<QueryColumns> {
+    let mut referenced = Vec::new();
+    for c in columns {
+        c.columns(&mut referenced);
+    }
+    if let Some(w) = where_clause {
+        w.columns(&mut referenced);
+    }
+    let rowid_alias = definition.rowid_alias();
+    let mut resolved = QueryColumns {
+        names: Vec::new(),
+        collations: Vec::new(),
+        affinities: Vec::new(),
+        sources: Vec::new(),
+        masks: Vec::new(),
+    };
+    for col in referenced {
+        if resolved.names.iter().any(|n| n.eq_ignore_ascii_case(col)) {
+            continue;
+        }
+        let column = match definition.column_index(col) {
+            Some(i) => {
+                resolved.sources.push(ColumnSource::of(definition, i));
+                Some(i)
+            }
+            // `rowid` is another name for an INTEGER PRIMARY KEY column, so
+            // it is masked like that column.
+            None if is_rowid_name(col) => {
+                resolved.sources.push(ColumnSource::Rowid);
+                rowid_alias
+            }
+            None => {
+                return Err(RqliteError::ColumnNotFound {
+                    name: col.to_string(),
+                    table: definition.name.clone(),
+                }
+                .into());
+            }
+        };
+        resolved.masks.push(column.and_then(|i| {
+            settings
+                .redactions
+                .mask(&definition.name, &definition.columns[i].name)
+        }));
+        resolved.collations.push(definition.column_collation(col)?);
+        resolved.affinities.push(definition.column_affinity(col));
+        resolved.names.push(col.to_string());
+    }
+    Ok(resolved)
+}
+
+/// Where a compiled query's rows come from.
+pub enum Input {
+    /// The query's table, reached by the access path `plan` chooses.
+    Table,
+    /// Rows an `Output::Matched` program yielded, handed to the VM by its
+    /// caller (see `Vm::with_rows`). They were masked and passed the WHERE
+    /// clause when they were matched.
+    Matched,
+}
+
+/// What a compiled query yields for each row.
+pub enum Output {
+    /// The SELECT's result columns, aggregated if they call for it.
+    Results,
+    /// The table columns the query reads (see `query_columns`) of each row
+    /// the WHERE clause passes, for a caller that combines rows from several
+    /// tables before projecting them.
+    Matched,
+    /// The rowid of each row the access path reaches, before the WHERE
+    /// clause judges it.
+    Reached,
+}
+
+/// Compiles `select` into a program for `vm::Vm`. The access path `plan`
+/// chooses becomes the loop: a scan rewinds a table cursor and steps it to
+/// the end; an index lookup seeks an index cursor and, unless the index
+/// covers the query, seeks the table by each entry's rowid; a rowid lookup
+/// seeks the table once. Each row's columns are loaded into registers and
+/// redacted, the WHERE clause jumps to the next row when it fails, and the
+/// result columns are computed into registers and yielded. An aggregate
+/// query feeds its aggregates instead, then computes one row from their
+/// results and the last row's columns. Rows given as `Input::Matched` are
+/// instead stepped through in turn, as they are.
+pub fn compile(
+    pager: &mut Pager,
+    select: &Select,
+    input: Input,
+    output: Output,
+    settings: &Settings,
+) -> Result<Program> {
+    let header = pager.header()?;
+    let (table_schema, definition) = find_table_schema(pager, &select.table)?;
+    let where_clause = select.where_clause.as_ref();
+    let mut columns = query_columns(&definition, &select.columns, where_clause, settings)?;
+    if let Output::Reached = output {
+        // Loaded after the columns the query reads, and yielded alone.
+        columns.names.push("rowid".to_string());
+        columns.collations.push(Collation::Binary);
+        columns.affinities.push(Affinity::Integer);
+        columns.sources.push(ColumnSource::Rowid);
+        columns.masks.push(None);
+    }
+    let results = match output {
+        Output::Results => Some(&select.columns),
+        Output::Matched | Output::Reached => None,
+    };
+    let mut calls = Vec::new();
+    for c in results.into_iter().flatten() {
+        c.aggregate_calls(&mut calls);
+    }
+    let is_aggregate = results.is_some_and(|r| r.iter().any(|c| c.is_aggregate()));
+    let mut code = Codegen {
+        ops: Vec::new(),
+        registers: 0,
+        names: &columns.names,
+        collations: &columns.collations,
+        affinities: &columns.affinities,
+        sources: &columns.sources,
+        columns: 0,
+        count: None,
+        aggregates: Vec::new(),
+    };
+    let mut cursors = Vec::new();
+    let root = table_schema.rootpage;
+
+    // count(*) alone needs only the number of rows, not the rows.
+    let counts_table = matches!(input, Input::Table) && where_clause.is_none();
+    if is_aggregate && calls.is_empty() && columns.names.is_empty() && counts_table {
+        cursors.push(CursorSpec::Table {
+            name: definition.name.clone(),
+            root,
+            sources: Vec::new(),
+            columns: Vec::new(),
+        });
+        code.emit(Op::OpenRead { cursor: 0 });
+        let count = code.alloc(1);
+        code.emit(Op::Count {
+            cursor: 0,
+            dest: count,
+        });
+        code.count = Some(count);
+        code.result_row(&select.columns)?;
+        code.emit(Op::Halt);
+        return Ok(code.finish(cursors, Vec::new()));
+    }
+
+    let width = columns.names.len();
+    code.columns = code.alloc(width);
+    // An aggregate query's other columns take their values from the last
+    // row the WHERE clause passes, or NULL if there is none.
+    let (last, count) = if is_aggregate {
+        let last = code.alloc(width);
+        for i in 0..width {
+            code.emit(Op::Null { dest: last + i });
+        }
+        let count = code.alloc(1);
+        code.emit(Op::Integer {
+            value: 0,
+            dest: count,
+        });
+        (last, count)
+    } else {
+        (0, 0)
+    };
+
+    // Jumps past the loop, taken when the access path finds nothing, and
+    // jumps to the next row.
+    let mut exits = Vec::new();
+    let mut skips = Vec::new();
+    // The cursor the loop steps, and the address it steps back to.
+    let stepped;
+    match input {
+        Input::Matched => {
+            cursors.push(CursorSpec::Rows {
+                columns: columns.names.clone(),
+            });
+            code.emit(Op::OpenRead { cursor: 0 });
+            exits.push(code.emit(Op::Rewind { cursor: 0, jump: 0 }));
+            stepped = Some((0, code.ops.len()));
+            code.load_row(0);
+        }
+        Input::Table => {
+            let mut terms = Vec::new();
+            if let Some(w) = where_clause {
+                conjuncts(w, &mut terms);
+            }
+            let plan = plan(
+                pager,
+                &header,
+                &definition,
+                root,
+                &terms,
+                &columns.sources,
+                settings,
+            )?;
+            let table = CursorSpec::Table {
+                name: definition.name.clone(),
+                root,
+                sources: columns.sources.clone(),
+                columns: columns.names.clone(),
+            };
+            match plan.path {
+                AccessPath::FullScan => {
+                    cursors.push(table);
+                    code.emit(Op::OpenRead { cursor: 0 });
+                    exits.push(code.emit(Op::Rewind { cursor: 0, jump: 0 }));
+                    stepped = Some((0, code.ops.len()));
+                    code.load_row(0);
+                }
+                AccessPath::Rowid(rowid) => {
+                    cursors.push(table);
+                    code.emit(Op::OpenRead { cursor: 0 });
+                    let key = code.alloc(1);
+                    code.emit(Op::Integer {
+                        value: rowid,
+                        dest: key,
+                    });
+                    exits.push(code.emit(Op::SeekRowid {
+                        cursor: 0,
+                        rowid: key,
+                        jump: 0,
+                    }));
+                    stepped = None;
+                    code.load_row(0);
+                }
+                AccessPath::IndexEq { index, value } => {
+                    let (cursor, fields) = open_index(
+                        &mut code,
+                        &mut cursors,
+                        table,
+                        &columns.sources,
+                        &definition,
+                        index,
+                    );
+                    let key = code.alloc(1);
+                    code.load(&value, key);
+                    exits.push(code.emit(Op::SeekKey {
+                        cursor,
+                        key,
+                        jump: 0,
+                    }));
+                    stepped = Some((cursor, code.ops.len()));
+                    code.load_entry(cursor, fields, &mut skips);
+                }
+                AccessPath::IndexRange {
+                    index,
+                    lower,
+                    upper,
+                } => {
+                    let (cursor, fields) = open_index(
+                        &mut code,
+                        &mut cursors,
+                        table,
+                        &columns.sources,
+                        &definition,
+                        index,
+                    );
+                    let mut bound = |value: Option<Value>| {
+                        value.map(|v| {
+                            let reg = code.alloc(1);
+                            code.load(&v, reg);
+                            reg
+                        })
+                    };
+                    let (lower, upper) = (bound(lower), bound(upper));
+                    exits.push(code.emit(Op::SeekRange {
+                        cursor,
+                        lower,
+                        upper,
+                        jump: 0,
+                    }));
+                    stepped = Some((cursor, code.ops.len()));
+                    code.load_entry(cursor, fields, &mut skips);
+                }
+            }
+        }
+    }
+    // Masks apply before the WHERE clause, so a query only ever sees the
+    // redacted values and can't filter on the originals. Matched rows have
+    // been through both already.
+    if let Input::Table = input {
+        for (i, mask) in columns.masks.iter().enumerate() {
+            if let Some(mask) = mask {
+                code.emit(Op::Redact {
+                    reg: code.columns + i,
+                    mask: *mask,
+                });
+            }
+        }
+        if let Some(w) = where_clause.filter(|_| !matches!(output, Output::Reached)) {
+            code.branch(w, false, &mut skips)?;
+        }
+    }
+
+    let mut aggregates = Vec::new();
+    match results {
+        None if matches!(output, Output::Reached) => {
+            code.emit(Op::ResultRow {
+                start: code.columns + width - 1,
+                count: 1,
+            });
+        }
+        None => {
+            code.emit(Op::ResultRow {
+                start: code.columns,
+                count: width,
+            });
+        }
+        Some(results) if !is_aggregate => code.result_row(results)?,
+        Some(_) => {
+            code.emit(Op::AddImm {
+                reg: count,
+                value: 1,
+            });
+            if width > 0 && select.columns.iter().any(reads_bare_column) {
+                code.emit(Op::Copy {
+                    src: code.columns,
+                    dest: last,
+                    count: width,
+                });
+            }
+            for (i, call) in calls.iter().enumerate() {
+                let Expr::Function { name, args } = call else {
+                    continue;
+                };
+                let first = code.alloc(args.len());
+                for (j, arg) in args.iter().enumerate() {
+                    code.expr(arg, first + j)?;
+                }
+                code.emit(Op::AggStep {
+                    aggregate: i,
+                    args: first,
+                    argc: args.len(),
+                });
+                aggregates.push((name.clone(), args.len()));
+            }
+        }
+    }
+
+    code.land(&skips);
+    if let Some((cursor, top)) = stepped {
+        code.emit(Op::Next { cursor, jump: top });
+    }
+    code.land(&exits);
+    if is_aggregate {
+        for (i, call) in calls.iter().enumerate() {
+            let dest = code.alloc(1);
+            code.emit(Op::AggFinal { aggregate: i, dest });
+            code.aggregates.push((call, dest));
+        }
+        code.columns = last;
+        code.count = Some(count);
+        code.result_row(&select.columns)?;
+    }
+    code.emit(Op::Halt);
+    Ok(code.finish(cursors, aggregates))
+}
+
+/// Whether `expr` reads a column outside any aggregate call, a column an
+/// aggregate query takes from its last row.
+fn reads_bare_column(expr: &Expr) -> bool {
+    match expr {
+        Expr::Column(_) | Expr::DoubleQuoted(_) => true,
+        Expr::Function { name, .. } if is_aggregate(name) => false,
+        Expr::Function { args, .. } => args.iter().any(reads_bare_column),
+        Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Collate { expr, .. } => {
+            reads_bare_column(expr)
+        }
+        Expr::Binary { left, right, .. } => reads_bare_column(left) || reads_bare_column(right),
+        Expr::Literal(_) | Expr::Variable(_) | Expr::CountStar | Expr::Unsupported(_) => false,
+    }
+}
+
+/// Opens a cursor on `index`, and one on the table behind it unless the
+/// index covers the query. Returns the index cursor, and where each column
+/// the query reads sits in an entry if the index covers it.
+fn open_index(
+    code: &mut Codegen,
+    cursors: &mut Vec<CursorSpec>,
+    table: CursorSpec,
+    sources: &[ColumnSource],
+    definition: &TableSchema,
+    index: KeyIndex,
+) -> (usize, Option<Vec<usize>>) {
+    let fields = covering_fields(&index.columns, sources, definition);
+    if fields.is_none() {
+        cursors.push(table);
+        code.emit(Op::OpenRead { cursor: 0 });
+    }
+    let cursor = cursors.len();
+    cursors.push(CursorSpec::Index(index));
+    code.emit(Op::OpenRead { cursor });
+    (cursor, fields)
+}
+
+/// The state of a compilation: the instructions so far, the registers
+/// handed out, and where expressions find what they refer to.
+struct Codegen<'q> {
+    ops: Vec<Op>,
+    registers: usize,
+    names: &'q [String],
+    collations: &'q [Collation],
+    affinities: &'q [Affinity],
+    sources: &'q [ColumnSource],
+    /// The first of the registers holding the columns in `names`.
+    columns: Reg,
+    /// The register counting the rows, once count(*) can be read.
+    count: Option<Reg>,
+    /// Each aggregate call with the register its result is in, once the
+    /// results can be read.
+    aggregates: Vec<(&'q Expr, Reg)>,
+}
+
+impl<'q> Codegen<'q> {
+    fn finish(self, cursors: Vec<CursorSpec>, aggregates: Vec<(String, usize)>) -> Program {
+        Program {
+            ops: self.ops,
+            cursors,
+            registers: self.registers,
+            aggregates,
+        }
+    }
+
+    /// Hands out `n` consecutive registers.
+    fn alloc(&mut self, n: usize) -> Reg {
+        let first = self.registers + 1;
+        self.registers += n;
+        first
+    }
+
+    fn emit(&mut self, op: Op) -> Addr {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    /// Points the jumps of the instructions at `jumps` to the next
+    /// instruction emitted.
+    fn land(&mut self, jumps: &[Addr]) {
+        let here = self.ops.len();
+        for &at in jumps {
+            if let Some(jump) = self.ops[at].jump_mut() {
+                *jump = here;
+            }
+        }
+    }
+
+    /// Loads the columns the query reads from a table cursor's row.
+    fn load_row(&mut self, cursor: usize) {
+        for i in 0..self.names.len() {
+            self.emit(Op::Column {
+                cursor,
+                column: i,
+                dest: self.columns + i,
+            });
+        }
+    }
+
+    /// Loads the columns the query reads for an index cursor's entry: from
+    /// the entry's `fields` if the index covers them, or else from the row
+    /// its rowid names in the table, on cursor 0. An entry whose row is
+    /// missing is skipped, by a jump added to `skips`. Index keys are stored
+    /// as the table's fields are, so a REAL column's whole numbers are
+    /// integers there too and are converted as the table cursor converts
+    /// them.
+    fn load_entry(&mut self, cursor: usize, fields: Option<Vec<usize>>, skips: &mut Vec<Addr>) {
+        match fields {
+            Some(fields) => {
+                for (i, field) in fields.into_iter().enumerate() {
+                    let dest = self.columns + i;
+                    self.emit(Op::Column {
+                        cursor,
+                        column: field,
+                        dest,
+                    });
+                    if matches!(self.sources[i], ColumnSource::Real(_)) {
+                        self.emit(Op::RealAffinity { reg: dest });
+                    }
+                }
+            }
+            None => {
+                let rowid = self.alloc(1);
+                self.emit(Op::IdxRowid {
+                    cursor,
+                    dest: rowid,
+                });
+                skips.push(self.emit(Op::SeekRowid {
+                    cursor: 0,
+                    rowid,
+                    jump: 0,
+                }));
+                self.load_row(0);
+            }
+        }
+    }
+
+    /// Computes the result columns into consecutive registers and yields
+    /// them.
+    fn result_row(&mut self, results: &'q [Expr]) -> Result<()> {
+        let start = self.alloc(results.len());
+        for (i, result) in results.iter().enumerate() {
+            self.expr(result, start + i)?;
+        }
+        self.emit(Op::ResultRow {
+            start,
+            count: results.len(),
+        });
+        Ok(())
+    }
+
+    fn column(&self, name: &str) -> Result<Reg> {
+        match self.names.iter().position(|n| n.eq_ignore_ascii_case(name)) {
+            Some(i) => Ok(self.columns + i),
+            None => bail!("column not found"),
+        }
+    }
+
+    fn collation(&self, name: &str) -> Collation {
+        match self.names.iter().position(|n| n.eq_ignore_ascii_case(name)) {
+            Some(i) => self.collations[i],
+            None => Collation::Binary,
+        }
+    }
+
+    fn affinity(&self, name: &str) -> Affinity {
+        match self.names.iter().position(|n| n.eq_ignore_ascii_case(name)) {
+            Some(i) => self.affinities[i],
+            None => Affinity::Blob,
+        }
+    }
+
+    fn load(&mut self, value: &Value, dest: Reg) {
+        self.emit(match value {
+            Value::Null => Op::Null { dest },
+            Value::Integer(value) => Op::Integer {
+                value: *value,
+                dest,
+            },
+            Value::Real(value) => Op::Real {
+                value: *value,
+                dest,
+            },
+            Value::Text(value) => Op::String8 {
+                value: value.clone(),
+                dest,
+            },
+            Value::Blob(value) => Op::Blob {
+                value: value.clone(),
+                dest,
+            },
+        });
+    }
+
+    /// A register holding the value of `expr`: a column's own register, or
+    /// a new one the value is computed into.
+    fn operand(&mut self, expr: &'q Expr) -> Result<Reg> {
+        match expr {
+            Expr::Column(name) | Expr::DoubleQuoted(name) => self.column(name),
+            Expr::Collate { expr, .. } => self.operand(expr),
+            _ => {
+                let reg = self.alloc(1);
+                self.expr(expr, reg)?;
+                Ok(reg)
+            }
+        }
+    }
+
+    /// Computes the value of `expr` into `dest`, as `eval` would.
+    fn expr(&mut self, expr: &'q Expr, dest: Reg) -> Result<()> {
+        match expr {
+            Expr::Literal(value) => self.load(value, dest),
+            Expr::Column(name) | Expr::DoubleQuoted(name) => {
+                let src = self.column(name)?;
+                self.emit(Op::Copy {
+                    src,
+                    dest,
+                    count: 1,
+                });
+            }
+            Expr::Variable(name) => bail!("unbound variable: {}", name),
+            Expr::CountStar => {
+                let Some(src) = self.count else {
+                    bail!("misuse of aggregate: count()");
+                };
+                self.emit(Op::Copy {
+                    src,
+                    dest,
+                    count: 1,
+                });
+            }
+            Expr::Collate { expr, .. } => self.expr(expr, dest)?,
+            Expr::Unsupported(u) => return Err(unsupported(u.capability.feature().name)),
+            Expr::Unary {
+                op: UnaryOp::Plus,
+                expr,
+            } => self.expr(expr, dest)?,
+            Expr::Unary { op, expr } => {
+                let src = self.operand(expr)?;
+                self.emit(Op::Unary { op: *op, src, dest });
+            }
+            Expr::IsNull { expr, negated } => {
+                let src = self.operand(expr)?;
+                self.emit(Op::Integer { value: 1, dest });
+                let jump = self.emit(if *negated {
+                    Op::NotNull { src, jump: 0 }
+                } else {
+                    Op::IsNull { src, jump: 0 }
+                });
+                self.emit(Op::Integer { value: 0, dest });
+                self.land(&[jump]);
+            }
+            // The right operand is skipped when the left one decides.
+            Expr::Binary {
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                left,
+                right,
+            } => {
+                let lhs = self.operand(left)?;
+                let and = *op == BinaryOp::And;
+                self.emit(Op::Integer {
+                    value: (!and) as i64,
+                    dest,
+                });
+                let jump = self.emit(if and {
+                    Op::IfNot {
+                        src: lhs,
+                        null_jumps: false,
+                        jump: 0,
+                    }
+                } else {
+                    Op::If {
+                        src: lhs,
+                        null_jumps: false,
+                        jump: 0,
+                    }
+                });
+                let rhs = self.operand(right)?;
+                self.emit(if and {
+                    Op::And { lhs, rhs, dest }
+                } else {
+                    Op::Or { lhs, rhs, dest }
+                });
+                self.land(&[jump]);
+            }
+            Expr::Binary { op, left, right } => {
+                let lhs = self.operand(left)?;
+                let rhs = self.operand(right)?;
+                let collation = comparison_collation(left, right, &|name| self.collation(name));
+                let affinity = comparison_affinity(left, right, &|name| self.affinity(name));
+                self.emit(Op::Binary {
+                    op: *op,
+                    lhs,
+                    rhs,
+                    dest,
+                    affinity,
+                    collation,
+                });
+            }
+            Expr::Function { name, .. } if is_aggregate(name) => {
+                let Some(&(_, src)) = self.aggregates.iter().find(|(c, _)| *c == expr) else {
+                    bail!("misuse of aggregate: {}()", name);
+                };
+                self.emit(Op::Copy {
+                    src,
+                    dest,
+                    count: 1,
+                });
+            }
+            Expr::Function { name, args } => {
+                let first = self.alloc(args.len());
+                for (i, arg) in args.iter().enumerate() {
+                    self.expr(arg, first + i)?;
+                }
+                self.emit(Op::Function {
+                    name: name.clone(),
+                    args: first,
+                    argc: args.len(),
+                    dest,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits a jump taken when `expr` is true, if `when`, or else when it is
+    /// false or NULL, and adds the jumps to `jumps` for the caller to land.
+    /// A comparison jumps directly; AND and OR skip their right operand when
+    /// the left one decides.
+    fn branch(&mut self, expr: &'q Expr, when: bool, jumps: &mut Vec<Addr>) -> Result<()> {
+        match expr {
+            Expr::Binary {
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                left,
+                right,
+            } => {
+                // `a AND b` is false as soon as `a` is; `a OR b` true as
+                // soon as `a` is. Otherwise `b` decides.
+                let decisive = *op == BinaryOp::Or;
+                if decisive == when {
+                    self.branch(left, when, jumps)?;
+                    self.branch(right, when, jumps)?;
+                } else {
+                    let mut decided = Vec::new();
+                    self.branch(left, !when, &mut decided)?;
+                    self.branch(right, when, jumps)?;
+                    self.land(&decided);
+                }
+            }
+            Expr::Binary { op, left, right } if let Some(comparison) = Comparison::from_op(*op) => {
+                let lhs = self.operand(left)?;
+                let rhs = self.operand(right)?;
+                let collation = comparison_collation(left, right, &|name| self.collation(name));
+                let affinity = comparison_affinity(left, right, &|name| self.affinity(name));
+                jumps.push(self.emit(Op::Compare {
+                    comparison: if when {
+                        comparison
+                    } else {
+                        comparison.negate()
+                    },
+                    lhs,
+                    rhs,
+                    affinity,
+                    collation,
+                    null_jumps: !when,
+                    jump: 0,
+                }));
+            }
+            Expr::IsNull { expr, negated } => {
+                let src = self.operand(expr)?;
+                jumps.push(self.emit(if when != *negated {
+                    Op::IsNull { src, jump: 0 }
+                } else {
+                    Op::NotNull { src, jump: 0 }
+                }));
+            }
+            _ => {
+                let src = self.operand(expr)?;
+                jumps.push(self.emit(if when {
+                    Op::If {
+                        src,
+                        null_jumps: false,
+                        jump: 0,
+                    }
+                } else {
+                    Op::IfNot {
+                        src,
+                        null_jumps: true,
+                        jump: 0,
+                    }
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse_statement;
+    use crate::vm::Vm;
+
+    /// A conformance fixture, and a query compiled against it.
+    fn compiled(db: &str, sql: &str) -> (Pager, Program) {
+        let path = format!("{}/tests/conformance/{}", env!("CARGO_MANIFEST_DIR"), db);
+        let settings = Settings::default();
+        let mut pager = Pager::open(&path, &settings).unwrap();
+        let (_, select) = parse_statement(sql).unwrap();
+        let program = compile(
+            &mut pager,
+            &select,
+            Input::Table,
+            Output::Results,
+            &settings,
+        )
+        .unwrap();
+        (pager, program)
+    }
+
+    /// A query's rows, sorted so that access paths reading them in another
+    /// order agree.
+    fn sorted_rows(db: &str, sql: &str) -> Vec<Vec<Value>> {
+        let (mut pager, program) = compiled(db, sql);
+        let settings = Settings::default();
+        let vm = Vm::new(&mut pager, program, &settings).unwrap();
+        let mut rows: Vec<Vec<Value>> = vm.collect::<Result<_>>().unwrap();
+        rows.sort_by(|a, b| a[0].compare(&b[0]));
+        rows
+    }
+
+    /// The indexes a query's program opens.
+    fn indexes_used(db: &str, sql: &str) -> Vec<String> {
+        let (_, program) = compiled(db, sql);
+        program
+            .cursors
+            .iter()
+            .filter_map(|cursor| match cursor {
+                CursorSpec::Index(index) => Some(index.name.clone()),
+                CursorSpec::Table { .. } | CursorSpec::Rows { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn index_on_an_expression_is_used() {
+        // people_lower is `CREATE INDEX people_lower ON people(lower(name))`.
+        for sql in [
+            "SELECT id, name FROM people WHERE lower(name) = 'alice'",
+            "SELECT id FROM people WHERE LOWER(name) >= 'b' AND lower(name) < 'd'",
+        ] {
+            assert_eq!(indexes_used("people.db", sql), ["people_lower"], "{}", sql);
+        }
+        // Another expression of the same column can't use it.
+        assert!(indexes_used(
+            "people.db",
+            "SELECT id FROM people WHERE upper(name) = 'ALICE'"
+        )
+        .is_empty());
+    }
+
+    /// An index holds its column's values with the column's affinity, so a
+    /// seek must convert the literal as the comparison does or it finds
+    /// other rows than a scan. compare_indexed.db has a column of each
+    /// affinity, each indexed.
+    #[test]
+    fn index_and_full_scan_find_the_same_rows() {
+        let db = "compare_indexed.db";
+        for term in [
+            "a > 5",
+            "a = 5",
+            "a < 50",
+            "a = '9'",
+            "n = '10'",
+            "n < '5.5'",
+            "n > 'a'",
+            "d = 1",
+            "d = '1'",
+            "d > 1",
+            "d < '2'",
+            "i = '10'",
+            "i > '9'",
+            "r = '10'",
+            "r < 9.5",
+        ] {
+            let indexed = format!("SELECT id FROM t WHERE {}", term);
+            // As true as the term, but no index serves it.
+            let scanned = format!("SELECT id FROM t WHERE ({}) OR 0", term);
+            assert!(!indexes_used(db, &indexed).is_empty(), "{}", indexed);
+            assert!(indexes_used(db, &scanned).is_empty(), "{}", scanned);
+            assert_eq!(
+                sorted_rows(db, &indexed),
+                sorted_rows(db, &scanned),
+                "{}",
+                term
+            );
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use anyhow::{bail, Result};
+
+use crate::error::{unsupported, RqliteError};
+use crate::pager::Pager;
+use crate::sql::{parse_create_index, parse_create_table, tokenize, ColumnConstraint};
+use crate::sql::{IndexedColumn, TableConstraint, TableSchema, Token};
+use crate::support::check_table;
+use crate::value::Value;
+use crate::{read_schema_rows, QueryResult, SchemaRow};
+
+/// Runs `PRAGMA [main.]name`, `PRAGMA name(arg)` or `PRAGMA name = value`
+/// for the pragmas that report on the database, with sqlite3's columns.
+/// Setting one would write to the file, so only reading is supported.
+pub fn pragma(pager: &mut Pager, sql: &str) -> Result<QueryResult> {
+    let tokens = tokenize(sql)?;
+    let mut tokens = tokens
+        .iter()
+        .skip(1)
+        .filter(|t| **t != Token::Semicolon)
+        .peekable();
+    let mut name = match tokens.next().and_then(name_of) {
+        Some(name) => name,
+        None => bail!("missing pragma name"),
+    };
+    if tokens.next_if_eq(&&Token::Dot).is_some() {
+        if !name.eq_ignore_ascii_case("main") {
+            bail!("unknown database {}", name);
+        }
+        name = match tokens.next().and_then(name_of) {
+            Some(name) => name,
+            None => bail!("missing pragma name"),
+        };
+    }
+    let name = name.to_ascii_lowercase();
+    let arg = match tokens.next() {
+        None => None,
+        Some(Token::LParen) => {
+            let arg = tokens.next().and_then(name_of);
+            if tokens.next() != Some(&Token::RParen) {
+                bail!("malformed PRAGMA {}", name);
+            }
+            arg
+        }
+        Some(Token::Eq) => {
+            return Err(unsupported(format!(
+                "PRAGMA {} = ... (no write support yet)",
+                name
+            )))
+        }
+        Some(_) => bail!("malformed PRAGMA {}", name),
+    };
+    if tokens.next().is_some() {
+        bail!("malformed PRAGMA {}", name);
+    }
+
+    let header = pager.header()?;
+    let value = match name.as_str() {
+        "page_size" => Value::Integer(header.page_size as i64),
+        "page_count" => Value::Integer(pager.page_count(&header)? as i64),
+        // Both are stored as signed 32-bit integers.
+        "user_version" => Value::Integer(header.user_version as i32 as i64),
+        "application_id" => Value::Integer(header.application_id as i32 as i64),
+        "freelist_count" => Value::Integer(header.freelist_pages as i64),
+        "journal_mode" => Value::Text(if header.is_wal() { "wal" } else { "delete" }.to_string()),
+        "encoding" => Value::Text(
+            match header.text_encoding_code {
+                2 => "UTF-16le",
+                3 => "UTF-16be",
+                _ => "UTF-8",
+            }
+            .to_string(),
+        ),
+        "table_info" | "index_list" | "index_info" => {
+            let schema = read_schema_rows(pager)?;
+            let (columns, rows) = match (name.as_str(), arg) {
+                (_, None) => (&[][..], Vec::new()),
+                ("table_info", Some(table)) => (
+                    &["cid", "name", "type", "notnull", "dflt_value", "pk"][..],
+                    table_info(&schema, &table)?,
+                ),
+                ("index_list", Some(table)) => (
+                    &["seq", "name", "unique", "origin", "partial"][..],
+                    index_list(&schema, &table)?,
+                ),
+                (_, Some(index)) => (&["seqno", "cid", "name"][..], index_info(&schema, &index)),
+            };
+            return Ok(QueryResult {
+                columns: columns.iter().map(|c| c.to_string()).collect(),
+                rows,
+            });
+        }
+        _ => return Err(unsupported(format!("PRAGMA {}", name))),
+    };
+    Ok(QueryResult {
+        columns: vec![name],
+        rows: vec![vec![value]],
+    })
+}
+
+/// A pragma or table name, or an argument given as a string or number.
+fn name_of(token: &Token) -> Option<String> {
+    match token {
+        Token::Ident(s) | Token::QuotedIdent(s) | Token::DoubleQuoted(s) | Token::Str(s) => {
+            Some(s.clone())
+        }
+        Token::Integer(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Runs `.indexes [TABLE]`: each index's name, table and keys, for every
+/// table in schema order or for the one named. As in `index_list`, the
+/// automatic indexes behind PRIMARY KEY and UNIQUE constraints are
+/// included.
+pub fn index_summary(pager: &mut Pager, table: Option<&str>) -> Result<QueryResult> {
+    let schema = read_schema_rows(pager)?;
+    let tables: Vec<TableSchema> = match table {
+        Some(name) => match find_table(&schema, name)? {
+            Some(table) => vec![table],
+            None => {
+                return Err(RqliteError::TableNotFound {
+                    name: name.to_string(),
+                }
+                .into());
+            }
+        },
+        None => schema
+            .iter()
+            .filter(|r| r.kind == "table" && !r.name.starts_with("sqlite_"))
+            .filter_map(|r| parse_create_table(&r.sql).ok())
+            .collect(),
+    };
+    let rows = tables
+        .iter()
+        .flat_map(|table| {
+            indexes(&schema, table).into_iter().map(|index| {
+                vec![
+                    Value::Text(index.name),
+                    Value::Text(table.name.clone()),
+                    Value::Text(index.keys.join(", ")),
+                ]
+            })
+        })
+        .collect();
+    Ok(QueryResult {
+        columns: ["name", "table", "columns"].map(String::from).to_vec(),
+        rows,
+    })
+}
+
+/// The definition of table `name`; None if there is no such table, as
+/// sqlite3 then prints nothing.
+fn find_table(schema: &[SchemaRow], name: &str) -> Result<Option<TableSchema>> {
+    let Some(row) = schema
+        .iter()
+        .find(|r| matches!(r.kind.as_str(), "table" | "view") && r.name.eq_ignore_ascii_case(name))
+    else {
+        return Ok(None);
+    };
+    check_table(&row.kind, &row.name, &row.sql)?;
+    Ok(Some(parse_create_table(&row.sql)?))
+}
+
+fn table_info(schema: &[SchemaRow], table: &str) -> Result<Vec<Vec<Value>>> {
+    let Some(table) = find_table(schema, table)? else {
+        return Ok(Vec::new());
+    };
+    let key = table.primary_key();
+    let mut rows = Vec::new();
+    // Generated columns are hidden from table_info.
+    let visible = table.columns.iter().filter(|c| {
+        !c.constraints
+            .iter()
+            .any(|k| matches!(k, ColumnConstraint::Generated { .. }))
+    });
+    for (cid, column) in visible.enumerate() {
+        let pk = key
+            .iter()
+            .position(|k| k.eq_ignore_ascii_case(&column.name))
+            .map_or(0, |i| i + 1);
+        // A WITHOUT ROWID table's key columns are implicitly NOT NULL.
+        let not_null = (table.without_rowid && pk > 0)
+            || column
+                .constraints
+                .iter()
+                .any(|k| matches!(k, ColumnConstraint::NotNull));
+        let default = column.constraints.iter().find_map(|k| match k {
+            ColumnConstraint::Default { sql, .. } => Some(Value::Text(sql.clone())),
+            _ => None,
+        });
+        rows.push(vec![
+            Value::Integer(cid as i64),
+            Value::Text(column.name.clone()),
+            Value::Text(declared_type(&column.type_name)),
+            Value::Integer(not_null as i64),
+            default.unwrap_or(Value::Null),
+            Value::Integer(pk as i64),
+        ]);
+    }
+    Ok(rows)
+}
+
+/// A column's type as SQLite reports it: the standard type names, which
+/// SQLite recognizes when parsing, in upper case; any other as written.
+fn declared_type(type_name: &str) -> String {
+    const STANDARD: [&str; 6] = ["ANY", "BLOB", "INT", "INTEGER", "REAL", "TEXT"];
+    match STANDARD.iter().find(|t| t.eq_ignore_ascii_case(type_name)) {
+        Some(t) => t.to_string(),
+        None => type_name.to_string(),
+    }
+}
+
+/// An index as index_list and index_info describe it.
+struct IndexEntry {
+    name: String,
+    unique: bool,
+    /// `c` for CREATE INDEX, `u` for a UNIQUE constraint, `pk` for a
+    /// PRIMARY KEY.
+    origin: &'static str,
+    partial: bool,
+    /// Each key's table column number (-2 for an expression) and name.
+    columns: Vec<(i64, Option<String>)>,
+    /// Each key as written: a column name or an expression, with its
+    /// COLLATE and DESC if it has them.
+    keys: Vec<String>,
+}
+
+fn index_list(schema: &[SchemaRow], table: &str) -> Result<Vec<Vec<Value>>> {
+    let Some(definition) = find_table(schema, table)? else {
+        return Ok(Vec::new());
+    };
+    // Newest first, as sqlite3 lists them.
+    let rows = indexes(schema, &definition)
+        .into_iter()
+        .rev()
+        .enumerate()
+        .map(|(seq, index)| {
+            vec![
+                Value::Integer(seq as i64),
+                Value::Text(index.name),
+                Value::Integer(index.unique as i64),
+                Value::Text(index.origin.to_string()),
+                Value::Integer(index.partial as i64),
+            ]
+        })
+        .collect();
+    Ok(rows)
+}
+
+fn index_info(schema: &[SchemaRow], index: &str) -> Vec<Vec<Value>> {
+    let tables = schema
+        .iter()
+        .filter(|r| r.kind == "table")
+        .filter_map(|r| parse_create_table(&r.sql).ok());
+    for table in tables {
+        let found = indexes(schema, &table)
+            .into_iter()
+            .find(|i| i.name.eq_ignore_ascii_case(index));
+        if let Some(found) = found {
+            return found
+                .columns
+                .into_iter()
+                .enumerate()
+                .map(|(seqno, (cid, name))| {
+                    vec![
+                        Value::Integer(seqno as i64),
+                        Value::Integer(cid),
+                        name.map_or(Value::Null, Value::Text),
+                    ]
+                })
+                .collect();
+        }
+    }
+    Vec::new()
+}
+
+/// A table's indexes in the order they were created: first those its
+/// PRIMARY KEY and UNIQUE constraints imply, then its CREATE INDEXes.
+fn indexes(schema: &[SchemaRow], table: &TableSchema) -> Vec<IndexEntry> {
+    let column = |name: &str| match table.column_index(name) {
+        Some(cid) => (cid as i64, Some(table.columns[cid].name.clone())),
+        None => (-1, None),
+    };
+    let mut entries: Vec<IndexEntry> = Vec::new();
+    for (keys, primary) in constraint_keys(table) {
+        // A constraint on the same columns as an earlier one shares its
+        // index, and takes no number of its own.
+        let same = entries.iter_mut().find(|e| {
+            e.columns.len() == keys.len()
+                && e.columns
+                    .iter()
+                    .zip(&keys)
+                    .all(|(c, k)| c.1.as_ref().is_some_and(|c| c.eq_ignore_ascii_case(k)))
+        });
+        if let Some(same) = same {
+            if primary {
+                same.origin = "pk";
+            }
+            continue;
+        }
+        entries.push(IndexEntry {
+            name: format!("sqlite_autoindex_{}_{}", table.name, entries.len() + 1),
+            unique: true,
+            origin: if primary { "pk" } else { "u" },
+            partial: false,
+            columns: keys.iter().map(|k| column(k)).collect(),
+            keys,
+        });
+    }
+    let created = schema
+        .iter()
+        .filter(|r| r.kind == "index" && r.tbl_name.eq_ignore_ascii_case(&table.name))
+        .filter_map(|r| parse_create_index(&r.sql).ok());
+    for index in created {
+        entries.push(IndexEntry {
+            name: index.name,
+            unique: index.unique,
+            origin: "c",
+            partial: index.where_sql.is_some(),
+            columns: index
+                .columns
+                .iter()
+                .map(|c| {
+                    if c.is_expression {
+                        (-2, None)
+                    } else {
+                        column(&c.name)
+                    }
+                })
+                .collect(),
+            keys: index.columns.iter().map(key_text).collect(),
+        });
+    }
+    entries
+}
+
+/// An index key as `.indexes` shows it, such as `name COLLATE NOCASE DESC`.
+fn key_text(column: &IndexedColumn) -> String {
+    let mut text = column.name.clone();
+    if let Some(collation) = &column.collation {
+        text.push_str(" COLLATE ");
+        text.push_str(collation);
+    }
+    if column.descending {
+        text.push_str(" DESC");
+    }
+    text
+}
+
+/// The key columns of each PRIMARY KEY and UNIQUE constraint that needs an
+/// index, in the order SQLite creates them, and whether it is the key. An
+/// INTEGER PRIMARY KEY is the rowid and needs none.
+fn constraint_keys(table: &TableSchema) -> Vec<(Vec<String>, bool)> {
+    let alias = table.rowid_alias();
+    let mut keys = Vec::new();
+    for (i, column) in table.columns.iter().enumerate() {
+        for constraint in &column.constraints {
+            match constraint {
+                ColumnConstraint::PrimaryKey { .. } if alias != Some(i) => {
+                    keys.push((vec![column.name.clone()], true))
+                }
+                ColumnConstraint::Unique => keys.push((vec![column.name.clone()], false)),
+                _ => {}
+            }
+        }
+    }
+    for constraint in &table.constraints {
+        match constraint {
+            TableConstraint::PrimaryKey(columns) => {
+                let is_alias = columns.len() == 1
+                    && alias.is_some()
+                    && alias == table.column_index(&columns[0]);
+                if !is_alias {
+                    keys.push((columns.clone(), true));
+                }
+            }
+            TableConstraint::Unique(columns) => keys.push((columns.clone(), false)),
+            _ => {}
+        }
+    }
+    keys
+}
@@ -0,0 +1,325 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use crate::dump::{quote_name, sql_literal, table_columns};
+use crate::freelist::read_freelist;
+use crate::header::DbHeader;
+use crate::pager::Pager;
+use crate::record::{decode_value, read_varint, real_affinity, serial_type_size, ColumnSource};
+use crate::redact::{Mask, Redactions};
+use crate::settings::Settings;
+use crate::value::Value;
+use crate::{read_schema_rows, SchemaRow};
+
+/// A table whose rows can be put back: its schema row, the INSERT column
+/// list, and where each column's value comes from.
+struct Target<'a> {
+    row: &'a SchemaRow,
+    columns: String,
+    sources: Vec<ColumnSource>,
+    masks: Vec<Option<Mask>>,
+    rows: Vec<Vec<Value>>,
+}
+
+/// Writes SQL that rebuilds as much of a damaged database as can be read,
+/// as sqlite3's `.recover` does. Rather than walking each table's b-tree,
+/// which stops at the first bad page, every page of the file is examined
+/// and each table leaf cell that decodes is kept. A leaf is credited to a
+/// table when the table's b-tree still reaches it; rows from leaves nothing
+/// reaches go into a `lost_and_found` table with their page number, field
+/// count and rowid. Free pages are skipped, so deleted rows stay deleted,
+/// and WITHOUT ROWID tables, whose rows live in index pages, are not
+/// recovered.
+pub fn recover(path: &str, settings: &Settings, out: &mut dyn Write) -> Result<()> {
+    let mut pager = Pager::open(path, settings)?;
+    let header = pager.header()?;
+    let page_count = pager.page_count(&header)?.min(u32::MAX as u64) as u32;
+    // A damaged schema or freelist leaves everything unattributed, or every
+    // page a candidate, rather than ending the recovery.
+    let schema = read_schema_rows(&mut pager).unwrap_or_default();
+    let free: HashSet<u32> = match read_freelist(&mut pager, &header) {
+        Ok(freelist) => freelist.trunks.into_iter().chain(freelist.leaves).collect(),
+        Err(_) => HashSet::new(),
+    };
+
+    let mut targets = Vec::new();
+    let mut owners: HashMap<u32, Option<usize>> = HashMap::new();
+    // The schema's own b-tree holds no rows to recover.
+    for page_no in tree_pages(&mut pager, &header, 1, page_count).0 {
+        owners.insert(page_no, None);
+    }
+    for row in &schema {
+        let internal = row.name.starts_with("sqlite_") && row.name != "sqlite_sequence";
+        if row.kind != "table" || row.rootpage == 0 || internal {
+            continue;
+        }
+        let Ok((columns, sources, masks)) = table_columns(row, &settings.redactions) else {
+            continue;
+        };
+        let (leaves, interior) = tree_pages(&mut pager, &header, row.rootpage, page_count);
+        for page_no in leaves {
+            owners.entry(page_no).or_insert(Some(targets.len()));
+        }
+        for page_no in interior {
+            owners.entry(page_no).or_insert(None);
+        }
+        targets.push(Target {
+            row,
+            columns,
+            sources,
+            masks,
+            rows: Vec::new(),
+        });
+    }
+
+    let mut lost = Vec::new();
+    for page_no in 1..=page_count {
+        if free.contains(&page_no) {
+            continue;
+        }
+        let owner = owners.get(&page_no).copied();
+        if owner == Some(None) {
+            continue;
+        }
+        let Ok(page) = pager.read_page(page_no, &header) else {
+            continue;
+        };
+        let cells = leaf_cells(&mut pager, &header, page_no, &page, page_count);
+        match owner {
+            Some(Some(target)) => {
+                let target = &mut targets[target];
+                for (rowid, fields) in cells {
+                    let mut values: Vec<Value> = target
+                        .sources
+                        .iter()
+                        .map(|source| match *source {
+                            ColumnSource::Rowid => Value::Integer(rowid),
+                            ColumnSource::Field(i) => fields.get(i).cloned().unwrap_or(Value::Null),
+                            ColumnSource::Real(i) => {
+                                real_affinity(fields.get(i).cloned().unwrap_or(Value::Null))
+                            }
+                        })
+                        .collect();
+                    Redactions::apply(&target.masks, &mut values);
+                    target.rows.push(values);
+                }
+            }
+            _ => {
+                for (rowid, fields) in cells {
+                    let mut row = vec![
+                        Value::Null,
+                        Value::Integer(page_no as i64),
+                        Value::Integer(fields.len() as i64),
+                        Value::Integer(rowid),
+                    ];
+                    row.extend(fields);
+                    lost.push(row);
+                }
+            }
+        }
+    }
+
+    writeln!(out, "BEGIN;")?;
+    for row in schema.iter().filter(|r| r.kind == "table") {
+        if !row.name.starts_with("sqlite_") {
+            writeln!(out, "{};", row.sql)?;
+        }
+    }
+    for target in &targets {
+        if target.row.name == "sqlite_sequence" {
+            writeln!(out, "DELETE FROM sqlite_sequence;")?;
+        }
+        for values in &target.rows {
+            let literals: Vec<String> = values.iter().map(sql_literal).collect();
+            writeln!(
+                out,
+                "INSERT OR IGNORE INTO {}{} VALUES({});",
+                quote_name(&target.row.name),
+                target.columns,
+                literals.join(",")
+            )?;
+        }
+    }
+    for row in &schema {
+        if row.kind != "table" && !row.sql.is_empty() && !row.name.starts_with("sqlite_") {
+            writeln!(out, "{};", row.sql)?;
+        }
+    }
+    // Lost rows can't be matched to a redaction profile's columns, so
+    // under a profile they are left out rather than shown unmasked.
+    if !lost.is_empty() && settings.redactions.is_empty() {
+        let mut name = "lost_and_found".to_string();
+        let mut n = 0;
+        while schema.iter().any(|r| r.name.eq_ignore_ascii_case(&name)) {
+            name = format!("lost_and_found_{}", n);
+            n += 1;
+        }
+        let fields = lost.iter().map(|r| r.len() - 4).max().unwrap_or(0);
+        let columns: String = (0..fields).map(|i| format!(", c{}", i)).collect();
+        writeln!(
+            out,
+            "CREATE TABLE {}(rootpgno INTEGER, pgno INTEGER, nfield INTEGER, id INTEGER{});",
+            name, columns
+        )?;
+        for row in &lost {
+            let literals: Vec<String> = row.iter().map(sql_literal).collect();
+            writeln!(out, "INSERT INTO {} VALUES({});", name, literals.join(","))?;
+        }
+    }
+    writeln!(out, "COMMIT;")?;
+    Ok(())
+}
+
+/// The leaf and interior pages a table b-tree reaches from `root`, as far
+/// as it can be followed: a page that can't be read or isn't a table page
+/// ends its branch, and no page is visited twice.
+fn tree_pages(
+    pager: &mut Pager,
+    header: &DbHeader,
+    root: u32,
+    page_count: u32,
+) -> (Vec<u32>, Vec<u32>) {
+    let mut leaves = Vec::new();
+    let mut interior = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(page_no) = stack.pop() {
+        if page_no == 0 || page_no > page_count || !seen.insert(page_no) {
+            continue;
+        }
+        let Ok(page) = pager.read_page(page_no, header) else {
+            continue;
+        };
+        let page = &page[..header.usable_size().min(page.len())];
+        let at = if page_no == 1 { 100 } else { 0 };
+        match page.get(at) {
+            Some(0x0D) => leaves.push(page_no),
+            Some(0x05) => {
+                interior.push(page_no);
+                let Some(cells) = cell_offsets(page, at, 12) else {
+                    continue;
+                };
+                stack.extend(cells.into_iter().filter_map(|c| be32(page, c)));
+                stack.extend(be32(page, at + 8));
+            }
+            _ => {}
+        }
+    }
+    (leaves, interior)
+}
+
+/// The rowid and fields of each cell on a table leaf page that decodes in
+/// full; cells that don't are dropped. Any other page has none.
+fn leaf_cells(
+    pager: &mut Pager,
+    header: &DbHeader,
+    page_no: u32,
+    page: &[u8],
+    page_count: u32,
+) -> Vec<(i64, Vec<Value>)> {
+    let page = &page[..header.usable_size().min(page.len())];
+    let at = if page_no == 1 { 100 } else { 0 };
+    if page.get(at) != Some(&0x0D) {
+        return Vec::new();
+    }
+    let Some(cells) = cell_offsets(page, at, 8) else {
+        return Vec::new();
+    };
+    cells
+        .into_iter()
+        .filter_map(|offset| {
+            let (payload_size, n1) = read_varint(page, offset)?;
+            let (rowid, n2) = read_varint(page, offset + n1)?;
+            let payload = payload(
+                pager,
+                header,
+                page,
+                offset + n1 + n2,
+                payload_size,
+                page_count,
+            )?;
+            Some((rowid as i64, record_fields(&payload, header)?))
+        })
+        .collect()
+}
+
+/// The cell offsets in a page's pointer array, if the array and every
+/// offset lie within the page.
+fn cell_offsets(page: &[u8], at: usize, header_size: usize) -> Option<Vec<usize>> {
+    let count = u16::from_be_bytes([*page.get(at + 3)?, *page.get(at + 4)?]) as usize;
+    (0..count)
+        .map(|i| {
+            let idx = at + header_size + i * 2;
+            let offset = u16::from_be_bytes([*page.get(idx)?, *page.get(idx + 1)?]) as usize;
+            (offset < page.len()).then_some(offset)
+        })
+        .collect()
+}
+
+/// A cell's whole payload, starting at `start`: the part on the page and the
+/// rest from its overflow chain.
+fn payload(
+    pager: &mut Pager,
+    header: &DbHeader,
+    page: &[u8],
+    start: usize,
+    size: u64,
+    page_count: u32,
+) -> Option<Vec<u8>> {
+    let size = usize::try_from(size).ok()?;
+    let usable = header.usable_size();
+    let max_local = usable - 35;
+    let local = if size <= max_local {
+        size
+    } else {
+        let min_local = (usable - 12) * 32 / 255 - 23;
+        let k = min_local + (size - min_local) % (usable - 4);
+        if k <= max_local {
+            k
+        } else {
+            min_local
+        }
+    };
+    let mut payload = page.get(start..start + local)?.to_vec();
+    if local == size {
+        return Some(payload);
+    }
+    let mut next = be32(page, start + local)?;
+    // Each overflow page adds at least one byte, so a longer chain loops.
+    for _ in 0..page_count {
+        if next == 0 || next > page_count {
+            return None;
+        }
+        let overflow = pager.read_page(next, header).ok()?;
+        let take = (size - payload.len()).min(usable - 4);
+        payload.extend_from_slice(overflow.get(4..4 + take)?);
+        if payload.len() == size {
+            return Some(payload);
+        }
+        next = be32(&overflow, 0)?;
+    }
+    None
+}
+
+/// The fields of a record, decoding text leniently; None if its header or
+/// body runs past the payload.
+fn record_fields(payload: &[u8], header: &DbHeader) -> Option<Vec<Value>> {
+    let (header_size, mut pos) = read_varint(payload, 0)?;
+    let header_size = usize::try_from(header_size).ok()?;
+    let mut body = header_size;
+    let mut fields = Vec::new();
+    while pos < header_size {
+        let (serial, n) = read_varint(payload, pos)?;
+        pos += n;
+        let size = serial_type_size(serial);
+        let bytes = payload.get(body..body.checked_add(size)?)?;
+        body += size;
+        fields.push(decode_value(serial, bytes, header.text_encoding, true).ok()?);
+    }
+    Some(fields)
+}
+
+fn be32(page: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(page.get(at..at + 4)?.try_into().ok()?))
+}
@@ -0,0 +1,141 @@
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+
+use crate::json::{self, Json};
+use crate::value::Value;
+
+/// How a redacted column's values are replaced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mask {
+    /// Every value becomes NULL.
+    Null,
+    /// The hex SHA-256 of the value, so equal values still match (in joins
+    /// or GROUP BY) without revealing what they were.
+    Hash,
+    /// Text with all but its last four characters replaced by `*`. At most
+    /// half the characters are kept, and text of four or fewer is masked
+    /// whole, so a short value is never shown in full.
+    Partial,
+}
+
+impl Mask {
+    fn parse(name: &str) -> Result<Mask> {
+        match name.to_ascii_lowercase().as_str() {
+            "null" => Ok(Mask::Null),
+            "hash" => Ok(Mask::Hash),
+            "partial" => Ok(Mask::Partial),
+            _ => bail!("unknown mask: {} (expected null, hash or partial)", name),
+        }
+    }
+
+    /// NULL stays NULL under every mask, so redaction doesn't hide which
+    /// values are missing.
+    pub fn apply(self, value: &Value) -> Value {
+        if value.is_null() {
+            return Value::Null;
+        }
+        match self {
+            Mask::Null => Value::Null,
+            Mask::Hash => {
+                let digest = Sha256::digest(value.to_bytes());
+                Value::Text(digest.iter().map(|b| format!("{:02x}", b)).collect())
+            }
+            Mask::Partial => {
+                let text = value.to_string();
+                let len = text.chars().count();
+                let shown = if len <= 4 { 0 } else { (len / 2).min(4) };
+                let kept = text.chars().skip(len - shown);
+                Value::Text("*".repeat(len - shown) + &kept.collect::<String>())
+            }
+        }
+    }
+}
+
+/// A redaction profile: the mask for each `table.column` it names. Loaded
+/// from a JSON object such as `{"users.email": "hash", "users.ssn":
+/// "partial"}`.
+#[derive(Debug, Clone, Default)]
+pub struct Redactions {
+    /// The file the profile came from, for `.show`.
+    pub source: String,
+    masks: Vec<(String, String, Mask)>,
+}
+
+impl Redactions {
+    pub fn load(path: &str) -> Result<Redactions> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => bail!("cannot read redaction profile {}: {}", path, e),
+        };
+        let Json::Object(members) = json::parse(&text)? else {
+            bail!("redaction profile must be a JSON object");
+        };
+        let mut masks = Vec::new();
+        for (key, mask) in members {
+            let Some((table, column)) = key.split_once('.') else {
+                bail!("expected table.column in redaction profile, got {}", key);
+            };
+            let Json::String(mask) = mask else {
+                bail!("mask for {} must be a string", key);
+            };
+            masks.push((table.to_string(), column.to_string(), Mask::parse(&mask)?));
+        }
+        Ok(Redactions {
+            source: path.to_string(),
+            masks,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.masks.is_empty()
+    }
+
+    /// The mask for a column, matching names case-insensitively as SQLite
+    /// does.
+    pub fn mask(&self, table: &str, column: &str) -> Option<Mask> {
+        self.masks
+            .iter()
+            .find(|(t, c, _)| t.eq_ignore_ascii_case(table) && c.eq_ignore_ascii_case(column))
+            .map(|&(_, _, mask)| mask)
+    }
+
+    /// Masks a row in place; `masks` lines up with its values.
+    pub fn apply(masks: &[Option<Mask>], values: &mut [Value]) {
+        for (value, mask) in values.iter_mut().zip(masks) {
+            if let Some(mask) = mask {
+                *value = mask.apply(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(text: &str) -> Value {
+        Mask::Partial.apply(&Value::Text(text.to_string()))
+    }
+
+    #[test]
+    fn partial_keeps_the_last_four() {
+        assert_eq!(partial("123-45-6789"), Value::Text("*******6789".into()));
+        assert_eq!(partial("12345678"), Value::Text("****5678".into()));
+        assert_eq!(
+            Mask::Partial.apply(&Value::Integer(4111111111111111)),
+            Value::Text("************1111".into())
+        );
+    }
+
+    #[test]
+    fn partial_never_shows_a_short_value() {
+        assert_eq!(partial(""), Value::Text("".into()));
+        assert_eq!(partial("7"), Value::Text("*".into()));
+        assert_eq!(partial("1234"), Value::Text("****".into()));
+        // Longer values keep at most half.
+        assert_eq!(partial("12345"), Value::Text("***45".into()));
+        assert_eq!(partial("1234567"), Value::Text("****567".into()));
+        assert_eq!(partial("ünï"), Value::Text("***".into()));
+        assert_eq!(Mask::Partial.apply(&Value::Null), Value::Null);
+    }
+}
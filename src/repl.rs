@@ -0,0 +1,367 @@
+use anyhow::{bail, Result};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+use std::fs::File;
+use std::io::{BufRead, BufReader, IsTerminal};
+use std::path::PathBuf;
+
+use crate::help::COMMANDS;
+use crate::sql::{is_blank, parse_create_table, split_statements, Location};
+use crate::{execute, read_schema_rows, report, Context};
+
+const PROMPT: &str = "rqlite> ";
+/// Shown while a statement continues onto another line.
+const CONTINUE: &str = "   ...> ";
+
+/// Offered for completion alongside table and column names.
+const KEYWORDS: &[&str] = &[
+    "ALL", "AND", "ANALYZE", "AS", "ASC", "BETWEEN", "BY", "CASE", "CAST", "COLLATE", "CROSS",
+    "DESC", "DISTINCT", "ELSE", "END", "EXISTS", "EXPLAIN", "FROM", "GLOB", "GROUP", "HAVING",
+    "IN", "INNER", "IS", "JOIN", "LEFT", "LIKE", "LIMIT", "NATURAL", "NOT", "NULL", "ON", "OR",
+    "ORDER", "PRAGMA", "REGEXP", "SELECT", "THEN", "UNION", "WHEN", "WHERE",
+];
+
+/// Reads SQL and dot-commands and runs each against `context`, until
+/// `.quit`, `.exit` or end of input. A dot-command is one line; SQL runs
+/// once a `;` ends it, however many lines it spans, and a line may hold
+/// several statements. A command that fails is reported and the shell
+/// carries on, as sqlite3's does. At a terminal lines are read with
+/// editing, history kept in `~/.rqlite_history`, and tab completion; piped
+/// input is read plainly and shows no prompt, so only the output is
+/// printed, and fails at its end if any command did.
+pub fn run(context: &mut Context) -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        let failures = run_lines(context, std::io::stdin().lock().lines(), None, false)?;
+        return check(failures, "stdin");
+    }
+
+    let mut editor: Editor<Completion, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(Completion::default()));
+    let history = history_path();
+    if let Some(path) = &history {
+        // Missing on first use.
+        let _ = editor.load_history(path);
+    }
+    let mut input = Commands::default();
+    'lines: loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.refresh(context);
+        }
+        let prompt = if input.pending.is_empty() {
+            PROMPT
+        } else {
+            CONTINUE
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C abandons the statement being typed, as in sqlite3.
+            Err(ReadlineError::Interrupted) => {
+                input.pending.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => {
+                if let Some((command, _)) = input.finish() {
+                    run_command(context, &command, None);
+                }
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str())?;
+        }
+        for (command, _) in input.push(&line) {
+            if is_quit(&command) {
+                break 'lines;
+            }
+            run_command(context, &command, None);
+        }
+    }
+    if let Some(path) = &history
+        && let Err(e) = editor.save_history(path)
+    {
+        eprintln!("Error: could not save history to {}: {}", path.display(), e);
+    }
+    Ok(())
+}
+
+/// Runs the script in `path` for `.read`, reporting each failure with the
+/// line it is on. With `bail` the first failure ends the script; otherwise
+/// the rest still runs. Either way `.read` fails if a command did.
+pub fn read(context: &mut Context, path: &str, bail: bool) -> Result<()> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => bail!("cannot open \"{}\": {}", path, e),
+    };
+    let lines = BufReader::new(file).lines();
+    check(run_lines(context, lines, Some(path), bail)?, path)
+}
+
+/// Fails if any command from `source` did, each having been reported.
+fn check(failures: Failures, source: &str) -> Result<()> {
+    match failures {
+        Failures::None => Ok(()),
+        Failures::Stopped(line) => bail!("{} stopped at line {}", source, line),
+        Failures::Counted(1) => bail!("1 command in {} failed", source),
+        Failures::Counted(n) => bail!("{} commands in {} failed", n, source),
+    }
+}
+
+/// What went wrong in a script.
+enum Failures {
+    None,
+    /// Commands that failed, when the script carried on past them.
+    Counted(usize),
+    /// The line of the command that ended the script.
+    Stopped(usize),
+}
+
+/// Runs each command in `lines` until `.quit`, `.exit` or their end.
+/// `source` names the file they come from, for error messages.
+fn run_lines(
+    context: &mut Context,
+    lines: impl Iterator<Item = std::io::Result<String>>,
+    source: Option<&str>,
+    bail: bool,
+) -> Result<Failures> {
+    let mut input = Commands::default();
+    let mut failed = 0;
+    let mut commands = Vec::new();
+    let mut lines = lines.fuse();
+    loop {
+        match lines.next() {
+            Some(line) => commands.extend(input.push(&line?)),
+            None => commands.extend(input.finish()),
+        }
+        for (command, (line, column)) in commands.drain(..) {
+            if is_quit(&command) {
+                return Ok(counted(failed));
+            }
+            let at = source.map(|file| Location { file, line, column });
+            if !run_command(context, &command, at.as_ref()) {
+                if bail {
+                    return Ok(Failures::Stopped(line));
+                }
+                failed += 1;
+            }
+        }
+        if input.done {
+            return Ok(counted(failed));
+        }
+    }
+}
+
+fn counted(failed: usize) -> Failures {
+    match failed {
+        0 => Failures::None,
+        n => Failures::Counted(n),
+    }
+}
+
+fn is_quit(command: &str) -> bool {
+    matches!(command, ".quit" | ".exit")
+}
+
+/// Runs one command, reporting a failure, with where it is in a script
+/// when `at` says; false if it failed.
+fn run_command(context: &mut Context, command: &str, at: Option<&Location>) -> bool {
+    match execute(context, command) {
+        Ok(()) => true,
+        Err(e) => {
+            report(&e, at);
+            false
+        }
+    }
+}
+
+/// Lines of input gathered into commands. A dot-command is one line; SQL
+/// runs once a `;` ends it, however many lines it spans, and a line may
+/// hold several statements.
+#[derive(Default)]
+struct Commands {
+    /// SQL read but not yet ended.
+    pending: String,
+    /// The line and column `pending` starts at, counted from 1.
+    start: (usize, usize),
+    /// Lines taken so far.
+    lines: usize,
+    /// Set once the end of input has been taken.
+    done: bool,
+}
+
+impl Commands {
+    /// Takes the next line, returning each command it completes with the
+    /// line and column that command starts at.
+    fn push(&mut self, line: &str) -> Vec<(String, (usize, usize))> {
+        self.lines += 1;
+        if self.pending.is_empty() {
+            let command = line.trim();
+            if command.starts_with('.') {
+                return vec![(command.to_string(), (self.lines, 1))];
+            }
+            self.start = (self.lines, 1);
+        }
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        let (statements, rest) = split_statements(&self.pending);
+        let commands = statements
+            .iter()
+            .map(|statement| (statement.to_string(), self.place(statement)))
+            .collect();
+        let (start, rest) = (self.place(rest), rest.to_string());
+        self.start = start;
+        self.pending = if is_blank(&rest) { String::new() } else { rest };
+        commands
+    }
+
+    /// Takes the end of input, returning the statement left without its
+    /// `;`, if any.
+    fn finish(&mut self) -> Option<(String, (usize, usize))> {
+        self.done = true;
+        if is_blank(&self.pending) {
+            return None;
+        }
+        let statement = self.pending.trim();
+        let found = (statement.to_string(), self.place(statement));
+        self.pending.clear();
+        Some(found)
+    }
+
+    /// The line and column `text`, a slice of `pending`, starts at.
+    fn place(&self, text: &str) -> (usize, usize) {
+        let offset = text.as_ptr() as usize - self.pending.as_ptr() as usize;
+        let before = &self.pending[..offset];
+        let (line, column) = self.start;
+        match before.rfind('\n') {
+            Some(i) => (
+                line + before.matches('\n').count(),
+                before[i + 1..].chars().count() + 1,
+            ),
+            None => (line, column + before.chars().count()),
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").filter(|h| !h.is_empty())?;
+    Some(PathBuf::from(home).join(".rqlite_history"))
+}
+
+/// Tab completion of dot-commands at the start of a line, and elsewhere of
+/// keywords, table names and column names. `table.` completes that
+/// table's columns.
+#[derive(Default)]
+struct Completion {
+    /// Each table or view and its column names.
+    tables: Vec<(String, Vec<String>)>,
+}
+
+impl Completion {
+    /// Rereads the names from the schema, which the database keeps between
+    /// commands while it is unchanged. A schema that can't be read leaves
+    /// the last names in place.
+    fn refresh(&mut self, context: &mut Context) {
+        let Ok(mut pager) = context.db.snapshot() else {
+            return;
+        };
+        let Ok(rows) = read_schema_rows(&mut pager) else {
+            return;
+        };
+        context.db.remember(&pager);
+        self.tables = rows
+            .iter()
+            .filter(|r| matches!(r.kind.as_str(), "table" | "view"))
+            .map(|r| {
+                let columns = parse_create_table(&r.sql)
+                    .map(|t| t.columns.into_iter().map(|c| c.name).collect())
+                    .unwrap_or_default();
+                (r.name.clone(), columns)
+            })
+            .collect();
+    }
+
+    fn candidates(&self, before: &str, word: &str) -> (usize, Vec<String>) {
+        if before.trim().is_empty() && word.starts_with('.') {
+            let names = COMMANDS.iter().map(|c| c.name).chain([".exit"]);
+            return (0, matching(names, word));
+        }
+        if let Some((table, prefix)) = word.rsplit_once('.') {
+            let columns = self
+                .tables
+                .iter()
+                .filter(|(name, _)| name.eq_ignore_ascii_case(table))
+                .flat_map(|(_, columns)| columns.iter().map(String::as_str));
+            return (table.len() + 1, matching(columns, prefix));
+        }
+        // Keywords in the case the word is being typed in.
+        let lower = word.chars().next().is_some_and(char::is_lowercase);
+        let keywords = KEYWORDS.iter().map(|k| {
+            if lower {
+                k.to_ascii_lowercase()
+            } else {
+                k.to_string()
+            }
+        });
+        let names = self
+            .tables
+            .iter()
+            .flat_map(|(name, columns)| std::iter::once(name).chain(columns))
+            .cloned();
+        let mut found: Vec<String> = keywords
+            .chain(names)
+            .filter(|c| starts_with_ignore_case(c, word))
+            .collect();
+        found.sort();
+        found.dedup();
+        (0, found)
+    }
+}
+
+fn matching<'a>(names: impl Iterator<Item = &'a str>, prefix: &str) -> Vec<String> {
+    let mut found: Vec<String> = names
+        .filter(|n| starts_with_ignore_case(n, prefix))
+        .map(String::from)
+        .collect();
+    found.sort();
+    found.dedup();
+    found
+}
+
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.get(..prefix.len())
+        .is_some_and(|p| p.eq_ignore_ascii_case(prefix))
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .map_or(0, |i| {
+                i + line[i..].chars().next().map_or(1, char::len_utf8)
+            });
+        let (offset, candidates) = self.candidates(&line[..start], &line[start..pos]);
+        Ok((start + offset, candidates))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
@@ -0,0 +1,200 @@
+use anyhow::Result;
+
+use crate::json::Json;
+use crate::pager::Pager;
+use crate::settings::Settings;
+use crate::sql::{
+    parse_create_index, parse_create_table, ColumnConstraint, IndexSchema, TableConstraint,
+    TableSchema,
+};
+use crate::{read_schema_rows, SchemaRow};
+
+/// A machine-readable description of a database's schema: tables with their
+/// columns and constraints, indexes, views and triggers. Each object also
+/// carries its `CREATE` statement as stored in sqlite_schema. SQLite's own
+/// `sqlite_*` tables and the automatic indexes behind UNIQUE and PRIMARY KEY
+/// constraints are left out.
+pub fn export_json(path: &str, settings: &Settings) -> Result<String> {
+    let mut pager = Pager::open(path, settings)?;
+    let rows = read_schema_rows(&mut pager)?;
+    let user_rows = |kind: &'static str| {
+        rows.iter()
+            .filter(move |r| r.kind == kind && !r.name.starts_with("sqlite_") && !r.sql.is_empty())
+    };
+
+    let mut tables = Vec::new();
+    for row in user_rows("table") {
+        tables.push(table_json(row)?);
+    }
+    let mut indexes = Vec::new();
+    for row in user_rows("index") {
+        indexes.push(index_json(&parse_create_index(&row.sql)?, &row.sql));
+    }
+    let views = user_rows("view")
+        .map(|r| {
+            object(vec![
+                ("name", Json::text(&r.name)),
+                ("sql", Json::text(&r.sql)),
+            ])
+        })
+        .collect();
+    let triggers = user_rows("trigger")
+        .map(|r| {
+            object(vec![
+                ("name", Json::text(&r.name)),
+                ("table", Json::text(&r.tbl_name)),
+                ("sql", Json::text(&r.sql)),
+            ])
+        })
+        .collect();
+
+    let schema = object(vec![
+        ("tables", Json::Array(tables)),
+        ("indexes", Json::Array(indexes)),
+        ("views", Json::Array(views)),
+        ("triggers", Json::Array(triggers)),
+    ]);
+    Ok(schema.to_pretty())
+}
+
+fn table_json(row: &SchemaRow) -> Result<Json> {
+    if row.sql.to_lowercase().starts_with("create virtual table") {
+        return Ok(object(vec![
+            ("name", Json::text(&row.name)),
+            ("virtual", Json::Bool(true)),
+            ("sql", Json::text(&row.sql)),
+        ]));
+    }
+    let table = parse_create_table(&row.sql)?;
+
+    let columns = table
+        .columns
+        .iter()
+        .map(|c| {
+            let has = |f: fn(&ColumnConstraint) -> bool| c.constraints.iter().any(f);
+            let collation = c.collation().map(Json::text);
+            object(vec![
+                ("name", Json::text(&c.name)),
+                ("type", Json::text(&c.type_name)),
+                (
+                    "not_null",
+                    Json::Bool(has(|k| matches!(k, ColumnConstraint::NotNull))),
+                ),
+                (
+                    "has_default",
+                    Json::Bool(has(|k| matches!(k, ColumnConstraint::Default { .. }))),
+                ),
+                (
+                    "generated",
+                    Json::Bool(has(|k| matches!(k, ColumnConstraint::Generated { .. }))),
+                ),
+                ("collate", collation.unwrap_or(Json::Null)),
+            ])
+        })
+        .collect();
+
+    let mut unique = Vec::new();
+    let mut foreign_keys = Vec::new();
+    for c in &table.columns {
+        for k in &c.constraints {
+            match k {
+                ColumnConstraint::Unique => unique.push(names(std::slice::from_ref(&c.name))),
+                ColumnConstraint::References(target) => foreign_keys.push(foreign_key(
+                    std::slice::from_ref(&c.name),
+                    &target.table,
+                    &target.columns,
+                )),
+                _ => {}
+            }
+        }
+    }
+    for k in &table.constraints {
+        match k {
+            TableConstraint::Unique(cols) => unique.push(names(cols)),
+            TableConstraint::ForeignKey { columns, reference } => {
+                foreign_keys.push(foreign_key(columns, &reference.table, &reference.columns))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(object(vec![
+        ("name", Json::text(&table.name)),
+        ("columns", Json::Array(columns)),
+        ("primary_key", names(&primary_key(&table))),
+        ("unique", Json::Array(unique)),
+        ("foreign_keys", Json::Array(foreign_keys)),
+        ("without_rowid", Json::Bool(table.without_rowid)),
+        ("strict", Json::Bool(table.strict)),
+        ("sql", Json::text(&row.sql)),
+    ]))
+}
+
+fn index_json(index: &IndexSchema, sql: &str) -> Json {
+    let columns = index
+        .columns
+        .iter()
+        .map(|c| {
+            object(vec![
+                ("name", Json::text(&c.name)),
+                ("expression", Json::Bool(c.is_expression)),
+                ("descending", Json::Bool(c.descending)),
+                (
+                    "collate",
+                    c.collation.as_deref().map_or(Json::Null, Json::text),
+                ),
+            ])
+        })
+        .collect();
+    object(vec![
+        ("name", Json::text(&index.name)),
+        ("table", Json::text(&index.table)),
+        ("unique", Json::Bool(index.unique)),
+        ("columns", Json::Array(columns)),
+        (
+            "where",
+            index.where_sql.as_deref().map_or(Json::Null, Json::text),
+        ),
+        ("sql", Json::text(sql)),
+    ])
+}
+
+/// The primary key columns, whether declared on a column or on the table.
+fn primary_key(table: &TableSchema) -> Vec<String> {
+    for k in &table.constraints {
+        if let TableConstraint::PrimaryKey(cols) = k {
+            return cols.clone();
+        }
+    }
+    table
+        .columns
+        .iter()
+        .filter(|c| {
+            c.constraints
+                .iter()
+                .any(|k| matches!(k, ColumnConstraint::PrimaryKey { .. }))
+        })
+        .map(|c| c.name.clone())
+        .collect()
+}
+
+fn foreign_key(columns: &[String], table: &str, references: &[String]) -> Json {
+    object(vec![
+        ("columns", names(columns)),
+        ("table", Json::text(table)),
+        ("references", names(references)),
+    ])
+}
+
+fn names(list: &[String]) -> Json {
+    Json::Array(list.iter().map(|n| Json::text(n)).collect())
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        members
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
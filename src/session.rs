@@ -0,0 +1,143 @@
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::value::Value;
+
+/// Saved queries and variables for one database file, persisted in the user's
+/// config directory so they survive across invocations.
+pub struct Session {
+    path: PathBuf,
+    db_path: String,
+    pub queries: Vec<(String, String)>,
+    pub vars: Vec<(String, String)>,
+}
+
+impl Session {
+    pub fn load(db_path: &str) -> Result<Session> {
+        let canonical = fs::canonicalize(db_path)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| db_path.to_string());
+        let path = config_dir()?
+            .join("sessions")
+            .join(format!("{:016x}", fnv1a(canonical.as_bytes())));
+
+        let mut session = Session {
+            path,
+            db_path: canonical,
+            queries: Vec::new(),
+            vars: Vec::new(),
+        };
+        if !session.path.exists() {
+            return Ok(session);
+        }
+
+        for line in fs::read_to_string(&session.path)?.lines() {
+            let mut parts = line.splitn(3, ' ');
+            let (kind, name, value) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(k), Some(n), Some(v)) => (k, n, v),
+                _ => continue,
+            };
+            match kind {
+                "query" => session.queries.push((name.to_string(), value.to_string())),
+                "var" => session.vars.push((name.to_string(), value.to_string())),
+                _ => {}
+            }
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = format!("# rqlite session for {}\n", self.db_path);
+        for (name, sql) in &self.queries {
+            out.push_str(&format!("query {} {}\n", name, sql));
+        }
+        for (name, value) in &self.vars {
+            out.push_str(&format!("var {} {}\n", name, value));
+        }
+        fs::write(&self.path, out)?;
+        Ok(())
+    }
+
+    pub fn save_query(&mut self, name: &str, sql: &str) -> Result<()> {
+        check_name(name)?;
+        let sql = sql.trim().replace(['\r', '\n'], " ");
+        upsert(&mut self.queries, name, sql);
+        self.save()
+    }
+
+    pub fn query(&self, name: &str) -> Result<&str> {
+        match self.queries.iter().find(|(n, _)| n == name) {
+            Some((_, sql)) => Ok(sql),
+            None => bail!("no saved query named {}", name),
+        }
+    }
+
+    pub fn set_var(&mut self, name: &str, value: &str) -> Result<()> {
+        check_name(name)?;
+        upsert(&mut self.vars, name, value.trim().to_string());
+        self.save()
+    }
+
+    pub fn var(&self, name: &str) -> Option<Value> {
+        self.vars
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| parse_var_value(v))
+    }
+}
+
+fn upsert(entries: &mut Vec<(String, String)>, name: &str, value: String) {
+    match entries.iter_mut().find(|(n, _)| n == name) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((name.to_string(), value)),
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        bail!("invalid name: {}", name);
+    }
+    Ok(())
+}
+
+/// Interprets a stored variable: integers and reals become numbers, quoted
+/// strings lose their quotes, anything else is text.
+fn parse_var_value(raw: &str) -> Value {
+    if let Ok(i) = raw.parse::<i64>() {
+        return Value::Integer(i);
+    }
+    if let Ok(r) = raw.parse::<f64>() {
+        return Value::Real(r);
+    }
+    for quote in ['\'', '"'] {
+        if raw.len() >= 2 && raw.starts_with(quote) && raw.ends_with(quote) {
+            return Value::Text(raw[1..raw.len() - 1].to_string());
+        }
+    }
+    Value::Text(raw.to_string())
+}
+
+fn config_dir() -> Result<PathBuf> {
+    if let Ok(dir) = std::env::var("XDG_CONFIG_HOME")
+        && !dir.is_empty()
+    {
+        return Ok(Path::new(&dir).join("rqlite"));
+    }
+    match std::env::var("HOME") {
+        Ok(home) => Ok(Path::new(&home).join(".config").join("rqlite")),
+        Err(_) => bail!("cannot locate a config directory: HOME is not set"),
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
@@ -0,0 +1,147 @@
+use anyhow::{bail, Result};
+use std::time::Duration;
+
+use crate::error::unsupported;
+use crate::params::DateStorage;
+use crate::redact::Redactions;
+
+/// Tunables for one invocation, set by name like a PRAGMA (`--pragma
+/// NAME=VALUE`), and passed down to whatever reads them instead of one
+/// parameter per knob.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Decode malformed text with U+FFFD replacements instead of failing.
+    pub lossy_text: bool,
+    /// Printed in place of NULL by every output format but json and insert.
+    pub null_value: String,
+    /// Put between values, and after each row, by the list, csv and tsv
+    /// formats in place of their own; None keeps the format's.
+    pub separator: Option<String>,
+    pub row_separator: Option<String>,
+    /// Start results with a row of column names, in the formats where that
+    /// is optional.
+    pub headers: bool,
+    /// The table the insert format's statements insert into.
+    pub insert_table: String,
+    /// Fail a query that would return more rows than this.
+    pub max_rows: Option<usize>,
+    /// Read without taking SQLite's SHARED lock, for files nothing writes to.
+    pub no_lock: bool,
+    /// How long to keep retrying while a writer holds the database locked.
+    pub busy_timeout: Duration,
+    /// Pages the pager keeps in memory; 0 turns the cache off.
+    pub cache_size: usize,
+    /// Read the database file through a memory map instead of copying
+    /// pages (needs the `mmap` feature).
+    pub mmap: bool,
+    /// Masks applied to the columns a redaction profile names, in query
+    /// results and dumps.
+    pub redactions: Redactions,
+    /// How `{"$date": ...}` query parameters are stored.
+    pub date_storage: DateStorage,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            lossy_text: false,
+            null_value: String::new(),
+            separator: None,
+            row_separator: None,
+            headers: false,
+            insert_table: "table".to_string(),
+            max_rows: None,
+            no_lock: false,
+            busy_timeout: Duration::ZERO,
+            cache_size: 2000,
+            mmap: false,
+            redactions: Redactions::default(),
+            date_storage: DateStorage::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Sets one setting from its name and textual value, as given on the
+    /// command line.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name.to_ascii_lowercase().as_str() {
+            "lossy_text" => self.lossy_text = parse_bool(value)?,
+            "null_value" => self.null_value = value.to_string(),
+            "separator" => self.separator = Some(value.to_string()),
+            "row_separator" => self.row_separator = Some(value.to_string()),
+            "headers" => self.headers = parse_bool(value)?,
+            "insert_table" => self.insert_table = value.to_string(),
+            "max_rows" => {
+                self.max_rows = match value.parse::<usize>() {
+                    Ok(0) => None,
+                    Ok(n) => Some(n),
+                    Err(_) => bail!("invalid value for max_rows: {}", value),
+                }
+            }
+            "no_lock" => self.no_lock = parse_bool(value)?,
+            "busy_timeout" => match value.parse::<u64>() {
+                Ok(ms) => self.busy_timeout = Duration::from_millis(ms),
+                Err(_) => bail!("invalid value for busy_timeout: {}", value),
+            },
+            "cache_size" => match value.parse::<usize>() {
+                Ok(pages) => self.cache_size = pages,
+                Err(_) => bail!("invalid value for cache_size: {}", value),
+            },
+            "redact" => self.redactions = Redactions::load(value)?,
+            "mmap" => {
+                self.mmap = parse_bool(value)?;
+                if self.mmap && !cfg!(feature = "mmap") {
+                    return Err(unsupported("mmap (built without the mmap feature)"));
+                }
+            }
+            "date_storage" => self.date_storage = DateStorage::parse(value)?,
+            _ => bail!("unknown setting: {}", name),
+        }
+        Ok(())
+    }
+
+    /// Every setting with its current value, in `set` syntax.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+        vec![
+            ("lossy_text", on_off(self.lossy_text)),
+            ("null_value", format!("{:?}", self.null_value)),
+            ("separator", separator(&self.separator)),
+            ("row_separator", separator(&self.row_separator)),
+            ("headers", on_off(self.headers)),
+            ("insert_table", format!("{:?}", self.insert_table)),
+            ("max_rows", self.max_rows.unwrap_or(0).to_string()),
+            ("no_lock", on_off(self.no_lock)),
+            ("busy_timeout", self.busy_timeout.as_millis().to_string()),
+            ("cache_size", self.cache_size.to_string()),
+            ("mmap", on_off(self.mmap)),
+            ("redact", format!("{:?}", self.redactions.source)),
+            ("date_storage", self.date_storage.name().to_string()),
+        ]
+    }
+
+    pub fn check_row_count(&self, rows: usize) -> Result<()> {
+        if let Some(limit) = self.max_rows
+            && rows > limit
+        {
+            bail!("result has more than max_rows ({}) rows", limit);
+        }
+        Ok(())
+    }
+}
+
+fn separator(text: &Option<String>) -> String {
+    match text {
+        Some(text) => format!("{:?}", text),
+        None => "format default".to_string(),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "on" | "true" | "yes" => Ok(true),
+        "0" | "off" | "false" | "no" => Ok(false),
+        _ => bail!("expected on or off, got {}", value),
+    }
+}
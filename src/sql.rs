@@ -0,0 +1,2623 @@
+use anyhow::{bail, Result};
+use std::fmt;
+use std::ops::Range;
+
+use crate::affinity::Affinity;
+use crate::collation::Collation;
+use crate::error::{syntax_error, unsupported};
+use crate::functions::{is_aggregate, quote};
+use crate::support::{check_select, Capability};
+use crate::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    /// `` `name` `` or `[name]`: always an identifier, never a keyword.
+    QuotedIdent(String),
+    /// `"name"`: an identifier, or a string if no such column exists.
+    DoubleQuoted(String),
+    Str(String),
+    Variable(String),
+    /// `?` or `?NNN`, with the digits if any.
+    Positional(String),
+    Integer(i64),
+    Real(f64),
+    Blob(Vec<u8>),
+    Star,
+    Dot,
+    Comma,
+    LParen,
+    RParen,
+    Semicolon,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Concat,
+    /// `->` and `->>`, SQLite's JSON operators.
+    Arrow,
+    LongArrow,
+    Tilde,
+    BitAnd,
+    BitOr,
+    ShiftLeft,
+    ShiftRight,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>> {
+    Ok(tokenize_with_spans(input)?.0)
+}
+
+/// Tokens along with the range of chars each one was read from.
+fn tokenize_with_spans(input: &str) -> Result<(Vec<Token>, Vec<Range<usize>>)> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    let mut start = 0;
+
+    while i < chars.len() {
+        if spans.len() < tokens.len() {
+            spans.push(start..i);
+        }
+        start = i;
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            // Like SQLite, an unterminated block comment runs to the end of input.
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i = (i + 2).min(chars.len());
+            continue;
+        }
+        if (c == 'x' || c == 'X') && chars.get(i + 1) == Some(&'\'') {
+            let start = i + 2;
+            let end = match chars[start..].iter().position(|&ch| ch == '\'') {
+                Some(len) => start + len,
+                None => return Err(syntax_error(input, i, "unterminated blob literal")),
+            };
+            let hex: String = chars[start..end].iter().collect();
+            let blob = parse_hex_blob(&hex).map_err(|detail| syntax_error(input, i, detail))?;
+            tokens.push(Token::Blob(blob));
+            i = end + 1;
+            continue;
+        }
+        // As in SQLite, any non-ASCII character may appear in an identifier.
+        if c.is_ascii_alphabetic() || c == '_' || !c.is_ascii() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || !chars[i].is_ascii())
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit()))
+        {
+            let (token, len) =
+                scan_number(&chars[i..]).map_err(|detail| syntax_error(input, i, detail))?;
+            tokens.push(token);
+            i += len;
+            continue;
+        }
+        if (c == '$' || c == ':' || c == '@')
+            && chars
+                .get(i + 1)
+                .is_some_and(|n| n.is_ascii_alphanumeric() || *n == '_')
+        {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Variable(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c == '?' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            tokens.push(Token::Positional(chars[start..i].iter().collect()));
+            continue;
+        }
+        if c == '[' {
+            let start = i + 1;
+            let end = match chars[start..].iter().position(|&ch| ch == ']') {
+                Some(len) => start + len,
+                None => {
+                    let text: String = chars[i..].iter().collect();
+                    return Err(syntax_error(
+                        input,
+                        i,
+                        format!("unrecognized token: \"{}\"", text),
+                    ));
+                }
+            };
+            tokens.push(Token::QuotedIdent(chars[start..end].iter().collect()));
+            i = end + 1;
+            continue;
+        }
+        if c == '\'' || c == '"' || c == '`' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(syntax_error(input, start, "unterminated string literal")),
+                    Some(&ch) if ch == c => {
+                        if chars.get(i + 1) == Some(&c) {
+                            text.push(c);
+                            i += 2;
+                        } else {
+                            i += 1;
+                            break;
+                        }
+                    }
+                    Some(&ch) => {
+                        text.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(match c {
+                '"' => Token::DoubleQuoted(text),
+                '`' => Token::QuotedIdent(text),
+                _ => Token::Str(text),
+            });
+            continue;
+        }
+
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match (c, next) {
+            ('|', Some('|')) => (Token::Concat, 2),
+            ('-', Some('>')) if chars.get(i + 2) == Some(&'>') => (Token::LongArrow, 3),
+            ('-', Some('>')) => (Token::Arrow, 2),
+            ('=', Some('=')) => (Token::Eq, 2),
+            ('!', Some('=')) => (Token::NotEq, 2),
+            ('<', Some('>')) => (Token::NotEq, 2),
+            ('<', Some('=')) => (Token::LtEq, 2),
+            ('>', Some('=')) => (Token::GtEq, 2),
+            ('<', Some('<')) => (Token::ShiftLeft, 2),
+            ('>', Some('>')) => (Token::ShiftRight, 2),
+            ('|', _) => (Token::BitOr, 1),
+            ('&', _) => (Token::BitAnd, 1),
+            ('~', _) => (Token::Tilde, 1),
+            ('=', _) => (Token::Eq, 1),
+            ('<', _) => (Token::Lt, 1),
+            ('>', _) => (Token::Gt, 1),
+            ('*', _) => (Token::Star, 1),
+            (',', _) => (Token::Comma, 1),
+            ('.', _) => (Token::Dot, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            (';', _) => (Token::Semicolon, 1),
+            ('+', _) => (Token::Plus, 1),
+            ('-', _) => (Token::Minus, 1),
+            ('/', _) => (Token::Slash, 1),
+            ('%', _) => (Token::Percent, 1),
+            _ => {
+                return Err(syntax_error(
+                    input,
+                    i,
+                    format!("unexpected character: {}", c),
+                ))
+            }
+        };
+        tokens.push(token);
+        i += len;
+    }
+    if spans.len() < tokens.len() {
+        spans.push(start..i);
+    }
+
+    Ok((tokens, spans))
+}
+
+/// Scans a numeric literal: decimal integers, `0x` hex integers, and reals
+/// with an optional fraction and exponent. Integers too large for i64 become
+/// reals, as in SQLite, which also allows an underscore between two digits
+/// (`1_000`) and ignores it.
+fn scan_number(chars: &[char]) -> Result<(Token, usize), String> {
+    if chars[0] == '0' && matches!(chars.get(1), Some('x' | 'X')) {
+        let len = scan_digits(chars, 2, char::is_ascii_hexdigit);
+        let digits: String = chars[2..len].iter().filter(|&&c| c != '_').collect();
+        return match u64::from_str_radix(&digits, 16) {
+            Ok(v) => Ok((Token::Integer(v as i64), len)),
+            Err(_) => Err(format!("unrecognized token: \"0x{}\"", digits)),
+        };
+    }
+
+    let mut len = scan_digits(chars, 0, char::is_ascii_digit);
+    let mut is_real = false;
+    if len < chars.len() && chars[len] == '.' {
+        is_real = true;
+        len = scan_digits(chars, len + 1, char::is_ascii_digit);
+    }
+    if len < chars.len() && (chars[len] == 'e' || chars[len] == 'E') {
+        let mut exp_len = len + 1;
+        if exp_len < chars.len() && (chars[exp_len] == '+' || chars[exp_len] == '-') {
+            exp_len += 1;
+        }
+        let digits_start = exp_len;
+        exp_len = scan_digits(chars, exp_len, char::is_ascii_digit);
+        if exp_len == digits_start {
+            let text: String = chars[..exp_len].iter().collect();
+            return Err(format!("malformed number: {}", text));
+        }
+        is_real = true;
+        len = exp_len;
+    }
+    if len < chars.len() && (chars[len].is_ascii_alphabetic() || chars[len] == '_') {
+        let mut end = len;
+        while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+            end += 1;
+        }
+        let text: String = chars[..end].iter().collect();
+        return Err(format!("unrecognized token: \"{}\"", text));
+    }
+
+    let text: String = chars[..len].iter().filter(|&&c| c != '_').collect();
+    if !is_real && let Ok(v) = text.parse::<i64>() {
+        return Ok((Token::Integer(v), len));
+    }
+    match text.parse::<f64>() {
+        Ok(v) => Ok((Token::Real(v), len)),
+        Err(_) => Err(format!("unrecognized token: \"{}\"", text)),
+    }
+}
+
+/// The end of the run of digits from `start`, including any underscore
+/// between two of them.
+fn scan_digits(chars: &[char], start: usize, is_digit: fn(&char) -> bool) -> usize {
+    let mut len = start;
+    while let Some(c) = chars.get(len) {
+        let separator = *c == '_'
+            && len > start
+            && is_digit(&chars[len - 1])
+            && chars.get(len + 1).is_some_and(is_digit);
+        if !is_digit(c) && !separator {
+            break;
+        }
+        len += 1;
+    }
+    len
+}
+
+fn parse_hex_blob(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("unrecognized token: \"X'{}'\"", hex));
+    }
+    let mut out = Vec::with_capacity(hex.len() / 2);
+    for i in (0..hex.len()).step_by(2) {
+        out.push(u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| e.to_string())?);
+    }
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Plus,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Concat,
+    Like,
+    Glob,
+    Regexp,
+}
+
+impl BinaryOp {
+    /// Higher binds tighter; NOT sits at 3, between AND and the comparisons.
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Like | BinaryOp::Glob | BinaryOp::Regexp => {
+                4
+            }
+            BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => 5,
+            BinaryOp::Add | BinaryOp::Sub => 7,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 8,
+            BinaryOp::Concat => 9,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Or => "OR",
+            BinaryOp::And => "AND",
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Concat => "||",
+            BinaryOp::Like => "LIKE",
+            BinaryOp::Glob => "GLOB",
+            BinaryOp::Regexp => "REGEXP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Variable(String),
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+    CountStar,
+    /// A double-quoted name not yet resolved against the table's columns.
+    DoubleQuoted(String),
+    /// `expr COLLATE name`, which sets the collation a comparison uses.
+    Collate {
+        expr: Box<Expr>,
+        collation: Collation,
+    },
+    /// Syntax the reader can't run, kept as written so that the statement
+    /// can be refused with everything it lacks (see `support::check_select`).
+    Unsupported(Unsupported),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsupported {
+    pub capability: Capability,
+    pub sql: String,
+    /// Where it starts in the statement, counted in tokens.
+    pub at: usize,
+}
+
+impl Expr {
+    pub fn is_aggregate(&self) -> bool {
+        match self {
+            Expr::CountStar => true,
+            Expr::Literal(_)
+            | Expr::Column(_)
+            | Expr::Variable(_)
+            | Expr::DoubleQuoted(_)
+            | Expr::Unsupported(_) => false,
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Collate { expr, .. } => {
+                expr.is_aggregate()
+            }
+            Expr::Binary { left, right, .. } => left.is_aggregate() || right.is_aggregate(),
+            Expr::Function { name, args } => {
+                is_aggregate(name) || args.iter().any(|a| a.is_aggregate())
+            }
+        }
+    }
+
+    /// Collects the calls to aggregate functions (other than `count(*)`),
+    /// whose values come from accumulating over the rows.
+    pub fn aggregate_calls<'a>(&'a self, out: &mut Vec<&'a Expr>) {
+        match self {
+            Expr::Function { name, .. } if is_aggregate(name) => out.push(self),
+            Expr::Literal(_)
+            | Expr::Column(_)
+            | Expr::Variable(_)
+            | Expr::CountStar
+            | Expr::DoubleQuoted(_)
+            | Expr::Unsupported(_) => {}
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Collate { expr, .. } => {
+                expr.aggregate_calls(out)
+            }
+            Expr::Binary { left, right, .. } => {
+                left.aggregate_calls(out);
+                right.aggregate_calls(out);
+            }
+            Expr::Function { args, .. } => {
+                for a in args {
+                    a.aggregate_calls(out);
+                }
+            }
+        }
+    }
+
+    /// Collects every column name referenced by the expression.
+    pub fn columns<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Column(name) | Expr::DoubleQuoted(name) => out.push(name),
+            Expr::Literal(_) | Expr::Variable(_) | Expr::CountStar | Expr::Unsupported(_) => {}
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Collate { expr, .. } => {
+                expr.columns(out)
+            }
+            Expr::Binary { left, right, .. } => {
+                left.columns(out);
+                right.columns(out);
+            }
+            Expr::Function { args, .. } => {
+                for a in args {
+                    a.columns(out);
+                }
+            }
+        }
+    }
+
+    /// The construct this node is, as `support::FEATURES` lists them.
+    pub fn capability(&self) -> Capability {
+        match self {
+            Expr::Literal(_) => Capability::Literal,
+            Expr::Column(_) | Expr::DoubleQuoted(_) => Capability::Column,
+            Expr::Variable(_) => Capability::Variable,
+            Expr::Unary { .. } | Expr::Binary { .. } => Capability::Operator,
+            Expr::IsNull { .. } => Capability::IsNull,
+            Expr::Function { .. } => Capability::Function,
+            Expr::CountStar => Capability::CountStar,
+            Expr::Collate { .. } => Capability::Collate,
+            Expr::Unsupported(u) => u.capability,
+        }
+    }
+
+    /// Calls `visit` on the expression and on every node beneath it.
+    pub fn walk(&self, visit: &mut dyn FnMut(&Expr)) {
+        visit(self);
+        match self {
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Collate { expr, .. } => {
+                expr.walk(visit)
+            }
+            Expr::Binary { left, right, .. } => {
+                left.walk(visit);
+                right.walk(visit);
+            }
+            Expr::Function { args, .. } => {
+                for a in args {
+                    a.walk(visit);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether every node of the expression is one the reader can run.
+    pub fn is_supported(&self) -> bool {
+        let mut supported = true;
+        self.walk(&mut |e| supported &= e.capability().feature().supported);
+        supported
+    }
+
+    /// The expression without any COLLATE applied to it.
+    pub fn uncollated(&self) -> &Expr {
+        match self {
+            Expr::Collate { expr, .. } => expr.uncollated(),
+            _ => self,
+        }
+    }
+}
+
+/// Renders the expression as SQL, parenthesised only where precedence
+/// requires it. BETWEEN shows as the pair of comparisons it stands for.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // An operand that binds more loosely than the operator around it.
+        let operand = |e: &Expr, min: u8| match e {
+            Expr::Binary { op, .. } if op.precedence() < min => format!("({})", e),
+            Expr::Unary {
+                op: UnaryOp::Not, ..
+            } if min > 3 => format!("({})", e),
+            _ => e.to_string(),
+        };
+        match self {
+            Expr::Literal(v) => write!(f, "{}", quote(v)),
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Variable(name) => write!(f, "${}", name),
+            Expr::DoubleQuoted(name) => write!(f, "\"{}\"", name.replace('"', "\"\"")),
+            Expr::CountStar => write!(f, "count(*)"),
+            Expr::Unary { op, expr } => match op {
+                UnaryOp::Neg => write!(f, "-{}", operand(expr, 10)),
+                UnaryOp::Plus => write!(f, "+{}", operand(expr, 10)),
+                UnaryOp::Not => write!(f, "NOT {}", operand(expr, 3)),
+            },
+            Expr::Binary { op, left, right } => {
+                let p = op.precedence();
+                // Operators are left-associative, so a right operand of the
+                // same precedence needs parentheses.
+                let (left, right) = (operand(left, p), operand(right, p + 1));
+                write!(f, "{} {} {}", left, op.symbol(), right)
+            }
+            Expr::IsNull { expr, negated } => {
+                let not = if *negated { " NOT" } else { "" };
+                write!(f, "{} IS{} NULL", operand(expr, 5), not)
+            }
+            Expr::Function { name, args } => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Collate { expr, collation } => {
+                write!(f, "{} COLLATE {}", operand(expr, 9), collation.name())
+            }
+            Expr::Unsupported(u) => write!(f, "{}", u.sql),
+        }
+    }
+}
+
+impl Expr {
+    /// Replaces every `$name` / `:name` / `@name` variable with its value.
+    pub fn bind(&mut self, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<()> {
+        match self {
+            Expr::Variable(name) => match lookup(name) {
+                Some(v) => *self = Expr::Literal(v),
+                None => bail!("no such variable: {}", name),
+            },
+            Expr::Literal(_)
+            | Expr::Column(_)
+            | Expr::CountStar
+            | Expr::DoubleQuoted(_)
+            | Expr::Unsupported(_) => {}
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Collate { expr, .. } => {
+                expr.bind(lookup)?
+            }
+            Expr::Binary { left, right, .. } => {
+                left.bind(lookup)?;
+                right.bind(lookup)?;
+            }
+            Expr::Function { args, .. } => {
+                for a in args {
+                    a.bind(lookup)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns each `"name"` into a column reference if the table has such a
+    /// column and into a string literal otherwise, as SQLite does.
+    pub fn resolve_double_quoted(&mut self, is_column: &dyn Fn(&str) -> bool) {
+        match self {
+            Expr::DoubleQuoted(name) => {
+                let name = std::mem::take(name);
+                *self = if is_column(&name) {
+                    Expr::Column(name)
+                } else {
+                    Expr::Literal(Value::Text(name))
+                };
+            }
+            Expr::Literal(_)
+            | Expr::Column(_)
+            | Expr::Variable(_)
+            | Expr::CountStar
+            | Expr::Unsupported(_) => {}
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Collate { expr, .. } => {
+                expr.resolve_double_quoted(is_column)
+            }
+            Expr::Binary { left, right, .. } => {
+                left.resolve_double_quoted(is_column);
+                right.resolve_double_quoted(is_column);
+            }
+            Expr::Function { args, .. } => {
+                for a in args {
+                    a.resolve_double_quoted(is_column);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Select {
+    pub columns: Vec<Expr>,
+    /// Each result column's text as written in the statement.
+    pub labels: Vec<String>,
+    pub table: String,
+    pub where_clause: Option<Expr>,
+    /// Clauses the reader can't run, such as ORDER BY or a join, in the
+    /// order written.
+    pub unsupported: Vec<Unsupported>,
+}
+
+impl Select {
+    pub fn bind(&mut self, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<()> {
+        for c in &mut self.columns {
+            c.bind(lookup)?;
+        }
+        if let Some(w) = &mut self.where_clause {
+            w.bind(lookup)?;
+        }
+        Ok(())
+    }
+
+    pub fn resolve_double_quoted(&mut self, is_column: &dyn Fn(&str) -> bool) {
+        for c in &mut self.columns {
+            c.resolve_double_quoted(is_column);
+        }
+        if let Some(w) = &mut self.where_clause {
+            w.resolve_double_quoted(is_column);
+        }
+    }
+
+    /// Result column names as SQLite reports them: a bare column reference
+    /// takes the column's declared name, anything else its text as written.
+    pub fn column_names(&self, table: &TableSchema) -> Vec<String> {
+        self.columns
+            .iter()
+            .zip(&self.labels)
+            .map(|(expr, label)| {
+                let Expr::Column(name) = expr else {
+                    return label.clone();
+                };
+                let index = table
+                    .column_index(name)
+                    .or_else(|| is_rowid_name(name).then(|| table.rowid_alias()).flatten());
+                match index {
+                    Some(i) => table.columns[i].name.clone(),
+                    None => label.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A parsed `CREATE TABLE` statement.
+#[derive(Debug, Clone)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+    pub without_rowid: bool,
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ColumnDef {
+    pub name: String,
+    /// Declared type as written, e.g. `DECIMAL(10,2)`; empty when omitted.
+    pub type_name: String,
+    pub constraints: Vec<ColumnConstraint>,
+}
+
+impl ColumnDef {
+    /// The affinity the declared type gives the column.
+    pub fn affinity(&self) -> Affinity {
+        Affinity::of_type(&self.type_name)
+    }
+
+    /// The collation named by the column's COLLATE clause, if it has one.
+    pub fn collation(&self) -> Option<&str> {
+        self.constraints.iter().find_map(|c| match c {
+            ColumnConstraint::Collate(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// A column's constraints, keeping only what the reader uses: the reader
+/// never evaluates a CHECK, DEFAULT or generated column's expression.
+#[derive(Debug, Clone)]
+pub enum ColumnConstraint {
+    PrimaryKey {
+        descending: bool,
+    },
+    NotNull,
+    Unique,
+    Check,
+    /// The default value's text as written (inside any parentheses).
+    Default {
+        sql: String,
+    },
+    Collate(String),
+    References(ForeignKeyTarget),
+    Generated {
+        stored: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum TableConstraint {
+    PrimaryKey(Vec<String>),
+    Unique(Vec<String>),
+    Check,
+    ForeignKey {
+        columns: Vec<String>,
+        reference: ForeignKeyTarget,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ForeignKeyTarget {
+    pub table: String,
+    pub columns: Vec<String>,
+}
+
+impl TableSchema {
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The collation a column compares text with: the one it declares, or
+    /// BINARY. The rowid, being an integer, is BINARY too.
+    pub fn column_collation(&self, name: &str) -> Result<Collation> {
+        match self
+            .column_index(name)
+            .and_then(|i| self.columns[i].collation())
+        {
+            Some(collation) => Collation::parse(collation),
+            None => Ok(Collation::Binary),
+        }
+    }
+
+    /// The affinity a column has; the rowid's is INTEGER.
+    pub fn column_affinity(&self, name: &str) -> Affinity {
+        match self.column_index(name) {
+            Some(i) => self.columns[i].affinity(),
+            None => Affinity::Integer,
+        }
+    }
+
+    /// Whether `name` is a declared column or one of the rowid pseudo-columns.
+    pub fn has_column(&self, name: &str) -> bool {
+        self.column_index(name).is_some() || (!self.without_rowid && is_rowid_name(name))
+    }
+
+    /// The columns of the PRIMARY KEY, from a table constraint or a column's
+    /// own; empty if there is none.
+    pub fn primary_key(&self) -> Vec<String> {
+        let table_key = self.constraints.iter().find_map(|c| match c {
+            TableConstraint::PrimaryKey(cols) => Some(cols.clone()),
+            _ => None,
+        });
+        table_key.unwrap_or_else(|| {
+            self.columns
+                .iter()
+                .filter(|c| {
+                    c.constraints
+                        .iter()
+                        .any(|k| matches!(k, ColumnConstraint::PrimaryKey { .. }))
+                })
+                .map(|c| c.name.clone())
+                .collect()
+        })
+    }
+
+    /// The column declared `INTEGER PRIMARY KEY`, whose value is the rowid
+    /// rather than a stored record field. Following SQLite, the declared
+    /// type must be exactly INTEGER, and a column-level `PRIMARY KEY DESC`
+    /// does not create an alias.
+    pub fn rowid_alias(&self) -> Option<usize> {
+        if self.without_rowid {
+            return None;
+        }
+        let table_key = self.constraints.iter().find_map(|c| match c {
+            TableConstraint::PrimaryKey(cols) => Some(cols),
+            _ => None,
+        });
+        let index = match table_key {
+            Some(cols) if cols.len() == 1 => self.column_index(&cols[0])?,
+            Some(_) => return None,
+            None => self.columns.iter().position(|c| {
+                c.constraints.iter().any(|k| {
+                    matches!(
+                        k,
+                        ColumnConstraint::PrimaryKey {
+                            descending: false,
+                            ..
+                        }
+                    )
+                })
+            })?,
+        };
+        if self.columns[index]
+            .type_name
+            .eq_ignore_ascii_case("INTEGER")
+        {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+/// A parsed `CREATE INDEX` statement.
+#[derive(Debug, Clone)]
+pub struct IndexSchema {
+    pub name: String,
+    pub table: String,
+    pub unique: bool,
+    pub columns: Vec<IndexedColumn>,
+    /// The WHERE clause of a partial index, as written.
+    pub where_sql: Option<String>,
+    /// The same clause parsed; None as well when it uses syntax the parser
+    /// doesn't support.
+    pub predicate: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexedColumn {
+    /// The column name, or the expression as written for an index on an
+    /// expression.
+    pub name: String,
+    pub is_expression: bool,
+    /// The expression parsed, for an index on an expression; None as well
+    /// when it uses syntax the parser doesn't support.
+    pub expr: Option<Expr>,
+    pub descending: bool,
+    pub collation: Option<String>,
+}
+
+/// `rowid`, `_rowid_` and `oid` name the rowid unless a real column
+/// shadows them.
+pub fn is_rowid_name(name: &str) -> bool {
+    ["rowid", "_rowid_", "oid"]
+        .iter()
+        .any(|n| n.eq_ignore_ascii_case(name))
+}
+
+/// SQLite's keywords, which must be quoted to be used as a name.
+const RESERVED: &[&str] = &[
+    "ABORT",
+    "ACTION",
+    "ADD",
+    "AFTER",
+    "ALL",
+    "ALTER",
+    "ALWAYS",
+    "ANALYZE",
+    "AND",
+    "AS",
+    "ASC",
+    "ATTACH",
+    "AUTOINCREMENT",
+    "BEFORE",
+    "BEGIN",
+    "BETWEEN",
+    "BY",
+    "CASCADE",
+    "CASE",
+    "CAST",
+    "CHECK",
+    "COLLATE",
+    "COLUMN",
+    "COMMIT",
+    "CONFLICT",
+    "CONSTRAINT",
+    "CREATE",
+    "CROSS",
+    "CURRENT",
+    "CURRENT_DATE",
+    "CURRENT_TIME",
+    "CURRENT_TIMESTAMP",
+    "DATABASE",
+    "DEFAULT",
+    "DEFERRABLE",
+    "DEFERRED",
+    "DELETE",
+    "DESC",
+    "DETACH",
+    "DISTINCT",
+    "DO",
+    "DROP",
+    "EACH",
+    "ELSE",
+    "END",
+    "ESCAPE",
+    "EXCEPT",
+    "EXCLUDE",
+    "EXCLUSIVE",
+    "EXISTS",
+    "EXPLAIN",
+    "FAIL",
+    "FILTER",
+    "FIRST",
+    "FOLLOWING",
+    "FOR",
+    "FOREIGN",
+    "FROM",
+    "FULL",
+    "GENERATED",
+    "GLOB",
+    "GROUP",
+    "GROUPS",
+    "HAVING",
+    "IF",
+    "IGNORE",
+    "IMMEDIATE",
+    "IN",
+    "INDEX",
+    "INDEXED",
+    "INITIALLY",
+    "INNER",
+    "INSERT",
+    "INSTEAD",
+    "INTERSECT",
+    "INTO",
+    "IS",
+    "ISNULL",
+    "JOIN",
+    "KEY",
+    "LAST",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "MATCH",
+    "MATERIALIZED",
+    "NATURAL",
+    "NO",
+    "NOT",
+    "NOTHING",
+    "NOTNULL",
+    "NULL",
+    "NULLS",
+    "OF",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OTHERS",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "PLAN",
+    "PRAGMA",
+    "PRECEDING",
+    "PRIMARY",
+    "QUERY",
+    "RAISE",
+    "RANGE",
+    "RECURSIVE",
+    "REFERENCES",
+    "REGEXP",
+    "REINDEX",
+    "RELEASE",
+    "RENAME",
+    "REPLACE",
+    "RESTRICT",
+    "RETURNING",
+    "RIGHT",
+    "ROLLBACK",
+    "ROW",
+    "ROWS",
+    "SAVEPOINT",
+    "SELECT",
+    "SET",
+    "TABLE",
+    "TEMP",
+    "TEMPORARY",
+    "THEN",
+    "TIES",
+    "TO",
+    "TRANSACTION",
+    "TRIGGER",
+    "UNBOUNDED",
+    "UNION",
+    "UNIQUE",
+    "UPDATE",
+    "USING",
+    "VACUUM",
+    "VALUES",
+    "VIEW",
+    "VIRTUAL",
+    "WHEN",
+    "WHERE",
+    "WINDOW",
+    "WITH",
+    "WITHOUT",
+];
+
+/// The statements that change a database. The reader recognises them by
+/// their first keyword so as to refuse them plainly, rather than as a
+/// SELECT with a typo in it.
+const WRITE_STATEMENTS: &[&str] = &[
+    "INSERT", "REPLACE", "UPDATE", "DELETE", "CREATE", "DROP", "ALTER", "VACUUM", "REINDEX",
+];
+
+/// Other statements SQLite runs and the reader doesn't, refused the same
+/// way.
+const OTHER_STATEMENTS: &[&str] = &[
+    "WITH",
+    "VALUES",
+    "BEGIN",
+    "COMMIT",
+    "END",
+    "ROLLBACK",
+    "SAVEPOINT",
+    "RELEASE",
+    "ATTACH",
+    "DETACH",
+];
+
+pub fn is_keyword(word: &str) -> bool {
+    RESERVED.iter().any(|k| k.eq_ignore_ascii_case(word))
+}
+
+pub fn parse_create_table(sql: &str) -> Result<TableSchema> {
+    let mut parser = Parser::new(sql)?;
+    let schema = parser.parse_create_table()?;
+    parser.eat(&Token::Semicolon);
+    if parser.peek().is_some() {
+        let detail = format!("unexpected {} after statement", parser.found(parser.pos));
+        return Err(parser.error(detail));
+    }
+    Ok(schema)
+}
+
+pub fn parse_create_index(sql: &str) -> Result<IndexSchema> {
+    let mut parser = Parser::new(sql)?;
+    parser.parse_create_index()
+}
+
+pub fn parse_select(sql: &str) -> Result<Select> {
+    let mut parser = Parser::new(sql)?;
+    parser.parse_whole_select()
+}
+
+/// Parses a statement the reader runs: `SELECT ...`, or `EXPLAIN SELECT ...`
+/// for which the flag is set.
+pub fn parse_statement(sql: &str) -> Result<(bool, Select)> {
+    let mut parser = Parser::new(sql)?;
+    let explain = parser.eat_keyword("EXPLAIN");
+    if explain && parser.peek_keyword("QUERY") && parser.peek_keyword_at(1, "PLAN") {
+        return Err(unsupported("EXPLAIN QUERY PLAN"));
+    }
+    Ok((explain, parser.parse_whole_select()?))
+}
+
+/// Where a statement read from a file starts in it.
+#[derive(Debug, Clone)]
+pub struct Location<'a> {
+    pub file: &'a str,
+    /// Counted from 1.
+    pub line: usize,
+    /// In chars, counted from 1.
+    pub column: usize,
+}
+
+/// The line and column, both counted from 1, of char `position` in `sql`,
+/// and that line and the token starting there, underlined with carets:
+///
+/// ```text
+///  --> line 1, column 13
+///   |
+/// 1 | SELECT name apples
+///   |             ^^^^^^
+/// ```
+///
+/// With `at`, the place is given in the file the SQL was read from.
+pub fn point_at(sql: &str, position: usize, at: Option<&Location>) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let position = position.min(chars.len());
+    let start = chars[..position]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |i| i + 1);
+    let end = chars[position..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(chars.len(), |i| position + i);
+    let first = at.map_or(1, |at| at.line);
+    let line_no = chars[..start].iter().filter(|&&c| c == '\n').count() + first;
+    // The SQL may start partway along its first line.
+    let column = match at {
+        Some(at) if start == 0 => at.column + position,
+        _ => position - start + 1,
+    };
+    let line: String = chars[start..end].iter().collect();
+    // The token's length, if the SQL tokenizes and one starts there; a
+    // single caret otherwise, as at the end of the SQL.
+    let width = tokenize_with_spans(sql)
+        .ok()
+        .and_then(|(_, spans)| spans.into_iter().find(|s| s.start == position))
+        .map_or(1, |s| s.end.min(end) - s.start)
+        .max(1);
+    // Tabs are kept so the carets line up under them.
+    let indent: String = chars[start..position]
+        .iter()
+        .map(|&c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let gutter = " ".repeat(line_no.to_string().len());
+    let file = at.map_or(String::new(), |at| format!("{}, ", at.file));
+    format!(
+        "{gutter}--> {}line {}, column {}\n{gutter} |\n{} | {}\n{gutter} | {}{}\n",
+        file,
+        line_no,
+        column,
+        line_no,
+        line.trim_end(),
+        indent,
+        "^".repeat(width)
+    )
+}
+
+/// Splits `input` at each `;` outside quotes and comments, returning the
+/// statements it ends (blank ones dropped) and whatever follows the last,
+/// which is incomplete. Quotes and comments are followed as the tokenizer
+/// reads them, so an unterminated string leaves everything after it in
+/// the remainder.
+pub fn split_statements(input: &str) -> (Vec<&str>, &str) {
+    let bytes = input.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = input[i..].find('\n').map_or(bytes.len(), |n| i + n);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = input[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |n| i + 2 + n + 2);
+            }
+            quote @ (b'\'' | b'"' | b'`' | b'[') => {
+                let close = if quote == b'[' { b']' } else { quote };
+                // A doubled quote inside is two strings back to back here,
+                // which splits the same way.
+                match bytes[i + 1..].iter().position(|&b| b == close) {
+                    Some(n) => i += n + 2,
+                    None => i = bytes.len(),
+                }
+            }
+            b';' => {
+                let statement = input[start..i].trim();
+                if !is_blank(statement) {
+                    statements.push(statement);
+                }
+                i += 1;
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    (statements, &input[start..])
+}
+
+/// Whether `sql` holds nothing but whitespace and comments.
+pub fn is_blank(sql: &str) -> bool {
+    tokenize(sql).is_ok_and(|tokens| tokens.is_empty())
+}
+
+/// The clauses that can follow a SELECT's WHERE, none of which the reader
+/// runs yet.
+const CLAUSES: &[(&str, Capability)] = &[
+    ("GROUP", Capability::GroupBy),
+    ("HAVING", Capability::Having),
+    ("WINDOW", Capability::Window),
+    ("ORDER", Capability::OrderBy),
+    ("LIMIT", Capability::Limit),
+    ("UNION", Capability::Compound),
+    ("INTERSECT", Capability::Compound),
+    ("EXCEPT", Capability::Compound),
+];
+
+/// A symbol as written; other tokens as their debug form.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Token::Star => "*",
+            Token::Dot => ".",
+            Token::Comma => ",",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::Semicolon => ";",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Concat => "||",
+            Token::Eq => "=",
+            Token::NotEq => "!=",
+            Token::Lt => "<",
+            Token::LtEq => "<=",
+            Token::Gt => ">",
+            Token::GtEq => ">=",
+            other => return write!(f, "{:?}", other),
+        };
+        f.write_str(symbol)
+    }
+}
+
+/// The keywords a join starts with.
+const JOIN_KEYWORDS: &[&str] = &["JOIN", "INNER", "LEFT", "RIGHT", "FULL", "CROSS", "NATURAL"];
+
+/// The keywords that can follow a result column, so are not its alias.
+const COLUMN_FOLLOWERS: &[&str] = &[
+    "FROM",
+    "WHERE",
+    "GROUP",
+    "HAVING",
+    "WINDOW",
+    "ORDER",
+    "LIMIT",
+    "UNION",
+    "INTERSECT",
+    "EXCEPT",
+];
+
+/// The keywords that can follow a table name, so are not its alias.
+const FROM_FOLLOWERS: &[&str] = &[
+    "WHERE",
+    "GROUP",
+    "HAVING",
+    "WINDOW",
+    "ORDER",
+    "LIMIT",
+    "UNION",
+    "INTERSECT",
+    "EXCEPT",
+    "JOIN",
+    "INNER",
+    "LEFT",
+    "RIGHT",
+    "FULL",
+    "CROSS",
+    "NATURAL",
+    "INDEXED",
+    "NOT",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    source: Vec<char>,
+    spans: Vec<Range<usize>>,
+}
+
+impl Parser {
+    fn new(sql: &str) -> Result<Parser> {
+        let (tokens, spans) = tokenize_with_spans(sql)?;
+        Ok(Parser {
+            tokens,
+            pos: 0,
+            source: sql.chars().collect(),
+            spans,
+        })
+    }
+
+    /// The statement text covered by tokens `from..to`, as written.
+    fn source_text(&self, from: usize, to: usize) -> String {
+        match (self.spans.get(from), self.spans.get(to.wrapping_sub(1))) {
+            (Some(first), Some(last)) if from < to => {
+                self.source[first.start..last.end].iter().collect()
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// A syntax error at the next token, or at the end of the SQL.
+    fn error(&self, detail: impl Into<String>) -> anyhow::Error {
+        self.error_at(self.pos, detail)
+    }
+
+    /// Token `token` as written, quoted, for an error message.
+    fn found(&self, token: usize) -> String {
+        match token < self.tokens.len() {
+            true => format!("\"{}\"", self.source_text(token, token + 1)),
+            false => "end of input".to_string(),
+        }
+    }
+
+    fn error_at(&self, token: usize, detail: impl Into<String>) -> anyhow::Error {
+        let position = self.spans.get(token).map_or(self.source.len(), |s| s.start);
+        syntax_error(&self.source.iter().collect::<String>(), position, detail)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn eat(&mut self, tok: &Token) -> bool {
+        if self.peek() == Some(tok) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, tok: &Token) -> Result<()> {
+        if !self.eat(tok) {
+            let detail = format!("expected \"{}\", found {}", tok, self.found(self.pos));
+            return Err(self.error(detail));
+        }
+        Ok(())
+    }
+
+    fn peek_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(kw))
+    }
+
+    fn eat_keyword(&mut self, kw: &str) -> bool {
+        if self.peek_keyword(kw) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, kw: &str) -> Result<()> {
+        if !self.eat_keyword(kw) {
+            return Err(self.error(format!("expected {}, found {}", kw, self.found(self.pos))));
+        }
+        Ok(())
+    }
+
+    fn parse_name(&mut self, what: &str) -> Result<String> {
+        match self.peek() {
+            Some(
+                Token::Ident(name)
+                | Token::QuotedIdent(name)
+                | Token::DoubleQuoted(name)
+                | Token::Str(name),
+            ) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error(format!("expected {}, found {}", what, self.found(self.pos)))),
+        }
+    }
+
+    fn parse_name_list(&mut self) -> Result<Vec<String>> {
+        self.expect(&Token::LParen)?;
+        let mut names = Vec::new();
+        loop {
+            names.push(self.parse_name("column name")?);
+            // Indexed columns may carry COLLATE and ASC/DESC.
+            if self.eat_keyword("COLLATE") {
+                self.parse_name("collation name")?;
+            }
+            if !self.eat_keyword("ASC") {
+                self.eat_keyword("DESC");
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+        Ok(names)
+    }
+
+    /// Returns the tokens inside a parenthesised group, consuming the group.
+    fn take_group(&mut self) -> Result<Vec<Token>> {
+        self.expect(&Token::LParen)?;
+        let start = self.pos;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Token::LParen) => depth += 1,
+                Some(Token::RParen) => depth -= 1,
+                Some(_) => {}
+                None => return Err(self.error("unbalanced parentheses")),
+            }
+        }
+        Ok(self.tokens[start..self.pos - 1].to_vec())
+    }
+
+    fn parse_create_table(&mut self) -> Result<TableSchema> {
+        self.expect_keyword("CREATE")?;
+        if !self.eat_keyword("TEMP") {
+            self.eat_keyword("TEMPORARY");
+        }
+        self.expect_keyword("TABLE")?;
+        if self.eat_keyword("IF") {
+            self.expect_keyword("NOT")?;
+            self.expect_keyword("EXISTS")?;
+        }
+        let mut name = self.parse_name("table name")?;
+        if self.eat(&Token::Dot) {
+            name = self.parse_name("table name")?;
+        }
+        if self.peek_keyword("AS") {
+            bail!("CREATE TABLE ... AS SELECT is not supported");
+        }
+
+        self.expect(&Token::LParen)?;
+        let mut schema = TableSchema {
+            name,
+            columns: Vec::new(),
+            constraints: Vec::new(),
+            without_rowid: false,
+            strict: false,
+        };
+        loop {
+            if self.is_table_constraint_start() {
+                schema.constraints.push(self.parse_table_constraint()?);
+            } else if schema.constraints.is_empty() {
+                schema.columns.push(self.parse_column_def()?);
+            } else {
+                return Err(self.error("column definitions must come before table constraints"));
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+
+        loop {
+            if self.eat_keyword("WITHOUT") {
+                self.expect_keyword("ROWID")?;
+                schema.without_rowid = true;
+            } else if self.eat_keyword("STRICT") {
+                schema.strict = true;
+            } else {
+                break;
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        Ok(schema)
+    }
+
+    fn parse_create_index(&mut self) -> Result<IndexSchema> {
+        self.expect_keyword("CREATE")?;
+        let unique = self.eat_keyword("UNIQUE");
+        self.expect_keyword("INDEX")?;
+        if self.eat_keyword("IF") {
+            self.expect_keyword("NOT")?;
+            self.expect_keyword("EXISTS")?;
+        }
+        let mut name = self.parse_name("index name")?;
+        if self.eat(&Token::Dot) {
+            name = self.parse_name("index name")?;
+        }
+        self.expect_keyword("ON")?;
+        let table = self.parse_name("table name")?;
+
+        self.expect(&Token::LParen)?;
+        let mut columns = Vec::new();
+        loop {
+            columns.push(self.parse_indexed_column()?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+
+        let (where_sql, predicate) = if self.eat_keyword("WHERE") {
+            let mut end = self.tokens.len();
+            if self.tokens.last() == Some(&Token::Semicolon) {
+                end -= 1;
+            }
+            let text = self.source_text(self.pos, end);
+            let predicate = match self.parse_expr() {
+                Ok(expr) if self.pos == end && expr.is_supported() => Some(expr),
+                _ => None,
+            };
+            self.pos = self.tokens.len();
+            (Some(text), predicate)
+        } else {
+            self.eat(&Token::Semicolon);
+            (None, None)
+        };
+        if self.peek().is_some() {
+            let detail = format!("unexpected {} after statement", self.found(self.pos));
+            return Err(self.error(detail));
+        }
+        Ok(IndexSchema {
+            name,
+            table,
+            unique,
+            columns,
+            where_sql,
+            predicate,
+        })
+    }
+
+    /// One entry of an index's column list: a column or an expression,
+    /// optionally followed by COLLATE and ASC/DESC.
+    fn parse_indexed_column(&mut self) -> Result<IndexedColumn> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(tok) = self.peek() {
+            match tok {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 0 => break,
+                Token::RParen => depth -= 1,
+                Token::Comma if depth == 0 => break,
+                _ => {}
+            }
+            let at_suffix = ["COLLATE", "ASC", "DESC"]
+                .iter()
+                .any(|kw| self.peek_keyword(kw));
+            if depth == 0 && at_suffix {
+                break;
+            }
+            self.pos += 1;
+        }
+        let end = self.pos;
+        if start == end {
+            let detail = format!("expected indexed column, found {}", self.found(self.pos));
+            return Err(self.error(detail));
+        }
+        let collation = if self.eat_keyword("COLLATE") {
+            Some(self.parse_name("collation name")?)
+        } else {
+            None
+        };
+        let descending = self.eat_keyword("DESC");
+        if !descending {
+            self.eat_keyword("ASC");
+        }
+
+        let (name, is_expression) = match &self.tokens[start..end] {
+            [Token::Ident(n) | Token::QuotedIdent(n) | Token::DoubleQuoted(n)] => {
+                (n.clone(), false)
+            }
+            _ => (self.source_text(start, end), true),
+        };
+        let expr = if is_expression {
+            let mut inner = Parser {
+                tokens: self.tokens[start..end].to_vec(),
+                pos: 0,
+                source: Vec::new(),
+                spans: Vec::new(),
+            };
+            match inner.parse_expr() {
+                Ok(expr) if inner.peek().is_none() && expr.is_supported() => Some(expr),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        Ok(IndexedColumn {
+            name,
+            is_expression,
+            expr,
+            descending,
+            collation,
+        })
+    }
+
+    fn is_table_constraint_start(&self) -> bool {
+        ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"]
+            .iter()
+            .any(|kw| self.peek_keyword(kw))
+    }
+
+    fn parse_table_constraint(&mut self) -> Result<TableConstraint> {
+        if self.eat_keyword("CONSTRAINT") {
+            self.parse_name("constraint name")?;
+        }
+        let constraint = if self.eat_keyword("PRIMARY") {
+            self.expect_keyword("KEY")?;
+            TableConstraint::PrimaryKey(self.parse_name_list()?)
+        } else if self.eat_keyword("UNIQUE") {
+            TableConstraint::Unique(self.parse_name_list()?)
+        } else if self.eat_keyword("CHECK") {
+            self.take_group()?;
+            TableConstraint::Check
+        } else if self.eat_keyword("FOREIGN") {
+            self.expect_keyword("KEY")?;
+            let columns = self.parse_name_list()?;
+            self.expect_keyword("REFERENCES")?;
+            TableConstraint::ForeignKey {
+                columns,
+                reference: self.parse_foreign_key_target()?,
+            }
+        } else {
+            let detail = format!("expected table constraint, found {}", self.found(self.pos));
+            return Err(self.error(detail));
+        };
+        self.skip_conflict_clause()?;
+        Ok(constraint)
+    }
+
+    fn parse_column_def(&mut self) -> Result<ColumnDef> {
+        let name = self.parse_name("column name")?;
+        let type_name = self.parse_type_name()?;
+        let mut constraints = Vec::new();
+        loop {
+            if self.eat_keyword("CONSTRAINT") {
+                self.parse_name("constraint name")?;
+            }
+            let constraint = if self.eat_keyword("PRIMARY") {
+                self.expect_keyword("KEY")?;
+                let descending = self.eat_keyword("DESC");
+                if !descending {
+                    self.eat_keyword("ASC");
+                }
+                self.skip_conflict_clause()?;
+                self.eat_keyword("AUTOINCREMENT");
+                ColumnConstraint::PrimaryKey { descending }
+            } else if self.eat_keyword("NOT") {
+                self.expect_keyword("NULL")?;
+                self.skip_conflict_clause()?;
+                ColumnConstraint::NotNull
+            } else if self.eat_keyword("NULL") {
+                self.skip_conflict_clause()?;
+                continue;
+            } else if self.eat_keyword("UNIQUE") {
+                self.skip_conflict_clause()?;
+                ColumnConstraint::Unique
+            } else if self.eat_keyword("CHECK") {
+                self.take_group()?;
+                ColumnConstraint::Check
+            } else if self.eat_keyword("DEFAULT") {
+                let start = self.pos;
+                let sql = if self.peek() == Some(&Token::LParen) {
+                    self.take_group()?;
+                    self.source_text(start + 1, self.pos - 1)
+                } else {
+                    self.parse_unary()?;
+                    self.source_text(start, self.pos)
+                };
+                ColumnConstraint::Default { sql }
+            } else if self.eat_keyword("COLLATE") {
+                ColumnConstraint::Collate(self.parse_name("collation name")?)
+            } else if self.eat_keyword("REFERENCES") {
+                ColumnConstraint::References(self.parse_foreign_key_target()?)
+            } else if self.peek_keyword("GENERATED") || self.peek_keyword("AS") {
+                if self.eat_keyword("GENERATED") {
+                    self.expect_keyword("ALWAYS")?;
+                }
+                self.expect_keyword("AS")?;
+                self.take_group()?;
+                let stored = self.eat_keyword("STORED");
+                if !stored {
+                    self.eat_keyword("VIRTUAL");
+                }
+                ColumnConstraint::Generated { stored }
+            } else {
+                break;
+            };
+            constraints.push(constraint);
+        }
+        Ok(ColumnDef {
+            name,
+            type_name,
+            constraints,
+        })
+    }
+
+    /// Reads a declared type such as `INTEGER`, `VARCHAR(255)`,
+    /// `DECIMAL(10, 2)` or `UNSIGNED BIG INT`. Returns "" when absent.
+    fn parse_type_name(&mut self) -> Result<String> {
+        const CONSTRAINT_KEYWORDS: [&str; 12] = [
+            "CONSTRAINT",
+            "PRIMARY",
+            "NOT",
+            "NULL",
+            "UNIQUE",
+            "CHECK",
+            "DEFAULT",
+            "COLLATE",
+            "REFERENCES",
+            "GENERATED",
+            "AS",
+            "AUTOINCREMENT",
+        ];
+        let mut words = Vec::new();
+        while let Some(Token::Ident(word)) = self.peek() {
+            if CONSTRAINT_KEYWORDS
+                .iter()
+                .any(|kw| word.eq_ignore_ascii_case(kw))
+            {
+                break;
+            }
+            words.push(word.clone());
+            self.pos += 1;
+        }
+        let mut type_name = words.join(" ");
+        if !type_name.is_empty() && self.peek() == Some(&Token::LParen) {
+            let mut args = Vec::new();
+            let mut arg = String::new();
+            for tok in self.take_group()? {
+                match tok {
+                    Token::Comma => args.push(std::mem::take(&mut arg)),
+                    Token::Minus => arg.push('-'),
+                    Token::Plus => arg.push('+'),
+                    Token::Integer(i) => arg.push_str(&i.to_string()),
+                    Token::Real(r) => arg.push_str(&r.to_string()),
+                    Token::Ident(w) => arg.push_str(&w),
+                    _ => {
+                        let detail =
+                            format!("unexpected {} in type name", self.found(self.pos - 1));
+                        return Err(self.error_at(self.pos - 1, detail));
+                    }
+                }
+            }
+            args.push(arg);
+            type_name = format!("{}({})", type_name, args.join(","));
+        }
+        Ok(type_name)
+    }
+
+    fn parse_foreign_key_target(&mut self) -> Result<ForeignKeyTarget> {
+        let table = self.parse_name("table name")?;
+        let columns = if self.peek() == Some(&Token::LParen) {
+            self.parse_name_list()?
+        } else {
+            Vec::new()
+        };
+        // ON DELETE/UPDATE actions, MATCH and DEFERRABLE clauses don't
+        // affect reading.
+        loop {
+            if self.eat_keyword("ON") {
+                self.next();
+                if self.eat_keyword("SET") {
+                    self.next();
+                } else if !self.eat_keyword("CASCADE") && !self.eat_keyword("RESTRICT") {
+                    self.expect_keyword("NO")?;
+                    self.expect_keyword("ACTION")?;
+                }
+            } else if self.eat_keyword("MATCH") {
+                self.next();
+            } else if self.peek_keyword("NOT") && self.peek_keyword_at(1, "DEFERRABLE") {
+                self.pos += 1;
+            } else if self.eat_keyword("DEFERRABLE") {
+                if self.eat_keyword("INITIALLY") {
+                    self.next();
+                }
+            } else {
+                break;
+            }
+        }
+        Ok(ForeignKeyTarget { table, columns })
+    }
+
+    fn skip_conflict_clause(&mut self) -> Result<()> {
+        if self.eat_keyword("ON") {
+            self.expect_keyword("CONFLICT")?;
+            self.next();
+        }
+        Ok(())
+    }
+
+    fn peek_keyword_at(&self, offset: usize, kw: &str) -> bool {
+        matches!(self.tokens.get(self.pos + offset), Some(Token::Ident(s)) if s.eq_ignore_ascii_case(kw))
+    }
+
+    /// The next token, in upper case, if it is one of `keywords`.
+    fn peek_keyword_among(&self, keywords: &[&str]) -> Option<String> {
+        match self.peek() {
+            Some(Token::Ident(s)) if keywords.iter().any(|k| k.eq_ignore_ascii_case(s)) => {
+                Some(s.to_ascii_uppercase())
+            }
+            _ => None,
+        }
+    }
+
+    /// A SELECT that makes up the whole of the SQL, but for a semicolon.
+    fn parse_whole_select(&mut self) -> Result<Select> {
+        if let Some(word) = self.peek_keyword_among(WRITE_STATEMENTS) {
+            return Err(unsupported(format!("{} (no write support yet)", word)));
+        }
+        if let Some(word) = self.peek_keyword_among(OTHER_STATEMENTS) {
+            return Err(unsupported(word));
+        }
+        let select = self.parse_select()?;
+        self.eat(&Token::Semicolon);
+        if self.peek().is_some() {
+            let detail = format!("unexpected {} after statement", self.found(self.pos));
+            return Err(self.error(detail));
+        }
+        check_select(&select)?;
+        Ok(select)
+    }
+
+    fn parse_select(&mut self) -> Result<Select> {
+        let select_start = self.pos;
+        self.expect_keyword("SELECT")?;
+        let mut unsupported = Vec::new();
+        let start = self.pos;
+        if self.eat_keyword("DISTINCT") {
+            unsupported.push(self.unsupported(Capability::Distinct, start));
+        } else {
+            self.eat_keyword("ALL");
+        }
+        let mut columns = Vec::new();
+        let mut labels = Vec::new();
+        loop {
+            let start = self.pos;
+            columns.push(self.parse_expr()?);
+            labels.push(self.source_text(start, self.pos));
+            if self.eat_alias(COLUMN_FOLLOWERS)? {
+                unsupported.push(self.unsupported(Capability::Alias, start));
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        let mut table = String::new();
+        if self.eat_keyword("FROM") {
+            let start = self.pos;
+            if self.peek() == Some(&Token::LParen) {
+                self.take_group()?;
+                unsupported.push(self.unsupported(Capability::Subquery, start));
+            } else {
+                table = self.parse_name("table name")?;
+                if self.eat(&Token::Dot) {
+                    table = self.parse_name("table name")?;
+                    unsupported.push(self.unsupported(Capability::SchemaName, start));
+                }
+                if self.peek() == Some(&Token::LParen) {
+                    self.take_group()?;
+                    unsupported.push(self.unsupported(Capability::TableFunction, start));
+                }
+            }
+            let start = self.pos;
+            if self.eat_alias(FROM_FOLLOWERS)? {
+                unsupported.push(self.unsupported(Capability::Alias, start));
+            }
+            let start = self.pos;
+            if self.eat_keyword("INDEXED") {
+                self.expect_keyword("BY")?;
+                self.parse_name("index name")?;
+                unsupported.push(self.unsupported(Capability::IndexedBy, start));
+            } else if self.peek_keyword("NOT") && self.peek_keyword_at(1, "INDEXED") {
+                self.pos += 2;
+                unsupported.push(self.unsupported(Capability::IndexedBy, start));
+            }
+            let start = self.pos;
+            let joined = self.peek() == Some(&Token::Comma)
+                || JOIN_KEYWORDS.iter().any(|kw| self.peek_keyword(kw));
+            if joined {
+                self.pos += 1;
+                self.skip_clause();
+                unsupported.push(self.unsupported(Capability::Join, start));
+            }
+        } else {
+            unsupported.push(self.unsupported(Capability::NoFrom, select_start));
+        }
+        let where_clause = if self.eat_keyword("WHERE") {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        while let Some(&(keyword, capability)) =
+            CLAUSES.iter().find(|(kw, _)| self.peek_keyword(kw))
+        {
+            let start = self.pos;
+            self.pos += 1;
+            if capability == Capability::Compound {
+                // The rest of the statement belongs to the compound.
+                while self.peek().is_some_and(|t| *t != Token::Semicolon) {
+                    self.pos += 1;
+                }
+            } else {
+                if matches!(keyword, "GROUP" | "ORDER") {
+                    self.expect_keyword("BY")?;
+                }
+                self.skip_clause();
+            }
+            unsupported.push(self.unsupported(capability, start));
+        }
+        Ok(Select {
+            columns,
+            labels,
+            table,
+            where_clause,
+            unsupported,
+        })
+    }
+
+    /// Consumes an alias (`AS name`, or a bare name or string not in
+    /// `not_alias`), and says whether there was one.
+    fn eat_alias(&mut self, not_alias: &[&str]) -> Result<bool> {
+        if self.eat_keyword("AS") {
+            self.parse_name("alias")?;
+            return Ok(true);
+        }
+        let bare = match self.peek() {
+            Some(Token::Ident(name)) => !not_alias.iter().any(|kw| kw.eq_ignore_ascii_case(name)),
+            Some(Token::QuotedIdent(_) | Token::DoubleQuoted(_) | Token::Str(_)) => true,
+            _ => false,
+        };
+        if bare {
+            self.pos += 1;
+        }
+        Ok(bare)
+    }
+
+    /// Skips tokens up to the next clause of a SELECT, outside parentheses.
+    fn skip_clause(&mut self) {
+        let mut depth = 0;
+        while let Some(tok) = self.peek() {
+            match tok {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 0 => break,
+                Token::RParen => depth -= 1,
+                Token::Semicolon if depth == 0 => break,
+                _ => {}
+            }
+            let at_clause =
+                self.peek_keyword("WHERE") || CLAUSES.iter().any(|(kw, _)| self.peek_keyword(kw));
+            if depth == 0 && at_clause {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// The tokens from `start` to here, as a construct the reader can't run.
+    fn unsupported(&self, capability: Capability, start: usize) -> Unsupported {
+        Unsupported {
+            capability,
+            sql: self.source_text(start, self.pos),
+            at: start,
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr> {
+        self.parse_binary(0)
+    }
+
+    fn binary_op(&self) -> Option<(BinaryOp, u8)> {
+        let op = match self.peek()? {
+            Token::Ident(s) if s.eq_ignore_ascii_case("OR") => BinaryOp::Or,
+            Token::Ident(s) if s.eq_ignore_ascii_case("AND") => BinaryOp::And,
+            Token::Ident(s) if s.eq_ignore_ascii_case("LIKE") => BinaryOp::Like,
+            Token::Ident(s) if s.eq_ignore_ascii_case("GLOB") => BinaryOp::Glob,
+            Token::Ident(s) if s.eq_ignore_ascii_case("REGEXP") => BinaryOp::Regexp,
+            Token::Eq => BinaryOp::Eq,
+            Token::NotEq => BinaryOp::NotEq,
+            Token::Lt => BinaryOp::Lt,
+            Token::LtEq => BinaryOp::LtEq,
+            Token::Gt => BinaryOp::Gt,
+            Token::GtEq => BinaryOp::GtEq,
+            Token::Plus => BinaryOp::Add,
+            Token::Minus => BinaryOp::Sub,
+            Token::Star => BinaryOp::Mul,
+            Token::Slash => BinaryOp::Div,
+            Token::Percent => BinaryOp::Rem,
+            Token::Concat => BinaryOp::Concat,
+            _ => return None,
+        };
+        Some((op, op.precedence()))
+    }
+
+    fn parse_binary(&mut self, min_prec: u8) -> Result<Expr> {
+        let start = self.pos;
+        let mut left = if self.eat_keyword("NOT") {
+            let expr = self.parse_binary(3)?;
+            Expr::Unary {
+                op: UnaryOp::Not,
+                expr: Box::new(expr),
+            }
+        } else {
+            self.parse_unary()?
+        };
+
+        loop {
+            // COLLATE binds tighter than any operator.
+            if self.eat_keyword("COLLATE") {
+                let collation = Collation::parse(&self.parse_name("collation name")?)?;
+                left = Expr::Collate {
+                    expr: Box::new(left),
+                    collation,
+                };
+                continue;
+            }
+            if min_prec <= 4 && self.peek_keyword("IS") {
+                self.pos += 1;
+                let negated = self.eat_keyword("NOT");
+                if self.eat_keyword("NULL") {
+                    left = Expr::IsNull {
+                        expr: Box::new(left),
+                        negated,
+                    };
+                    continue;
+                }
+                if self.eat_keyword("DISTINCT") {
+                    self.expect_keyword("FROM")?;
+                }
+                self.parse_binary(5)?;
+                left = Expr::Unsupported(self.unsupported(Capability::Is, start));
+                continue;
+            }
+            if min_prec <= 4 && self.eat_keyword("MATCH") {
+                left = self.parse_match(start)?;
+                continue;
+            }
+            if min_prec <= 4 && self.peek_keyword("NOTNULL") {
+                self.pos += 1;
+                left = Expr::IsNull {
+                    expr: Box::new(left),
+                    negated: true,
+                };
+                continue;
+            }
+            if min_prec <= 4 && self.peek_keyword("ISNULL") {
+                self.pos += 1;
+                left = Expr::IsNull {
+                    expr: Box::new(left),
+                    negated: false,
+                };
+                continue;
+            }
+            if min_prec <= 4 && self.peek_keyword("BETWEEN") {
+                self.pos += 1;
+                left = self.parse_between(left)?;
+                continue;
+            }
+            if min_prec <= 4 && self.eat_keyword("IN") {
+                left = self.parse_in(start)?;
+                continue;
+            }
+            if min_prec <= 4 && self.peek_keyword("NOT") {
+                let save = self.pos;
+                self.pos += 1;
+                if self.eat_keyword("BETWEEN") {
+                    left = Expr::Unary {
+                        op: UnaryOp::Not,
+                        expr: Box::new(self.parse_between(left)?),
+                    };
+                    continue;
+                }
+                if self.eat_keyword("IN") {
+                    left = self.parse_in(start)?;
+                    continue;
+                }
+                if self.eat_keyword("MATCH") {
+                    left = self.parse_match(start)?;
+                    continue;
+                }
+                // `NOT NULL` is another spelling of NOTNULL.
+                if self.eat_keyword("NULL") {
+                    left = Expr::IsNull {
+                        expr: Box::new(left),
+                        negated: true,
+                    };
+                    continue;
+                }
+                match self.binary_op() {
+                    Some((op @ (BinaryOp::Like | BinaryOp::Glob | BinaryOp::Regexp), prec)) => {
+                        self.pos += 1;
+                        let right = self.parse_binary(prec + 1)?;
+                        let matched = Expr::Binary {
+                            op,
+                            left: Box::new(left),
+                            right: Box::new(right),
+                        };
+                        left = self.parse_escape(
+                            Expr::Unary {
+                                op: UnaryOp::Not,
+                                expr: Box::new(matched),
+                            },
+                            op,
+                            start,
+                        )?;
+                        continue;
+                    }
+                    _ => {
+                        self.pos = save;
+                        break;
+                    }
+                }
+            }
+            // Bitwise operators bind between comparisons and addition.
+            let bitwise = matches!(
+                self.peek(),
+                Some(Token::BitAnd | Token::BitOr | Token::ShiftLeft | Token::ShiftRight)
+            );
+            if min_prec <= 6 && bitwise {
+                self.pos += 1;
+                self.parse_binary(7)?;
+                left = Expr::Unsupported(self.unsupported(Capability::Bitwise, start));
+                continue;
+            }
+            // The JSON operators bind as tightly as `||`.
+            if min_prec <= 9 && matches!(self.peek(), Some(Token::Arrow | Token::LongArrow)) {
+                self.pos += 1;
+                self.parse_binary(10)?;
+                left = Expr::Unsupported(self.unsupported(Capability::Extract, start));
+                continue;
+            }
+            let (op, prec) = match self.binary_op() {
+                Some(op) if op.1 >= min_prec => op,
+                _ => break,
+            };
+            self.pos += 1;
+            let right = self.parse_binary(prec + 1)?;
+            let binary = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+            left = self.parse_escape(binary, op, start)?;
+        }
+
+        Ok(left)
+    }
+
+    /// `matched`, or if it is a LIKE (or GLOB or REGEXP, which SQLite parses
+    /// the same way) followed by `ESCAPE char`, which the reader can't run,
+    /// the whole of it from `start`.
+    fn parse_escape(&mut self, matched: Expr, op: BinaryOp, start: usize) -> Result<Expr> {
+        let like = matches!(op, BinaryOp::Like | BinaryOp::Glob | BinaryOp::Regexp);
+        if !like || !self.eat_keyword("ESCAPE") {
+            return Ok(matched);
+        }
+        self.parse_binary(op.precedence() + 1)?;
+        Ok(Expr::Unsupported(
+            self.unsupported(Capability::Escape, start),
+        ))
+    }
+
+    /// The rest of `expr [NOT] MATCH pattern`, from `start`, which the reader
+    /// can't run.
+    fn parse_match(&mut self, start: usize) -> Result<Expr> {
+        self.parse_binary(5)?;
+        if self.eat_keyword("ESCAPE") {
+            self.parse_binary(5)?;
+        }
+        Ok(Expr::Unsupported(
+            self.unsupported(Capability::Match, start),
+        ))
+    }
+
+    /// The rest of `expr BETWEEN low AND high`, which is shorthand for
+    /// `expr >= low AND expr <= high`. The bounds bind tighter than AND.
+    fn parse_between(&mut self, expr: Expr) -> Result<Expr> {
+        let low = self.parse_binary(5)?;
+        self.expect_keyword("AND")?;
+        let high = self.parse_binary(5)?;
+        let compare = |op, bound| Expr::Binary {
+            op,
+            left: Box::new(expr.clone()),
+            right: Box::new(bound),
+        };
+        Ok(Expr::Binary {
+            op: BinaryOp::And,
+            left: Box::new(compare(BinaryOp::GtEq, low)),
+            right: Box::new(compare(BinaryOp::LtEq, high)),
+        })
+    }
+
+    /// The rest of `expr [NOT] IN (...)` or `expr [NOT] IN table`, from
+    /// `start`, which the reader can't run.
+    fn parse_in(&mut self, start: usize) -> Result<Expr> {
+        if self.peek() == Some(&Token::LParen) {
+            self.take_group()?;
+        } else {
+            self.parse_name("table name")?;
+        }
+        Ok(Expr::Unsupported(self.unsupported(Capability::In, start)))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Minus) {
+            let start = self.pos;
+            let expr = self.parse_unary()?;
+            match expr {
+                Expr::Literal(Value::Integer(v)) if v != i64::MIN => {
+                    return Ok(Expr::Literal(Value::Integer(-v)));
+                }
+                // 9223372036854775808 is too large for an integer, but its
+                // negation is i64::MIN, which SQLite keeps an integer.
+                Expr::Literal(Value::Real(_))
+                    if self.is_integer_literal(start, "9223372036854775808") =>
+                {
+                    return Ok(Expr::Literal(Value::Integer(i64::MIN)));
+                }
+                Expr::Literal(Value::Real(v)) => return Ok(Expr::Literal(Value::Real(-v))),
+                _ => {}
+            }
+            return Ok(Expr::Unary {
+                op: UnaryOp::Neg,
+                expr: Box::new(expr),
+            });
+        }
+        if self.eat(&Token::Plus) {
+            let expr = self.parse_unary()?;
+            return Ok(Expr::Unary {
+                op: UnaryOp::Plus,
+                expr: Box::new(expr),
+            });
+        }
+        let start = self.pos;
+        if self.eat(&Token::Tilde) {
+            self.parse_unary()?;
+            return Ok(Expr::Unsupported(
+                self.unsupported(Capability::Bitwise, start),
+            ));
+        }
+        self.parse_primary()
+    }
+
+    /// Whether the tokens from `start` to the current one are the decimal
+    /// integer `digits`, perhaps in parentheses.
+    /// `expr [ASC | DESC] [NULLS FIRST | NULLS LAST], ...`, as in an
+    /// aggregate's ORDER BY.
+    fn parse_ordering_terms(&mut self) -> Result<()> {
+        loop {
+            self.parse_expr()?;
+            if !self.eat_keyword("ASC") {
+                self.eat_keyword("DESC");
+            }
+            if self.eat_keyword("NULLS") && !self.eat_keyword("FIRST") {
+                self.expect_keyword("LAST")?;
+            }
+            if !self.eat(&Token::Comma) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn is_integer_literal(&self, start: usize, digits: &str) -> bool {
+        let mut numbers =
+            (start..self.pos).filter(|&i| !matches!(self.tokens[i], Token::LParen | Token::RParen));
+        match (numbers.next(), numbers.next()) {
+            (Some(i), None) => {
+                let text: String = self.source[self.spans[i].clone()]
+                    .iter()
+                    .filter(|&&c| c != '_')
+                    .collect();
+                text.trim_start_matches('0') == digits
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let start = self.pos;
+        let qualified = matches!(
+            self.peek(),
+            Some(Token::Ident(_) | Token::QuotedIdent(_) | Token::DoubleQuoted(_))
+        ) && self.tokens.get(self.pos + 1) == Some(&Token::Dot);
+        if qualified {
+            self.pos += 2;
+            if !self.eat(&Token::Star) {
+                self.parse_name("column name")?;
+            }
+            return Ok(Expr::Unsupported(
+                self.unsupported(Capability::QualifiedColumn, start),
+            ));
+        }
+        match self.next() {
+            Some(Token::Integer(v)) => Ok(Expr::Literal(Value::Integer(v))),
+            Some(Token::Real(v)) => Ok(Expr::Literal(Value::Real(v))),
+            Some(Token::Blob(b)) => Ok(Expr::Literal(Value::Blob(b))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::Text(s))),
+            Some(Token::QuotedIdent(name)) => Ok(Expr::Column(name)),
+            Some(Token::DoubleQuoted(name)) => Ok(Expr::DoubleQuoted(name)),
+            Some(Token::Variable(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Positional(_)) => Ok(Expr::Unsupported(
+                self.unsupported(Capability::Positional, start),
+            )),
+            Some(Token::Star) => Ok(Expr::Unsupported(
+                self.unsupported(Capability::ResultStar, start),
+            )),
+            Some(Token::LParen) if self.peek_keyword("SELECT") => {
+                self.pos = start;
+                self.take_group()?;
+                Ok(Expr::Unsupported(
+                    self.unsupported(Capability::Subquery, start),
+                ))
+            }
+            Some(Token::LParen) => {
+                let expr = self.parse_expr()?;
+                if self.eat(&Token::Comma) {
+                    self.parse_expr()?;
+                    while self.eat(&Token::Comma) {
+                        self.parse_expr()?;
+                    }
+                    self.expect(&Token::RParen)?;
+                    return Ok(Expr::Unsupported(
+                        self.unsupported(Capability::RowValue, start),
+                    ));
+                }
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                // The clause keywords can't be column names, except WINDOW.
+                let reserved = COLUMN_FOLLOWERS
+                    .iter()
+                    .any(|kw| *kw != "WINDOW" && kw.eq_ignore_ascii_case(&name));
+                if reserved {
+                    let detail = format!("unexpected {} in expression", self.found(self.pos - 1));
+                    return Err(self.error_at(self.pos - 1, detail));
+                }
+                if name.eq_ignore_ascii_case("NULL") {
+                    return Ok(Expr::Literal(Value::Null));
+                }
+                if name.eq_ignore_ascii_case("CASE") {
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match self.next() {
+                            Some(Token::Ident(k)) if k.eq_ignore_ascii_case("CASE") => depth += 1,
+                            Some(Token::Ident(k)) if k.eq_ignore_ascii_case("END") => depth -= 1,
+                            Some(_) => {}
+                            None => return Err(self.error("expected END, found end of input")),
+                        }
+                    }
+                    return Ok(Expr::Unsupported(self.unsupported(Capability::Case, start)));
+                }
+                let special = [("CAST", Capability::Cast), ("EXISTS", Capability::Exists)]
+                    .into_iter()
+                    .find(|(kw, _)| name.eq_ignore_ascii_case(kw));
+                if let Some((_, capability)) = special
+                    && self.peek() == Some(&Token::LParen)
+                {
+                    self.take_group()?;
+                    return Ok(Expr::Unsupported(self.unsupported(capability, start)));
+                }
+                if !self.eat(&Token::LParen) {
+                    return Ok(Expr::Column(name));
+                }
+                // What of the call the reader can't run, the last one found.
+                let mut refused = None;
+                let count_star = name.eq_ignore_ascii_case("COUNT") && self.eat(&Token::Star);
+                let mut args = Vec::new();
+                if count_star {
+                    self.expect(&Token::RParen)?;
+                } else if !self.eat(&Token::RParen) {
+                    if self.eat_keyword("DISTINCT") {
+                        refused = Some(Capability::Distinct);
+                    }
+                    args.push(self.parse_expr()?);
+                    while self.eat(&Token::Comma) {
+                        args.push(self.parse_expr()?);
+                    }
+                    if self.eat_keyword("ORDER") {
+                        self.expect_keyword("BY")?;
+                        self.parse_ordering_terms()?;
+                        refused = Some(Capability::OrderBy);
+                    }
+                    self.expect(&Token::RParen)?;
+                }
+                if self.peek_keyword("FILTER")
+                    && self.tokens.get(self.pos + 1) == Some(&Token::LParen)
+                {
+                    self.pos += 1;
+                    self.take_group()?;
+                    refused = Some(Capability::Filter);
+                }
+                if self.eat_keyword("OVER") {
+                    if self.peek() == Some(&Token::LParen) {
+                        self.take_group()?;
+                    } else {
+                        self.parse_name("window name")?;
+                    }
+                    refused = Some(Capability::Window);
+                }
+                if let Some(capability) = refused {
+                    return Ok(Expr::Unsupported(self.unsupported(capability, start)));
+                }
+                if count_star {
+                    return Ok(Expr::CountStar);
+                }
+                Ok(Expr::Function {
+                    name: name.to_lowercase(),
+                    args,
+                })
+            }
+            _ => {
+                let detail = format!("unexpected {} in expression", self.found(self.pos - 1));
+                Err(self.error_at(self.pos - 1, detail))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RqliteError;
+
+    fn literals(sql: &str) -> Vec<Expr> {
+        parse_statement(sql).unwrap().1.columns
+    }
+
+    #[test]
+    fn negated_integer_literals_fold() {
+        let min = Expr::Literal(Value::Integer(i64::MIN));
+        assert_eq!(
+            literals("SELECT -9223372036854775808, -(9223372036854775808), - 09223372036854775808 FROM t"),
+            [min.clone(), min.clone(), min]
+        );
+        // Only i64::MIN itself fits: one past it, or written as a real, stays real.
+        assert_eq!(
+            literals(
+                "SELECT -9223372036854775809, -9223372036854775808.0, 9223372036854775808 FROM t"
+            ),
+            [
+                Expr::Literal(Value::Real(-9223372036854775808.0)),
+                Expr::Literal(Value::Real(-9223372036854775808.0)),
+                Expr::Literal(Value::Real(9223372036854775808.0)),
+            ]
+        );
+        assert_eq!(
+            literals("SELECT -9223372036854775807 FROM t"),
+            [Expr::Literal(Value::Integer(-9223372036854775807))]
+        );
+    }
+
+    #[test]
+    fn digit_separators_are_ignored() {
+        assert_eq!(
+            literals("SELECT 1_000, 0x1_F, 1_0.2_5, 1e1_0, -9_223_372_036_854_775_808 FROM t"),
+            vec![
+                Expr::Literal(Value::Integer(1000)),
+                Expr::Literal(Value::Integer(31)),
+                Expr::Literal(Value::Real(10.25)),
+                Expr::Literal(Value::Real(1e10)),
+                Expr::Literal(Value::Integer(i64::MIN)),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_statements_are_unsupported() {
+        for (sql, refused) in [
+            ("INSERT INTO t VALUES (1)", "INSERT"),
+            ("insert or replace into t values (1)", "INSERT"),
+            ("UPDATE t SET a = 1", "UPDATE"),
+            ("DELETE FROM t", "DELETE"),
+            ("create table t (a)", "CREATE"),
+            ("EXPLAIN DROP TABLE t", "DROP"),
+        ] {
+            let e = parse_statement(sql).unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                format!("unsupported: {} (no write support yet)", refused)
+            );
+            assert!(matches!(
+                e.downcast_ref(),
+                Some(RqliteError::Unsupported { .. })
+            ));
+        }
+    }
+
+    /// Only SQL that doesn't parse is a syntax error, which the CLI points
+    /// at; valid statements the reader can't run are plainly unsupported.
+    #[test]
+    fn only_bad_sql_is_a_syntax_error() {
+        let is_syntax_error = |sql: &str| {
+            let e = parse_statement(sql).unwrap_err();
+            matches!(e.downcast_ref(), Some(RqliteError::SyntaxError { .. }))
+        };
+        for sql in [
+            "INSERT INTO t VALUES (1)",
+            "WITH x AS (SELECT 1) SELECT * FROM x",
+            "VALUES (1)",
+            "BEGIN",
+            "ATTACH 'other.db' AS other",
+            "EXPLAIN QUERY PLAN SELECT a FROM t",
+        ] {
+            assert!(!is_syntax_error(sql), "{}", sql);
+        }
+        // Valid SELECTs the reader can't run are refused by capability.
+        for (sql, refused) in [
+            ("SELECT 1", "SELECT without FROM (SELECT 1)"),
+            ("SELECT 1 WHERE 1", "SELECT without FROM (SELECT 1)"),
+            ("SELECT a FROM t WHERE a IS 'z'", "IS comparison (a IS 'z')"),
+            (
+                "SELECT a FROM t WHERE a IS NOT DISTINCT FROM b",
+                "IS comparison (a IS NOT DISTINCT FROM b)",
+            ),
+            (
+                "SELECT a FROM t WHERE a LIKE 'x!%' ESCAPE '!'",
+                "ESCAPE (a LIKE 'x!%' ESCAPE '!')",
+            ),
+            (
+                "SELECT a FROM t WHERE a NOT LIKE 'x!%' ESCAPE '!'",
+                "ESCAPE (a NOT LIKE 'x!%' ESCAPE '!')",
+            ),
+            ("SELECT a FROM main.t", "schema name (main.t)"),
+            (
+                "SELECT a FROM t INDEXED BY t_a",
+                "INDEXED BY (INDEXED BY t_a)",
+            ),
+            ("SELECT a FROM t NOT INDEXED", "INDEXED BY (NOT INDEXED)"),
+            ("SELECT a FROM t WHERE a = ?", "positional parameter (?)"),
+            ("SELECT a FROM t WHERE a = ?1", "positional parameter (?1)"),
+            ("SELECT ~a FROM t", "bitwise operator (~a)"),
+            ("SELECT a & 1 FROM t", "bitwise operator (a & 1)"),
+            ("SELECT a | 1 << 2 FROM t", "bitwise operator (a | 1 << 2)"),
+            (
+                "SELECT a = 1 + b >> 2 FROM t",
+                "bitwise operator (1 + b >> 2)",
+            ),
+            ("SELECT a FROM t WHERE a MATCH 'x'", "MATCH (a MATCH 'x')"),
+            (
+                "SELECT a FROM t WHERE a GLOB 'x' ESCAPE '!'",
+                "ESCAPE (a GLOB 'x' ESCAPE '!')",
+            ),
+            (
+                "SELECT a -> '$.b' ->> 'c' FROM t",
+                "-> operator (a -> '$.b' ->> 'c')",
+            ),
+            (
+                "SELECT (a, b) = (1, 2) FROM t",
+                "row value ((a, b)), row value ((1, 2))",
+            ),
+            (
+                "SELECT count(DISTINCT a) FROM t",
+                "DISTINCT (count(DISTINCT a))",
+            ),
+            (
+                "SELECT group_concat(a ORDER BY a DESC NULLS LAST) FROM t",
+                "ORDER BY (group_concat(a ORDER BY a DESC NULLS LAST))",
+            ),
+            (
+                "SELECT count(*) FILTER (WHERE a) FROM t",
+                "FILTER (count(*) FILTER (WHERE a))",
+            ),
+            (
+                "SELECT count(*) OVER () FROM t",
+                "window function (count(*) OVER ())",
+            ),
+            ("SELECT a 'x' FROM t", "alias (a 'x')"),
+            (
+                "SELECT a FROM (SELECT a FROM t)",
+                "subquery ((SELECT a FROM t))",
+            ),
+            (
+                "SELECT value FROM json_each('[1]')",
+                "table-valued function (json_each('[1]'))",
+            ),
+        ] {
+            let e = parse_statement(sql).unwrap_err();
+            assert!(!is_syntax_error(sql), "{}", sql);
+            assert_eq!(
+                e.to_string(),
+                format!("unsupported: {}", refused),
+                "{}",
+                sql
+            );
+        }
+        for sql in [
+            "SELEC a FROM t",
+            "SELECT a FROM t WHERE a = = 1",
+            "INTO t",
+            "SELECT a, FROM t",
+            "SELECT 1__000 FROM t",
+        ] {
+            assert!(is_syntax_error(sql), "{}", sql);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+
+use crate::collation::Collation;
+use crate::cursor::TableCursor;
+use crate::error::RqliteError;
+use crate::header::DbHeader;
+use crate::pager::Pager;
+use crate::planner::index_column_collation;
+use crate::record::ColumnSource;
+use crate::settings::Settings;
+use crate::sql::{parse_create_index, parse_create_table};
+use crate::value::Value;
+use crate::{read_schema_rows, scan_table_btree_count, IndexPage};
+
+/// Statistics from a database's sqlite_stat1 table, in the form `ANALYZE`
+/// writes them: for each index, `N a1 a2 ...`, its N entries and about how
+/// many of them share each value of its first i columns; for a table with
+/// no indexes, its row count alone.
+pub struct Stats {
+    rows: Vec<StatRow>,
+}
+
+struct StatRow {
+    table: String,
+    index: Option<String>,
+    numbers: Vec<f64>,
+}
+
+impl Stats {
+    /// Reads sqlite_stat1; a database never analyzed has no statistics.
+    pub fn read(pager: &mut Pager, settings: &Settings) -> Result<Stats> {
+        let header = pager.header()?;
+        let root = read_schema_rows(pager)?
+            .into_iter()
+            .find(|r| r.kind == "table" && r.name.eq_ignore_ascii_case("sqlite_stat1"))
+            .map(|r| r.rootpage);
+        let Some(root) = root else {
+            return Ok(Stats { rows: Vec::new() });
+        };
+        let sources = [
+            ColumnSource::Field(0),
+            ColumnSource::Field(1),
+            ColumnSource::Field(2),
+        ];
+        let mut rows = Vec::new();
+        for row in TableCursor::new(pager, root, &header, &sources, settings)? {
+            let row = row?;
+            let (Value::Text(table), Value::Text(stat)) = (&row[0], &row[2]) else {
+                continue;
+            };
+            let index = match &row[1] {
+                Value::Text(index) => Some(index.clone()),
+                _ => None,
+            };
+            // Options such as `unordered` or `sz=N` may follow the numbers.
+            let numbers = stat
+                .split_whitespace()
+                .map_while(|n| n.parse().ok())
+                .collect();
+            rows.push(StatRow {
+                table: table.clone(),
+                index,
+                numbers,
+            });
+        }
+        Ok(Stats { rows })
+    }
+
+    /// The row count of `table`, if it has been analyzed: the most entries
+    /// any of its rows records, since a partial index holds fewer.
+    pub fn table_rows(&self, table: &str) -> Option<f64> {
+        self.rows
+            .iter()
+            .filter(|r| r.table.eq_ignore_ascii_case(table))
+            .filter_map(|r| r.numbers.first().copied())
+            .reduce(f64::max)
+    }
+
+    /// `N a1 a2 ...` for `index`, if it has been analyzed.
+    pub fn index(&self, index: &str) -> Option<&[f64]> {
+        self.rows
+            .iter()
+            .find(|r| {
+                r.index
+                    .as_deref()
+                    .is_some_and(|i| i.eq_ignore_ascii_case(index))
+            })
+            .map(|r| r.numbers.as_slice())
+            .filter(|n| !n.is_empty())
+    }
+}
+
+/// The sqlite_stat1 rows (`tbl`, `idx`, `stat`) that `ANALYZE` would write
+/// for `target`, a table or an index, or for every table without one. The
+/// numbers are computed as SQLite computes them.
+pub fn analyze(
+    pager: &mut Pager,
+    target: Option<&str>,
+    settings: &Settings,
+) -> Result<Vec<Vec<Value>>> {
+    let header = pager.header()?;
+    let schema = read_schema_rows(pager)?;
+    let target = target.filter(|t| !t.eq_ignore_ascii_case("main"));
+    if let Some(target) = target {
+        let exists = schema.iter().any(|r| {
+            matches!(r.kind.as_str(), "table" | "index") && r.name.eq_ignore_ascii_case(target)
+        });
+        if !exists {
+            return Err(RqliteError::TableNotFound {
+                name: target.to_string(),
+            }
+            .into());
+        }
+    }
+
+    let mut rows = Vec::new();
+    for table in &schema {
+        if table.kind != "table" || table.rootpage == 0 || table.name.starts_with("sqlite_") {
+            continue;
+        }
+        let indexes: Vec<_> = schema
+            .iter()
+            .filter(|r| {
+                r.kind == "index" && r.rootpage != 0 && r.tbl_name.eq_ignore_ascii_case(&table.name)
+            })
+            .filter(|r| {
+                target.is_none_or(|t| {
+                    t.eq_ignore_ascii_case(&table.name) || t.eq_ignore_ascii_case(&r.name)
+                })
+            })
+            .collect();
+        let whole_table = target.is_none_or(|t| t.eq_ignore_ascii_case(&table.name));
+        if indexes.is_empty() && !whole_table {
+            continue;
+        }
+        let definition = parse_create_table(&table.sql).ok();
+        let text = |s: &str| Value::Text(s.to_string());
+        let mut stats = Vec::new();
+        let count = match &definition {
+            // The table is itself an index on its PRIMARY KEY, which ANALYZE
+            // records under the table's name.
+            Some(definition) if definition.without_rowid => {
+                let key = definition.primary_key();
+                let collations = key
+                    .iter()
+                    .map(|k| definition.column_collation(k).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                let (entries, stat) = index_stat(
+                    pager,
+                    table.rootpage,
+                    &header,
+                    Some(key.len()),
+                    &collations,
+                    settings,
+                )?;
+                if whole_table && entries > 0 {
+                    stats.push(vec![text(&table.name), text(&table.name), text(&stat)]);
+                }
+                entries
+            }
+            _ => {
+                let mut count = 0;
+                scan_table_btree_count(pager, table.rootpage, &header, &mut count)?;
+                count as u64
+            }
+        };
+        // The row count has a row of its own unless an index that holds
+        // every row gives it.
+        let mut counted = !stats.is_empty();
+        for index in indexes {
+            let parsed = parse_create_index(&index.sql).ok();
+            // Automatic indexes have no SQL: their keys are every field but
+            // the last, and compare as BINARY.
+            let (keys, collations) = match (&parsed, &definition) {
+                (Some(parsed), Some(definition)) => (
+                    Some(parsed.columns.len()),
+                    parsed
+                        .columns
+                        .iter()
+                        .map(|c| index_column_collation(c, definition).unwrap_or_default())
+                        .collect(),
+                ),
+                _ => (None, Vec::new()),
+            };
+            let (entries, stat) =
+                index_stat(pager, index.rootpage, &header, keys, &collations, settings)?;
+            // As in SQLite, an empty index gets no row unless it is partial.
+            let partial = parsed.is_some_and(|p| p.where_sql.is_some());
+            if entries > 0 || partial {
+                stats.push(vec![text(&table.name), text(&index.name), text(&stat)]);
+            }
+            counted |= !partial;
+        }
+        if whole_table && !counted && count > 0 {
+            stats.push(vec![
+                text(&table.name),
+                Value::Null,
+                text(&count.to_string()),
+            ]);
+        }
+        rows.extend(stats);
+    }
+    Ok(rows)
+}
+
+/// The entry count of an index with `keys` key columns (by default, every
+/// field but the rowid), and its stat1 string: the count, then for each
+/// prefix of the keys the average number of entries per distinct prefix,
+/// rounded up.
+fn index_stat(
+    pager: &mut Pager,
+    root: u32,
+    header: &DbHeader,
+    keys: Option<usize>,
+    collations: &[Collation],
+    settings: &Settings,
+) -> Result<(u64, String)> {
+    let mut entries = 0u64;
+    let mut distinct: Vec<u64> = vec![0; keys.unwrap_or(0)];
+    let mut previous: Option<Vec<Value>> = None;
+    walk_index(pager, root, header, settings, 0, &mut |entry| {
+        let keys = keys
+            .unwrap_or(entry.len().saturating_sub(1))
+            .min(entry.len());
+        distinct.resize(keys, 0);
+        let differs = previous.as_ref().map_or(0, |p| {
+            (0..keys)
+                .find(|&i| {
+                    let collation = collations.get(i).copied().unwrap_or_default();
+                    p.get(i)
+                        .is_none_or(|v| collation.compare(v, &entry[i]) != Ordering::Equal)
+                })
+                .unwrap_or(keys)
+        });
+        for d in &mut distinct[differs..] {
+            *d += 1;
+        }
+        entries += 1;
+        previous = Some(entry);
+    })?;
+    let mut stat = entries.to_string();
+    for &d in &distinct {
+        let mut average = entries.div_ceil(d.max(1));
+        // As SQLite does: nearly all-distinct keys count as unique.
+        if average == 2 && entries * 10 <= d * 11 {
+            average = 1;
+        }
+        stat.push_str(&format!(" {}", average));
+    }
+    Ok((entries, stat))
+}
+
+/// Calls `visit` with every entry of an index b-tree, in index order.
+fn walk_index(
+    pager: &mut Pager,
+    page_no: u32,
+    header: &DbHeader,
+    settings: &Settings,
+    depth: usize,
+    visit: &mut dyn FnMut(Vec<Value>),
+) -> Result<()> {
+    let Some(page) = IndexPage::read(pager, page_no, header, depth)? else {
+        return Ok(());
+    };
+    for i in 0..=page.cell_count {
+        if !page.is_leaf {
+            walk_index(pager, page.child(i)?, header, settings, depth + 1, visit)?;
+        }
+        if i < page.cell_count {
+            visit(page.entry(i, header, settings)?);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use anyhow::Result;
+
+use crate::pager::Pager;
+use crate::planner::{self, Input, Output};
+use crate::settings::Settings;
+use crate::sql::{is_rowid_name, parse_statement, ColumnConstraint, Expr, Select, TableSchema};
+use crate::value::Value;
+use crate::vm::{Program, Vm};
+use crate::why;
+use crate::{find_table_schema, RowIter};
+
+/// A parsed query, ready to run against a database.
+pub struct Statement {
+    select: Select,
+    /// Whether the statement is `EXPLAIN SELECT ...`, which lists the
+    /// query's program rather than running it.
+    explain: bool,
+}
+
+impl Statement {
+    pub fn prepare(sql: &str) -> Result<Statement> {
+        let (explain, select) = parse_statement(sql)?;
+        Ok(Statement { select, explain })
+    }
+
+    /// Traces one row through the query's plan instead of running it; see
+    /// `why::trace`.
+    pub fn why(&self, pager: &mut Pager, rowid: i64, settings: &Settings) -> Result<Vec<String>> {
+        why::trace(pager, &self.select, rowid, settings)
+    }
+
+    /// Substitutes `$name` variables with the values `lookup` gives.
+    pub fn bind(&mut self, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<()> {
+        self.select.bind(lookup)
+    }
+
+    /// Runs the query. Rows are read from the database as the returned
+    /// iterator is advanced, so a full scan holds one row at a time rather
+    /// than the whole table. An `EXPLAIN` statement yields the listing of
+    /// the query's program instead (see `Program::explain`).
+    pub fn query<'a>(&self, pager: &'a mut Pager, settings: &'a Settings) -> Result<Rows<'a>> {
+        self.compile(pager, settings)?.run(pager, settings)
+    }
+
+    /// Plans the query and compiles it to a program. The program holds on to
+    /// the schema it was compiled against, so it stays valid only as long as
+    /// the schema does.
+    pub fn compile(&self, pager: &mut Pager, settings: &Settings) -> Result<Compiled> {
+        let (_, table) = find_table_schema(pager, &self.select.table)?;
+        let mut select = self.select.clone();
+        select.resolve_double_quoted(&|name| table.has_column(name));
+        let columns = select.column_names(&table);
+        let meta = column_meta(&select, &table, &columns);
+        let program = planner::compile(pager, &select, Input::Table, Output::Results, settings)?;
+        Ok(Compiled {
+            program,
+            columns,
+            meta,
+            explain: self.explain,
+        })
+    }
+}
+
+/// A statement compiled against one version of a database's schema, which
+/// can be run any number of times.
+#[derive(Clone)]
+pub struct Compiled {
+    program: Program,
+    columns: Vec<String>,
+    meta: Vec<ColumnMeta>,
+    explain: bool,
+}
+
+impl Compiled {
+    pub fn run<'a>(&self, pager: &'a mut Pager, settings: &'a Settings) -> Result<Rows<'a>> {
+        if self.explain {
+            let columns: Vec<String> = ["addr", "opcode", "p1", "p2", "p3", "p4", "p5", "comment"]
+                .map(String::from)
+                .to_vec();
+            let meta = columns
+                .iter()
+                .map(|name| ColumnMeta {
+                    name: name.clone(),
+                    declared_type: None,
+                    table: None,
+                    column: None,
+                    not_null: None,
+                })
+                .collect();
+            return Ok(Rows {
+                columns,
+                meta,
+                rows: Box::new(self.program.explain().into_iter().map(Ok)),
+            });
+        }
+        let rows = Vm::new(pager, self.program.clone(), settings)?
+            .enumerate()
+            .map(move |(i, row)| {
+                settings.check_row_count(i + 1)?;
+                row
+            });
+        Ok(Rows {
+            columns: self.columns.clone(),
+            meta: self.meta.clone(),
+            rows: Box::new(rows),
+        })
+    }
+}
+
+/// What is known of a result column beyond its name, like sqlite3's
+/// `sqlite3_column_decltype()` and `sqlite3_column_origin_name()`. Only a
+/// result column that names a table column directly has an origin; for an
+/// expression every field is None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMeta {
+    pub name: String,
+    /// The origin column's declared type, as written; None if it has none.
+    pub declared_type: Option<String>,
+    pub table: Option<String>,
+    pub column: Option<String>,
+    /// Whether the column can never be NULL: declared NOT NULL, or the rowid.
+    pub not_null: Option<bool>,
+}
+
+fn column_meta(select: &Select, table: &TableSchema, names: &[String]) -> Vec<ColumnMeta> {
+    let rowid_alias = table.rowid_alias();
+    select
+        .columns
+        .iter()
+        .zip(names)
+        .map(|(expr, name)| {
+            let mut meta = ColumnMeta {
+                name: name.clone(),
+                declared_type: None,
+                table: None,
+                column: None,
+                not_null: None,
+            };
+            let Expr::Column(column) = expr else {
+                return meta;
+            };
+            meta.table = Some(table.name.clone());
+            // `rowid` names an INTEGER PRIMARY KEY column when there is one.
+            let index = table
+                .column_index(column)
+                .or_else(|| is_rowid_name(column).then_some(rowid_alias).flatten());
+            match index {
+                Some(i) => {
+                    let def = &table.columns[i];
+                    let not_null = def
+                        .constraints
+                        .iter()
+                        .any(|c| matches!(c, ColumnConstraint::NotNull));
+                    meta.declared_type = Some(def.type_name.clone()).filter(|t| !t.is_empty());
+                    meta.column = Some(def.name.clone());
+                    meta.not_null = Some(not_null || Some(i) == rowid_alias);
+                }
+                // A rowid pseudo-column, reported as sqlite3 does.
+                None if is_rowid_name(column) => {
+                    meta.declared_type = Some("INTEGER".to_string());
+                    meta.column = Some("rowid".to_string());
+                    meta.not_null = Some(true);
+                }
+                None => meta.table = None,
+            }
+            meta
+        })
+        .collect()
+}
+
+/// The result of a query: its column names, and its rows as an iterator.
+pub struct Rows<'a> {
+    columns: Vec<String>,
+    meta: Vec<ColumnMeta>,
+    rows: RowIter<'a>,
+}
+
+impl Rows<'_> {
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Each result column's declared type, origin and nullability.
+    pub fn column_meta(&self) -> &[ColumnMeta] {
+        &self.meta
+    }
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Result<Vec<Value>>> {
+        self.rows.next()
+    }
+}
@@ -0,0 +1,187 @@
+use crate::error::unsupported;
+use crate::functions::check_arity;
+use crate::sql::{ColumnConstraint, Expr, Select, TableSchema, Unsupported};
+use crate::value::Value;
+use anyhow::{bail, Result};
+
+/// Rejects schema objects that have no b-tree of their own to scan.
+pub fn check_table(kind: &str, name: &str, sql: &str) -> Result<()> {
+    let sql_lower = sql.to_lowercase();
+    if kind == "view" {
+        return Err(unsupported(format!("view {}", name)));
+    }
+    if sql_lower.starts_with("create virtual table") {
+        let module = sql_lower
+            .split(" using ")
+            .nth(1)
+            .and_then(|rest| rest.split(|c: char| c == '(' || c.is_whitespace()).next())
+            .unwrap_or("unknown");
+        return Err(unsupported(format!(
+            "virtual table {} (module {})",
+            name, module
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects table layouts the scanner would misread: WITHOUT ROWID tables
+/// (stored as index b-trees) and virtual generated columns (not stored in
+/// the record, which shifts every later field).
+pub fn check_definition(schema: &TableSchema) -> Result<()> {
+    if schema.without_rowid {
+        return Err(unsupported(format!("WITHOUT ROWID table {}", schema.name)));
+    }
+    for column in &schema.columns {
+        let is_virtual = column
+            .constraints
+            .iter()
+            .any(|c| matches!(c, ColumnConstraint::Generated { stored: false, .. }));
+        if is_virtual {
+            return Err(unsupported(format!(
+                "virtual generated column {} in {}",
+                column.name, schema.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The constructs a SELECT can be made of. The parser tags every node it
+/// builds with one, and `FEATURES` says which of them a query can use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    Literal,
+    Column,
+    Variable,
+    Positional,
+    Operator,
+    Bitwise,
+    Is,
+    Escape,
+    Match,
+    Extract,
+    RowValue,
+    IsNull,
+    Collate,
+    Function,
+    CountStar,
+    ResultStar,
+    QualifiedColumn,
+    SchemaName,
+    IndexedBy,
+    NoFrom,
+    Alias,
+    Distinct,
+    Join,
+    GroupBy,
+    Having,
+    OrderBy,
+    Limit,
+    Compound,
+    Subquery,
+    Case,
+    Cast,
+    In,
+    Exists,
+    Window,
+    Filter,
+    TableFunction,
+}
+
+pub struct Feature {
+    pub capability: Capability,
+    pub name: &'static str,
+    pub supported: bool,
+}
+
+const fn feature(capability: Capability, name: &'static str, supported: bool) -> Feature {
+    Feature {
+        capability,
+        name,
+        supported,
+    }
+}
+
+/// What the reader can run. Unsupported constructs are still parsed, so a
+/// statement using several of them is refused once, naming them all.
+pub const FEATURES: &[Feature] = &[
+    feature(Capability::Literal, "literal", true),
+    feature(Capability::Column, "column reference", true),
+    feature(Capability::Variable, "variable", true),
+    feature(Capability::Positional, "positional parameter", false),
+    feature(Capability::Operator, "operator", true),
+    feature(Capability::Bitwise, "bitwise operator", false),
+    feature(Capability::Is, "IS comparison", false),
+    feature(Capability::Escape, "ESCAPE", false),
+    feature(Capability::Match, "MATCH", false),
+    feature(Capability::Extract, "-> operator", false),
+    feature(Capability::RowValue, "row value", false),
+    feature(Capability::IsNull, "IS NULL", true),
+    feature(Capability::Collate, "COLLATE", true),
+    feature(Capability::Function, "function call", true),
+    feature(Capability::CountStar, "count(*)", true),
+    feature(Capability::ResultStar, "SELECT *", false),
+    feature(Capability::QualifiedColumn, "qualified column name", false),
+    feature(Capability::SchemaName, "schema name", false),
+    feature(Capability::IndexedBy, "INDEXED BY", false),
+    feature(Capability::NoFrom, "SELECT without FROM", false),
+    feature(Capability::Alias, "alias", false),
+    feature(Capability::Distinct, "DISTINCT", false),
+    feature(Capability::Join, "join", false),
+    feature(Capability::GroupBy, "GROUP BY", false),
+    feature(Capability::Having, "HAVING", false),
+    feature(Capability::OrderBy, "ORDER BY", false),
+    feature(Capability::Limit, "LIMIT", false),
+    feature(Capability::Compound, "compound SELECT", false),
+    feature(Capability::Subquery, "subquery", false),
+    feature(Capability::Case, "CASE", false),
+    feature(Capability::Cast, "CAST", false),
+    feature(Capability::In, "IN", false),
+    feature(Capability::Exists, "EXISTS", false),
+    feature(Capability::Window, "window function", false),
+    feature(Capability::Filter, "FILTER", false),
+    feature(Capability::TableFunction, "table-valued function", false),
+];
+
+impl Capability {
+    pub fn feature(self) -> &'static Feature {
+        FEATURES
+            .iter()
+            .find(|f| f.capability == self)
+            .expect("every capability is in FEATURES")
+    }
+}
+
+/// Refuses a query that uses anything the reader can't run, before any row
+/// is read: unsupported constructs, unknown functions and wrong argument
+/// counts are reported together, constructs in the order written, rather
+/// than one at a time and after part of the output has been printed.
+pub fn check_select(select: &Select) -> Result<()> {
+    let mut refused: Vec<Unsupported> = select.unsupported.clone();
+    let mut problems = Vec::new();
+    let mut check = |expr: &Expr| match expr {
+        Expr::Unsupported(u) if !u.capability.feature().supported => refused.push(u.clone()),
+        Expr::Function { name, args } => {
+            if let Err(e) = check_arity(name, &vec![Value::Null; args.len()]) {
+                problems.push(e.to_string());
+            }
+        }
+        _ => {}
+    };
+    for expr in select.columns.iter().chain(&select.where_clause) {
+        expr.walk(&mut check);
+    }
+    if !refused.is_empty() {
+        refused.sort_by_key(|u| u.at);
+        let pieces: Vec<String> = refused
+            .iter()
+            .map(|u| format!("{} ({})", u.capability.feature().name, u.sql))
+            .collect();
+        problems.insert(0, pieces.join(", "));
+        return Err(unsupported(problems.join("; ")));
+    }
+    if !problems.is_empty() {
+        bail!("{}", problems.join("; "));
+    }
+    Ok(())
+}
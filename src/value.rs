@@ -0,0 +1,217 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A single SQL value, mirroring SQLite's five storage classes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Numeric view of the value, as used by arithmetic and numeric functions.
+    /// Text is parsed leniently (a leading numeric prefix), anything else is 0.
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Null => 0.0,
+            Value::Integer(i) => *i as f64,
+            Value::Real(r) => *r,
+            Value::Text(s) => parse_numeric_prefix(s).map(|v| v.to_f64()).unwrap_or(0.0),
+            Value::Blob(b) => parse_numeric_prefix(&String::from_utf8_lossy(b))
+                .map(|v| v.to_f64())
+                .unwrap_or(0.0),
+        }
+    }
+
+    pub fn to_i64(&self) -> i64 {
+        match self {
+            Value::Integer(i) => *i,
+            Value::Real(r) => *r as i64,
+            Value::Text(s) => match parse_numeric_prefix(s) {
+                Some(Value::Integer(i)) => i,
+                Some(Value::Real(r)) => r as i64,
+                _ => 0,
+            },
+            Value::Blob(b) => Value::Text(String::from_utf8_lossy(b).into_owned()).to_i64(),
+            Value::Null => 0,
+        }
+    }
+
+    /// The bytes of the value as seen by blob functions such as `hex()`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Null => Vec::new(),
+            Value::Blob(b) => b.clone(),
+            other => other.to_string().into_bytes(),
+        }
+    }
+
+    /// Numeric value of a text that looks entirely like a number, used when
+    /// comparing a numeric column against a text literal.
+    pub fn as_numeric_text(&self) -> Option<Value> {
+        match self {
+            Value::Text(s) => {
+                let trimmed = s.trim();
+                if let Ok(i) = trimmed.parse::<i64>() {
+                    Some(Value::Integer(i))
+                } else if let Ok(r) = trimmed.parse::<f64>() {
+                    if trimmed.chars().any(|c| c.is_ascii_digit()) {
+                        Some(Value::Real(r))
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+
+    /// Total ordering following SQLite's rules: NULL < numbers < text < blob,
+    /// text compared bytewise (BINARY collation).
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Integer(a), Value::Real(b)) => compare_f64(*a as f64, *b),
+            (Value::Real(a), Value::Integer(b)) => compare_f64(*a, *b as f64),
+            (Value::Real(a), Value::Real(b)) => compare_f64(*a, *b),
+            (Value::Text(a), Value::Text(b)) => a.as_bytes().cmp(b.as_bytes()),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+fn compare_f64(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+/// Parses the longest numeric prefix of `s`, the way SQLite converts text
+/// to a number in arithmetic contexts.
+fn parse_numeric_prefix(s: &str) -> Option<Value> {
+    let s = s.trim_start();
+    let bytes = s.as_bytes();
+    let mut end = 0;
+    if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+        end += 1;
+    }
+    let digits_start = end;
+    while end < bytes.len() && bytes[end].is_ascii_digit() {
+        end += 1;
+    }
+    let mut is_real = false;
+    if end < bytes.len() && bytes[end] == b'.' {
+        is_real = true;
+        end += 1;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+    }
+    if end == digits_start || (is_real && end == digits_start + 1) {
+        return None;
+    }
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exp_end = end + 1;
+        if exp_end < bytes.len() && (bytes[exp_end] == b'+' || bytes[exp_end] == b'-') {
+            exp_end += 1;
+        }
+        let exp_digits = exp_end;
+        while exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+            exp_end += 1;
+        }
+        if exp_end > exp_digits {
+            is_real = true;
+            end = exp_end;
+        }
+    }
+    let text = &s[..end];
+    if !is_real && let Ok(i) = text.parse::<i64>() {
+        return Some(Value::Integer(i));
+    }
+    text.parse::<f64>().ok().map(Value::Real)
+}
+
+/// Formats a real the way the sqlite3 shell does (`%!.15g`).
+pub fn format_real(r: f64) -> String {
+    if r.is_nan() {
+        return String::new();
+    }
+    if r.is_infinite() {
+        return if r > 0.0 {
+            "Inf".to_string()
+        } else {
+            "-Inf".to_string()
+        };
+    }
+    if r == 0.0 {
+        return "0.0".to_string();
+    }
+
+    let sci = format!("{:.14e}", r);
+    let (mantissa, exp) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let exp: i32 = exp.parse().unwrap_or(0);
+    let negative = mantissa.starts_with('-');
+    let digits: String = mantissa.chars().filter(|c| c.is_ascii_digit()).collect();
+    let digits = digits.trim_end_matches('0');
+    let digits = if digits.is_empty() { "0" } else { digits };
+    let sign = if negative { "-" } else { "" };
+
+    if !(-4..15).contains(&exp) {
+        let (first, rest) = digits.split_at(1);
+        let rest = if rest.is_empty() { "0" } else { rest };
+        let exp_sign = if exp < 0 { '-' } else { '+' };
+        return format!("{}{}.{}e{}{:02}", sign, first, rest, exp_sign, exp.abs());
+    }
+
+    let mut out = String::from(sign);
+    if exp < 0 {
+        out.push_str("0.");
+        for _ in 0..(-exp - 1) {
+            out.push('0');
+        }
+        out.push_str(digits);
+    } else {
+        let int_len = exp as usize + 1;
+        if digits.len() <= int_len {
+            out.push_str(digits);
+            for _ in digits.len()..int_len {
+                out.push('0');
+            }
+            out.push_str(".0");
+        } else {
+            out.push_str(&digits[..int_len]);
+            out.push('.');
+            out.push_str(&digits[int_len..]);
+        }
+    }
+    out
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Real(r) => write!(f, "{}", format_real(*r)),
+            Value::Text(s) => write!(f, "{}", s),
+            Value::Blob(b) => write!(f, "{}", String::from_utf8_lossy(b)),
+        }
+    }
+}
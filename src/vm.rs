@@ -0,0 +1,1134 @@
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+
+use crate::affinity::Affinity;
+use crate::aggregate::{self, Accumulator};
+use crate::collation::Collation;
+use crate::cursor::TableCursor;
+use crate::eval::{binary, compare_values, is_true, unary};
+use crate::functions::{call_scalar, quote};
+use crate::header::DbHeader;
+use crate::pager::Pager;
+use crate::planner::KeyIndex;
+use crate::record::{real_affinity, ColumnSource};
+use crate::redact::Mask;
+use crate::settings::Settings;
+use crate::sql::{BinaryOp, UnaryOp};
+use crate::value::Value;
+use crate::{
+    scan_index_btree_for_value, scan_index_range, scan_table_btree_count,
+    scan_table_btree_for_rowid, KeyBounds,
+};
+
+/// A register number. Registers are numbered from 1, as in SQLite, and
+/// start out NULL.
+pub type Reg = usize;
+
+/// The address of an instruction: its position in the program.
+pub type Addr = usize;
+
+/// The test a comparison instruction applies to its operands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn from_op(op: BinaryOp) -> Option<Comparison> {
+        Some(match op {
+            BinaryOp::Eq => Comparison::Eq,
+            BinaryOp::NotEq => Comparison::Ne,
+            BinaryOp::Lt => Comparison::Lt,
+            BinaryOp::LtEq => Comparison::Le,
+            BinaryOp::Gt => Comparison::Gt,
+            BinaryOp::GtEq => Comparison::Ge,
+            _ => return None,
+        })
+    }
+
+    /// The comparison that holds exactly when this one doesn't, for
+    /// operands that aren't NULL.
+    pub fn negate(self) -> Comparison {
+        match self {
+            Comparison::Eq => Comparison::Ne,
+            Comparison::Ne => Comparison::Eq,
+            Comparison::Lt => Comparison::Ge,
+            Comparison::Le => Comparison::Gt,
+            Comparison::Gt => Comparison::Le,
+            Comparison::Ge => Comparison::Lt,
+        }
+    }
+
+    fn opcode(self) -> &'static str {
+        match self {
+            Comparison::Eq => "Eq",
+            Comparison::Ne => "Ne",
+            Comparison::Lt => "Lt",
+            Comparison::Le => "Le",
+            Comparison::Gt => "Gt",
+            Comparison::Ge => "Ge",
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::Ne => ordering != Ordering::Equal,
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// One instruction of a program, in the manner of SQLite's VDBE: cursors
+/// walk b-trees, registers hold values, and an instruction that branches
+/// names the address to `jump` to.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Opens cursor `cursor` on the b-tree the program's cursor list gives
+    /// for it.
+    OpenRead {
+        cursor: usize,
+    },
+    /// Moves a table cursor to the table's first row, or jumps if there is
+    /// none.
+    Rewind {
+        cursor: usize,
+        jump: Addr,
+    },
+    /// Moves a cursor to its next row or entry and jumps back; falls
+    /// through once there are no more.
+    Next {
+        cursor: usize,
+        jump: Addr,
+    },
+    /// Moves a table cursor to the row with the rowid in `rowid`, or jumps
+    /// if there is no such row.
+    SeekRowid {
+        cursor: usize,
+        rowid: Reg,
+        jump: Addr,
+    },
+    /// Moves an index cursor to the first entry whose first key equals
+    /// `key` (see `scan_index_btree_for_value`), or jumps if there is none.
+    SeekKey {
+        cursor: usize,
+        key: Reg,
+        jump: Addr,
+    },
+    /// Moves an index cursor to the first entry whose first key lies between
+    /// `lower` and `upper` inclusive, or jumps if there is none. A missing
+    /// bound leaves that end open.
+    SeekRange {
+        cursor: usize,
+        lower: Option<Reg>,
+        upper: Option<Reg>,
+        jump: Addr,
+    },
+    /// Stores the number of rows in a table cursor's table, without reading
+    /// them.
+    Count {
+        cursor: usize,
+        dest: Reg,
+    },
+    /// Stores a column of the cursor's row: for a table, the position of
+    /// the column in the fields the cursor reads; for an index, the field
+    /// of the entry.
+    Column {
+        cursor: usize,
+        column: usize,
+        dest: Reg,
+    },
+    /// Stores the rowid of an index cursor's entry.
+    IdxRowid {
+        cursor: usize,
+        dest: Reg,
+    },
+    Null {
+        dest: Reg,
+    },
+    Integer {
+        value: i64,
+        dest: Reg,
+    },
+    Real {
+        value: f64,
+        dest: Reg,
+    },
+    String8 {
+        value: String,
+        dest: Reg,
+    },
+    Blob {
+        value: Vec<u8>,
+        dest: Reg,
+    },
+    /// Copies `count` registers from `src` on to `dest` on.
+    Copy {
+        src: Reg,
+        dest: Reg,
+        count: usize,
+    },
+    /// Converts an integer to a real, for a column with REAL affinity read
+    /// from an index entry.
+    RealAffinity {
+        reg: Reg,
+    },
+    /// Replaces a value with its redacted form.
+    Redact {
+        reg: Reg,
+        mask: Mask,
+    },
+    Unary {
+        op: UnaryOp,
+        src: Reg,
+        dest: Reg,
+    },
+    /// An operator other than AND and OR, as the expression evaluator
+    /// applies it; NULL if either operand is.
+    Binary {
+        op: BinaryOp,
+        lhs: Reg,
+        rhs: Reg,
+        dest: Reg,
+        affinity: Affinity,
+        collation: Collation,
+    },
+    /// AND and OR under three-valued logic. The code before them skips the
+    /// right operand when the left decides.
+    And {
+        lhs: Reg,
+        rhs: Reg,
+        dest: Reg,
+    },
+    Or {
+        lhs: Reg,
+        rhs: Reg,
+        dest: Reg,
+    },
+    /// Jumps if `lhs` and `rhs` pass `comparison` under `affinity` and
+    /// `collation`, or, when either is NULL, if `null_jumps`.
+    Compare {
+        comparison: Comparison,
+        lhs: Reg,
+        rhs: Reg,
+        affinity: Affinity,
+        collation: Collation,
+        null_jumps: bool,
+        jump: Addr,
+    },
+    IsNull {
+        src: Reg,
+        jump: Addr,
+    },
+    NotNull {
+        src: Reg,
+        jump: Addr,
+    },
+    /// Jumps if the value is true, or NULL and `null_jumps`.
+    If {
+        src: Reg,
+        null_jumps: bool,
+        jump: Addr,
+    },
+    /// Jumps if the value is false, or NULL and `null_jumps`.
+    IfNot {
+        src: Reg,
+        null_jumps: bool,
+        jump: Addr,
+    },
+    /// Calls a scalar function on `argc` registers from `args` on.
+    Function {
+        name: String,
+        args: Reg,
+        argc: usize,
+        dest: Reg,
+    },
+    /// Feeds `argc` registers from `args` on to one of the program's
+    /// aggregates.
+    AggStep {
+        aggregate: usize,
+        args: Reg,
+        argc: usize,
+    },
+    /// Stores an aggregate's result, once every row is fed to it.
+    AggFinal {
+        aggregate: usize,
+        dest: Reg,
+    },
+    AddImm {
+        reg: Reg,
+        value: i64,
+    },
+    /// Yields `count` registers from `start` on as a result row, leaving
+    /// them NULL.
+    ResultRow {
+        start: Reg,
+        count: usize,
+    },
+    Halt,
+}
+
+impl Op {
+    /// Where the instruction jumps, if it can.
+    pub fn jump_mut(&mut self) -> Option<&mut Addr> {
+        match self {
+            Op::Rewind { jump, .. }
+            | Op::Next { jump, .. }
+            | Op::SeekRowid { jump, .. }
+            | Op::SeekKey { jump, .. }
+            | Op::SeekRange { jump, .. }
+            | Op::Compare { jump, .. }
+            | Op::IsNull { jump, .. }
+            | Op::NotNull { jump, .. }
+            | Op::If { jump, .. }
+            | Op::IfNot { jump, .. } => Some(jump),
+            _ => None,
+        }
+    }
+}
+
+/// The b-tree a cursor reads.
+#[derive(Clone)]
+pub enum CursorSpec {
+    /// A table, read for the fields in `sources`, which the query calls
+    /// `columns`.
+    Table {
+        name: String,
+        root: u32,
+        sources: Vec<ColumnSource>,
+        columns: Vec<String>,
+    },
+    Index(KeyIndex),
+    /// Rows the VM's caller hands it (see `Vm::with_rows`), whose fields
+    /// the query calls `columns`.
+    Rows {
+        columns: Vec<String>,
+    },
+}
+
+/// A compiled query: see `planner::compile`.
+#[derive(Clone)]
+pub struct Program {
+    pub ops: Vec<Op>,
+    pub cursors: Vec<CursorSpec>,
+    /// The highest register the program uses.
+    pub registers: usize,
+    /// The aggregate functions `AggStep` feeds, by name and argument count.
+    pub aggregates: Vec<(String, usize)>,
+}
+
+impl Program {
+    /// The program as `EXPLAIN` lists it, one row per instruction: its
+    /// address, opcode, operands p1 to p5 and a comment on what it does.
+    /// The operands are laid out as in SQLite's listing where there is a
+    /// counterpart: p1 is usually a cursor or source register, p2 a jump
+    /// target or destination, and p4 a constant or function name.
+    pub fn explain(&self) -> Vec<Vec<Value>> {
+        self.ops
+            .iter()
+            .enumerate()
+            .map(|(addr, op)| {
+                let l = self.listing(op);
+                vec![
+                    Value::Integer(addr as i64),
+                    Value::Text(l.opcode.to_string()),
+                    Value::Integer(l.p1),
+                    Value::Integer(l.p2),
+                    Value::Integer(l.p3),
+                    l.p4.map_or(Value::Null, Value::Text),
+                    Value::Integer(l.p5),
+                    Value::Text(l.comment),
+                ]
+            })
+            .collect()
+    }
+
+    fn listing(&self, op: &Op) -> Listing {
+        let l = |opcode, p1: usize, p2: usize, p3: usize, comment| Listing {
+            opcode,
+            p1: p1 as i64,
+            p2: p2 as i64,
+            p3: p3 as i64,
+            p4: None,
+            p5: 0,
+            comment,
+        };
+        match op {
+            Op::OpenRead { cursor } => {
+                let (name, root) = match &self.cursors[*cursor] {
+                    CursorSpec::Table { name, root, .. } => (name, *root),
+                    CursorSpec::Index(index) => (&index.name, index.root),
+                    // As SQLite's OpenPseudo, over rows from outside the file.
+                    CursorSpec::Rows { .. } => {
+                        return l("OpenPseudo", *cursor, 0, 0, "given rows".to_string());
+                    }
+                };
+                l(
+                    "OpenRead",
+                    *cursor,
+                    root as usize,
+                    0,
+                    format!("root={}; {}", root, name),
+                )
+            }
+            Op::Rewind { cursor, jump } => l("Rewind", *cursor, *jump, 0, String::new()),
+            Op::Next { cursor, jump } => l("Next", *cursor, *jump, 0, String::new()),
+            Op::SeekRowid {
+                cursor,
+                rowid,
+                jump,
+            } => l(
+                "SeekRowid",
+                *cursor,
+                *jump,
+                *rowid,
+                format!("intkey=r[{}]", rowid),
+            ),
+            Op::SeekKey { cursor, key, jump } => {
+                l("SeekKey", *cursor, *jump, *key, format!("key=r[{}]", key))
+            }
+            Op::SeekRange {
+                cursor,
+                lower,
+                upper,
+                jump,
+            } => {
+                let mut bounds = Vec::new();
+                bounds.extend(lower.map(|r| format!("key>=r[{}]", r)));
+                bounds.extend(upper.map(|r| format!("key<=r[{}]", r)));
+                Listing {
+                    p4: upper.map(|r| r.to_string()),
+                    ..l(
+                        "SeekRange",
+                        *cursor,
+                        *jump,
+                        lower.unwrap_or(0),
+                        bounds.join(" AND "),
+                    )
+                }
+            }
+            Op::Count { cursor, dest } => {
+                l("Count", *cursor, *dest, 0, format!("r[{}]=count()", dest))
+            }
+            Op::Column {
+                cursor,
+                column,
+                dest,
+            } => {
+                let name = match &self.cursors[*cursor] {
+                    CursorSpec::Table { name, columns, .. } => {
+                        format!("{}.{}", name, columns.get(*column).map_or("?", |c| c))
+                    }
+                    CursorSpec::Index(index) => match index.columns.get(*column) {
+                        Some(Some(c)) => format!("{}.{}", index.name, c),
+                        Some(None) => format!("{}.(expression)", index.name),
+                        None => format!("{}.rowid", index.name),
+                    },
+                    CursorSpec::Rows { columns } => {
+                        columns.get(*column).map_or("?", |c| c).to_string()
+                    }
+                };
+                l(
+                    "Column",
+                    *cursor,
+                    *column,
+                    *dest,
+                    format!("r[{}]={}", dest, name),
+                )
+            }
+            Op::IdxRowid { cursor, dest } => {
+                l("IdxRowid", *cursor, *dest, 0, format!("r[{}]=rowid", dest))
+            }
+            Op::Null { dest } => l("Null", 0, *dest, 0, format!("r[{}]=NULL", dest)),
+            Op::Integer { value, dest } => Listing {
+                p1: *value,
+                ..l("Integer", 0, *dest, 0, format!("r[{}]={}", dest, value))
+            },
+            Op::Real { value, dest } => Listing {
+                p4: Some(Value::Real(*value).to_string()),
+                ..l(
+                    "Real",
+                    0,
+                    *dest,
+                    0,
+                    format!("r[{}]={}", dest, Value::Real(*value)),
+                )
+            },
+            Op::String8 { value, dest } => {
+                let quoted = quote(&Value::Text(value.clone())).to_string();
+                Listing {
+                    p4: Some(value.clone()),
+                    ..l("String8", 0, *dest, 0, format!("r[{}]={}", dest, quoted))
+                }
+            }
+            Op::Blob { value, dest } => {
+                let quoted = quote(&Value::Blob(value.clone())).to_string();
+                Listing {
+                    p4: Some(quoted.clone()),
+                    ..l(
+                        "Blob",
+                        value.len(),
+                        *dest,
+                        0,
+                        format!("r[{}]={}", dest, quoted),
+                    )
+                }
+            }
+            Op::Copy { src, dest, count } => l(
+                "Copy",
+                *src,
+                *dest,
+                count.saturating_sub(1),
+                format!("{}={}", registers(*dest, *count), registers(*src, *count)),
+            ),
+            Op::RealAffinity { reg } => l("RealAffinity", *reg, 0, 0, String::new()),
+            Op::Redact { reg, mask } => Listing {
+                p4: Some(format!("{:?}", mask).to_lowercase()),
+                ..l(
+                    "Redact",
+                    *reg,
+                    0,
+                    0,
+                    format!("r[{}]=redact(r[{}])", reg, reg),
+                )
+            },
+            Op::Unary { op, src, dest } => {
+                let (opcode, symbol) = match op {
+                    UnaryOp::Not => ("Not", "NOT "),
+                    UnaryOp::Neg => ("Negative", "-"),
+                    UnaryOp::Plus => ("Plus", "+"),
+                };
+                l(
+                    opcode,
+                    *src,
+                    *dest,
+                    0,
+                    format!("r[{}]={}r[{}]", dest, symbol, src),
+                )
+            }
+            Op::Binary {
+                op,
+                lhs,
+                rhs,
+                dest,
+                affinity,
+                collation,
+            } => {
+                let opcode = match op {
+                    BinaryOp::Add => "Add",
+                    BinaryOp::Sub => "Subtract",
+                    BinaryOp::Mul => "Multiply",
+                    BinaryOp::Div => "Divide",
+                    BinaryOp::Rem => "Remainder",
+                    BinaryOp::Concat => "Concat",
+                    BinaryOp::Like => "Like",
+                    BinaryOp::Glob => "Glob",
+                    BinaryOp::Regexp => "Regexp",
+                    BinaryOp::And => "And",
+                    BinaryOp::Or => "Or",
+                    op => Comparison::from_op(*op).map_or("Binary", Comparison::opcode),
+                };
+                let comment = format!("r[{}]=r[{}] {} r[{}]", dest, lhs, op.symbol(), rhs);
+                let is_comparison = Comparison::from_op(*op).is_some();
+                Listing {
+                    p4: is_comparison.then(|| collation.name().to_string()),
+                    p5: if is_comparison { affinity.code() } else { 0 },
+                    ..l(opcode, *lhs, *rhs, *dest, comment)
+                }
+            }
+            Op::And { lhs, rhs, dest } => l(
+                "And",
+                *lhs,
+                *rhs,
+                *dest,
+                format!("r[{}]=r[{}] AND r[{}]", dest, lhs, rhs),
+            ),
+            Op::Or { lhs, rhs, dest } => l(
+                "Or",
+                *lhs,
+                *rhs,
+                *dest,
+                format!("r[{}]=r[{}] OR r[{}]", dest, lhs, rhs),
+            ),
+            // As in SQLite, p3 is compared with p1, and p5 holds the
+            // affinity, with 0x10 set when NULL jumps.
+            Op::Compare {
+                comparison,
+                lhs,
+                rhs,
+                affinity,
+                collation,
+                null_jumps,
+                jump,
+            } => {
+                let null = if *null_jumps { " or NULL" } else { "" };
+                Listing {
+                    p4: Some(collation.name().to_string()),
+                    p5: affinity.code() | if *null_jumps { 0x10 } else { 0 },
+                    ..l(
+                        comparison.opcode(),
+                        *rhs,
+                        *jump,
+                        *lhs,
+                        format!(
+                            "if r[{}]{}r[{}]{} goto {}",
+                            lhs,
+                            comparison.symbol(),
+                            rhs,
+                            null,
+                            jump
+                        ),
+                    )
+                }
+            }
+            Op::IsNull { src, jump } => l(
+                "IsNull",
+                *src,
+                *jump,
+                0,
+                format!("if r[{}]==NULL goto {}", src, jump),
+            ),
+            Op::NotNull { src, jump } => l(
+                "NotNull",
+                *src,
+                *jump,
+                0,
+                format!("if r[{}]!=NULL goto {}", src, jump),
+            ),
+            Op::If {
+                src,
+                null_jumps,
+                jump,
+            } => {
+                let null = if *null_jumps { " or NULL" } else { "" };
+                l(
+                    "If",
+                    *src,
+                    *jump,
+                    *null_jumps as usize,
+                    format!("if r[{}]{} goto {}", src, null, jump),
+                )
+            }
+            Op::IfNot {
+                src,
+                null_jumps,
+                jump,
+            } => {
+                let null = if *null_jumps { " or NULL" } else { "" };
+                l(
+                    "IfNot",
+                    *src,
+                    *jump,
+                    *null_jumps as usize,
+                    format!("if !r[{}]{} goto {}", src, null, jump),
+                )
+            }
+            Op::Function {
+                name,
+                args,
+                argc,
+                dest,
+            } => Listing {
+                p4: Some(format!("{}({})", name, argc)),
+                p5: *argc as i64,
+                ..l(
+                    "Function",
+                    0,
+                    *args,
+                    *dest,
+                    format!("r[{}]={}({})", dest, name, registers(*args, *argc)),
+                )
+            },
+            Op::AggStep {
+                aggregate,
+                args,
+                argc,
+            } => {
+                let name = &self.aggregates[*aggregate].0;
+                Listing {
+                    p4: Some(format!("{}({})", name, argc)),
+                    p5: *argc as i64,
+                    ..l(
+                        "AggStep",
+                        *aggregate,
+                        *args,
+                        0,
+                        format!("accum[{}] step({})", aggregate, registers(*args, *argc)),
+                    )
+                }
+            }
+            Op::AggFinal { aggregate, dest } => {
+                let (name, argc) = &self.aggregates[*aggregate];
+                Listing {
+                    p4: Some(format!("{}({})", name, argc)),
+                    ..l(
+                        "AggFinal",
+                        *aggregate,
+                        *dest,
+                        0,
+                        format!("r[{}]=accum[{}]", dest, aggregate),
+                    )
+                }
+            }
+            Op::AddImm { reg, value } => Listing {
+                p2: *value,
+                ..l(
+                    "AddImm",
+                    *reg,
+                    0,
+                    0,
+                    format!("r[{}]=r[{}]+{}", reg, reg, value),
+                )
+            },
+            Op::ResultRow { start, count } => l(
+                "ResultRow",
+                *start,
+                *count,
+                0,
+                format!("output={}", registers(*start, *count)),
+            ),
+            Op::Halt => l("Halt", 0, 0, 0, String::new()),
+        }
+    }
+
+    fn table(&self, cursor: usize) -> Result<(u32, &[ColumnSource])> {
+        match &self.cursors[cursor] {
+            CursorSpec::Table { root, sources, .. } => Ok((*root, sources)),
+            _ => bail!("cursor {} is not on a table", cursor),
+        }
+    }
+
+    fn index(&self, cursor: usize) -> Result<&KeyIndex> {
+        match &self.cursors[cursor] {
+            CursorSpec::Index(index) => Ok(index),
+            _ => bail!("cursor {} is not on an index", cursor),
+        }
+    }
+}
+
+/// One row of an `EXPLAIN` listing.
+struct Listing {
+    opcode: &'static str,
+    p1: i64,
+    p2: i64,
+    p3: i64,
+    p4: Option<String>,
+    p5: i64,
+    comment: String,
+}
+
+/// `r[N]` for one register, `r[N..M]` for several.
+fn registers(start: Reg, count: usize) -> String {
+    match count {
+        0 => String::new(),
+        1 => format!("r[{}]", start),
+        _ => format!("r[{}..{}]", start, start + count - 1),
+    }
+}
+
+/// A cursor's position.
+enum Cursor {
+    Closed,
+    /// The row a table cursor is on, if any.
+    Table {
+        row: Option<Vec<Value>>,
+    },
+    /// The entries an index seek found, and the one the cursor is on.
+    Index {
+        entries: Vec<Vec<Value>>,
+        at: usize,
+    },
+    /// The rows the VM was given, and the one the cursor is on.
+    Rows {
+        rows: Vec<Vec<Value>>,
+        at: usize,
+    },
+}
+
+/// The pager, lent to the cursor of a table scan while the scan runs.
+struct Storage<'a> {
+    pager: Option<&'a mut Pager>,
+    scan: Option<(usize, TableCursor<'a, Pager>)>,
+}
+
+impl Storage<'_> {
+    fn pager(&mut self) -> &mut Pager {
+        match (&mut self.pager, &mut self.scan) {
+            (Some(pager), _) => pager,
+            (None, Some((_, scan))) => scan.pager(),
+            (None, None) => unreachable!("the pager is lent only to a scan"),
+        }
+    }
+}
+
+/// Runs a program, yielding its result rows as the iterator is advanced.
+/// Execution stops at each `ResultRow` and resumes from there, so a scan
+/// reads the table one row at a time.
+pub struct Vm<'a> {
+    program: Program,
+    header: DbHeader,
+    settings: &'a Settings,
+    storage: Storage<'a>,
+    cursors: Vec<Cursor>,
+    registers: Vec<Value>,
+    accumulators: Vec<Box<dyn Accumulator>>,
+    /// The rows a `CursorSpec::Rows` cursor steps through.
+    rows: Vec<Vec<Value>>,
+    pc: Addr,
+    halted: bool,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(pager: &'a mut Pager, program: Program, settings: &'a Settings) -> Result<Vm<'a>> {
+        let header = pager.header()?;
+        let accumulators = program
+            .aggregates
+            .iter()
+            .map(|(name, argc)| aggregate::accumulator(name, *argc))
+            .collect::<Result<_>>()?;
+        Ok(Vm {
+            cursors: program.cursors.iter().map(|_| Cursor::Closed).collect(),
+            registers: vec![Value::Null; program.registers + 1],
+            program,
+            header,
+            settings,
+            storage: Storage {
+                pager: Some(pager),
+                scan: None,
+            },
+            accumulators,
+            rows: Vec::new(),
+            pc: 0,
+            halted: false,
+        })
+    }
+
+    /// Gives the program rows to read through its `CursorSpec::Rows`
+    /// cursor, such as those matched in several databases.
+    pub fn with_rows(mut self, rows: Vec<Vec<Value>>) -> Vm<'a> {
+        self.rows = rows;
+        self
+    }
+
+    /// Runs until the program yields a row, or halts.
+    fn run(&mut self) -> Result<Option<Vec<Value>>> {
+        loop {
+            let pc = self.pc;
+            self.pc += 1;
+            let r = &mut self.registers;
+            match &self.program.ops[pc] {
+                Op::OpenRead { cursor } => {
+                    self.cursors[*cursor] = match &self.program.cursors[*cursor] {
+                        CursorSpec::Table { .. } => Cursor::Table { row: None },
+                        CursorSpec::Index(_) => Cursor::Index {
+                            entries: Vec::new(),
+                            at: 0,
+                        },
+                        CursorSpec::Rows { .. } => Cursor::Rows {
+                            rows: std::mem::take(&mut self.rows),
+                            at: 0,
+                        },
+                    };
+                }
+                Op::Rewind { cursor, jump } => {
+                    if let Cursor::Rows { rows, at } = &mut self.cursors[*cursor] {
+                        *at = 0;
+                        if rows.is_empty() {
+                            self.pc = *jump;
+                        }
+                        continue;
+                    }
+                    let (root, sources) = self.program.table(*cursor)?;
+                    let Some(pager) = self.storage.pager.take() else {
+                        bail!("only one table can be scanned at a time");
+                    };
+                    let mut scan =
+                        TableCursor::new(pager, root, &self.header, sources, self.settings)?;
+                    let row = scan.next().transpose()?;
+                    self.storage.scan = Some((*cursor, scan));
+                    if row.is_none() {
+                        self.pc = *jump;
+                    }
+                    self.cursors[*cursor] = Cursor::Table { row };
+                }
+                Op::Next { cursor, jump } => {
+                    let more = match &mut self.cursors[*cursor] {
+                        Cursor::Table { row } => match &mut self.storage.scan {
+                            Some((scanning, scan)) if scanning == cursor => {
+                                *row = scan.next().transpose()?;
+                                row.is_some()
+                            }
+                            _ => bail!("cursor {} is not scanning", cursor),
+                        },
+                        Cursor::Index { entries: rows, at } | Cursor::Rows { rows, at } => {
+                            *at += 1;
+                            *at < rows.len()
+                        }
+                        Cursor::Closed => false,
+                    };
+                    if more {
+                        self.pc = *jump;
+                    }
+                }
+                Op::SeekRowid {
+                    cursor,
+                    rowid,
+                    jump,
+                } => {
+                    let (root, sources) = self.program.table(*cursor)?;
+                    let rowid = r[*rowid].to_i64() as u64;
+                    let row = scan_table_btree_for_rowid(
+                        self.storage.pager(),
+                        root,
+                        &self.header,
+                        rowid,
+                        sources,
+                        self.settings,
+                    )?;
+                    if row.is_none() {
+                        self.pc = *jump;
+                    }
+                    self.cursors[*cursor] = Cursor::Table { row };
+                }
+                Op::SeekKey { cursor, key, jump } => {
+                    let index = self.program.index(*cursor)?;
+                    let entries = scan_index_btree_for_value(
+                        self.storage.pager(),
+                        index,
+                        &self.header,
+                        &r[*key],
+                        self.settings,
+                    )?;
+                    if entries.is_empty() {
+                        self.pc = *jump;
+                    }
+                    self.cursors[*cursor] = Cursor::Index { entries, at: 0 };
+                }
+                Op::SeekRange {
+                    cursor,
+                    lower,
+                    upper,
+                    jump,
+                } => {
+                    let index = self.program.index(*cursor)?;
+                    let lower = match lower {
+                        Some(lower) => r[*lower].clone(),
+                        None => Value::Real(f64::NEG_INFINITY),
+                    };
+                    let bounds = KeyBounds {
+                        lower: &lower,
+                        upper: upper.map(|u| &r[u]),
+                        descending: index.descending,
+                        collation: index.collation,
+                    };
+                    let mut entries = Vec::new();
+                    scan_index_range(
+                        self.storage.pager(),
+                        index.root,
+                        &self.header,
+                        &bounds,
+                        self.settings,
+                        &mut entries,
+                    )?;
+                    if entries.is_empty() {
+                        self.pc = *jump;
+                    }
+                    self.cursors[*cursor] = Cursor::Index { entries, at: 0 };
+                }
+                Op::Count { cursor, dest } => {
+                    let (root, _) = self.program.table(*cursor)?;
+                    let mut count = 0;
+                    scan_table_btree_count(self.storage.pager(), root, &self.header, &mut count)?;
+                    r[*dest] = Value::Integer(count as i64);
+                }
+                Op::Column {
+                    cursor,
+                    column,
+                    dest,
+                } => {
+                    let value = match &self.cursors[*cursor] {
+                        Cursor::Table { row: Some(row) } => row.get(*column),
+                        Cursor::Index { entries: rows, at } | Cursor::Rows { rows, at } => {
+                            rows.get(*at).and_then(|e| e.get(*column))
+                        }
+                        _ => None,
+                    };
+                    r[*dest] = value.cloned().unwrap_or(Value::Null);
+                }
+                Op::IdxRowid { cursor, dest } => {
+                    let rowid = match &self.cursors[*cursor] {
+                        Cursor::Index { entries, at } => entries.get(*at).and_then(|e| e.last()),
+                        _ => None,
+                    };
+                    r[*dest] = Value::Integer(rowid.map_or(0, Value::to_i64));
+                }
+                Op::Null { dest } => r[*dest] = Value::Null,
+                Op::Integer { value, dest } => r[*dest] = Value::Integer(*value),
+                Op::Real { value, dest } => r[*dest] = Value::Real(*value),
+                Op::String8 { value, dest } => r[*dest] = Value::Text(value.clone()),
+                Op::Blob { value, dest } => r[*dest] = Value::Blob(value.clone()),
+                Op::Copy { src, dest, count } => {
+                    for i in 0..*count {
+                        r[dest + i] = r[src + i].clone();
+                    }
+                }
+                Op::RealAffinity { reg } => {
+                    r[*reg] = real_affinity(r[*reg].clone());
+                }
+                Op::Redact { reg, mask } => r[*reg] = mask.apply(&r[*reg]),
+                Op::Unary { op, src, dest } => r[*dest] = unary(*op, r[*src].clone()),
+                Op::Binary {
+                    op,
+                    lhs,
+                    rhs,
+                    dest,
+                    affinity,
+                    collation,
+                } => r[*dest] = binary(*op, &r[*lhs], &r[*rhs], *affinity, *collation)?,
+                Op::And { lhs, rhs, dest } => {
+                    let is_false = |v: &Value| !v.is_null() && !is_true(v);
+                    r[*dest] = if is_false(&r[*lhs]) || is_false(&r[*rhs]) {
+                        Value::Integer(0)
+                    } else if r[*lhs].is_null() || r[*rhs].is_null() {
+                        Value::Null
+                    } else {
+                        Value::Integer(1)
+                    };
+                }
+                Op::Or { lhs, rhs, dest } => {
+                    r[*dest] = if is_true(&r[*lhs]) || is_true(&r[*rhs]) {
+                        Value::Integer(1)
+                    } else if r[*lhs].is_null() || r[*rhs].is_null() {
+                        Value::Null
+                    } else {
+                        Value::Integer(0)
+                    };
+                }
+                Op::Compare {
+                    comparison,
+                    lhs,
+                    rhs,
+                    affinity,
+                    collation,
+                    null_jumps,
+                    jump,
+                } => {
+                    let (l, r) = (&r[*lhs], &r[*rhs]);
+                    let jumps = if l.is_null() || r.is_null() {
+                        *null_jumps
+                    } else {
+                        comparison.holds(compare_values(l, r, *affinity, *collation))
+                    };
+                    if jumps {
+                        self.pc = *jump;
+                    }
+                }
+                Op::IsNull { src, jump } => {
+                    if r[*src].is_null() {
+                        self.pc = *jump;
+                    }
+                }
+                Op::NotNull { src, jump } => {
+                    if !r[*src].is_null() {
+                        self.pc = *jump;
+                    }
+                }
+                Op::If {
+                    src,
+                    null_jumps,
+                    jump,
+                } => {
+                    let v = &r[*src];
+                    if if v.is_null() { *null_jumps } else { is_true(v) } {
+                        self.pc = *jump;
+                    }
+                }
+                Op::IfNot {
+                    src,
+                    null_jumps,
+                    jump,
+                } => {
+                    let v = &r[*src];
+                    if if v.is_null() {
+                        *null_jumps
+                    } else {
+                        !is_true(v)
+                    } {
+                        self.pc = *jump;
+                    }
+                }
+                Op::Function {
+                    name,
+                    args,
+                    argc,
+                    dest,
+                } => {
+                    let encoding = self.header.text_encoding;
+                    r[*dest] = call_scalar(name, &r[*args..args + argc], encoding)?
+                }
+                Op::AggStep {
+                    aggregate,
+                    args,
+                    argc,
+                } => self.accumulators[*aggregate].step(&r[*args..args + argc])?,
+                Op::AggFinal { aggregate, dest } => {
+                    r[*dest] = self.accumulators[*aggregate].finish()?;
+                }
+                Op::AddImm { reg, value } => {
+                    r[*reg] = Value::Integer(r[*reg].to_i64().wrapping_add(*value));
+                }
+                // The registers are computed afresh for each row, so their
+                // values are moved out rather than copied.
+                Op::ResultRow { start, count } => {
+                    let row = r[*start..start + count]
+                        .iter_mut()
+                        .map(|v| std::mem::replace(v, Value::Null))
+                        .collect();
+                    return Ok(Some(row));
+                }
+                Op::Halt => return Ok(None),
+            }
+        }
+    }
+}
+
+impl Iterator for Vm<'_> {
+    type Item = Result<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Result<Vec<Value>>> {
+        if self.halted {
+            return None;
+        }
+        match self.run() {
+            Ok(Some(row)) => Some(Ok(row)),
+            Ok(None) => {
+                self.halted = true;
+                None
+            }
+            // An error ends the program rather than repeating it.
+            Err(e) => {
+                self.halted = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
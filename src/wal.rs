@@ -0,0 +1,274 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{prelude::*, ErrorKind, SeekFrom};
+
+const WAL_HEADER_SIZE: u64 = 32;
+const FRAME_HEADER_SIZE: u64 = 24;
+const WAL_VERSION: u32 = 3_007_000;
+
+/// How much of the WAL a reader sees, fixed when its read begins.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Snapshot {
+    /// Every committed frame, when no other connection has the WAL open.
+    Whole,
+    /// The first `n` frames: those committed as of the WAL-index header
+    /// when the read began. With none, the database file is up to date.
+    Frames(u32),
+}
+
+/// The `-wal` file of a database in WAL mode: page images committed since
+/// the last checkpoint, which supersede the copies in the database file.
+pub struct Wal {
+    file: File,
+    page_size: usize,
+    /// File offset of the newest committed image of each page.
+    frames: HashMap<u32, u64>,
+    /// Database size in pages as of the last commit; 0 if nothing is
+    /// committed.
+    pub db_pages: u32,
+    /// The running checksum as of the last commit, which every commit
+    /// changes.
+    pub commit: (u32, u32),
+}
+
+impl Wal {
+    /// Opens the WAL at `path` and indexes the committed frames `snapshot`
+    /// takes in. A missing, short or unrecognised WAL holds nothing, as in
+    /// SQLite.
+    pub fn open(path: &str, snapshot: Snapshot) -> Result<Option<Wal>> {
+        let max_frames = match snapshot {
+            Snapshot::Frames(0) => return Ok(None),
+            Snapshot::Frames(n) => n,
+            Snapshot::Whole => u32::MAX,
+        };
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let len = file.metadata()?.len();
+        if len < WAL_HEADER_SIZE {
+            return Ok(None);
+        }
+        let mut header = [0u8; WAL_HEADER_SIZE as usize];
+        file.read_exact(&mut header)?;
+        let magic = be32(&header[0..4]);
+        let page_size = be32(&header[8..12]) as usize;
+        if magic & !1 != 0x377f_0682
+            || be32(&header[4..8]) != WAL_VERSION
+            || !page_size.is_power_of_two()
+            || !(512..=65536).contains(&page_size)
+        {
+            return Ok(None);
+        }
+        // The low bit of the magic number gives the byte order of the
+        // checksummed words.
+        let big_endian = magic & 1 == 1;
+        let mut checksum = wal_checksum(big_endian, (0, 0), &header[..24]);
+        if checksum != (be32(&header[24..28]), be32(&header[28..32])) {
+            return Ok(None);
+        }
+        let salt = &header[16..24];
+
+        // Frames after the last commit frame belong to a transaction that
+        // never committed, so they only count once a commit frame follows.
+        // The log ends at the first frame with a stale salt (left over from
+        // before the WAL was restarted) or a bad checksum (a torn write), or
+        // where the snapshot does: later frames are another writer's.
+        let frame_size = FRAME_HEADER_SIZE + page_size as u64;
+        let mut frames = HashMap::new();
+        let mut pending = Vec::new();
+        let mut db_pages = 0;
+        let mut commit = (0, 0);
+        let mut offset = WAL_HEADER_SIZE;
+        let mut frame = vec![0u8; frame_size as usize];
+        let mut read = 0;
+        while offset + frame_size <= len && read < max_frames {
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut frame)?;
+            let page_no = be32(&frame[0..4]);
+            let commit_size = be32(&frame[4..8]);
+            if page_no == 0 || &frame[8..16] != salt {
+                break;
+            }
+            checksum = wal_checksum(big_endian, checksum, &frame[..8]);
+            checksum = wal_checksum(big_endian, checksum, &frame[24..]);
+            if checksum != (be32(&frame[16..20]), be32(&frame[20..24])) {
+                break;
+            }
+            pending.push((page_no, offset + FRAME_HEADER_SIZE));
+            if commit_size != 0 {
+                frames.extend(pending.drain(..));
+                db_pages = commit_size;
+                commit = checksum;
+            }
+            offset += frame_size;
+            read += 1;
+        }
+
+        Ok(Some(Wal {
+            file,
+            page_size,
+            frames,
+            db_pages,
+            commit,
+        }))
+    }
+
+    /// Whether the WAL has a committed image of `page_no`.
+    #[cfg(feature = "mmap")]
+    pub fn has_page(&self, page_no: u32) -> bool {
+        self.frames.contains_key(&page_no)
+    }
+
+    /// The newest committed image of `page_no`, if the WAL has one.
+    pub fn read_page(&mut self, page_no: u32) -> Result<Option<Vec<u8>>> {
+        let Some(&offset) = self.frames.get(&page_no) else {
+            return Ok(None);
+        };
+        let mut page = vec![0u8; self.page_size];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut page)?;
+        Ok(Some(page))
+    }
+}
+
+/// SQLite's WAL checksum: a running pair of sums over 32-bit words, seeded
+/// with the previous checksum so that each frame's covers the whole log.
+pub fn wal_checksum(big_endian: bool, (mut s0, mut s1): (u32, u32), data: &[u8]) -> (u32, u32) {
+    let word = |b: &[u8]| {
+        let b = [b[0], b[1], b[2], b[3]];
+        if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    };
+    for pair in data.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&pair[0..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&pair[4..8])).wrapping_add(s0);
+    }
+    (s0, s1)
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 512;
+    const SALT: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    /// A big-endian WAL of 512-byte pages holding `frames`, each a page
+    /// number, the commit size (0 if not a commit frame) and the byte the
+    /// page is filled with.
+    fn wal(frames: &[(u32, u32, u8)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(0x377f_0683u32.to_be_bytes());
+        out.extend(WAL_VERSION.to_be_bytes());
+        out.extend((PAGE_SIZE as u32).to_be_bytes());
+        out.extend(0u32.to_be_bytes());
+        out.extend(SALT);
+        let mut sum = wal_checksum(true, (0, 0), &out);
+        out.extend(sum.0.to_be_bytes());
+        out.extend(sum.1.to_be_bytes());
+        for &(page_no, commit_size, fill) in frames {
+            let mut header = Vec::new();
+            header.extend(page_no.to_be_bytes());
+            header.extend(commit_size.to_be_bytes());
+            let page = vec![fill; PAGE_SIZE];
+            sum = wal_checksum(true, sum, &header);
+            sum = wal_checksum(true, sum, &page);
+            out.extend(header);
+            out.extend(SALT);
+            out.extend(sum.0.to_be_bytes());
+            out.extend(sum.1.to_be_bytes());
+            out.extend(page);
+        }
+        out
+    }
+
+    /// The file offset of frame `n`'s header.
+    fn frame_at(n: usize) -> usize {
+        WAL_HEADER_SIZE as usize + n * (FRAME_HEADER_SIZE as usize + PAGE_SIZE)
+    }
+
+    /// `bytes` opened as a WAL, with the first byte of each page it has
+    /// for pages 1 and 2.
+    fn open(name: &str, bytes: &[u8], snapshot: Snapshot) -> Option<(u32, [Option<u8>; 2])> {
+        let path = std::env::temp_dir().join(format!("rqlite-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        let wal = Wal::open(path.to_str().unwrap(), snapshot).unwrap();
+        let result = wal.map(|mut wal| {
+            let mut first = |page_no| wal.read_page(page_no).unwrap().map(|p| p[0]);
+            let pages = [first(1), first(2)];
+            (wal.db_pages, pages)
+        });
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn only_committed_frames_are_read() {
+        let bytes = wal(&[(1, 0, 0xa1), (2, 2, 0xb1), (1, 0, 0xa2)]);
+        assert_eq!(
+            open("committed", &bytes, Snapshot::Whole),
+            Some((2, [Some(0xa1), Some(0xb1)]))
+        );
+        let bytes = wal(&[(1, 1, 0xa1), (1, 1, 0xa2)]);
+        assert_eq!(
+            open("newest", &bytes, Snapshot::Whole),
+            Some((1, [Some(0xa2), None]))
+        );
+    }
+
+    #[test]
+    fn the_log_ends_at_a_stale_salt_or_a_bad_checksum() {
+        let good = wal(&[(1, 1, 0xa1), (2, 2, 0xb1)]);
+        let mut stale = good.clone();
+        stale[frame_at(1) + 8] ^= 1;
+        assert_eq!(
+            open("stale", &stale, Snapshot::Whole),
+            Some((1, [Some(0xa1), None]))
+        );
+        // A torn write: the page doesn't match the frame's checksum.
+        let mut torn = good.clone();
+        torn[frame_at(1) + FRAME_HEADER_SIZE as usize + 100] ^= 1;
+        assert_eq!(
+            open("torn", &torn, Snapshot::Whole),
+            Some((1, [Some(0xa1), None]))
+        );
+        // Frames chain their checksums, so one torn frame ends the log even
+        // if a later one is intact.
+        let mut chained = wal(&[(1, 1, 0xa1), (2, 2, 0xb1), (1, 2, 0xa2)]);
+        chained[frame_at(1) + FRAME_HEADER_SIZE as usize] ^= 1;
+        assert_eq!(
+            open("chained", &chained, Snapshot::Whole),
+            Some((1, [Some(0xa1), None]))
+        );
+    }
+
+    #[test]
+    fn a_bad_header_means_no_wal() {
+        let mut bytes = wal(&[(1, 1, 0xa1)]);
+        bytes[12] ^= 1;
+        assert_eq!(open("header", &bytes, Snapshot::Whole), None);
+        let mut bytes = wal(&[(1, 1, 0xa1)]);
+        bytes[4..8].copy_from_slice(&3_007_001u32.to_be_bytes());
+        assert_eq!(open("version", &bytes, Snapshot::Whole), None);
+    }
+
+    #[test]
+    fn a_snapshot_reads_only_its_frames() {
+        let bytes = wal(&[(1, 1, 0xa1), (1, 1, 0xa2)]);
+        assert_eq!(
+            open("first", &bytes, Snapshot::Frames(1)),
+            Some((1, [Some(0xa1), None]))
+        );
+        assert_eq!(open("none", &bytes, Snapshot::Frames(0)), None);
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{prelude::*, ErrorKind, SeekFrom};
+use std::thread;
+use std::time::Duration;
+
+use crate::lock::{held_by_other, try_lock_byte, ByteLock};
+use crate::wal::{wal_checksum, Snapshot};
+
+/// The `-shm` file's lock bytes, where SQLite's unix VFS puts them: the
+/// WAL-index's eight locks from byte 120, the reader slots' among them, then
+/// the "dead man switch" every connection holds a read lock on while it uses
+/// the file.
+const READ_LOCK: i64 = 123;
+const DMS: i64 = 128;
+/// Reader slot 0 ignores the WAL; the others read it up to their mark.
+const READERS: usize = 5;
+
+/// The WAL-index header, kept twice, then the checkpoint info: the frames
+/// copied back into the database so far and each reader slot's mark.
+const HEADER_SIZE: usize = 48;
+const BACKFILLED: usize = 96;
+const READ_MARKS: usize = 100;
+const INFO_END: usize = READ_MARKS + 4 * READERS;
+
+/// How often a read that keeps finding the WAL-index in flux is retried
+/// before the database counts as locked, as in SQLite.
+const RETRIES: u32 = 100;
+
+/// The `-shm` file of a database in WAL mode: the WAL-index its connections
+/// share. A reader holds a read lock on one of its reader slots, which keeps
+/// checkpoints from copying frames newer than the slot's mark into the
+/// database file, and writers from restarting the WAL, until the reader is
+/// done. The locks go with the file when it is dropped.
+pub struct WalIndex {
+    file: File,
+}
+
+impl WalIndex {
+    /// Opens the `-shm` file for the database at `path`, creating it if no
+    /// connection has yet.
+    pub fn open(path: &str) -> Result<WalIndex> {
+        let shm = format!("{}-shm", path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&shm)
+            .map_err(|e| anyhow!("unable to open {}: {} (--no-lock reads without it)", shm, e))?;
+        Ok(WalIndex { file })
+    }
+
+    /// Joins the connections using the WAL-index and takes a reader slot,
+    /// the way SQLite's readers do, returning how much of the WAL the slot
+    /// lets this reader see. Returns None if other connections are in the
+    /// way, so the database is locked for now.
+    pub fn begin_read(&mut self) -> Result<Option<Snapshot>> {
+        match held_by_other(&self.file, DMS)? {
+            // Another connection is resetting the WAL-index.
+            ByteLock::Write => return Ok(None),
+            ByteLock::Read => {
+                if !try_lock_byte(&self.file, DMS, ByteLock::Read)? {
+                    return Ok(None);
+                }
+            }
+            // No other connection has the WAL open, so whatever the file
+            // holds is stale. Like SQLite, the first connection truncates it
+            // (to 3 bytes rather than 0, as SQLite does) so the next one
+            // rebuilds it from the WAL. This reader scans the WAL itself and
+            // holds slot 1, whose mark now reads 0, so no checkpoint copies
+            // anything back until it is done.
+            ByteLock::Unlocked => {
+                if !try_lock_byte(&self.file, DMS, ByteLock::Write)? {
+                    return Ok(None);
+                }
+                self.file.set_len(3)?;
+                try_lock_byte(&self.file, READ_LOCK + 1, ByteLock::Read)?;
+                try_lock_byte(&self.file, DMS, ByteLock::Read)?;
+                return Ok(Some(Snapshot::Whole));
+            }
+        }
+        for attempt in 0..RETRIES {
+            if let Some(snapshot) = self.try_begin_read()? {
+                return Ok(Some(snapshot));
+            }
+            // SQLite's backoff: none at first, then growing quadratically.
+            if attempt >= 10 {
+                let delay = (attempt as u64 - 9).pow(2) * 39;
+                thread::sleep(Duration::from_micros(delay));
+            } else if attempt > 5 {
+                thread::sleep(Duration::from_micros(1));
+            }
+        }
+        Ok(None)
+    }
+
+    /// One try at taking a reader slot: None if the WAL-index changed
+    /// underneath, or every slot was busy.
+    fn try_begin_read(&mut self) -> Result<Option<Snapshot>> {
+        let Some(shm) = self.read_shm()? else {
+            return Ok(None);
+        };
+        let header = &shm[..HEADER_SIZE];
+        let max_frame = ne32(&header[16..20]);
+        let mark = |shm: &[u8], slot: usize| ne32(&shm[READ_MARKS + 4 * slot..]);
+
+        // With every frame already in the database file, slot 0 reads
+        // without the WAL; it keeps new frames from being copied back.
+        if ne32(&shm[BACKFILLED..]) == max_frame
+            && try_lock_byte(&self.file, READ_LOCK, ByteLock::Read)?
+        {
+            if self
+                .read_shm()?
+                .is_some_and(|now| now[..HEADER_SIZE] == *header)
+            {
+                return Ok(Some(Snapshot::Frames(0)));
+            }
+            try_lock_byte(&self.file, READ_LOCK, ByteLock::Unlocked)?;
+            return Ok(None);
+        }
+
+        // Otherwise the slot with the latest mark at or before the last
+        // commit, after moving a free slot's mark up to it if possible.
+        let mut slot = 0;
+        let mut latest = 0;
+        for i in 1..READERS {
+            let m = mark(&shm, i);
+            if latest <= m && m <= max_frame {
+                slot = i;
+                latest = m;
+            }
+        }
+        if latest < max_frame || slot == 0 {
+            for i in 1..READERS {
+                let at = READ_LOCK + i as i64;
+                if try_lock_byte(&self.file, at, ByteLock::Write)? {
+                    self.file
+                        .seek(SeekFrom::Start((READ_MARKS + 4 * i) as u64))?;
+                    self.file.write_all(&max_frame.to_ne_bytes())?;
+                    try_lock_byte(&self.file, at, ByteLock::Unlocked)?;
+                    slot = i;
+                    latest = max_frame;
+                    break;
+                }
+            }
+        }
+        if slot == 0 {
+            return Ok(None);
+        }
+        let at = READ_LOCK + slot as i64;
+        if !try_lock_byte(&self.file, at, ByteLock::Read)? {
+            return Ok(None);
+        }
+        // A checkpoint may have moved the mark, or a commit the header,
+        // before the lock was granted.
+        if let Some(now) = self.read_shm()?
+            && mark(&now, slot) == latest
+            && now[..HEADER_SIZE] == *header
+        {
+            return Ok(Some(Snapshot::Frames(max_frame)));
+        }
+        try_lock_byte(&self.file, at, ByteLock::Unlocked)?;
+        Ok(None)
+    }
+
+    /// The WAL-index header and checkpoint info, if the header is intact:
+    /// both copies agree, it is initialised and its checksum holds. The
+    /// fields are in the writer's native byte order.
+    fn read_shm(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut shm = vec![0u8; INFO_END];
+        self.file.seek(SeekFrom::Start(0))?;
+        match self.file.read_exact(&mut shm) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let (first, second) = (&shm[..HEADER_SIZE], &shm[HEADER_SIZE..2 * HEADER_SIZE]);
+        let checksum = wal_checksum(cfg!(target_endian = "big"), (0, 0), &first[..40]);
+        let intact = first == second
+            && first[12] != 0
+            && checksum == (ne32(&first[40..44]), ne32(&first[44..48]));
+        Ok(intact.then_some(shm))
+    }
+}
+
+fn ne32(bytes: &[u8]) -> u32 {
+    u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
@@ -0,0 +1,189 @@
+use anyhow::{bail, Result};
+
+use crate::eval::is_true;
+use crate::functions::quote;
+use crate::pager::Pager;
+use crate::planner::{self, conjuncts, query_columns, AccessPath, Input, Output};
+use crate::settings::Settings;
+use crate::sql::{BinaryOp, Expr, Select};
+use crate::value::Value;
+use crate::vm::Vm;
+use crate::{find_table_schema, format_row};
+
+/// Traces the row with rowid `rowid` through the plan of `select`, one line
+/// per step: whether the access path the query takes reaches the row, how
+/// each term of the WHERE clause judges it, and whether it is returned. The
+/// row is read by rowid, apart from the query, so a row the access path
+/// misses although the WHERE clause holds shows up as such. Every step runs
+/// as a compiled program, so it judges the row exactly as the query would.
+pub fn trace(
+    pager: &mut Pager,
+    select: &Select,
+    rowid: i64,
+    settings: &Settings,
+) -> Result<Vec<String>> {
+    let header = pager.header()?;
+    let (table_schema, definition) = find_table_schema(pager, &select.table)?;
+    if definition.without_rowid {
+        bail!("{} is a WITHOUT ROWID table", definition.name);
+    }
+    let Some(rowid_name) = ["rowid", "_rowid_", "oid"]
+        .into_iter()
+        .find(|n| definition.column_index(n).is_none())
+    else {
+        bail!(
+            "{} has columns named rowid, _rowid_ and oid",
+            definition.name
+        );
+    };
+    let mut select = select.clone();
+    select.resolve_double_quoted(&|name| definition.has_column(name));
+    let where_clause = select.where_clause.as_ref();
+    let columns = query_columns(&definition, &select.columns, where_clause, settings)?;
+    let mut terms = Vec::new();
+    if let Some(w) = where_clause {
+        conjuncts(w, &mut terms);
+    }
+
+    // The row by itself: `select` with a WHERE clause that holds only for
+    // it, ahead of any other so the row is read by rowid.
+    let this_row = Expr::Binary {
+        op: BinaryOp::Eq,
+        left: Box::new(Expr::Column(rowid_name.to_string())),
+        right: Box::new(Expr::Literal(Value::Integer(rowid))),
+    };
+    let mut lines = vec![format!("row: rowid {} of {}", rowid, definition.name)];
+    // Each term's columns, as the WHERE clause sees them (redacted).
+    let mut shown = Vec::new();
+    for term in &terms {
+        let mut referenced = Vec::new();
+        term.columns(&mut referenced);
+        for name in referenced {
+            if !shown.iter().any(|n: &String| n.eq_ignore_ascii_case(name)) {
+                shown.push(name.to_string());
+            }
+        }
+    }
+    let mut read: Vec<Expr> = shown.iter().cloned().map(Expr::Column).collect();
+    read.push(Expr::Literal(Value::Integer(1)));
+    let Some(values) = run_on_row(pager, &select, &this_row, read, None, settings)? else {
+        lines.push("row: no such row".to_string());
+        return Ok(lines);
+    };
+
+    let plan = planner::plan(
+        pager,
+        &header,
+        &definition,
+        table_schema.rootpage,
+        &terms,
+        &columns.sources,
+        settings,
+    )?;
+    lines.push(format!(
+        "access: {}, estimated {:.0} rows at cost {:.0}",
+        plan.describe(),
+        plan.rows,
+        plan.cost
+    ));
+    let program = planner::compile(pager, &select, Input::Table, Output::Reached, settings)?;
+    let reached_rows = Vm::new(pager, program, settings)?.collect::<Result<Vec<_>>>()?;
+    if let AccessPath::IndexEq { .. } | AccessPath::IndexRange { .. } = plan.path {
+        lines.push(format!("access: {} index entries", reached_rows.len()));
+    }
+    let reached = reached_rows.contains(&vec![Value::Integer(rowid)]);
+    if !reached {
+        lines.push("access: the row is not among them".to_string());
+    }
+
+    for term in &terms {
+        let verdict = match run_on_row(
+            pager,
+            &select,
+            &this_row,
+            vec![(*term).clone()],
+            None,
+            settings,
+        ) {
+            Ok(Some(v)) if v[0] != Value::Null => is_true(&v[0]).to_string(),
+            Ok(_) => "NULL".to_string(),
+            Err(e) => format!("error: {}", e),
+        };
+        let mut referenced = Vec::new();
+        term.columns(&mut referenced);
+        let mut with: Vec<String> = Vec::new();
+        for (i, name) in referenced.iter().enumerate() {
+            if referenced[..i].iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            let at = shown.iter().position(|n| n.eq_ignore_ascii_case(name));
+            let value = at.map_or(Value::Null, |i| values[i].clone());
+            with.push(format!("{} = {}", name, quote(&value)));
+        }
+        lines.push(format!("where: {} -> {}", term, verdict));
+        if !with.is_empty() {
+            lines.push(format!("  with {}", with.join(", ")));
+        }
+    }
+
+    // The query itself, over the row alone; an aggregate would yield a row
+    // either way, so it only asks whether the row passes.
+    let is_aggregate = select.columns.iter().any(|c| c.is_aggregate());
+    let projected = if is_aggregate {
+        run_on_row(
+            pager,
+            &select,
+            &this_row,
+            vec![Expr::Literal(Value::Integer(1))],
+            where_clause,
+            settings,
+        )?
+    } else {
+        run_on_row(
+            pager,
+            &select,
+            &this_row,
+            select.columns.clone(),
+            where_clause,
+            settings,
+        )?
+    };
+    let result = match (reached, projected) {
+        (false, Some(_)) => {
+            "not returned, though the WHERE clause holds: the access path misses it".to_string()
+        }
+        (false, None) => "not returned: pruned by the access path".to_string(),
+        (true, None) => "not returned: the WHERE clause is not true".to_string(),
+        (true, Some(_)) if is_aggregate => "counted in the aggregate".to_string(),
+        (true, Some(row)) => format!("returned as {}", format_row(&row)),
+    };
+    lines.push(format!("result: {}", result));
+    Ok(lines)
+}
+
+/// Runs `select` with `columns` over the row `this_row` picks, if `also`
+/// holds for it, and returns what the query yields.
+fn run_on_row(
+    pager: &mut Pager,
+    select: &Select,
+    this_row: &Expr,
+    columns: Vec<Expr>,
+    also: Option<&Expr>,
+    settings: &Settings,
+) -> Result<Option<Vec<Value>>> {
+    let where_clause = match also {
+        Some(w) => Expr::Binary {
+            op: BinaryOp::And,
+            left: Box::new(this_row.clone()),
+            right: Box::new(w.clone()),
+        },
+        None => this_row.clone(),
+    };
+    let select = Select {
+        columns,
+        where_clause: Some(where_clause),
+        ..select.clone()
+    };
+    let program = planner::compile(pager, &select, Input::Table, Output::Results, settings)?;
+    Vm::new(pager, program, settings)?.next().transpose()
+}
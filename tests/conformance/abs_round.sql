@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT abs(-stock), round(price), round(price, 1) FROM products WHERE stock < 50
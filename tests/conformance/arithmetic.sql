@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT id, stock * 2 + 1, price * stock, 7 / 2, 7 % 3, 7.0 / 2 FROM products WHERE id < 4
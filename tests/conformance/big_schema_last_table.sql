@@ -0,0 +1,2 @@
+-- db: big_schema.db
+SELECT id, name, note FROM t39;
@@ -0,0 +1,2 @@
+-- db: big_schema.db
+SELECT id, column_with_a_long_name_000, column_with_a_long_name_079 FROM wide;
@@ -0,0 +1,9 @@
+-- Builds affinity.db: sqlite3 affinity.db < build/affinity.sql
+-- Whole numbers in REAL columns are stored as integers and must read back
+-- as reals; NUMERIC and untyped columns keep what was stored.
+CREATE TABLE prices (id INTEGER PRIMARY KEY, price REAL, weight FLOAT, ratio DOUBLE PRECISION, amount NUMERIC, total DECIMAL(10,2), other);
+CREATE INDEX prices_price ON prices(price);
+INSERT INTO prices VALUES (1, 3.0, 2.0, 5.0, 3.0, 4.0, 3.0);
+INSERT INTO prices VALUES (2, 2.5, 1.5, 0.5, 2.5, 7.25, 2.5);
+INSERT INTO prices VALUES (3, 3, 10, -4, 3, 8, 3);
+INSERT INTO prices VALUES (4, -9007199254740992.0, 0.0, NULL, NULL, NULL, NULL);
@@ -0,0 +1,168 @@
+-- A schema too large for page 1: forty tables on 1024-byte pages, so
+-- sqlite_schema is an interior page over several leaves, and one table
+-- whose CREATE statement spills onto overflow pages.
+PRAGMA page_size = 1024;
+CREATE TABLE t00(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 0');
+INSERT INTO t00(name) VALUES ('row of t00');
+CREATE TABLE t01(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 1');
+INSERT INTO t01(name) VALUES ('row of t01');
+CREATE TABLE t02(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 2');
+INSERT INTO t02(name) VALUES ('row of t02');
+CREATE TABLE t03(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 3');
+INSERT INTO t03(name) VALUES ('row of t03');
+CREATE TABLE t04(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 4');
+INSERT INTO t04(name) VALUES ('row of t04');
+CREATE TABLE t05(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 5');
+INSERT INTO t05(name) VALUES ('row of t05');
+CREATE TABLE t06(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 6');
+INSERT INTO t06(name) VALUES ('row of t06');
+CREATE TABLE t07(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 7');
+INSERT INTO t07(name) VALUES ('row of t07');
+CREATE TABLE t08(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 8');
+INSERT INTO t08(name) VALUES ('row of t08');
+CREATE TABLE t09(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 9');
+INSERT INTO t09(name) VALUES ('row of t09');
+CREATE TABLE t10(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 10');
+INSERT INTO t10(name) VALUES ('row of t10');
+CREATE TABLE t11(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 11');
+INSERT INTO t11(name) VALUES ('row of t11');
+CREATE TABLE t12(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 12');
+INSERT INTO t12(name) VALUES ('row of t12');
+CREATE TABLE t13(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 13');
+INSERT INTO t13(name) VALUES ('row of t13');
+CREATE TABLE t14(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 14');
+INSERT INTO t14(name) VALUES ('row of t14');
+CREATE TABLE t15(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 15');
+INSERT INTO t15(name) VALUES ('row of t15');
+CREATE TABLE t16(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 16');
+INSERT INTO t16(name) VALUES ('row of t16');
+CREATE TABLE t17(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 17');
+INSERT INTO t17(name) VALUES ('row of t17');
+CREATE TABLE t18(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 18');
+INSERT INTO t18(name) VALUES ('row of t18');
+CREATE TABLE t19(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 19');
+INSERT INTO t19(name) VALUES ('row of t19');
+CREATE TABLE t20(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 20');
+INSERT INTO t20(name) VALUES ('row of t20');
+CREATE TABLE t21(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 21');
+INSERT INTO t21(name) VALUES ('row of t21');
+CREATE TABLE t22(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 22');
+INSERT INTO t22(name) VALUES ('row of t22');
+CREATE TABLE t23(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 23');
+INSERT INTO t23(name) VALUES ('row of t23');
+CREATE TABLE t24(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 24');
+INSERT INTO t24(name) VALUES ('row of t24');
+CREATE TABLE t25(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 25');
+INSERT INTO t25(name) VALUES ('row of t25');
+CREATE TABLE t26(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 26');
+INSERT INTO t26(name) VALUES ('row of t26');
+CREATE TABLE t27(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 27');
+INSERT INTO t27(name) VALUES ('row of t27');
+CREATE TABLE t28(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 28');
+INSERT INTO t28(name) VALUES ('row of t28');
+CREATE TABLE t29(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 29');
+INSERT INTO t29(name) VALUES ('row of t29');
+CREATE TABLE t30(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 30');
+INSERT INTO t30(name) VALUES ('row of t30');
+CREATE TABLE t31(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 31');
+INSERT INTO t31(name) VALUES ('row of t31');
+CREATE TABLE t32(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 32');
+INSERT INTO t32(name) VALUES ('row of t32');
+CREATE TABLE t33(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 33');
+INSERT INTO t33(name) VALUES ('row of t33');
+CREATE TABLE t34(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 34');
+INSERT INTO t34(name) VALUES ('row of t34');
+CREATE TABLE t35(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 35');
+INSERT INTO t35(name) VALUES ('row of t35');
+CREATE TABLE t36(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 36');
+INSERT INTO t36(name) VALUES ('row of t36');
+CREATE TABLE t37(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 37');
+INSERT INTO t37(name) VALUES ('row of t37');
+CREATE TABLE t38(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 38');
+INSERT INTO t38(name) VALUES ('row of t38');
+CREATE TABLE t39(id INTEGER PRIMARY KEY, name TEXT, note TEXT DEFAULT 'table number 39');
+INSERT INTO t39(name) VALUES ('row of t39');
+CREATE TABLE wide(
+  id INTEGER PRIMARY KEY,
+  column_with_a_long_name_000 TEXT DEFAULT 'x',
+  column_with_a_long_name_001 TEXT DEFAULT 'x',
+  column_with_a_long_name_002 TEXT DEFAULT 'x',
+  column_with_a_long_name_003 TEXT DEFAULT 'x',
+  column_with_a_long_name_004 TEXT DEFAULT 'x',
+  column_with_a_long_name_005 TEXT DEFAULT 'x',
+  column_with_a_long_name_006 TEXT DEFAULT 'x',
+  column_with_a_long_name_007 TEXT DEFAULT 'x',
+  column_with_a_long_name_008 TEXT DEFAULT 'x',
+  column_with_a_long_name_009 TEXT DEFAULT 'x',
+  column_with_a_long_name_010 TEXT DEFAULT 'x',
+  column_with_a_long_name_011 TEXT DEFAULT 'x',
+  column_with_a_long_name_012 TEXT DEFAULT 'x',
+  column_with_a_long_name_013 TEXT DEFAULT 'x',
+  column_with_a_long_name_014 TEXT DEFAULT 'x',
+  column_with_a_long_name_015 TEXT DEFAULT 'x',
+  column_with_a_long_name_016 TEXT DEFAULT 'x',
+  column_with_a_long_name_017 TEXT DEFAULT 'x',
+  column_with_a_long_name_018 TEXT DEFAULT 'x',
+  column_with_a_long_name_019 TEXT DEFAULT 'x',
+  column_with_a_long_name_020 TEXT DEFAULT 'x',
+  column_with_a_long_name_021 TEXT DEFAULT 'x',
+  column_with_a_long_name_022 TEXT DEFAULT 'x',
+  column_with_a_long_name_023 TEXT DEFAULT 'x',
+  column_with_a_long_name_024 TEXT DEFAULT 'x',
+  column_with_a_long_name_025 TEXT DEFAULT 'x',
+  column_with_a_long_name_026 TEXT DEFAULT 'x',
+  column_with_a_long_name_027 TEXT DEFAULT 'x',
+  column_with_a_long_name_028 TEXT DEFAULT 'x',
+  column_with_a_long_name_029 TEXT DEFAULT 'x',
+  column_with_a_long_name_030 TEXT DEFAULT 'x',
+  column_with_a_long_name_031 TEXT DEFAULT 'x',
+  column_with_a_long_name_032 TEXT DEFAULT 'x',
+  column_with_a_long_name_033 TEXT DEFAULT 'x',
+  column_with_a_long_name_034 TEXT DEFAULT 'x',
+  column_with_a_long_name_035 TEXT DEFAULT 'x',
+  column_with_a_long_name_036 TEXT DEFAULT 'x',
+  column_with_a_long_name_037 TEXT DEFAULT 'x',
+  column_with_a_long_name_038 TEXT DEFAULT 'x',
+  column_with_a_long_name_039 TEXT DEFAULT 'x',
+  column_with_a_long_name_040 TEXT DEFAULT 'x',
+  column_with_a_long_name_041 TEXT DEFAULT 'x',
+  column_with_a_long_name_042 TEXT DEFAULT 'x',
+  column_with_a_long_name_043 TEXT DEFAULT 'x',
+  column_with_a_long_name_044 TEXT DEFAULT 'x',
+  column_with_a_long_name_045 TEXT DEFAULT 'x',
+  column_with_a_long_name_046 TEXT DEFAULT 'x',
+  column_with_a_long_name_047 TEXT DEFAULT 'x',
+  column_with_a_long_name_048 TEXT DEFAULT 'x',
+  column_with_a_long_name_049 TEXT DEFAULT 'x',
+  column_with_a_long_name_050 TEXT DEFAULT 'x',
+  column_with_a_long_name_051 TEXT DEFAULT 'x',
+  column_with_a_long_name_052 TEXT DEFAULT 'x',
+  column_with_a_long_name_053 TEXT DEFAULT 'x',
+  column_with_a_long_name_054 TEXT DEFAULT 'x',
+  column_with_a_long_name_055 TEXT DEFAULT 'x',
+  column_with_a_long_name_056 TEXT DEFAULT 'x',
+  column_with_a_long_name_057 TEXT DEFAULT 'x',
+  column_with_a_long_name_058 TEXT DEFAULT 'x',
+  column_with_a_long_name_059 TEXT DEFAULT 'x',
+  column_with_a_long_name_060 TEXT DEFAULT 'x',
+  column_with_a_long_name_061 TEXT DEFAULT 'x',
+  column_with_a_long_name_062 TEXT DEFAULT 'x',
+  column_with_a_long_name_063 TEXT DEFAULT 'x',
+  column_with_a_long_name_064 TEXT DEFAULT 'x',
+  column_with_a_long_name_065 TEXT DEFAULT 'x',
+  column_with_a_long_name_066 TEXT DEFAULT 'x',
+  column_with_a_long_name_067 TEXT DEFAULT 'x',
+  column_with_a_long_name_068 TEXT DEFAULT 'x',
+  column_with_a_long_name_069 TEXT DEFAULT 'x',
+  column_with_a_long_name_070 TEXT DEFAULT 'x',
+  column_with_a_long_name_071 TEXT DEFAULT 'x',
+  column_with_a_long_name_072 TEXT DEFAULT 'x',
+  column_with_a_long_name_073 TEXT DEFAULT 'x',
+  column_with_a_long_name_074 TEXT DEFAULT 'x',
+  column_with_a_long_name_075 TEXT DEFAULT 'x',
+  column_with_a_long_name_076 TEXT DEFAULT 'x',
+  column_with_a_long_name_077 TEXT DEFAULT 'x',
+  column_with_a_long_name_078 TEXT DEFAULT 'x',
+  column_with_a_long_name_079 TEXT DEFAULT 'x'
+);
+INSERT INTO wide(id, column_with_a_long_name_079) VALUES (1, 'last column');
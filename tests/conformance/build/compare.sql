@@ -0,0 +1,11 @@
+-- Builds compare.db: sqlite3 compare.db < build/compare.sql
+-- One column of each affinity, holding values of every storage class, for
+-- comparisons whose operands convert by affinity.
+CREATE TABLE t (id INTEGER PRIMARY KEY, a TEXT, n NUMERIC, d, i INTEGER, r REAL);
+INSERT INTO t VALUES (1, '9', 9, 1, 9, 9);
+INSERT INTO t VALUES (2, '10', '10', '1', '10', '10');
+INSERT INTO t VALUES (3, 'abc', 'abc', '2', 'abc', 'abc');
+INSERT INTO t VALUES (4, 5, '5.0', 2, 5, 5);
+INSERT INTO t VALUES (5, NULL, NULL, x'31', NULL, NULL);
+INSERT INTO t VALUES (6, ' 7', ' 7', ' 7', ' 7', ' 7');
+INSERT INTO t VALUES (7, '1e1', '1e1', 1.5, '1e1', '1e1');
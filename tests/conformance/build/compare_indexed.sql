@@ -0,0 +1,11 @@
+-- Builds compare_indexed.db: sqlite3 compare_indexed.db < build/compare_indexed.sql
+-- compare.db's rows, with an index on each column and enough rows of NULLs
+-- besides that a comparison with one of them reads the index.
+.read build/compare.sql
+WITH RECURSIVE filler(x) AS (SELECT 100 UNION ALL SELECT x + 1 FROM filler WHERE x < 399)
+INSERT INTO t (id) SELECT x FROM filler;
+CREATE INDEX t_a ON t(a);
+CREATE INDEX t_n ON t(n);
+CREATE INDEX t_d ON t(d);
+CREATE INDEX t_i ON t(i);
+CREATE INDEX t_r ON t(r);
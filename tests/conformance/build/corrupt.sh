@@ -0,0 +1,22 @@
+#!/bin/sh
+# Builds the corrupt_*.db fixtures by damaging copies of shop.db (4096-byte
+# pages: products is rooted at page 2, orders at page 3). Run from
+# tests/conformance: sh build/corrupt.sh
+set -e
+poke() { printf "$3" | dd of="$1" bs=1 seek="$2" conv=notrunc status=none; }
+
+# Too short for a database header.
+head -c 50 shop.db > corrupt_short.db
+# Cut off after page 2, so orders' root page is past the end of the file.
+head -c 8192 shop.db > corrupt_truncated.db
+# Page 2's first cell pointer points past the end of the page.
+cp shop.db corrupt_cell_pointer.db && poke corrupt_cell_pointer.db 4104 '\377\360'
+# Page 2 claims more cells than its pointer array has room for.
+cp shop.db corrupt_cell_count.db && poke corrupt_cell_count.db 4099 '\017\377'
+# Page 3's type byte is not a b-tree page type.
+cp shop.db corrupt_page_type.db && poke corrupt_page_type.db 8192 '\007'
+# Page 3 becomes an interior page whose only child is itself.
+cp shop.db corrupt_page_loop.db
+poke corrupt_page_loop.db 8192 '\005\000\000\000\000\017\360\000\000\000\000\003'
+# Page 2's first record claims a header longer than its payload.
+cp shop.db corrupt_record.db && poke corrupt_record.db 8167 '\177'
@@ -0,0 +1,10 @@
+-- Builds people.db: sqlite3 people.db < build/people.sql
+-- An index on lower(name), which a lookup on lower(name) should use.
+CREATE TABLE people (id INTEGER PRIMARY KEY, name TEXT);
+CREATE INDEX people_lower ON people(lower(name));
+INSERT INTO people VALUES (1, 'Alice');
+INSERT INTO people VALUES (2, 'BOB');
+INSERT INTO people VALUES (3, 'alice');
+INSERT INTO people VALUES (4, 'Émile');
+INSERT INTO people VALUES (5, NULL);
+INSERT INTO people VALUES (6, 'carol');
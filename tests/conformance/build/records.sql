@@ -0,0 +1,14 @@
+-- Builds records.db: sqlite3 records.db < build/records.sql
+-- One value of every serial type, with integers at each size boundary, so
+-- the record decoder and encoder can be checked against sqlite3's bytes.
+CREATE TABLE records (id INTEGER PRIMARY KEY, a, b, c);
+INSERT INTO records VALUES (1, NULL, 0, 1);
+INSERT INTO records VALUES (2, 2, -1, 127);
+INSERT INTO records VALUES (3, 128, -128, -129);
+INSERT INTO records VALUES (4, 32767, 32768, -32769);
+INSERT INTO records VALUES (5, 8388607, 8388608, -8388609);
+INSERT INTO records VALUES (6, 2147483647, 2147483648, -2147483649);
+INSERT INTO records VALUES (7, 140737488355327, 140737488355328, -140737488355329);
+INSERT INTO records VALUES (8, 9223372036854775807, -9223372036854775807 - 1, 0.5);
+INSERT INTO records VALUES (9, -3.25e10, '', 'text');
+INSERT INTO records VALUES (10, X'', X'00ff', printf('%.60c', 'x'));
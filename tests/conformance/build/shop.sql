@@ -0,0 +1,17 @@
+-- Builds shop.db: sqlite3 shop.db < build/shop.sql
+CREATE TABLE products (id INTEGER PRIMARY KEY, name TEXT NOT NULL, category TEXT, price REAL, stock INTEGER);
+CREATE TABLE orders (id INTEGER PRIMARY KEY, product_id INTEGER, quantity INTEGER, note TEXT);
+CREATE INDEX orders_product ON orders(product_id);
+CREATE INDEX products_category ON products(category);
+INSERT INTO products VALUES (1, 'Apple', 'fruit', 0.5, 100);
+INSERT INTO products VALUES (2, 'Banana', 'fruit', 0.25, 150);
+INSERT INTO products VALUES (3, 'Carrot', 'vegetable', 0.1, 0);
+INSERT INTO products VALUES (4, 'Durian', 'fruit', 12.75, 3);
+INSERT INTO products VALUES (5, 'Eggplant', 'vegetable', 1.5, NULL);
+INSERT INTO products VALUES (6, 'Fig', NULL, 2.0, 20);
+INSERT INTO products VALUES (7, 'grape', 'fruit', 3.0, 40);
+INSERT INTO orders VALUES (1, 1, 10, 'weekly');
+INSERT INTO orders VALUES (2, 2, 5, NULL);
+INSERT INTO orders VALUES (3, 1, 3, 'it''s urgent');
+INSERT INTO orders VALUES (4, 4, 1, NULL);
+INSERT INTO orders VALUES (5, 9, 2, 'unknown product');
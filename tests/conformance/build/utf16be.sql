@@ -0,0 +1,9 @@
+-- Builds utf16be.db: sqlite3 utf16be.db < build/utf16be.sql
+-- Text is stored as UTF-16BE, which hex() shows.
+PRAGMA encoding = 'UTF-16be';
+CREATE TABLE words (id INTEGER PRIMARY KEY, word TEXT, n);
+CREATE INDEX words_word ON words(word);
+INSERT INTO words VALUES (1, 'aé', 12);
+INSERT INTO words VALUES (2, 'tëst', 1.5);
+INSERT INTO words VALUES (3, '', x'00ff');
+INSERT INTO words VALUES (4, NULL, NULL);
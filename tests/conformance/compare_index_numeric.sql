@@ -0,0 +1,2 @@
+-- db: compare_indexed.db
+SELECT id, n FROM t WHERE n = '10';
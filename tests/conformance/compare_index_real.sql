@@ -0,0 +1,2 @@
+-- db: compare_indexed.db
+SELECT id, r FROM t WHERE r < '9.5';
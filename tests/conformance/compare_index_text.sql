@@ -0,0 +1,2 @@
+-- db: compare_indexed.db
+SELECT id, a FROM t WHERE a < 50;
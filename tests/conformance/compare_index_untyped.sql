@@ -0,0 +1,2 @@
+-- db: compare_indexed.db
+SELECT id FROM t WHERE d = '1' OR d = 1;
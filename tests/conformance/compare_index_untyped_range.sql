@@ -0,0 +1,2 @@
+-- db: compare_indexed.db
+SELECT id, d FROM t WHERE d > 1;
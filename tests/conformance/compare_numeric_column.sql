@@ -0,0 +1,2 @@
+-- db: compare.db
+SELECT id FROM t WHERE n = '10' OR n < '5.5';
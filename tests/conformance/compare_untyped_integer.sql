@@ -0,0 +1,2 @@
+-- db: compare.db
+SELECT id FROM t WHERE d = 1;
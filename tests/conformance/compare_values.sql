@@ -0,0 +1,2 @@
+-- db: compare.db
+SELECT id, a > 5, a = '9', n = '5', d = 1, d = '1', i > '9', r = '10', 5 = '5', id = '4', a = n, a = d, d = n, a = i, a < d, a COLLATE NOCASE > 5, +a > 5 FROM t;
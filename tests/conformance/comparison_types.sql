@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT id FROM products WHERE price = 2
@@ -0,0 +1,2 @@
+-- db: corrupt_cell_count.db
+SELECT count(*) FROM products
@@ -0,0 +1,2 @@
+-- db: corrupt_cell_pointer.db
+SELECT name FROM products
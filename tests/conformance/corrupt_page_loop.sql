@@ -0,0 +1,2 @@
+-- db: corrupt_page_loop.db
+SELECT id FROM orders
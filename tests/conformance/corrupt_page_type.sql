@@ -0,0 +1,2 @@
+-- db: corrupt_page_type.db
+SELECT id FROM orders
@@ -0,0 +1,2 @@
+-- db: corrupt_record.db
+SELECT name FROM products
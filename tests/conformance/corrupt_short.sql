@@ -0,0 +1,2 @@
+-- db: corrupt_short.db
+SELECT name FROM products
@@ -0,0 +1,2 @@
+-- db: corrupt_truncated.db
+SELECT count(*) FROM orders
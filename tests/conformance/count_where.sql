@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT count(*) FROM products WHERE category = 'fruit'
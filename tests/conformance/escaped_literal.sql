@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT id FROM orders WHERE note = 'it''s urgent'
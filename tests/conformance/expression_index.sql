@@ -0,0 +1,2 @@
+-- db: people.db
+SELECT id, name FROM people WHERE lower(name) = 'alice';
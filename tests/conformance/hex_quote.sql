@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT hex(name), quote(name), quote(price), quote(stock) FROM products WHERE id > 4
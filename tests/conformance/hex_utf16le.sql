@@ -0,0 +1,2 @@
+-- db: utf16le.db
+SELECT id, word, hex(word), hex(n), hex('aé') FROM words;
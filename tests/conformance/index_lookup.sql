@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT id, quantity FROM orders WHERE product_id = 1
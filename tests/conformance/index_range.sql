@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT id FROM orders WHERE product_id > 1 AND product_id < 9
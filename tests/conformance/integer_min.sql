@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT -9223372036854775808, quote(-9223372036854775808), -(9223372036854775808), -9223372036854775808 - 1, -9223372036854775809, - -9223372036854775808 FROM products WHERE id = 1;
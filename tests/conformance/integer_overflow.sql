@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT 9223372036854775807 + 1, -9223372036854775807 - 1 FROM products WHERE id = 1
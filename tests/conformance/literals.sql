@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT 0x10, 1e3, .5, X'414243', 'a''b' FROM products WHERE id = 1
@@ -0,0 +1,2 @@
+-- db: people.db
+SELECT id, lower(name), upper(name), lower(42), upper(NULL) FROM people;
@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT stock + 1, stock IS NULL FROM products WHERE id = 5
@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT note FROM orders WHERE note LIKE 'it%'
@@ -0,0 +1,2 @@
+-- db: affinity.db
+SELECT id, price, weight, ratio, amount, total, other FROM prices
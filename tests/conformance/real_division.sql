@@ -0,0 +1,2 @@
+-- db: affinity.db
+SELECT id, price / 2, weight / 4, amount / 2, other / 2 FROM prices
@@ -0,0 +1,2 @@
+-- db: affinity.db
+SELECT price FROM prices WHERE price = 3
@@ -0,0 +1,2 @@
+-- db: affinity.db
+SELECT price / 2 FROM prices WHERE price > 2 AND price < 4
@@ -0,0 +1,2 @@
+-- db: affinity.db
+SELECT quote(price), quote(weight), quote(ratio), quote(amount), quote(total), quote(other) FROM prices WHERE id = 3
@@ -0,0 +1,2 @@
+-- db: affinity.db
+SELECT id FROM prices WHERE price / 2 = 1.5
@@ -0,0 +1,2 @@
+-- db: records.db
+SELECT id, quote(a), quote(b), quote(c) FROM records;
@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT rowid, name FROM products WHERE rowid = 2
@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT name, category FROM products WHERE id = 4
@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT name FROM products WHERE id >= 3 AND id < 6
@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT id, name, price FROM products
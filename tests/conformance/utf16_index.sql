@@ -0,0 +1,2 @@
+-- db: utf16be.db
+SELECT id, hex(word) FROM words WHERE word = 'tëst';
@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT name FROM products WHERE category = 'fruit' AND (stock < 50 OR price < 0.3)
@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT name FROM products WHERE price BETWEEN 0.25 AND 2
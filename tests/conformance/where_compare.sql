@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT name FROM products WHERE price > 1
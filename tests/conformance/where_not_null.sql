@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT name FROM products WHERE stock NOT NULL AND price < 1_000
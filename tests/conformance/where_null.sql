@@ -0,0 +1,2 @@
+-- db: shop.db
+SELECT name FROM products WHERE stock IS NULL OR category IS NULL